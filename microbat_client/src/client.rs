@@ -1,43 +1,111 @@
 use crate::render_result::{
-    MutationKind, QueryExecutionResult, RenderableMutationResult, RenderableQueryResult,
+    MutationKind, QueryExecutionResult, RenderableCommandResult, RenderableMutationResult,
+    RenderableQueryResult,
 };
-use microbat_protocol::data::data_values::MData;
-use microbat_protocol::messages::client_messages::MicrobatClientMessage;
+use microbat_protocol::data::data_values::{MData, ToMData};
+use microbat_protocol::data::table_model::{Column, DataRow, Row, TableSchema};
+use microbat_protocol::messages::client_messages::{split_statements, MicrobatClientMessage};
 use microbat_protocol::messages::server_messages::{
-    deserialize_server_message, MicrobatServerMessage,
+    deserialize_data_row, deserialize_server_message, is_server_message_type, BackendKey,
+    ErrorResponse, MicrobatServerMessage, Severity,
 };
-use microbat_protocol::messages::{read_message, MicrobatMessage};
-use microbat_protocol::MicrobatProtocolError;
+use microbat_protocol::messages::timeout::ConnectionTimeouts;
+use microbat_protocol::messages::{
+    negotiate_version, read_message_with, MicrobatMessage, ProtocolFeatures,
+    BACKEND_KEY_PROTOCOL_VERSION, COPY_PROTOCOL_VERSION, EXTENDED_QUERY_PROTOCOL_VERSION,
+    MAX_CHUNKED_ROW_SIZE, PING_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use microbat_protocol::{parameters, sqlstate, MicrobatProtocolError};
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Rows of COPY are sent in CopyData messages of about this many bytes
+const COPY_BATCH_SIZE: usize = 64 * 1024;
 
 #[derive(Debug)]
 pub struct MicroBatClientError {
+    /// SQLSTATE of an error sent by the server, see `microbat_protocol::sqlstate`
+    pub code: Option<String>,
     pub msg: String,
 }
 
+impl From<ErrorResponse> for MicroBatClientError {
+    fn from(error: ErrorResponse) -> Self {
+        MicroBatClientError {
+            msg: error.to_string(),
+            code: Some(error.code),
+        }
+    }
+}
+
 impl From<MicrobatProtocolError> for MicroBatClientError {
     fn from(error: MicrobatProtocolError) -> Self {
-        MicroBatClientError { msg: error.msg }
+        // Server that doesn't answer in time is more likely stalled than slow
+        let msg = if error.is_timeout() {
+            String::from("Server is not responding")
+        } else {
+            error.msg
+        };
+        MicroBatClientError { code: None, msg }
     }
 }
 
+/// Client gives up on a server that doesn't answer or read in this long
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// Options for microbat client instance
 pub struct MicrobatClientOpts {
     pub host: String,
     pub port: u32,
+    pub user: String,
+    pub password: String,
+    /// Timeout of reading and writing a message, None waits for the server forever
+    pub timeout: Option<Duration>,
 }
 
 /// MicrobatTcpClient for communicating with microbat server
 /// Use MicrobatTcpClient::connect(opts) to acquire working connection
-pub struct MicroBatTcpClient {
-    stream: TcpStream,
+///
+/// The client can be created over any stream with `with_stream`, which is handy for testing
+/// against `microbat_protocol::testing::MockServer`.
+pub struct MicroBatTcpClient<S: Read + Write + Unpin = TcpStream> {
+    stream: S,
+    // Protocol version and features negotiated in the handshake
+    version: u16,
+    features: ProtocolFeatures,
+    asides: Asides,
+    // Count of prepared statements, for naming them
+    prepared: u32,
+    // Key of the session, from servers that send one
+    backend_key: Option<BackendKey>,
+}
+
+/// Messages the server sends besides the responses, kept until the user takes them
+#[derive(Default)]
+struct Asides {
+    // Tables the server told were created, until taken with `take_schema_changes`
+    schema_changes: Vec<String>,
+    // Warnings of statements, until taken with `take_notices`
+    notices: Vec<ErrorResponse>,
+    // Latest values of the parameters the server told of
+    parameters: HashMap<String, String>,
+    // True once the server told that it is shutting down
+    shutting_down: bool,
+}
+
+/// Statement parsed by the server once with `MicroBatTcpClient::prepare`, executed with
+/// `MicroBatTcpClient::execute`. Valid only on the connection that prepared it.
+#[derive(Debug)]
+pub struct PreparedStatement {
+    name: String,
 }
 
 impl MicroBatTcpClient {
-    /// Creates new connected socket to microbat instance
-    /// Errors if TcpStream cannot be established or handshake is not succesfull
+    /// Creates new connected socket to microbat instance and logs in
+    /// Errors if TcpStream cannot be established or handshake or login is not succesfull
     pub fn connect(opts: MicrobatClientOpts) -> Result<Self, MicroBatClientError> {
         let connect_string = format!("{}:{}", opts.host, opts.port);
         println!("MICROBAT CLIENT");
@@ -45,159 +113,1478 @@ impl MicroBatTcpClient {
         println!();
         match TcpStream::connect(&connect_string) {
             Ok(stream) => {
-                let mut client = MicroBatTcpClient { stream };
-                match client.handshake() {
-                    Ok(_) => {
-                        println!("Handshake OK [{}]", client.describe());
-                        Ok(client)
-                    }
-                    Err(err) => Err(err),
+                ConnectionTimeouts {
+                    read: opts.timeout,
+                    write: opts.timeout,
                 }
+                .apply(&stream)?;
+                let mut client = MicroBatTcpClient {
+                    stream,
+                    version: PROTOCOL_VERSION,
+                    features: ProtocolFeatures::default(),
+                    asides: Asides::default(),
+                    prepared: 0,
+                    backend_key: None,
+                };
+                client.handshake()?;
+                println!("Handshake OK [{}]", client.describe());
+                client.authenticate(&opts.user, &opts.password)?;
+                Ok(client)
             }
             Err(err) => Err(MicroBatClientError {
-                msg: format!("Unable to connect {} [{}]", connect_string, err.to_string()),
+                code: None,
+                msg: format!("Unable to connect {} [{}]", connect_string, err),
             }),
         }
     }
 
+    /// Address of the server, followed by its version if the server told of it
     pub fn describe(&self) -> String {
-        match self.stream.peer_addr() {
+        let address = match self.stream.peer_addr() {
             Ok(address) => address.to_string(),
-            Err(err) => format!("UNKNOWN [{}]", err.to_string()),
+            Err(err) => format!("UNKNOWN [{}]", err),
+        };
+        match self.server_parameters().get(parameters::SERVER_VERSION) {
+            Some(version) => format!("{}, microbat {}", address, version),
+            None => address,
         }
     }
+}
+
+impl<S: Read + Write + Unpin> MicroBatTcpClient<S> {
+    /// Creates a new client over an already connected stream and performs the handshake
+    pub fn with_stream(stream: S) -> Result<Self, MicroBatClientError> {
+        let mut client = MicroBatTcpClient {
+            stream,
+            version: PROTOCOL_VERSION,
+            features: ProtocolFeatures::default(),
+            asides: Asides::default(),
+            prepared: 0,
+            backend_key: None,
+        };
+        client.handshake()?;
+        Ok(client)
+    }
 
+    /// Starts the session, requesting all protocol features this client supports. Handshakes
+    /// are framed with the features negotiated before them, i.e without any in the first one.
     pub fn handshake(&mut self) -> Result<(), MicroBatClientError> {
-        MicrobatClientMessage::Handshake.send(&mut self.stream)?;
-        read_handshake(&mut self.stream)?;
-        read_ready(&mut self.stream)
+        // Server is going away, so starting a session anew would only fail later
+        if self.asides.shutting_down {
+            return Err(shutting_down_error());
+        }
+        MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all())
+            .send_with(&mut self.stream, self.features)?;
+        // Server may not know all of the features, so read the handshake strictly
+        (self.version, self.features) = read_handshake(&mut self.stream, self.features)?;
+        if self.version >= BACKEND_KEY_PROTOCOL_VERSION {
+            match read_server_message(&mut self.stream, self.features, &mut self.asides)? {
+                MicrobatServerMessage::BackendKeyData(key) => self.backend_key = Some(key),
+                MicrobatServerMessage::Error(error) => {
+                    return Err(MicroBatClientError::from(error))
+                }
+                message => {
+                    return Err(MicroBatClientError {
+                        code: None,
+                        msg: format!(
+                            "Expecting 'BackendKeyData' from server but got '{}'",
+                            message
+                        ),
+                    })
+                }
+            }
+        }
+        read_ready(&mut self.stream, self.features, &mut self.asides)
+    }
+
+    /// Logs in, which server requires before running any queries
+    pub fn authenticate(&mut self, user: &str, password: &str) -> Result<(), MicroBatClientError> {
+        MicrobatClientMessage::Authenticate {
+            user: String::from(user),
+            password: String::from(password),
+        }
+        .send_with(&mut self.stream, self.features)?;
+        match read_server_message(&mut self.stream, self.features, &mut self.asides)? {
+            MicrobatServerMessage::AuthOk => Ok(()),
+            MicrobatServerMessage::AuthFailed(reason) => Err(MicroBatClientError {
+                code: None,
+                msg: reason,
+            }),
+            MicrobatServerMessage::Error(error) => Err(MicroBatClientError::from(error)),
+            message => Err(MicroBatClientError {
+                code: None,
+                msg: format!("Expecting 'AuthOk' from server but got '{}'", message),
+            }),
+        }
+    }
+
+    /// Protocol version negotiated with the server
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Protocol features negotiated with the server
+    pub fn features(&self) -> ProtocolFeatures {
+        self.features
+    }
+
+    /// Key the server identifies this session with, None if the server predates
+    /// `BACKEND_KEY_PROTOCOL_VERSION`. Acting on the session from another connection, e.g.
+    /// cancelling its statement, needs the key.
+    pub fn backend_key(&self) -> Option<BackendKey> {
+        self.backend_key
+    }
+
+    /// Parameters the server told of, like its version, by their names in
+    /// `microbat_protocol::parameters`. Empty if the server predates
+    /// `PARAMETER_STATUS_PROTOCOL_VERSION`.
+    pub fn server_parameters(&self) -> &HashMap<String, String> {
+        &self.asides.parameters
+    }
+
+    /// True once the server told that it is shutting down. The session has ended then, and
+    /// reconnecting is pointless until the server is started again.
+    pub fn is_shutting_down(&self) -> bool {
+        self.asides.shutting_down
+    }
+
+    /// Takes the names of the tables created since the last call, e.g. for refreshing a
+    /// cache of the catalog.
+    ///
+    /// Server tells of them only if schema notifications were negotiated and only along the
+    /// responses to queries and pings, so an idle client can `ping` to hear of them.
+    pub fn take_schema_changes(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.asides.schema_changes)
+    }
+
+    /// Takes the warnings and notices the server sent about the statements executed since
+    /// the last call. They don't fail the statements, but are worth showing to the user.
+    pub fn take_notices(&mut self) -> Vec<ErrorResponse> {
+        std::mem::take(&mut self.asides.notices)
+    }
+
+    /// Checks that the server is still there without running a query, returning the round
+    /// trip time. Pinging an idle connection now and then also keeps NATs from dropping it.
+    pub fn ping(&mut self) -> Result<Duration, MicroBatClientError> {
+        if self.version < PING_PROTOCOL_VERSION {
+            return Err(MicroBatClientError {
+                code: None,
+                msg: format!(
+                    "Server speaks protocol version {} but ping needs version {}",
+                    self.version, PING_PROTOCOL_VERSION
+                ),
+            });
+        }
+        let start = Instant::now();
+        MicrobatClientMessage::Ping.send_with(&mut self.stream, self.features)?;
+        match read_server_message(&mut self.stream, self.features, &mut self.asides)? {
+            MicrobatServerMessage::Pong => Ok(start.elapsed()),
+            MicrobatServerMessage::Error(error) => Err(MicroBatClientError::from(error)),
+            message => Err(MicroBatClientError {
+                code: None,
+                msg: format!("Expecting 'Pong' from server but got '{}'", message),
+            }),
+        }
     }
 
     pub fn disconnect(&mut self) -> Result<(), MicroBatClientError> {
-        MicrobatClientMessage::Disconnect.send(&mut self.stream)?;
+        MicrobatClientMessage::Disconnect.send_with(&mut self.stream, self.features)?;
         Ok(())
     }
+
+    /// Executes given query and collects the whole result before returning it.
+    ///
+    /// Query must be a single statement, use `query_all` for executing multiple statements.
     pub fn query(&mut self, sql: String) -> Result<QueryExecutionResult, MicroBatClientError> {
+        single_statement(&sql)?;
         let start = Instant::now();
+        MicrobatClientMessage::Query(sql).send_with(&mut self.stream, self.features)?;
+        self.read_result(start)
+    }
 
-        MicrobatClientMessage::Query(sql).send(&mut self.stream)?;
-
-        match read_message(&mut self.stream, deserialize_server_message)? {
-            MicrobatServerMessage::DataDescription(data_description) => {
-                let rows = read_data_rows_until_ready(&mut self.stream)?;
-                Ok(QueryExecutionResult::DataTable(RenderableQueryResult::new(
-                    data_description.columns,
-                    rows,
-                    start.elapsed(),
-                )))
-            }
-            MicrobatServerMessage::InsertResult(rows) => {
-                read_ready(&mut self.stream)?;
-                Ok(QueryExecutionResult::Mutation(
-                    RenderableMutationResult::new(MutationKind::INSERT, rows, start.elapsed()),
-                ))
-            }
+    /// Executes every statement of given query, e.g. a script, and returns their results.
+    ///
+    /// Server executes the statements one by one and a failing statement does not stop the
+    /// rest, so the result of each statement is returned separately. Error is returned only
+    /// if the query can't be sent at all.
+    pub fn query_all(
+        &mut self,
+        sql: String,
+    ) -> Result<Vec<Result<QueryExecutionResult, MicroBatClientError>>, MicroBatClientError> {
+        let statements = split_statements(&sql).len();
+        MicrobatClientMessage::Query(sql).send_with(&mut self.stream, self.features)?;
+        Ok((0..statements)
+            .map(|_| self.read_result(Instant::now()))
+            .collect())
+    }
+
+    /// Executes given query with parameters bound to placeholders `$1`, `$2`, ...
+    ///
+    /// Parameters are sent to the server separately from the sql, so values never need
+    /// quoting or escaping. They are converted with `ToMData`, so plain rust values and
+    /// `Option`s (`None` binds NULL) can be passed, e.g. `&[&1, &"foo", &None::<i32>]`.
+    pub fn query_with_params(
+        &mut self,
+        sql: String,
+        params: &[&dyn ToMData],
+    ) -> Result<QueryExecutionResult, MicroBatClientError> {
+        let params: Vec<MData> = params.iter().map(|param| param.to_mdata()).collect();
+        let start = Instant::now();
+        MicrobatClientMessage::ParameterizedQuery(sql, params)
+            .send_with(&mut self.stream, self.features)?;
+        self.read_result(start)
+    }
+
+    /// Has the server parse given statement once for executing it any number of times with
+    /// `execute`, saving parsing it anew for each execution.
+    pub fn prepare(&mut self, sql: String) -> Result<PreparedStatement, MicroBatClientError> {
+        single_statement(&sql)?;
+        if self.version < EXTENDED_QUERY_PROTOCOL_VERSION {
+            return Err(MicroBatClientError {
+                code: None,
+                msg: format!(
+                    "Server speaks protocol version {} but prepared statements need version {}",
+                    self.version, EXTENDED_QUERY_PROTOCOL_VERSION
+                ),
+            });
+        }
+        self.prepared += 1;
+        let name = format!("s{}", self.prepared);
+        MicrobatClientMessage::Parse(name.clone(), sql)
+            .send_with(&mut self.stream, self.features)?;
+        read_completion(
+            &mut self.stream,
+            self.features,
+            &mut self.asides,
+            MicrobatServerMessage::ParseComplete,
+        )?;
+        Ok(PreparedStatement { name })
+    }
+
+    /// Executes a prepared statement with parameters bound to placeholders `$1`, `$2`, ...
+    /// like `query_with_params`, and collects the whole result before returning it.
+    pub fn execute(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[&dyn ToMData],
+    ) -> Result<QueryExecutionResult, MicroBatClientError> {
+        let params: Vec<MData> = params.iter().map(|param| param.to_mdata()).collect();
+        let start = Instant::now();
+        // Sent together, saving a round trip
+        MicrobatClientMessage::Bind(statement.name.clone(), params)
+            .send_with(&mut self.stream, self.features)?;
+        MicrobatClientMessage::Execute(statement.name.clone())
+            .send_with(&mut self.stream, self.features)?;
+        let bound = read_completion(
+            &mut self.stream,
+            self.features,
+            &mut self.asides,
+            MicrobatServerMessage::BindComplete,
+        );
+        // Response to Execute is read even if binding failed, and the reason binding failed
+        // is the more telling error
+        let result = self.read_result(start);
+        bound?;
+        result
+    }
+
+    /// Executes `COPY table FROM STDIN` and sends given rows for it in CopyData messages,
+    /// saving the round trip and parsing of an INSERT per row. Returns the count of inserted
+    /// rows. Server inserts the rows only after all of them are sent.
+    pub fn copy_in(
+        &mut self,
+        sql: String,
+        rows: impl IntoIterator<Item = Vec<MData>>,
+    ) -> Result<u32, MicroBatClientError> {
+        single_statement(&sql)?;
+        if self.version < COPY_PROTOCOL_VERSION {
+            return Err(MicroBatClientError {
+                code: None,
+                msg: format!(
+                    "Server speaks protocol version {} but COPY needs version {}",
+                    self.version, COPY_PROTOCOL_VERSION
+                ),
+            });
+        }
+        MicrobatClientMessage::Query(sql).send_with(&mut self.stream, self.features)?;
+        match read_server_message(&mut self.stream, self.features, &mut self.asides)? {
+            MicrobatServerMessage::CopyIn(_) => {}
             MicrobatServerMessage::Error(error) => {
-                read_ready(&mut self.stream)?;
-                Err(MicroBatClientError { msg: error })
+                read_ready(&mut self.stream, self.features, &mut self.asides)?;
+                return Err(MicroBatClientError::from(error));
             }
-            message => Err(MicroBatClientError {
+            message => {
+                return Err(MicroBatClientError {
+                    code: None,
+                    msg: format!("Expecting 'CopyIn' from server but got '{}'", message),
+                })
+            }
+        }
+        let mut batch = vec![];
+        // Count of rows, and the count of columns and the header of each value
+        let mut batch_bytes = 4;
+        for row in rows {
+            batch_bytes += 4 + row.iter().map(|value| 5 + value.byte_len()).sum::<usize>();
+            batch.push(DataRow::new(row));
+            if batch_bytes >= COPY_BATCH_SIZE {
+                MicrobatClientMessage::CopyData(std::mem::take(&mut batch))
+                    .send_with(&mut self.stream, self.features)?;
+                batch_bytes = 4;
+            }
+        }
+        if !batch.is_empty() {
+            MicrobatClientMessage::CopyData(batch).send_with(&mut self.stream, self.features)?;
+        }
+        MicrobatClientMessage::CopyDone.send_with(&mut self.stream, self.features)?;
+        match read_query_response(&mut self.stream, self.features, &mut self.asides)? {
+            QueryStream::Inserted(rows) => Ok(rows),
+            QueryStream::Rows(_) => Err(MicroBatClientError {
+                code: None,
+                msg: String::from("Expecting 'InsertResult' from server but got 'DataDescription'"),
+            }),
+            QueryStream::Command(_) => Err(MicroBatClientError {
+                code: None,
+                msg: String::from("Expecting 'InsertResult' from server but got 'CommandComplete'"),
+            }),
+        }
+    }
+
+    /// Executes given query without reading the resulting rows.
+    ///
+    /// If the query produces a result set, rows are read from the server lazily
+    /// while iterating the returned `RowStream`.
+    pub fn query_stream(&mut self, sql: String) -> Result<QueryStream<'_, S>, MicroBatClientError> {
+        single_statement(&sql)?;
+        MicrobatClientMessage::Query(sql).send_with(&mut self.stream, self.features)?;
+        read_query_response(&mut self.stream, self.features, &mut self.asides)
+    }
+
+    /// Reads and collects the whole response to one statement
+    fn read_result(&mut self, start: Instant) -> Result<QueryExecutionResult, MicroBatClientError> {
+        match read_query_response(&mut self.stream, self.features, &mut self.asides)? {
+            QueryStream::Rows(rows) => {
+                let schema = rows.schema.clone();
+                let rows = rows.collect::<Result<Vec<Row>, MicroBatClientError>>()?;
+                Ok(QueryExecutionResult::DataTable(
+                    RenderableQueryResult::from_rows(schema, rows, start.elapsed()),
+                ))
+            }
+            QueryStream::Inserted(rows) => Ok(QueryExecutionResult::Mutation(
+                RenderableMutationResult::new(MutationKind::INSERT, rows, start.elapsed()),
+            )),
+            QueryStream::Command(tag) => Ok(QueryExecutionResult::Command(
+                RenderableCommandResult::new(tag, start.elapsed()),
+            )),
+        }
+    }
+}
+
+/// Response of a query whose rows, if any, are not yet read from the server
+pub enum QueryStream<'a, S: Read + Write + Unpin> {
+    Rows(RowStream<'a, S>),
+    Inserted(u32),
+    /// Tag of a statement that neither returns nor inserts rows, e.g. `CREATE TABLE`
+    Command(String),
+}
+
+/// Lazy iterator over the rows of a result set.
+///
+/// Rows are read from the server as `next()` needs them, a batch of rows at a time when the
+/// server sends them batched, and the iteration ends when the server sends Ready. Rows the
+/// server sends in chunks are reassembled before they are returned. Dropping the stream
+/// before it is consumed reads and discards the remaining rows, so the connection is always
+/// left ready for the next query.
+pub struct RowStream<'a, S: Read + Write + Unpin> {
+    stream: &'a mut S,
+    features: ProtocolFeatures,
+    asides: &'a mut Asides,
+    // Shared with every row of the stream
+    schema: Arc<TableSchema>,
+    // Rest of the last DataRowBatch
+    batched: VecDeque<DataRow>,
+    // Payload of a row received in DataRowChunk messages so far
+    chunks: Vec<u8>,
+    finished: bool,
+}
+
+impl<'a, S: Read + Write + Unpin> RowStream<'a, S> {
+    fn new(
+        stream: &'a mut S,
+        features: ProtocolFeatures,
+        asides: &'a mut Asides,
+        schema: TableSchema,
+    ) -> Self {
+        RowStream {
+            stream,
+            features,
+            asides,
+            schema: Arc::new(schema),
+            batched: VecDeque::new(),
+            chunks: vec![],
+            finished: false,
+        }
+    }
+
+    /// Columns of the result set as described by the server
+    pub fn columns(&self) -> &[Column] {
+        &self.schema.columns
+    }
+
+    /// Row of given values, which must have a value for each column. Rows after a malformed
+    /// one are still read, so the connection stays in sync with the server.
+    fn row(&self, row: DataRow) -> Result<Row, MicroBatClientError> {
+        if row.columns.len() != self.schema.columns.len() {
+            return Err(MicroBatClientError {
+                code: None,
                 msg: format!(
-                    "Expecting 'DataDescription' from server but got '{}'",
-                    message
+                    "Row has {} values but the result has {} columns",
+                    row.columns.len(),
+                    self.schema.columns.len()
                 ),
-            }),
+            });
         }
+        Ok(Row::new(self.schema.clone(), row))
     }
 }
 
-fn read_handshake(stream: &mut (impl Read + Write + Unpin)) -> Result<(), MicroBatClientError> {
-    match read_message(stream, deserialize_server_message)? {
-        MicrobatServerMessage::Handshake => Ok(()),
-        MicrobatServerMessage::Error(error) => Err(MicroBatClientError { msg: error }),
-        message => Err(MicroBatClientError {
-            msg: format!("Expecting 'Handshake' from server but got '{}'", message),
+impl<S: Read + Write + Unpin> Iterator for RowStream<'_, S> {
+    type Item = Result<Row, MicroBatClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.batched.pop_front() {
+                return Some(self.row(row));
+            }
+            if self.finished {
+                return None;
+            }
+            let message = match read_server_message(self.stream, self.features, self.asides) {
+                Ok(message) => message,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            };
+            match message {
+                MicrobatServerMessage::DataRow(row) => return Some(self.row(row)),
+                MicrobatServerMessage::DataRowBatch(rows) => self.batched = rows.into(),
+                MicrobatServerMessage::DataRowChunk { bytes, last } => {
+                    if self.chunks.len() + bytes.len() > MAX_CHUNKED_ROW_SIZE {
+                        self.finished = true;
+                        return Some(Err(MicroBatClientError {
+                            code: None,
+                            msg: format!(
+                                "Row is larger than {} bytes, the limit of chunked rows",
+                                MAX_CHUNKED_ROW_SIZE
+                            ),
+                        }));
+                    }
+                    self.chunks.extend(bytes);
+                    if last {
+                        let row = deserialize_data_row(&std::mem::take(&mut self.chunks));
+                        return Some(match row {
+                            Ok(row) => self.row(row),
+                            Err(err) => {
+                                self.finished = true;
+                                Err(err.into())
+                            }
+                        });
+                    }
+                }
+                MicrobatServerMessage::Ready => {
+                    self.finished = true;
+                    return None;
+                }
+                MicrobatServerMessage::Error(error) => {
+                    self.finished = true;
+                    // Server follows the error with Ready
+                    return Some(
+                        read_ready(self.stream, self.features, self.asides)
+                            .and(Err(MicroBatClientError::from(error))),
+                    );
+                }
+                message => {
+                    self.finished = true;
+                    return Some(Err(MicroBatClientError {
+                        code: None,
+                        msg: format!("Expecting 'DataRow' from server but got '{}'", message),
+                    }));
+                }
+            }
+        }
+    }
+}
+
+impl<S: Read + Write + Unpin> Drop for RowStream<'_, S> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// Checks that query has only one statement, so only one response is expected from the server
+fn single_statement(sql: &str) -> Result<(), MicroBatClientError> {
+    match split_statements(sql).len() {
+        1 => Ok(()),
+        statements => Err(MicroBatClientError {
+            code: None,
+            msg: format!(
+                "Query has {} statements, use query_all for multiple statements",
+                statements
+            ),
         }),
     }
 }
 
-fn read_ready(stream: &mut (impl Read + Write + Unpin)) -> Result<(), MicroBatClientError> {
-    match read_message(stream, deserialize_server_message)? {
-        MicrobatServerMessage::Ready => Ok(()),
-        MicrobatServerMessage::Error(error) => Err(MicroBatClientError { msg: error }),
+fn read_query_response<'a, S: Read + Write + Unpin>(
+    stream: &'a mut S,
+    features: ProtocolFeatures,
+    asides: &'a mut Asides,
+) -> Result<QueryStream<'a, S>, MicroBatClientError> {
+    match read_server_message(stream, features, asides)? {
+        MicrobatServerMessage::DataDescription(data_description) => Ok(QueryStream::Rows(
+            RowStream::new(stream, features, asides, data_description),
+        )),
+        MicrobatServerMessage::InsertResult(rows) => {
+            read_ready(stream, features, asides)?;
+            Ok(QueryStream::Inserted(rows))
+        }
+        MicrobatServerMessage::CommandComplete(tag) => {
+            read_ready(stream, features, asides)?;
+            Ok(QueryStream::Command(tag))
+        }
+        MicrobatServerMessage::Error(error) => {
+            read_ready(stream, features, asides)?;
+            Err(MicroBatClientError::from(error))
+        }
         message => Err(MicroBatClientError {
-            msg: format!("Expecting 'Ready' from server but got '{}'", message),
+            code: None,
+            msg: format!(
+                "Expecting 'DataDescription' from server but got '{}'",
+                message
+            ),
         }),
     }
 }
 
-fn read_data_rows_until_ready(
+/// Reads next message, skipping unknown messages and verifying checksums if those are
+/// negotiated. Schema changes, notices and parameters the server sends are collected to
+/// `asides` instead of being returned.
+///
+/// Fatal errors and ShuttingDown end the session, so those are returned as errors right
+/// away instead of expecting Ready after them.
+fn read_server_message(
     stream: &mut (impl Read + Write + Unpin),
-) -> Result<Vec<Vec<MData>>, MicroBatClientError> {
-    let mut rows: Vec<Vec<MData>> = vec![];
+    features: ProtocolFeatures,
+    asides: &mut Asides,
+) -> Result<MicrobatServerMessage, MicroBatClientError> {
     loop {
-        match read_message(stream, deserialize_server_message)? {
-            MicrobatServerMessage::DataRow(row) => {
-                rows.push(row.columns);
+        let message = read_message_with(
+            stream,
+            deserialize_server_message,
+            is_server_message_type,
+            features,
+        )?;
+        match message {
+            MicrobatServerMessage::SchemaChanged(table) => asides.schema_changes.push(table),
+            MicrobatServerMessage::Notice(notice) => asides.notices.push(notice),
+            MicrobatServerMessage::ParameterStatus(name, value) => {
+                asides.parameters.insert(name, value);
             }
-            MicrobatServerMessage::Error(error) => return Err(MicroBatClientError { msg: error }),
-            MicrobatServerMessage::Ready => return Ok(rows),
-            message => {
-                return Err(MicroBatClientError {
-                    msg: format!("Expecting 'DataRow' from server but got '{}'", message),
-                })
+            MicrobatServerMessage::ShuttingDown => {
+                asides.shutting_down = true;
+                return Err(shutting_down_error());
             }
+            // Older servers tell of shutting down with a fatal error
+            MicrobatServerMessage::Error(error) if error.severity == Severity::Fatal => {
+                asides.shutting_down |= error.code == sqlstate::ADMIN_SHUTDOWN;
+                return Err(MicroBatClientError::from(error));
+            }
+            message => return Ok(message),
         }
     }
 }
 
+fn shutting_down_error() -> MicroBatClientError {
+    MicroBatClientError {
+        code: Some(String::from(sqlstate::ADMIN_SHUTDOWN)),
+        msg: String::from("Server is shutting down"),
+    }
+}
+
+fn read_handshake(
+    stream: &mut (impl Read + Write + Unpin),
+    features: ProtocolFeatures,
+) -> Result<(u16, ProtocolFeatures), MicroBatClientError> {
+    match read_message_with(stream, deserialize_server_message, |_| true, features)? {
+        // Server answers with the older of the two versions, so it must be one client speaks
+        MicrobatServerMessage::Handshake(version, features) => match negotiate_version(version) {
+            Some(negotiated) if negotiated == version => Ok((version, features)),
+            _ => Err(MicroBatClientError {
+                code: None,
+                msg: format!("Server answered with unknown protocol version {}", version),
+            }),
+        },
+        MicrobatServerMessage::UnsupportedVersion(min, max) => Err(MicroBatClientError {
+            code: None,
+            msg: format!(
+                "Server supports protocol versions {} to {} but client speaks version {}",
+                min, max, PROTOCOL_VERSION
+            ),
+        }),
+        MicrobatServerMessage::Error(error) => Err(MicroBatClientError::from(error)),
+        message => Err(MicroBatClientError {
+            code: None,
+            msg: format!("Expecting 'Handshake' from server but got '{}'", message),
+        }),
+    }
+}
+
+/// Reads given message and the Ready after it, or the error the server sent instead
+fn read_completion(
+    stream: &mut (impl Read + Write + Unpin),
+    features: ProtocolFeatures,
+    asides: &mut Asides,
+    expected: MicrobatServerMessage,
+) -> Result<(), MicroBatClientError> {
+    match read_server_message(stream, features, asides)? {
+        message if message == expected => read_ready(stream, features, asides),
+        MicrobatServerMessage::Error(error) => {
+            read_ready(stream, features, asides)?;
+            Err(MicroBatClientError::from(error))
+        }
+        message => Err(MicroBatClientError {
+            code: None,
+            msg: format!("Expecting '{}' from server but got '{}'", expected, message),
+        }),
+    }
+}
+
+fn read_ready(
+    stream: &mut (impl Read + Write + Unpin),
+    features: ProtocolFeatures,
+    asides: &mut Asides,
+) -> Result<(), MicroBatClientError> {
+    match read_server_message(stream, features, asides)? {
+        MicrobatServerMessage::Ready => Ok(()),
+        MicrobatServerMessage::Error(error) => Err(MicroBatClientError::from(error)),
+        message => Err(MicroBatClientError {
+            code: None,
+            msg: format!("Expecting 'Ready' from server but got '{}'", message),
+        }),
+    }
+}
+
 #[cfg(test)]
 mod expect_message_tests {
     use super::*;
-    use std::cmp::min;
+    use microbat_protocol::data::data_values::MDataType;
+    use microbat_protocol::data::table_model::TableSchema;
+    use microbat_protocol::messages::server_messages::row_chunks;
+    use microbat_protocol::testing::MockServer;
+
+    fn handshake() -> MockServer {
+        MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+                key_data(),
+                MicrobatServerMessage::Ready,
+            ],
+        )
+    }
+
+    fn key_data() -> MicrobatServerMessage {
+        MicrobatServerMessage::BackendKeyData(BackendKey {
+            session_id: 1,
+            secret: 42,
+        })
+    }
+
+    fn query(sql: &str) -> MicrobatClientMessage {
+        MicrobatClientMessage::Query(String::from(sql))
+    }
+
+    fn description() -> MicrobatServerMessage {
+        MicrobatServerMessage::DataDescription(
+            TableSchema::new(vec![Column::new(String::from("id"), MDataType::Integer)]).unwrap(),
+        )
+    }
+
+    fn row(value: i32) -> MicrobatServerMessage {
+        MicrobatServerMessage::DataRow(DataRow::new(vec![MData::Integer(value)]))
+    }
+
+    #[test]
+    fn test_handshake() {
+        let server = handshake();
+        MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        server.assert_done();
+
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![MicrobatServerMessage::Error(ErrorResponse::new(
+                sqlstate::INTERNAL_ERROR,
+                "go away",
+            ))],
+        );
+        let error = MicroBatTcpClient::with_stream(server).err().unwrap();
+        assert_eq!(error.msg, "go away");
+    }
+
+    #[test]
+    fn test_handshake_again() {
+        // Second handshake is framed with the features of the first, checksums included
+        let server = handshake().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+                key_data(),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert!(client.features().checksums);
+        client.handshake().unwrap();
+        assert!(!client.features().checksums);
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_backend_key() {
+        let client = MicroBatTcpClient::with_stream(handshake()).unwrap();
+        assert_eq!(
+            client.backend_key(),
+            Some(BackendKey {
+                session_id: 1,
+                secret: 42
+            })
+        );
+
+        // Older server sends no key
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(
+                    BACKEND_KEY_PROTOCOL_VERSION - 1,
+                    ProtocolFeatures::all(),
+                ),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert_eq!(client.backend_key(), None);
+        server.assert_done();
+
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        assert_eq!(
+            MicroBatTcpClient::with_stream(server).err().unwrap().msg,
+            "Expecting 'BackendKeyData' from server but got 'Ready'"
+        );
+    }
+
+    #[test]
+    fn test_server_parameters() {
+        let parameter = |name: &str, value: &str| {
+            MicrobatServerMessage::ParameterStatus(String::from(name), String::from(value))
+        };
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+                key_data(),
+                parameter(parameters::SERVER_VERSION, "0.1.0"),
+                parameter(parameters::CLIENT_ENCODING, "UTF8"),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let server_parameters = client.server_parameters();
+        assert_eq!(server_parameters.len(), 2);
+        assert_eq!(server_parameters[parameters::SERVER_VERSION], "0.1.0");
+        assert_eq!(server_parameters[parameters::CLIENT_ENCODING], "UTF8");
+        server.assert_done();
+
+        let client = MicroBatTcpClient::with_stream(handshake()).unwrap();
+        assert!(client.server_parameters().is_empty());
+    }
+
+    #[test]
+    fn test_features_are_negotiated() {
+        let client = MicroBatTcpClient::with_stream(handshake()).unwrap();
+        assert!(client.features().skip_unknown_messages);
+        assert!(client.features().checksums);
+
+        // Older server does not know features and responds without them
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+                key_data(),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let client = MicroBatTcpClient::with_stream(server).unwrap();
+        assert!(!client.features().skip_unknown_messages);
+        assert!(!client.features().checksums);
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let authenticate = |password: &str| MicrobatClientMessage::Authenticate {
+            user: String::from("juho"),
+            password: String::from(password),
+        };
+        let server = handshake().expect(
+            authenticate("wrong"),
+            vec![MicrobatServerMessage::AuthFailed(String::from("nope"))],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert_eq!(
+            client.authenticate("juho", "wrong").err().unwrap().msg,
+            "nope"
+        );
+        server.assert_done();
+
+        let server = handshake().expect(authenticate("right"), vec![MicrobatServerMessage::AuthOk]);
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        client.authenticate("juho", "right").unwrap();
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_version_is_negotiated() {
+        let client = MicroBatTcpClient::with_stream(handshake()).unwrap();
+        assert_eq!(client.version(), PROTOCOL_VERSION);
+
+        // Server that predates versions answers without one
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(1, ProtocolFeatures::default()),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        assert_eq!(MicroBatTcpClient::with_stream(server).unwrap().version(), 1);
+
+        for (response, error) in [
+            (
+                MicrobatServerMessage::UnsupportedVersion(
+                    PROTOCOL_VERSION + 1,
+                    PROTOCOL_VERSION + 2,
+                ),
+                format!(
+                    "Server supports protocol versions {} to {} but client speaks version {}",
+                    PROTOCOL_VERSION + 1,
+                    PROTOCOL_VERSION + 2,
+                    PROTOCOL_VERSION
+                ),
+            ),
+            (
+                MicrobatServerMessage::Handshake(PROTOCOL_VERSION + 1, ProtocolFeatures::all()),
+                format!(
+                    "Server answered with unknown protocol version {}",
+                    PROTOCOL_VERSION + 1
+                ),
+            ),
+        ] {
+            let server = MockServer::new().expect(
+                MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+                vec![response],
+            );
+            assert_eq!(
+                MicroBatTcpClient::with_stream(server).err().unwrap().msg,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_ping() {
+        let server = handshake().expect(
+            MicrobatClientMessage::Ping,
+            vec![MicrobatServerMessage::Pong],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        client.ping().unwrap();
+        server.assert_done();
 
-    struct MockTcpStream {
-        read_data: Vec<u8>,
-        write_data: Vec<u8>,
+        // Older server would drop the connection, so nothing is sent
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(2, ProtocolFeatures::all()),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert_eq!(
+            client.ping().err().unwrap().msg,
+            "Server speaks protocol version 2 but ping needs version 3"
+        );
+        server.assert_done();
     }
 
-    impl Read for MockTcpStream {
-        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-            let size: usize = min(self.read_data.len(), buf.len());
-            buf[..size].copy_from_slice(&self.read_data[..size]);
-            Ok(size)
+    #[test]
+    fn test_command_complete() {
+        let server = handshake().expect(
+            query("create table foo (id integer)"),
+            vec![
+                MicrobatServerMessage::CommandComplete(String::from("CREATE TABLE")),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        match client
+            .query(String::from("create table foo (id integer)"))
+            .unwrap()
+        {
+            QueryExecutionResult::Command(result) => {
+                assert!(result.to_string().starts_with("CREATE TABLE\n"))
+            }
+            _ => panic!("Expecting command result"),
         }
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_shutting_down() {
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![MicrobatServerMessage::ShuttingDown],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert!(!client.is_shutting_down());
+        let error = client
+            .query(String::from("select id from foo"))
+            .err()
+            .unwrap();
+        assert_eq!(error.code.as_deref(), Some(sqlstate::ADMIN_SHUTDOWN));
+        assert_eq!(error.msg, "Server is shutting down");
+        assert!(client.is_shutting_down());
+        // No handshake is sent to a server going away
+        assert!(client.handshake().is_err());
+        server.assert_done();
+
+        // Older server tells with a fatal error, which is not followed by Ready
+        let server = handshake().expect(
+            MicrobatClientMessage::Ping,
+            vec![MicrobatServerMessage::Error(ErrorResponse::fatal(
+                sqlstate::ADMIN_SHUTDOWN,
+                "Server is shutting down",
+            ))],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert!(client.ping().is_err());
+        assert!(client.is_shutting_down());
+        server.assert_done();
     }
 
-    impl Write for MockTcpStream {
-        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-            self.write_data.append(&mut Vec::from(buf));
-            Ok(buf.len())
+    #[test]
+    fn test_schema_changes() {
+        let changed = |table: &str| MicrobatServerMessage::SchemaChanged(String::from(table));
+        let server = handshake()
+            .expect(
+                query("select id from foo"),
+                vec![
+                    description(),
+                    row(1),
+                    changed("foo"),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                MicrobatClientMessage::Ping,
+                vec![changed("bar"), changed("baz"), MicrobatServerMessage::Pong],
+            );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert!(client.features().schema_notifications);
+        assert!(client.take_schema_changes().is_empty());
+
+        client.query(String::from("select id from foo")).unwrap();
+        assert_eq!(client.take_schema_changes(), ["foo"]);
+        client.ping().unwrap();
+        assert_eq!(client.take_schema_changes(), ["bar", "baz"]);
+        assert!(client.take_schema_changes().is_empty());
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_notices() {
+        let warning = |message: &str| {
+            MicrobatServerMessage::Notice(ErrorResponse::warning(sqlstate::WARNING, message))
+        };
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![
+                warning("first"),
+                description(),
+                row(1),
+                warning("second"),
+                row(2),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert!(client.take_notices().is_empty());
+        // Notices don't end the result
+        match client.query(String::from("select id from foo")).unwrap() {
+            QueryExecutionResult::DataTable(result) => assert_eq!(result.row_count(), 2),
+            _ => panic!("Expecting data table"),
         }
+        let notices = client.take_notices();
+        assert_eq!(
+            notices
+                .iter()
+                .map(|notice| notice.message.as_str())
+                .collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+        assert_eq!(notices[0].severity, Severity::Warning);
+        assert!(client.take_notices().is_empty());
+        server.assert_done();
+    }
 
-        fn flush(&mut self) -> std::io::Result<()> {
-            Ok(())
+    #[test]
+    fn test_query() {
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![description(), row(1), row(2), MicrobatServerMessage::Ready],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        match client.query(String::from("select id from foo")).unwrap() {
+            QueryExecutionResult::DataTable(result) => {
+                assert_eq!(result.row_count(), 2);
+                assert_eq!(result.rows()[1].get::<i32>("id").unwrap(), 2);
+            }
+            _ => panic!("Expecting data table"),
         }
+        server.assert_done();
     }
 
-    // #[test]
-    // fn test_simple_read_expected() {
-    //     let mut write_stream = MockTcpStream {
-    //         read_data: vec![],
-    //         write_data: vec![],
-    //     };
-    //     MicrobatServerMessage::Handshake
-    //         .send(&mut write_stream)
-    //         .unwrap();
-    //     assert!(write_stream.write_data.len() > 0);
-    //
-    //     let mut read_stream = MockTcpStream {
-    //         read_data: write_stream.write_data,
-    //         write_data: vec![],
-    //     };
-    //
-    //     let result = expect_message(&mut read_stream, MicrobatServerMessage::Handshake);
-    //     assert!(result.is_ok());
-    //     assert_eq!(result.unwrap(), MicrobatServerMessage::Handshake);
-    // }
+    #[test]
+    fn test_batched_rows() {
+        let batch = |values: &[i32]| {
+            MicrobatServerMessage::DataRowBatch(
+                values
+                    .iter()
+                    .map(|value| DataRow::new(vec![MData::Integer(*value)]))
+                    .collect(),
+            )
+        };
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![
+                description(),
+                batch(&[1, 2]),
+                batch(&[]),
+                row(3),
+                batch(&[4, 5]),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let ids: Vec<i32> = match client.query_stream(String::from("select id from foo")) {
+            Ok(QueryStream::Rows(rows)) => {
+                rows.map(|row| row.unwrap().get("id").unwrap()).collect()
+            }
+            _ => panic!("Expecting rows"),
+        };
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_chunked_rows() {
+        let large = DataRow::new(vec![MData::Integer(2)]);
+        let mut responses = vec![description(), row(1)];
+        responses.extend(row_chunks(&large, 3));
+        responses.extend([row(3), MicrobatServerMessage::Ready]);
+        let server = handshake().expect(query("select id from foo"), responses);
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let ids: Vec<i32> = match client.query_stream(String::from("select id from foo")) {
+            Ok(QueryStream::Rows(rows)) => {
+                rows.map(|row| row.unwrap().get("id").unwrap()).collect()
+            }
+            _ => panic!("Expecting rows"),
+        };
+        assert_eq!(ids, vec![1, 2, 3]);
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_query_all() {
+        let server = handshake().expect(
+            query("select id from foo; select; select id from bar;"),
+            vec![
+                description(),
+                row(1),
+                MicrobatServerMessage::Ready,
+                MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::SYNTAX_ERROR, "bad")),
+                MicrobatServerMessage::Ready,
+                description(),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let results = client
+            .query_all(String::from(
+                "select id from foo; select; select id from bar;",
+            ))
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        match &results[0] {
+            Ok(QueryExecutionResult::DataTable(result)) => assert_eq!(result.row_count(), 1),
+            _ => panic!("Expecting data table"),
+        }
+        assert_eq!(results[1].as_ref().err().unwrap().msg, "bad");
+        match &results[2] {
+            Ok(QueryExecutionResult::DataTable(result)) => assert_eq!(result.row_count(), 0),
+            _ => panic!("Expecting data table"),
+        }
+        server.assert_done();
+
+        let mut client = MicroBatTcpClient::with_stream(handshake()).unwrap();
+        let error = client
+            .query(String::from("select 1; select 2;"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.msg,
+            "Query has 2 statements, use query_all for multiple statements"
+        );
+    }
+
+    #[test]
+    fn test_query_error() {
+        let server = handshake().expect(
+            query("select"),
+            vec![
+                MicrobatServerMessage::Error(ErrorResponse {
+                    position: Some((1, 7)),
+                    ..ErrorResponse::new(sqlstate::SYNTAX_ERROR, "Unexpected end of tokens")
+                }),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let error = client.query(String::from("select")).err().unwrap();
+        assert_eq!(error.msg, "Unexpected end of tokens at line 1 column 7");
+        assert_eq!(error.code.as_deref(), Some(sqlstate::SYNTAX_ERROR));
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_query_with_params() {
+        let server = handshake().expect(
+            MicrobatClientMessage::ParameterizedQuery(
+                String::from("select $1, $2, $3"),
+                vec![
+                    MData::Varchar(String::from("O'Brien")),
+                    MData::Null,
+                    MData::Integer(5),
+                ],
+            ),
+            vec![description(), MicrobatServerMessage::Ready],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        client
+            .query_with_params(
+                String::from("select $1, $2, $3"),
+                &[&"O'Brien", &None::<i32>, &5],
+            )
+            .unwrap();
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_prepared_statements() {
+        let parse = MicrobatClientMessage::Parse(
+            String::from("s1"),
+            String::from("select id from foo where id > $1"),
+        );
+        let bind = |value: i32| {
+            MicrobatClientMessage::Bind(String::from("s1"), vec![MData::Integer(value)])
+        };
+        let execute = MicrobatClientMessage::Execute(String::from("s1"));
+        let server = handshake()
+            .expect(
+                parse,
+                vec![
+                    MicrobatServerMessage::ParseComplete,
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                bind(1),
+                vec![
+                    MicrobatServerMessage::BindComplete,
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                execute.clone(),
+                vec![description(), row(2), MicrobatServerMessage::Ready],
+            )
+            .expect(
+                MicrobatClientMessage::Bind(String::from("s1"), vec![]),
+                vec![
+                    MicrobatServerMessage::Error(ErrorResponse::new(
+                        sqlstate::UNDEFINED_PARAMETER,
+                        "Statement has 1 parameters but 0 were given",
+                    )),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                execute,
+                vec![
+                    MicrobatServerMessage::Error(ErrorResponse::new(
+                        sqlstate::UNDEFINED_PARAMETER,
+                        "Statement has parameters but none are bound",
+                    )),
+                    MicrobatServerMessage::Ready,
+                ],
+            );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let statement = client
+            .prepare(String::from("select id from foo where id > $1"))
+            .unwrap();
+        match client.execute(&statement, &[&1]).unwrap() {
+            QueryExecutionResult::DataTable(result) => assert_eq!(result.row_count(), 1),
+            _ => panic!("Expecting data table"),
+        }
+        assert_eq!(
+            client.execute(&statement, &[]).err().unwrap().msg,
+            "Statement has 1 parameters but 0 were given"
+        );
+        server.assert_done();
+
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()),
+            vec![
+                MicrobatServerMessage::Handshake(5, ProtocolFeatures::all()),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert_eq!(
+            client.prepare(String::from("select 1")).err().unwrap().msg,
+            "Server speaks protocol version 5 but prepared statements need version 6"
+        );
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_copy_in() {
+        let rows = |range: std::ops::Range<i32>| {
+            range
+                .map(|id| vec![MData::Integer(id), MData::Varchar("x".repeat(1000))])
+                .collect::<Vec<_>>()
+        };
+        let copy = || query("copy foo from stdin");
+        let copy_in = || {
+            MicrobatServerMessage::CopyIn(
+                TableSchema::new(vec![
+                    Column::new(String::from("id"), MDataType::Integer),
+                    Column::new(String::from("name"), MDataType::Varchar),
+                ])
+                .unwrap(),
+            )
+        };
+        // Rows are sent in batches of about COPY_BATCH_SIZE bytes
+        let batch = |range| {
+            MicrobatClientMessage::CopyData(rows(range).into_iter().map(DataRow::new).collect())
+        };
+        let server = handshake()
+            .expect(copy(), vec![copy_in()])
+            .expect(batch(0..65), vec![])
+            .expect(batch(65..100), vec![])
+            .expect(
+                MicrobatClientMessage::CopyDone,
+                vec![
+                    MicrobatServerMessage::InsertResult(100),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                copy(),
+                vec![
+                    MicrobatServerMessage::Error(ErrorResponse::new(
+                        sqlstate::UNDEFINED_TABLE,
+                        "Table foo does not exist",
+                    )),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(copy(), vec![copy_in()])
+            .expect(
+                MicrobatClientMessage::CopyDone,
+                vec![
+                    MicrobatServerMessage::InsertResult(0),
+                    MicrobatServerMessage::Ready,
+                ],
+            );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        assert_eq!(
+            client
+                .copy_in(String::from("copy foo from stdin"), rows(0..100))
+                .unwrap(),
+            100
+        );
+        assert_eq!(
+            client
+                .copy_in(String::from("copy foo from stdin"), rows(0..1))
+                .err()
+                .unwrap()
+                .code
+                .as_deref(),
+            Some(sqlstate::UNDEFINED_TABLE)
+        );
+        assert_eq!(
+            client
+                .copy_in(String::from("copy foo from stdin"), vec![])
+                .unwrap(),
+            0
+        );
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_rows_are_read_lazily() {
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![description(), row(1), row(2), MicrobatServerMessage::Ready],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let mut rows = match client
+            .query_stream(String::from("select id from foo"))
+            .unwrap()
+        {
+            QueryStream::Rows(rows) => rows,
+            _ => panic!("Expecting rows"),
+        };
+        let total_bytes = server.unread();
+        assert_eq!(rows.columns().len(), 1);
+        assert_eq!(rows.next().unwrap().unwrap().get::<i32>("id").unwrap(), 1);
+        assert!(server.unread() < total_bytes);
+        assert!(server.unread() > 0);
+        assert_eq!(
+            rows.next().unwrap().unwrap().into_values(),
+            vec![MData::Integer(2)]
+        );
+        assert!(rows.next().is_none());
+        assert!(rows.next().is_none());
+        drop(rows);
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_dropping_row_stream_drains_remaining_rows() {
+        let server = handshake()
+            .expect(
+                query("select id from foo"),
+                vec![
+                    description(),
+                    row(1),
+                    row(2),
+                    row(3),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                query("insert"),
+                vec![
+                    MicrobatServerMessage::InsertResult(1),
+                    MicrobatServerMessage::Ready,
+                ],
+            );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        match client
+            .query_stream(String::from("select id from foo"))
+            .unwrap()
+        {
+            QueryStream::Rows(mut rows) => {
+                rows.next().unwrap().unwrap();
+            }
+            _ => panic!("Expecting rows"),
+        };
+        match client.query_stream(String::from("insert")).unwrap() {
+            QueryStream::Inserted(count) => assert_eq!(count, 1),
+            _ => panic!("Expecting insert result"),
+        };
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![
+                description(),
+                MicrobatServerMessage::DataRow(DataRow::new(vec![])),
+                MicrobatServerMessage::DataRowBatch(vec![DataRow::new(vec![
+                    MData::Integer(1),
+                    MData::Integer(2),
+                ])]),
+                row(3),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let error = client
+            .query(String::from("select id from foo"))
+            .err()
+            .unwrap();
+        assert_eq!(error.msg, "Row has 0 values but the result has 1 columns");
+        // Rest of the response is read, leaving the connection ready for the next query
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_error_in_row_stream() {
+        let server = handshake().expect(
+            query("select id from foo"),
+            vec![
+                description(),
+                row(1),
+                MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::INTERNAL_ERROR, "boom")),
+                MicrobatServerMessage::Ready,
+            ],
+        );
+        let mut client = MicroBatTcpClient::with_stream(server.clone()).unwrap();
+        let mut rows = match client
+            .query_stream(String::from("select id from foo"))
+            .unwrap()
+        {
+            QueryStream::Rows(rows) => rows,
+            _ => panic!("Expecting rows"),
+        };
+        assert!(rows.next().unwrap().is_ok());
+        assert_eq!(rows.next().unwrap().unwrap_err().msg, "boom");
+        assert!(rows.next().is_none());
+        drop(rows);
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_server_not_responding() {
+        // Accepts the connection but never answers the handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let error = MicroBatTcpClient::connect(MicrobatClientOpts {
+            host: String::from("127.0.0.1"),
+            port: u32::from(listener.local_addr().unwrap().port()),
+            user: String::from("microbat"),
+            password: String::from("microbat"),
+            timeout: Some(Duration::from_millis(50)),
+        })
+        .err()
+        .unwrap();
+        assert_eq!(error.msg, "Server is not responding");
+    }
 }
@@ -0,0 +1,3 @@
+pub mod client;
+pub mod render_result;
+pub mod variables;
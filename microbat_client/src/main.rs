@@ -1,15 +1,18 @@
-mod client;
-mod render_result;
 mod repl;
 
-use crate::client::{MicroBatTcpClient, MicrobatClientOpts};
 use crate::repl::MicrobatREPL;
+use microbat_client::client::{MicroBatTcpClient, MicrobatClientOpts, DEFAULT_TIMEOUT};
+use std::env;
 
 /// Boot up microbat client
 fn main() {
     match MicroBatTcpClient::connect(MicrobatClientOpts {
         host: String::from("localhost"),
         port: 7878,
+        // Like PGUSER and PGPASSWORD of psql
+        user: env::var("MICROBAT_USER").unwrap_or_else(|_| String::from("microbat")),
+        password: env::var("MICROBAT_PASSWORD").unwrap_or_else(|_| String::from("microbat")),
+        timeout: Some(DEFAULT_TIMEOUT),
     }) {
         Ok(client) => {
             let mut repl = MicrobatREPL::new(client);
@@ -1,15 +1,19 @@
 use microbat_protocol::data::data_values::MData;
-use microbat_protocol::data::table_model::Column;
+use microbat_protocol::data::date::{format_date, format_timestamp};
+use microbat_protocol::data::decimal::format_decimal;
+use microbat_protocol::data::table_model::{Column, DataRow, Row, TableSchema};
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Renderable result received from the server
 pub enum QueryExecutionResult {
     DataTable(RenderableQueryResult),
     Mutation(RenderableMutationResult),
+    Command(RenderableCommandResult),
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 pub enum MutationKind {
     INSERT,
     UPDATE,
@@ -50,10 +54,35 @@ impl RenderableMutationResult {
     }
 }
 
+/// Completed statement that neither returns nor inserts rows, rendered as its tag
+pub struct RenderableCommandResult {
+    tag: String,
+    time: Duration,
+}
+
+impl Display for RenderableCommandResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n\n", self.tag)?;
+        write!(f, "Query took {} ms.", self.time.as_millis())
+    }
+}
+
+impl RenderableCommandResult {
+    pub fn new(tag: String, time: Duration) -> Self {
+        RenderableCommandResult { tag, time }
+    }
+}
+
+/// Columns of the result of EXPLAIN, which is rendered as a tree of plan nodes
+const PLAN_COLUMNS: [&str; 5] = ["node_id", "parent_id", "node", "detail", "estimated_rows"];
+
+/// Binary values longer than this are rendered cut
+const MAX_RENDERED_BYTES: usize = 16;
+
 /// Renderable query result that is a table
 pub struct RenderableQueryResult {
-    columns: Vec<Column>,
-    rows: Vec<Vec<MData>>,
+    schema: Arc<TableSchema>,
+    rows: Vec<Row>,
     time: Duration,
     paddings: Vec<usize>,
 }
@@ -61,6 +90,14 @@ pub struct RenderableQueryResult {
 /// RenderableQueryResult implements Display
 impl Display for RenderableQueryResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_plan() {
+            for (index, row) in self.rows.iter().enumerate() {
+                if row.value("parent_id") == Some(&MData::Null) {
+                    self.plan_node(f, index, "", "")?;
+                }
+            }
+            return write!(f, "\nQuery took {} ms.", self.time.as_millis());
+        }
         self.top_and_bottom_line(f)?;
         self.columns(f)?;
         self.top_and_bottom_line(f)?;
@@ -76,9 +113,19 @@ impl RenderableQueryResult {
     /// Creates new RenderableQueryResults and calculates paddings for each column based
     /// on the lenght of the data in guven column.
     pub fn new(columns: Vec<Column>, rows: Vec<Vec<MData>>, time: Duration) -> Self {
-        let paddings = RenderableQueryResult::paddings(&columns, &rows);
+        let schema = Arc::new(TableSchema { columns });
+        let rows = rows
+            .into_iter()
+            .map(|row| Row::new(schema.clone(), DataRow::new(row)))
+            .collect();
+        RenderableQueryResult::from_rows(schema, rows, time)
+    }
+
+    /// Creates new RenderableQueryResult of rows described by given schema
+    pub fn from_rows(schema: Arc<TableSchema>, rows: Vec<Row>, time: Duration) -> Self {
+        let paddings = RenderableQueryResult::paddings(&schema.columns, &rows);
         RenderableQueryResult {
-            columns,
+            schema,
             rows,
             time,
             paddings,
@@ -90,12 +137,17 @@ impl RenderableQueryResult {
         self.rows.len()
     }
 
-    fn paddings(columns: &Vec<Column>, rows: &Vec<Vec<MData>>) -> Vec<usize> {
+    /// Rows of this result, whose values can be read by column name
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    fn paddings(columns: &[Column], rows: &[Row]) -> Vec<usize> {
         let mut paddings: Vec<usize> = vec![];
         for (index, column) in columns.iter().enumerate() {
             let mut longest = column.name.len();
             for data in rows {
-                match &data[index] {
+                match &data.values()[index] {
                     MData::Varchar(d) => {
                         if d.len() > longest {
                             longest = d.len();
@@ -107,11 +159,52 @@ impl RenderableQueryResult {
                             longest = lenght;
                         }
                     }
+                    MData::BigInt(value) => {
+                        let lenght = value.to_string().len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
                     MData::Null => {
                         if 4 > longest {
                             longest = 4
                         }
                     }
+                    MData::Bool(_) => {
+                        if 1 > longest {
+                            longest = 1
+                        }
+                    }
+                    MData::Float(value) => {
+                        let lenght = value.to_string().len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
+                    MData::Decimal(value, scale) => {
+                        let lenght = format_decimal(*value, *scale).len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
+                    MData::Date(days) => {
+                        let lenght = format_date(*days).len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
+                    MData::Timestamp(micros) => {
+                        let lenght = format_timestamp(*micros).len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
+                    MData::Bytes(bytes) => {
+                        let lenght = format_bytes(bytes).len();
+                        if lenght > longest {
+                            longest = lenght;
+                        }
+                    }
                 }
             }
             paddings.push(longest + 1);
@@ -120,16 +213,65 @@ impl RenderableQueryResult {
         paddings
     }
 
+    /// Whether this is the result of EXPLAIN
+    fn is_plan(&self) -> bool {
+        self.schema
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .eq(PLAN_COLUMNS)
+    }
+
+    /// Renders plan node at given row and the nodes below it. Nodes refer to their parent
+    /// with parent_id and are listed after it, so only later rows are looked at.
+    fn plan_node(
+        &self,
+        f: &mut Formatter<'_>,
+        index: usize,
+        prefix: &str,
+        child_prefix: &str,
+    ) -> std::fmt::Result {
+        let row = &self.rows[index];
+        write!(f, "{}", prefix)?;
+        if let Ok(node) = row.get::<String>("node") {
+            write!(f, "{}", node)?;
+        }
+        if let Ok(Some(detail)) = row.get::<Option<String>>("detail") {
+            write!(f, ": {}", detail)?;
+        }
+        if let Ok(Some(rows)) = row.get::<Option<i32>>("estimated_rows") {
+            write!(f, " [rows={}]", rows)?;
+        }
+        writeln!(f)?;
+
+        let children: Vec<usize> = (index + 1..self.rows.len())
+            .filter(|child| self.rows[*child].value("parent_id") == row.value("node_id"))
+            .collect();
+        for (position, child) in children.iter().enumerate() {
+            let (branch, continuation) = match position == children.len() - 1 {
+                true => ("└─ ", "   "),
+                false => ("├─ ", "│  "),
+            };
+            self.plan_node(
+                f,
+                *child,
+                &format!("{}{}", child_prefix, branch),
+                &format!("{}{}", child_prefix, continuation),
+            )?;
+        }
+        Ok(())
+    }
+
     fn top_and_bottom_line(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "-")?;
-        for (index, _column) in self.columns.iter().enumerate() {
+        for (index, _column) in self.schema.columns.iter().enumerate() {
             write!(f, "-{}-", "-".repeat(self.paddings[index]))?;
         }
         writeln!(f)
     }
 
     fn columns(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for (index, column) in self.columns.iter().enumerate() {
+        for (index, column) in self.schema.columns.iter().enumerate() {
             write!(f, "|")?;
             write!(f, " {}", column.name)?;
             let padding = self.paddings[index] - column.name.len();
@@ -141,8 +283,8 @@ impl RenderableQueryResult {
     }
 
     fn data_rows(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for (_index, row) in self.rows.iter().enumerate() {
-            for (index, column) in row.iter().enumerate() {
+        for row in self.rows.iter() {
+            for (index, column) in row.values().iter().enumerate() {
                 match column {
                     MData::Null => {
                         write!(f, "| null")?;
@@ -165,6 +307,59 @@ impl RenderableQueryResult {
                             write!(f, "{}", " ".repeat(padding))?;
                         }
                     }
+                    MData::BigInt(data) => {
+                        write!(f, "| {}", data)?;
+                        let padding = self.paddings[index] - data.to_string().len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Bool(data) => {
+                        write!(f, "| {}", if *data { "t" } else { "f" })?;
+                        let padding = self.paddings[index] - 1;
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Float(data) => {
+                        write!(f, "| {}", data)?;
+                        let padding = self.paddings[index] - data.to_string().len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Decimal(value, scale) => {
+                        let decimal = format_decimal(*value, *scale);
+                        write!(f, "| {}", decimal)?;
+                        let padding = self.paddings[index] - decimal.len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Date(days) => {
+                        let date = format_date(*days);
+                        write!(f, "| {}", date)?;
+                        let padding = self.paddings[index] - date.len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Timestamp(micros) => {
+                        let timestamp = format_timestamp(*micros);
+                        write!(f, "| {}", timestamp)?;
+                        let padding = self.paddings[index] - timestamp.len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
+                    MData::Bytes(bytes) => {
+                        let hex = format_bytes(bytes);
+                        write!(f, "| {}", hex)?;
+                        let padding = self.paddings[index] - hex.len();
+                        if padding > 0 {
+                            write!(f, "{}", " ".repeat(padding))?;
+                        }
+                    }
                 }
             }
             writeln!(f, "|")?;
@@ -173,6 +368,19 @@ impl RenderableQueryResult {
     }
 }
 
+/// Binary value as hex like \xdeadbeef. Long values are cut so they don't stretch the table.
+fn format_bytes(bytes: &[u8]) -> String {
+    let hex: String = bytes
+        .iter()
+        .take(MAX_RENDERED_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    match bytes.len() > MAX_RENDERED_BYTES {
+        true => format!("\\x{}...", hex),
+        false => format!("\\x{}", hex),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,6 +411,21 @@ mod tests {
         assert_expected_rendering(result.to_string(), expected);
     }
 
+    #[test]
+    fn test_render_command_result() {
+        let result =
+            RenderableCommandResult::new(String::from("CREATE TABLE"), Duration::from_secs(1));
+
+        #[rustfmt::skip]
+            let expected = vec![
+            "CREATE TABLE",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
     #[test]
     fn test_render_update_mutation_result() {
         let result =
@@ -338,6 +561,260 @@ mod tests {
         assert_expected_rendering(result.to_string(), expected);
     }
 
+    #[test]
+    fn test_bool_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("b"),
+                data_type: MDataType::Bool,
+            }],
+            vec![vec![MData::Bool(true)], vec![MData::Bool(false)]],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+            let expected = vec![
+            "-----",
+            "| b |",
+            "-----",
+            "| t |",
+            "| f |",
+            "-----",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_float_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("f"),
+                data_type: MDataType::Float,
+            }],
+            vec![vec![MData::Float(1.5)], vec![MData::Float(-0.25)]],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+            let expected = vec![
+            "---------",
+            "| f     |",
+            "---------",
+            "| 1.5   |",
+            "| -0.25 |",
+            "---------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_decimal_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("price"),
+                data_type: MDataType::Decimal {
+                    precision: 10,
+                    scale: 2,
+                },
+            }],
+            vec![vec![MData::Decimal(123456, 2)], vec![MData::Decimal(-5, 2)]],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+        let expected = vec![
+            "-----------",
+            "| price   |",
+            "-----------",
+            "| 1234.56 |",
+            "| -0.05   |",
+            "-----------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_date_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("d"),
+                data_type: MDataType::Date,
+            }],
+            vec![vec![MData::Date(19723)], vec![MData::Date(-1)]],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+            let expected = vec![
+            "--------------",
+            "| d          |",
+            "--------------",
+            "| 2024-01-01 |",
+            "| 1969-12-31 |",
+            "--------------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_plan_rendering() {
+        let columns = PLAN_COLUMNS
+            .iter()
+            .map(|name| Column {
+                name: String::from(*name),
+                data_type: MDataType::Integer,
+            })
+            .collect();
+        let node = |id: i32, parent: Option<i32>, node: &str, detail: Option<&str>, rows| {
+            vec![
+                MData::Integer(id),
+                parent.map_or(MData::Null, MData::Integer),
+                MData::Varchar(String::from(node)),
+                detail.map_or(MData::Null, |detail| MData::Varchar(String::from(detail))),
+                rows,
+            ]
+        };
+        let result = RenderableQueryResult::new(
+            columns,
+            vec![
+                node(1, None, "Sort", Some("1 key"), MData::Null),
+                node(2, Some(1), "Project", Some("2 columns"), MData::Null),
+                node(3, Some(2), "Nested Loop", None, MData::Integer(12)),
+                node(4, Some(3), "Scan", Some("FOO"), MData::Integer(4)),
+                node(5, Some(3), "Nested Loop", None, MData::Integer(3)),
+                node(6, Some(5), "Scan", Some("BAR"), MData::Integer(3)),
+                node(7, Some(5), "Scan", Some("BAZ"), MData::Integer(1)),
+            ],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+        let expected = vec![
+            "Sort: 1 key",
+            "└─ Project: 2 columns",
+            "   └─ Nested Loop [rows=12]",
+            "      ├─ Scan: FOO [rows=4]",
+            "      └─ Nested Loop [rows=3]",
+            "         ├─ Scan: BAR [rows=3]",
+            "         └─ Scan: BAZ [rows=1]",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+        assert_eq!(result.row_count(), 7);
+    }
+
+    #[test]
+    fn test_bigint_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("n"),
+                data_type: MDataType::BigInt,
+            }],
+            vec![vec![MData::BigInt(i64::MAX)], vec![MData::BigInt(-1)]],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+        let expected = vec![
+            "-----------------------",
+            "| n                   |",
+            "-----------------------",
+            "| 9223372036854775807 |",
+            "| -1                  |",
+            "-----------------------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_bytes_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("b"),
+                data_type: MDataType::Bytes,
+            }],
+            vec![
+                vec![MData::Bytes(vec![0xde, 0xad, 0xbe, 0xef])],
+                vec![MData::Bytes((0..20).collect())],
+            ],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+        let expected = vec![
+            "-----------------------------------------",
+            "| b                                     |",
+            "-----------------------------------------",
+            "| \\xdeadbeef                            |",
+            "| \\x000102030405060708090a0b0c0d0e0f... |",
+            "-----------------------------------------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
+    #[test]
+    fn test_timestamp_value_rendering() {
+        let result = RenderableQueryResult::new(
+            vec![Column {
+                name: String::from("t"),
+                data_type: MDataType::Timestamp,
+            }],
+            vec![
+                vec![MData::Timestamp(1_704_112_215_000_000)],
+                vec![MData::Timestamp(-500_000)],
+            ],
+            Duration::from_secs(1),
+        );
+
+        #[rustfmt::skip]
+        let expected = vec![
+            "-------------------------",
+            "| t                     |",
+            "-------------------------",
+            "| 2024-01-01 12:30:15   |",
+            "| 1969-12-31 23:59:59.5 |",
+            "-------------------------",
+            "",
+            "(2 rows)",
+            "",
+            "Query took 1000 ms.",
+            ""
+        ];
+        assert_expected_rendering(result.to_string(), expected);
+    }
+
     #[test]
     fn test_render_result_set_with_long_name() {
         let result = RenderableQueryResult::new(
@@ -1,5 +1,7 @@
-use crate::client::MicroBatTcpClient;
-use crate::render_result::QueryExecutionResult;
+use microbat_client::client::{MicroBatClientError, MicroBatTcpClient};
+use microbat_client::render_result::QueryExecutionResult;
+use microbat_client::variables::Variables;
+use microbat_protocol::escape::quote_identifier;
 use rustyline::error::ReadlineError;
 use rustyline::history::DefaultHistory;
 use rustyline::{DefaultEditor, Editor};
@@ -7,6 +9,7 @@ use rustyline::{DefaultEditor, Editor};
 pub struct MicrobatREPL {
     client: MicroBatTcpClient,
     rl: Editor<(), DefaultHistory>,
+    variables: Variables,
 }
 
 impl MicrobatREPL {
@@ -14,13 +17,21 @@ impl MicrobatREPL {
         MicrobatREPL {
             client,
             rl: DefaultEditor::new().unwrap(),
+            variables: Variables::new(),
         }
     }
 
     pub fn run(&mut self) {
         loop {
             match self.rl.readline("microbat> ") {
-                Ok(line) => self.execute_query(line),
+                Ok(line) => {
+                    self.execute_query(line);
+                    // Session has ended, and handshaking again would only fail
+                    if self.client.is_shutting_down() {
+                        println!("Disconnected, server is shutting down");
+                        break;
+                    }
+                }
                 Err(ReadlineError::Interrupted) => {
                     println!("CTRL-C");
                     self.client.disconnect().unwrap();
@@ -51,18 +62,98 @@ impl MicrobatREPL {
     }
 
     fn execute_query(&mut self, line: String) {
-        match self.client.query(line) {
-            Ok(result) => match result {
-                QueryExecutionResult::DataTable(result) => {
-                    println!("{}", result);
-                }
-                QueryExecutionResult::Mutation(result) => {
-                    println!("{}", result);
+        if let Some((sql, prefix)) = gset(&line) {
+            self.gset(sql, prefix);
+            return;
+        }
+        let sql = match line.trim().strip_prefix('\\') {
+            Some(command) => match meta_command(command) {
+                Ok(sql) => sql,
+                Err(msg) => {
+                    println!("ERROR: {}", msg);
+                    return;
                 }
             },
+            None => self.variables.interpolate(&line),
+        };
+        match self.client.query_all(sql) {
+            Ok(results) => {
+                self.print_notices();
+                for result in results {
+                    print_result(result);
+                }
+            }
             Err(err) => {
                 println!("ERROR: {}", err.msg);
             }
         }
     }
+
+    /// Executes a query returning one row and stores its columns in variables
+    fn gset(&mut self, sql: &str, prefix: &str) {
+        let result = self.client.query(self.variables.interpolate(sql));
+        self.print_notices();
+        let result = match result {
+            Ok(QueryExecutionResult::DataTable(result)) => result,
+            Ok(QueryExecutionResult::Mutation(_) | QueryExecutionResult::Command(_)) => {
+                println!("ERROR: \\gset needs a query that returns rows");
+                return;
+            }
+            Err(err) => {
+                println!("ERROR: {}", err.msg);
+                return;
+            }
+        };
+        match result.rows() {
+            [row] => self.variables.set_row(row, prefix),
+            [] => println!("ERROR: no rows returned for \\gset"),
+            _ => println!("ERROR: more than one row returned for \\gset"),
+        }
+    }
+
+    /// Prints the warnings the server sent about the executed statements
+    fn print_notices(&mut self) {
+        for notice in self.client.take_notices() {
+            println!("{}: {}", notice.severity, notice);
+        }
+    }
+}
+
+/// Splits `query \gset [prefix]` into the query and the prefix of variable names
+fn gset(line: &str) -> Option<(&str, &str)> {
+    let (sql, rest) = line.rsplit_once("\\gset")?;
+    let prefix = rest.trim();
+    match prefix.contains(char::is_whitespace) || sql.trim().is_empty() {
+        true => None,
+        false => Some((sql, prefix)),
+    }
+}
+
+/// Translates a backslash command to sql, `\d` lists tables and `\d name` describes a table
+fn meta_command(command: &str) -> Result<String, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words[..] {
+        ["d"] => Ok(String::from("show tables;")),
+        ["d", table] => Ok(format!("describe {};", quote_identifier(table))),
+        _ => Err(format!("Unknown command \\{}", command)),
+    }
+}
+
+fn print_result(result: Result<QueryExecutionResult, MicroBatClientError>) {
+    match result {
+        Ok(result) => match result {
+            QueryExecutionResult::DataTable(result) => {
+                println!("{}", result);
+            }
+            QueryExecutionResult::Mutation(result) => {
+                println!("{}", result);
+            }
+            QueryExecutionResult::Command(result) => {
+                println!("{}", result);
+            }
+        },
+        Err(err) => {
+            println!("ERROR: {}", err.msg);
+        }
+    }
 }
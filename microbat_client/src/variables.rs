@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use microbat_protocol::data::data_values::MData;
+use microbat_protocol::data::date::{format_date, format_timestamp};
+use microbat_protocol::data::decimal::format_decimal;
+use microbat_protocol::data::table_model::Row;
+use microbat_protocol::escape::{quote_identifier, quote_literal};
+
+/// Client side variables, set from a query result with `\gset` and substituted into later
+/// statements.
+///
+/// `:name` is replaced with the value as is, `:'name'` with the value quoted as a string
+/// literal and `:"name"` with the value quoted as an identifier. Names are case insensitive
+/// like identifiers, and references to unknown variables are left untouched.
+///
+/// ```
+/// use microbat_client::variables::Variables;
+///
+/// let mut variables = Variables::new();
+/// variables.set("maxid", String::from("42"));
+/// variables.set("name", String::from("O'Brien"));
+/// assert_eq!(
+///     variables.interpolate("select * from t where id = :maxid or name = :'name';"),
+///     "select * from t where id = 42 or name = 'O''Brien';"
+/// );
+/// ```
+#[derive(Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Variables::default()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_lowercase(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    /// Stores every column of the row in a variable named by the column, prefixed with given
+    /// prefix. A NULL value unsets the variable, as in psql.
+    pub fn set_row(&mut self, row: &Row, prefix: &str) {
+        for (column, value) in row.columns().iter().zip(row.values()) {
+            let name = format!("{}{}", prefix, column.name);
+            match value_text(value) {
+                Some(text) => self.set(&name, text),
+                None => {
+                    self.values.remove(&name.to_lowercase());
+                }
+            }
+        }
+    }
+
+    /// Replaces variable references in given sql. References inside string literals and
+    /// quoted identifiers are not replaced.
+    pub fn interpolate(&self, sql: &str) -> String {
+        let chars: Vec<char> = sql.chars().collect();
+        let mut interpolated = String::with_capacity(sql.len());
+        let mut quote = None;
+        let mut index = 0;
+        while index < chars.len() {
+            let char = chars[index];
+            match (quote, char) {
+                (Some(open), char) if char == open => quote = None,
+                (None, '\'' | '"') => quote = Some(char),
+                (None, ':') if index == 0 || chars[index - 1] != ':' => {
+                    if let Some((value, end)) = self.reference(&chars, index + 1) {
+                        interpolated.push_str(&value);
+                        index = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            interpolated.push(char);
+            index += 1;
+        }
+        interpolated
+    }
+
+    /// Substituted value and end of the variable reference starting after a colon at `start`
+    fn reference(&self, chars: &[char], start: usize) -> Option<(String, usize)> {
+        match chars.get(start) {
+            Some(quote @ ('\'' | '"')) => {
+                let (name, end) = identifier(chars, start + 1);
+                if name.is_empty() || chars.get(end) != Some(quote) {
+                    return None;
+                }
+                let value = self.get(&name)?;
+                Some(match quote {
+                    '\'' => (quote_literal(value), end + 1),
+                    _ => (quote_identifier(value), end + 1),
+                })
+            }
+            Some(_) => {
+                let (name, end) = identifier(chars, start);
+                let value = self.get(&name)?;
+                Some((value.to_owned(), end))
+            }
+            None => None,
+        }
+    }
+}
+
+/// Variable name starting at given position with the position after it. Names start with a
+/// letter or an underscore.
+fn identifier(chars: &[char], start: usize) -> (String, usize) {
+    let name: String = match chars.get(start) {
+        Some(first) if first.is_alphabetic() || *first == '_' => chars[start..]
+            .iter()
+            .take_while(|char| char.is_alphanumeric() || **char == '_')
+            .collect(),
+        _ => String::new(),
+    };
+    // Counted in chars, as names may have letters of several bytes
+    let end = start + name.chars().count();
+    (name, end)
+}
+
+/// Value as text that can be used in sql, or None for NULL
+fn value_text(value: &MData) -> Option<String> {
+    match value {
+        MData::Null => None,
+        MData::Integer(value) => Some(value.to_string()),
+        MData::BigInt(value) => Some(value.to_string()),
+        MData::Varchar(value) => Some(value.clone()),
+        MData::Bool(value) => Some(value.to_string()),
+        MData::Float(value) => Some(value.to_string()),
+        MData::Decimal(value, scale) => Some(format_decimal(*value, *scale)),
+        MData::Date(days) => Some(format_date(*days)),
+        MData::Timestamp(micros) => Some(format_timestamp(*micros)),
+        MData::Bytes(bytes) => Some(
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod variable_tests {
+    use super::*;
+    use microbat_protocol::data::data_values::MDataType;
+    use microbat_protocol::data::table_model::{Column, DataRow, TableSchema};
+    use std::sync::Arc;
+
+    fn variables() -> Variables {
+        let mut variables = Variables::new();
+        variables.set("ID", String::from("7"));
+        variables.set("name", String::from("it's"));
+        variables
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut variables = variables();
+        assert_eq!(
+            variables.interpolate("select :id, :'name', :\"name\" from foo;"),
+            "select 7, 'it''s', \"it's\" from foo;"
+        );
+        assert_eq!(
+            variables.interpolate("select :id+1, ':id', \":id\", x::id, :nope, :'nope';"),
+            "select 7+1, ':id', \":id\", x::id, :nope, :'nope';"
+        );
+        assert_eq!(variables.interpolate("select :'id"), "select :'id");
+        variables.set("määrä", String::from("5"));
+        assert_eq!(
+            variables.interpolate("select :määrä, 1, :'määrä' from t;"),
+            "select 5, 1, '5' from t;"
+        );
+        assert_eq!(variables.interpolate(":"), ":");
+    }
+
+    #[test]
+    fn test_set_row() {
+        let schema = Arc::new(
+            TableSchema::new(vec![
+                Column::new(String::from("MAXID"), MDataType::Integer),
+                Column::new(String::from("NAME"), MDataType::Varchar),
+                Column::new(String::from("ok"), MDataType::Bool),
+                Column::new(String::from("hash"), MDataType::Bytes),
+            ])
+            .unwrap(),
+        );
+        let mut variables = variables();
+        variables.set("p_name", String::from("old"));
+        variables.set_row(
+            &Row::new(
+                schema,
+                DataRow::new(vec![
+                    MData::Integer(42),
+                    MData::Null,
+                    MData::Bool(true),
+                    MData::Bytes(vec![0xbe, 0xef]),
+                ]),
+            ),
+            "p_",
+        );
+        assert_eq!(variables.get("p_maxid"), Some("42"));
+        assert_eq!(variables.get("p_name"), None);
+        assert_eq!(variables.get("P_OK"), Some("true"));
+        assert_eq!(variables.get("p_hash"), Some("beef"));
+        assert_eq!(variables.get("name"), Some("it's"));
+    }
+}
@@ -0,0 +1,324 @@
+//! Random generators for protocol types and round-trip assertions built on them.
+//!
+//! Available with the `arbitrary` feature. Types implement quickcheck's `Arbitrary`, so
+//! any serialization can be property tested with `quickcheck::quickcheck`:
+//!
+//! ```
+//! use microbat_protocol::arbitrary::assert_server_round_trip;
+//! use microbat_protocol::messages::server_messages::MicrobatServerMessage;
+//!
+//! quickcheck::quickcheck(assert_server_round_trip as fn(MicrobatServerMessage) -> bool);
+//! ```
+//!
+//! When adding a new data type or message, add it to the generators here and it gets
+//! round-trip coverage from the tests of this module.
+use std::fmt::Debug;
+
+use quickcheck::{Arbitrary, Gen};
+
+use crate::data::data_values::{deserialize_data_column, MData, MDataType};
+use crate::data::table_model::{Column, DataRow, TableSchema};
+use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
+use crate::messages::server_messages::{
+    deserialize_server_message, BackendKey, ErrorResponse, MicrobatServerMessage, Severity,
+};
+use crate::messages::{parse_frame, parse_frame_with, MicrobatMessage, ProtocolFeatures};
+use crate::MicrobatProtocolError;
+
+impl Arbitrary for MDataType {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let scale = u8::arbitrary(g) % 39;
+        g.choose(&[
+            MDataType::Null,
+            MDataType::Integer,
+            MDataType::BigInt,
+            MDataType::Varchar,
+            MDataType::Bool,
+            MDataType::Float,
+            MDataType::Decimal {
+                precision: 38,
+                scale,
+            },
+            MDataType::Date,
+            MDataType::Timestamp,
+            MDataType::Bytes,
+        ])
+        .unwrap()
+        .clone()
+    }
+}
+
+impl Arbitrary for MData {
+    fn arbitrary(g: &mut Gen) -> Self {
+        match MDataType::arbitrary(g) {
+            MDataType::Null => MData::Null,
+            MDataType::Integer => MData::Integer(i32::arbitrary(g)),
+            MDataType::BigInt => MData::BigInt(i64::arbitrary(g)),
+            MDataType::Varchar => MData::Varchar(String::arbitrary(g)),
+            MDataType::Bool => MData::Bool(bool::arbitrary(g)),
+            // NaN is not equal to itself, so it would never survive a round trip assertion
+            MDataType::Float => MData::Float(match f64::arbitrary(g) {
+                value if value.is_nan() => 0.0,
+                value => value,
+            }),
+            MDataType::Decimal { scale, .. } => MData::Decimal(i128::arbitrary(g), scale),
+            MDataType::Date => MData::Date(i32::arbitrary(g)),
+            MDataType::Timestamp => MData::Timestamp(i64::arbitrary(g)),
+            MDataType::Bytes => MData::Bytes(Vec::arbitrary(g)),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match self {
+            MData::Null => quickcheck::empty_shrinker(),
+            MData::Integer(value) => Box::new(value.shrink().map(MData::Integer)),
+            MData::BigInt(value) => Box::new(value.shrink().map(MData::BigInt)),
+            MData::Varchar(value) => Box::new(value.shrink().map(MData::Varchar)),
+            MData::Bool(value) => Box::new(value.shrink().map(MData::Bool)),
+            MData::Float(value) => Box::new(
+                value
+                    .shrink()
+                    .filter(|value| !value.is_nan())
+                    .map(MData::Float),
+            ),
+            MData::Decimal(value, scale) => {
+                let scale = *scale;
+                Box::new(
+                    value
+                        .shrink()
+                        .map(move |value| MData::Decimal(value, scale)),
+                )
+            }
+            MData::Date(value) => Box::new(value.shrink().map(MData::Date)),
+            MData::Timestamp(value) => Box::new(value.shrink().map(MData::Timestamp)),
+            MData::Bytes(value) => Box::new(value.shrink().map(MData::Bytes)),
+        }
+    }
+}
+
+impl Arbitrary for Column {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Column::new(String::arbitrary(g), MDataType::arbitrary(g))
+    }
+}
+
+impl Arbitrary for TableSchema {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let mut columns = Vec::<Column>::arbitrary(g);
+        if columns.is_empty() {
+            columns.push(Column::arbitrary(g));
+        }
+        TableSchema::new(columns).unwrap()
+    }
+}
+
+impl Arbitrary for DataRow {
+    fn arbitrary(g: &mut Gen) -> Self {
+        DataRow::new(Vec::<MData>::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.columns.shrink().map(DataRow::new))
+    }
+}
+
+impl Arbitrary for ErrorResponse {
+    fn arbitrary(g: &mut Gen) -> Self {
+        ErrorResponse {
+            code: String::arbitrary(g),
+            severity: *g
+                .choose(&[
+                    Severity::Error,
+                    Severity::Fatal,
+                    Severity::Warning,
+                    Severity::Notice,
+                ])
+                .unwrap(),
+            message: String::arbitrary(g),
+            position: Option::<(u32, u32)>::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for ProtocolFeatures {
+    fn arbitrary(g: &mut Gen) -> Self {
+        ProtocolFeatures {
+            skip_unknown_messages: bool::arbitrary(g),
+            schema_notifications: bool::arbitrary(g),
+            compression: bool::arbitrary(g),
+            checksums: bool::arbitrary(g),
+        }
+    }
+}
+
+impl Arbitrary for MicrobatClientMessage {
+    fn arbitrary(g: &mut Gen) -> Self {
+        match u8::arbitrary(g) % 11 {
+            0 => {
+                MicrobatClientMessage::Handshake(u16::arbitrary(g), ProtocolFeatures::arbitrary(g))
+            }
+            1 => MicrobatClientMessage::Query(String::arbitrary(g)),
+            2 => MicrobatClientMessage::ParameterizedQuery(
+                String::arbitrary(g),
+                Vec::<MData>::arbitrary(g),
+            ),
+            3 => MicrobatClientMessage::Authenticate {
+                user: String::arbitrary(g),
+                password: String::arbitrary(g),
+            },
+            4 => MicrobatClientMessage::Ping,
+            5 => MicrobatClientMessage::Parse(String::arbitrary(g), String::arbitrary(g)),
+            6 => MicrobatClientMessage::Bind(String::arbitrary(g), Vec::<MData>::arbitrary(g)),
+            7 => MicrobatClientMessage::Execute(String::arbitrary(g)),
+            8 => MicrobatClientMessage::CopyData(Vec::arbitrary(g)),
+            9 => MicrobatClientMessage::CopyDone,
+            _ => MicrobatClientMessage::Disconnect,
+        }
+    }
+}
+
+impl Arbitrary for MicrobatServerMessage {
+    fn arbitrary(g: &mut Gen) -> Self {
+        match u8::arbitrary(g) % 21 {
+            0 => {
+                MicrobatServerMessage::Handshake(u16::arbitrary(g), ProtocolFeatures::arbitrary(g))
+            }
+            1 => MicrobatServerMessage::Error(ErrorResponse::arbitrary(g)),
+            2 => MicrobatServerMessage::DataDescription(TableSchema::arbitrary(g)),
+            3 => MicrobatServerMessage::DataRow(DataRow::arbitrary(g)),
+            4 => MicrobatServerMessage::InsertResult(u32::arbitrary(g)),
+            5 => MicrobatServerMessage::UnsupportedVersion(u16::arbitrary(g), u16::arbitrary(g)),
+            6 => MicrobatServerMessage::AuthOk,
+            7 => MicrobatServerMessage::AuthFailed(String::arbitrary(g)),
+            8 => MicrobatServerMessage::Pong,
+            9 => MicrobatServerMessage::SchemaChanged(String::arbitrary(g)),
+            10 => MicrobatServerMessage::DataRowBatch(Vec::arbitrary(g)),
+            11 => MicrobatServerMessage::DataRowChunk {
+                bytes: Vec::arbitrary(g),
+                last: bool::arbitrary(g),
+            },
+            12 => MicrobatServerMessage::ParseComplete,
+            13 => MicrobatServerMessage::BindComplete,
+            14 => MicrobatServerMessage::Notice(ErrorResponse::arbitrary(g)),
+            15 => MicrobatServerMessage::CopyIn(TableSchema::arbitrary(g)),
+            16 => MicrobatServerMessage::BackendKeyData(BackendKey {
+                session_id: u32::arbitrary(g),
+                secret: u32::arbitrary(g),
+            }),
+            17 => {
+                MicrobatServerMessage::ParameterStatus(String::arbitrary(g), String::arbitrary(g))
+            }
+            18 => MicrobatServerMessage::ShuttingDown,
+            19 => MicrobatServerMessage::CommandComplete(String::arbitrary(g)),
+            _ => MicrobatServerMessage::Ready,
+        }
+    }
+}
+
+/// Serializes given message, deserializes it back and panics if the result differs from
+/// the original or the frame was not consumed exactly. Returns true so it can be used as
+/// a quickcheck property.
+pub fn assert_round_trip<T: MicrobatMessage + PartialEq + Debug>(
+    message: &T,
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+) -> bool {
+    let bytes = message.as_bytes();
+    match parse_frame(&bytes, deserializer) {
+        Ok(Some((deserialized, length))) => {
+            assert_eq!(&deserialized, message, "Round trip changed the message");
+            assert_eq!(length, bytes.len(), "Round trip did not consume the frame");
+        }
+        Ok(None) => panic!("Serialized {:?} is not a complete frame", message),
+        Err(err) => panic!("Can't deserialize {:?}: {}", message, err.msg),
+    }
+    true
+}
+
+/// Round trip property for client messages
+pub fn assert_client_round_trip(message: MicrobatClientMessage) -> bool {
+    assert_round_trip(&message, deserialize_client_message)
+}
+
+/// Round trip property for server messages
+pub fn assert_server_round_trip(message: MicrobatServerMessage) -> bool {
+    assert_round_trip(&message, deserialize_server_message)
+}
+
+/// Parses given bytes as client and server messages, with and without checksums, ignoring
+/// the results. Malformed bytes must fail with an error, so this panics only on a bug in
+/// the deserializers. Returns true so it can be used as a quickcheck property.
+pub fn assert_parsing_does_not_panic(bytes: Vec<u8>) -> bool {
+    let checksummed = ProtocolFeatures {
+        checksums: true,
+        ..ProtocolFeatures::default()
+    };
+    for features in [ProtocolFeatures::default(), checksummed] {
+        let _ = parse_frame_with(&bytes, deserialize_client_message, features);
+        let _ = parse_frame_with(&bytes, deserialize_server_message, features);
+    }
+    if let Some((message_type, payload)) = bytes.split_first() {
+        let _ = deserialize_client_message(*message_type, payload.len(), payload);
+        let _ = deserialize_server_message(*message_type, payload.len(), payload);
+        let _ = deserialize_data_column(*message_type, payload);
+    }
+    true
+}
+
+/// Round trip property for a single data value
+pub fn assert_data_round_trip(data: MData) -> bool {
+    match deserialize_data_column(data.type_byte(), &data.bytes()) {
+        Ok(deserialized) => assert_eq!(deserialized, data, "Round trip changed the value"),
+        Err(err) => panic!("Can't deserialize {:?}: {}", data, err.msg),
+    }
+    true
+}
+
+#[cfg(test)]
+mod arbitrary_tests {
+    use quickcheck::quickcheck;
+
+    use super::*;
+
+    #[test]
+    fn test_data_round_trip() {
+        quickcheck(assert_data_round_trip as fn(MData) -> bool);
+    }
+
+    #[test]
+    fn test_client_message_round_trip() {
+        quickcheck(assert_client_round_trip as fn(MicrobatClientMessage) -> bool);
+    }
+
+    #[test]
+    fn test_server_message_round_trip() {
+        quickcheck(assert_server_round_trip as fn(MicrobatServerMessage) -> bool);
+    }
+
+    #[test]
+    fn test_arbitrary_bytes_do_not_panic() {
+        quickcheck(assert_parsing_does_not_panic as fn(Vec<u8>) -> bool);
+    }
+
+    #[test]
+    fn test_corrupted_messages_do_not_panic() {
+        fn property(message: MicrobatServerMessage, position: usize, value: u8) -> bool {
+            let mut bytes = message.as_bytes();
+            let position = position % bytes.len();
+            let mut corrupted = bytes.clone();
+            corrupted[position] = value;
+            assert_parsing_does_not_panic(corrupted);
+            // Truncated, so the length no longer matches the frame
+            bytes.truncate(position);
+            assert_parsing_does_not_panic(bytes)
+        }
+        quickcheck(property as fn(MicrobatServerMessage, usize, u8) -> bool);
+    }
+
+    #[test]
+    fn test_data_rows_round_trip() {
+        fn property(row: DataRow) -> bool {
+            assert_server_round_trip(MicrobatServerMessage::DataRow(row))
+        }
+        quickcheck(property as fn(DataRow) -> bool);
+    }
+}
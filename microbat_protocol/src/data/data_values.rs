@@ -1,10 +1,17 @@
 use std::fmt::{Display, Formatter};
 
-use crate::static_values::{TYPE_BYTE_INTEGER, TYPE_BYTE_NULL, TYPE_BYTE_VARCHAR};
+use crate::data::decimal::{self, rescale, MAX_PRECISION};
+use crate::sqlstate;
+use crate::static_values::{
+    TYPE_BYTE_BIGINT, TYPE_BYTE_BOOL, TYPE_BYTE_BYTES, TYPE_BYTE_DATE, TYPE_BYTE_DECIMAL,
+    TYPE_BYTE_FLOAT, TYPE_BYTE_INTEGER, TYPE_BYTE_NULL, TYPE_BYTE_TIMESTAMP, TYPE_BYTE_VARCHAR,
+};
 use crate::MicrobatProtocolError;
 
 #[derive(Debug)]
 pub struct DataError {
+    /// SQLSTATE of the error, see `crate::sqlstate`
+    pub code: &'static str,
     pub msg: String,
 }
 
@@ -21,7 +28,55 @@ impl Display for DataError {
 pub enum MDataType {
     Null,
     Integer,
+    BigInt,
     Varchar,
+    Bool,
+    Float,
+    /// Fixed-point decimal with at most `precision` digits, `scale` of them after the point
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+    Date,
+    Timestamp,
+    Bytes,
+}
+
+impl Display for MDataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MDataType::Null => write!(f, "NULL"),
+            MDataType::Integer => write!(f, "INTEGER"),
+            MDataType::BigInt => write!(f, "BIGINT"),
+            MDataType::Varchar => write!(f, "VARCHAR"),
+            MDataType::Bool => write!(f, "BOOLEAN"),
+            MDataType::Float => write!(f, "FLOAT"),
+            MDataType::Decimal { precision, scale } => {
+                write!(f, "DECIMAL({},{})", precision, scale)
+            }
+            MDataType::Date => write!(f, "DATE"),
+            MDataType::Timestamp => write!(f, "TIMESTAMP"),
+            MDataType::Bytes => write!(f, "BYTEA"),
+        }
+    }
+}
+
+impl MDataType {
+    /// Marker byte of values of this type, the same as `MData::type_byte` gives
+    pub fn type_byte(&self) -> u8 {
+        match self {
+            MDataType::Null => TYPE_BYTE_NULL,
+            MDataType::Integer => TYPE_BYTE_INTEGER,
+            MDataType::BigInt => TYPE_BYTE_BIGINT,
+            MDataType::Varchar => TYPE_BYTE_VARCHAR,
+            MDataType::Bool => TYPE_BYTE_BOOL,
+            MDataType::Float => TYPE_BYTE_FLOAT,
+            MDataType::Decimal { .. } => TYPE_BYTE_DECIMAL,
+            MDataType::Date => TYPE_BYTE_DATE,
+            MDataType::Timestamp => TYPE_BYTE_TIMESTAMP,
+            MDataType::Bytes => TYPE_BYTE_BYTES,
+        }
+    }
 }
 
 /// The serializable data types of microbat. This is value in microbat, like an integer.
@@ -32,7 +87,18 @@ pub enum MDataType {
 pub enum MData {
     Null,
     Integer(i32),
+    BigInt(i64),
     Varchar(String),
+    Bool(bool),
+    Float(f64),
+    /// Value scaled by ten to the power of scale, and the scale
+    Decimal(i128, u8),
+    /// Days since 1970-01-01
+    Date(i32),
+    /// Microseconds since 1970-01-01 00:00:00 UTC
+    Timestamp(i64),
+    /// Raw binary data
+    Bytes(Vec<u8>),
 }
 
 impl MData {
@@ -41,6 +107,29 @@ impl MData {
             MData::Null => vec![],
             MData::Varchar(value) => value.as_bytes().to_vec(),
             MData::Integer(value) => value.to_be_bytes().to_vec(),
+            MData::BigInt(value) => value.to_be_bytes().to_vec(),
+            MData::Bool(value) => vec![u8::from(*value)],
+            MData::Float(value) => value.to_be_bytes().to_vec(),
+            MData::Decimal(value, scale) => [vec![*scale], value.to_be_bytes().to_vec()].concat(),
+            MData::Date(value) => value.to_be_bytes().to_vec(),
+            MData::Timestamp(value) => value.to_be_bytes().to_vec(),
+            MData::Bytes(value) => value.clone(),
+        }
+    }
+
+    /// Length of `bytes()` without building them
+    pub fn byte_len(&self) -> usize {
+        match self {
+            MData::Null => 0,
+            MData::Varchar(value) => value.len(),
+            MData::Integer(_) => 4,
+            MData::BigInt(_) => 8,
+            MData::Bool(_) => 1,
+            MData::Float(_) => 8,
+            MData::Decimal(..) => 17,
+            MData::Date(_) => 4,
+            MData::Timestamp(_) => 8,
+            MData::Bytes(value) => value.len(),
         }
     }
 
@@ -49,22 +138,91 @@ impl MData {
             MData::Null => TYPE_BYTE_NULL,
             MData::Varchar(_) => TYPE_BYTE_VARCHAR,
             MData::Integer(_) => TYPE_BYTE_INTEGER,
+            MData::BigInt(_) => TYPE_BYTE_BIGINT,
+            MData::Bool(_) => TYPE_BYTE_BOOL,
+            MData::Float(_) => TYPE_BYTE_FLOAT,
+            MData::Decimal(..) => TYPE_BYTE_DECIMAL,
+            MData::Date(_) => TYPE_BYTE_DATE,
+            MData::Timestamp(_) => TYPE_BYTE_TIMESTAMP,
+            MData::Bytes(_) => TYPE_BYTE_BYTES,
         }
     }
     pub fn matcher(&self) -> MDataType {
         match self {
             MData::Null => MDataType::Null,
             MData::Integer(_) => MDataType::Integer,
+            MData::BigInt(_) => MDataType::BigInt,
             MData::Varchar(_) => MDataType::Varchar,
+            MData::Bool(_) => MDataType::Bool,
+            MData::Float(_) => MDataType::Float,
+            MData::Decimal(_, scale) => MDataType::Decimal {
+                precision: MAX_PRECISION,
+                scale: *scale,
+            },
+            MData::Date(_) => MDataType::Date,
+            MData::Timestamp(_) => MDataType::Timestamp,
+            MData::Bytes(_) => MDataType::Bytes,
+        }
+    }
+
+    /// Value as a float if it is a number. Integers are converted, so arithmetic
+    /// and comparisons can mix integers and floats.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            MData::Integer(value) => Some(f64::from(*value)),
+            MData::BigInt(value) => Some(*value as f64),
+            MData::Float(value) => Some(*value),
+            MData::Decimal(value, scale) => Some(decimal::to_float(*value, *scale)),
+            _ => None,
+        }
+    }
+
+    /// Value as a scaled decimal and its scale if it is a decimal or an integer
+    pub fn as_decimal(&self) -> Option<(i128, u8)> {
+        match self {
+            MData::Integer(value) => Some((i128::from(*value), 0)),
+            MData::BigInt(value) => Some((i128::from(*value), 0)),
+            MData::Decimal(value, scale) => Some((*value, *scale)),
+            _ => None,
+        }
+    }
+
+    /// Value as a big integer if it is an integer of either size. Integers are promoted
+    /// to big integers when mixed with them.
+    pub fn as_bigint(&self) -> Option<i64> {
+        match self {
+            MData::Integer(value) => Some(i64::from(*value)),
+            MData::BigInt(value) => Some(*value),
+            _ => None,
         }
     }
 
     pub fn apply_plus(&self, right: MData) -> Result<MData, DataError> {
         match (self, &right) {
-            (MData::Integer(l_value), MData::Integer(r_value)) => {
-                Ok(MData::Integer(l_value + r_value))
+            (MData::Null, _) | (_, MData::Null) => Ok(MData::Null),
+            (MData::Integer(l_value), MData::Integer(r_value)) => l_value
+                .checked_add(*r_value)
+                .map(MData::Integer)
+                .ok_or_else(out_of_range),
+            (MData::Integer(_) | MData::BigInt(_), MData::Integer(_) | MData::BigInt(_)) => self
+                .as_bigint()
+                .unwrap()
+                .checked_add(right.as_bigint().unwrap())
+                .map(MData::BigInt)
+                .ok_or_else(out_of_range),
+            (MData::Decimal(..), MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..))
+            | (MData::Integer(_) | MData::BigInt(_), MData::Decimal(..)) => {
+                exact(self, &right, i128::checked_add)
+            }
+            (
+                MData::Float(_),
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+            )
+            | (MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..), MData::Float(_)) => {
+                float(self.as_float().unwrap() + right.as_float().unwrap())
             }
             _ => Err(DataError {
+                code: sqlstate::DATATYPE_MISMATCH,
                 msg: format!("Can't apply {:?} + {:?}", self, right),
             }),
         }
@@ -72,16 +230,225 @@ impl MData {
 
     pub fn apply_minus(&self, right: MData) -> Result<MData, DataError> {
         match (self, &right) {
-            (MData::Integer(l_value), MData::Integer(r_value)) => {
-                Ok(MData::Integer(l_value - r_value))
+            (MData::Null, _) | (_, MData::Null) => Ok(MData::Null),
+            (MData::Integer(l_value), MData::Integer(r_value)) => l_value
+                .checked_sub(*r_value)
+                .map(MData::Integer)
+                .ok_or_else(out_of_range),
+            (MData::Integer(_) | MData::BigInt(_), MData::Integer(_) | MData::BigInt(_)) => self
+                .as_bigint()
+                .unwrap()
+                .checked_sub(right.as_bigint().unwrap())
+                .map(MData::BigInt)
+                .ok_or_else(out_of_range),
+            (MData::Decimal(..), MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..))
+            | (MData::Integer(_) | MData::BigInt(_), MData::Decimal(..)) => {
+                exact(self, &right, i128::checked_sub)
+            }
+            (
+                MData::Float(_),
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+            )
+            | (MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..), MData::Float(_)) => {
+                float(self.as_float().unwrap() - right.as_float().unwrap())
             }
             _ => Err(DataError {
-                msg: format!("Can't apply {:?} + {:?}", self, right),
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: format!("Can't apply {:?} - {:?}", self, right),
             }),
         }
     }
 }
 
+fn out_of_range() -> DataError {
+    DataError {
+        code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+        msg: String::from("integer out of range"),
+    }
+}
+
+/// Exact arithmetic of decimals and integers, in the larger of their scales
+fn exact(
+    left: &MData,
+    right: &MData,
+    operation: fn(i128, i128) -> Option<i128>,
+) -> Result<MData, DataError> {
+    let (left, left_scale) = left.as_decimal().unwrap();
+    let (right, right_scale) = right.as_decimal().unwrap();
+    let scale = left_scale.max(right_scale);
+    rescale(left, left_scale, scale)
+        .zip(rescale(right, right_scale, scale))
+        .and_then(|(left, right)| operation(left, right))
+        .filter(|value| decimal::digits(*value) <= MAX_PRECISION)
+        .map(|value| MData::Decimal(value, scale))
+        .ok_or_else(|| DataError {
+            code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+            msg: String::from("numeric out of range"),
+        })
+}
+
+/// Float result of arithmetic, which overflows to infinity instead of wrapping
+fn float(value: f64) -> Result<MData, DataError> {
+    match value.is_finite() {
+        true => Ok(MData::Float(value)),
+        false => Err(DataError {
+            code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+            msg: String::from("float out of range"),
+        }),
+    }
+}
+
+/// Conversion of rust values into microbat values, used for example for binding query parameters.
+///
+/// `None` of an `Option` converts to `MData::Null`.
+pub trait ToMData {
+    fn to_mdata(&self) -> MData;
+}
+
+impl ToMData for MData {
+    fn to_mdata(&self) -> MData {
+        self.clone()
+    }
+}
+
+impl ToMData for i32 {
+    fn to_mdata(&self) -> MData {
+        MData::Integer(*self)
+    }
+}
+
+impl ToMData for i64 {
+    fn to_mdata(&self) -> MData {
+        MData::BigInt(*self)
+    }
+}
+
+impl ToMData for str {
+    fn to_mdata(&self) -> MData {
+        MData::Varchar(self.to_owned())
+    }
+}
+
+impl ToMData for f64 {
+    fn to_mdata(&self) -> MData {
+        MData::Float(*self)
+    }
+}
+
+impl ToMData for bool {
+    fn to_mdata(&self) -> MData {
+        MData::Bool(*self)
+    }
+}
+
+impl ToMData for String {
+    fn to_mdata(&self) -> MData {
+        MData::Varchar(self.clone())
+    }
+}
+
+impl<T: ToMData> ToMData for Option<T> {
+    fn to_mdata(&self) -> MData {
+        match self {
+            Some(value) => value.to_mdata(),
+            None => MData::Null,
+        }
+    }
+}
+
+impl<T: ToMData + ?Sized> ToMData for &T {
+    fn to_mdata(&self) -> MData {
+        (**self).to_mdata()
+    }
+}
+
+/// Conversion of microbat values into rust values, the reverse of `ToMData`. Used for reading
+/// columns of a `Row` by name.
+///
+/// `Option` converts `MData::Null` to `None`, other types fail on null.
+pub trait FromMData: Sized {
+    fn from_mdata(value: &MData) -> Result<Self, DataError>;
+}
+
+/// Error for a value that doesn't convert to the requested type
+fn not_convertible<T>(value: &MData) -> DataError {
+    DataError {
+        code: sqlstate::DATATYPE_MISMATCH,
+        msg: format!(
+            "Can't convert {} to {}",
+            value.matcher(),
+            std::any::type_name::<T>()
+        ),
+    }
+}
+
+impl FromMData for MData {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        Ok(value.clone())
+    }
+}
+
+impl FromMData for i32 {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        match value {
+            MData::Integer(value) => Ok(*value),
+            other => Err(not_convertible::<Self>(other)),
+        }
+    }
+}
+
+impl FromMData for i64 {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        value
+            .as_bigint()
+            .ok_or_else(|| not_convertible::<Self>(value))
+    }
+}
+
+impl FromMData for f64 {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        value
+            .as_float()
+            .ok_or_else(|| not_convertible::<Self>(value))
+    }
+}
+
+impl FromMData for bool {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        match value {
+            MData::Bool(value) => Ok(*value),
+            other => Err(not_convertible::<Self>(other)),
+        }
+    }
+}
+
+impl FromMData for String {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        match value {
+            MData::Varchar(value) => Ok(value.clone()),
+            other => Err(not_convertible::<Self>(other)),
+        }
+    }
+}
+
+impl FromMData for Vec<u8> {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        match value {
+            MData::Bytes(value) => Ok(value.clone()),
+            other => Err(not_convertible::<Self>(other)),
+        }
+    }
+}
+
+impl<T: FromMData> FromMData for Option<T> {
+    fn from_mdata(value: &MData) -> Result<Self, DataError> {
+        match value {
+            MData::Null => Ok(None),
+            value => T::from_mdata(value).map(Some),
+        }
+    }
+}
+
 pub fn deserialize_data_column(
     marker_byte: u8,
     bytes: &[u8],
@@ -89,16 +456,81 @@ pub fn deserialize_data_column(
     match marker_byte {
         TYPE_BYTE_NULL => Ok(MData::Null),
         TYPE_BYTE_INTEGER => {
-            let value = i32::from_be_bytes(bytes.try_into().unwrap());
+            let value = i32::from_be_bytes(bytes.try_into().map_err(|_| {
+                MicrobatProtocolError::new(format!(
+                    "Integer column must be 4 bytes but was {}",
+                    bytes.len()
+                ))
+            })?);
             Ok(MData::Integer(value))
         }
+        TYPE_BYTE_BIGINT => {
+            let value = i64::from_be_bytes(bytes.try_into().map_err(|_| {
+                MicrobatProtocolError::new(format!(
+                    "BigInt column must be 8 bytes but was {}",
+                    bytes.len()
+                ))
+            })?);
+            Ok(MData::BigInt(value))
+        }
         TYPE_BYTE_VARCHAR => {
             let value = String::from_utf8(bytes.to_vec())?;
             Ok(MData::Varchar(value))
         }
-        unknown => Err(MicrobatProtocolError {
-            msg: format!("Unknown data column marker {}", char::from(unknown)),
-        }),
+        TYPE_BYTE_FLOAT => {
+            let value = f64::from_be_bytes(bytes.try_into().map_err(|_| {
+                MicrobatProtocolError::new(format!(
+                    "Float column must be 8 bytes but was {}",
+                    bytes.len()
+                ))
+            })?);
+            Ok(MData::Float(value))
+        }
+        TYPE_BYTE_DECIMAL => {
+            let invalid_length = || {
+                MicrobatProtocolError::new(format!(
+                    "Decimal column must be 17 bytes but was {}",
+                    bytes.len()
+                ))
+            };
+            let (scale, value) = bytes.split_first().ok_or_else(invalid_length)?;
+            let value = i128::from_be_bytes(value.try_into().map_err(|_| invalid_length())?);
+            if *scale > MAX_PRECISION {
+                return Err(MicrobatProtocolError::new(format!(
+                    "Decimal scale {} exceeds the maximum of {}",
+                    scale, MAX_PRECISION
+                )));
+            }
+            Ok(MData::Decimal(value, *scale))
+        }
+        TYPE_BYTE_DATE => {
+            let value = i32::from_be_bytes(bytes.try_into().map_err(|_| {
+                MicrobatProtocolError::new(format!(
+                    "Date column must be 4 bytes but was {}",
+                    bytes.len()
+                ))
+            })?);
+            Ok(MData::Date(value))
+        }
+        TYPE_BYTE_TIMESTAMP => {
+            let value = i64::from_be_bytes(bytes.try_into().map_err(|_| {
+                MicrobatProtocolError::new(format!(
+                    "Timestamp column must be 8 bytes but was {}",
+                    bytes.len()
+                ))
+            })?);
+            Ok(MData::Timestamp(value))
+        }
+        TYPE_BYTE_BYTES => Ok(MData::Bytes(bytes.to_vec())),
+        TYPE_BYTE_BOOL => match bytes {
+            [0] => Ok(MData::Bool(false)),
+            [1] => Ok(MData::Bool(true)),
+            _ => Err(MicrobatProtocolError::new("Invalid bool column")),
+        },
+        unknown => Err(MicrobatProtocolError::new(format!(
+            "Unknown data column marker {}",
+            char::from(unknown)
+        ))),
     }
 }
 
@@ -131,6 +563,12 @@ mod serialization_tests {
         assert_eq!(m_varchar!("").type_byte(), TYPE_BYTE_VARCHAR);
         assert_eq!(m_varchar!("foo").type_byte(), TYPE_BYTE_VARCHAR);
         assert_eq!(m_int!(1).type_byte(), TYPE_BYTE_INTEGER);
+        assert_eq!(MData::BigInt(1).type_byte(), TYPE_BYTE_BIGINT);
+        assert_eq!(MData::Bool(true).type_byte(), TYPE_BYTE_BOOL);
+        assert_eq!(MData::Float(1.5).type_byte(), TYPE_BYTE_FLOAT);
+        assert_eq!(MData::Date(0).type_byte(), TYPE_BYTE_DATE);
+        assert_eq!(MData::Timestamp(0).type_byte(), TYPE_BYTE_TIMESTAMP);
+        assert_eq!(MData::Bytes(vec![]).type_byte(), TYPE_BYTE_BYTES);
     }
 
     #[test]
@@ -140,6 +578,98 @@ mod serialization_tests {
         assert_eq!(m_varchar!("foo").bytes().len(), 3);
         assert_eq!(m_int!(1).bytes().len(), 4);
         assert_eq!(m_int!(5).bytes().len(), 4);
+        assert_eq!(MData::Bool(false).bytes().len(), 1);
+        assert_eq!(MData::Float(1.5).bytes().len(), 8);
+        assert_eq!(MData::BigInt(1).bytes().len(), 8);
+    }
+
+    #[test]
+    fn test_bigint_arithmetic() {
+        assert_eq!(
+            MData::Integer(i32::MAX)
+                .apply_plus(MData::BigInt(1))
+                .unwrap(),
+            MData::BigInt(i64::from(i32::MAX) + 1)
+        );
+        assert_eq!(
+            MData::BigInt(5).apply_minus(m_int!(7)).unwrap(),
+            MData::BigInt(-2)
+        );
+        assert_eq!(
+            MData::BigInt(1).apply_plus(MData::Float(0.5)).unwrap(),
+            MData::Float(1.5)
+        );
+        assert_eq!(
+            MData::BigInt(i64::MAX)
+                .apply_plus(m_int!(1))
+                .unwrap_err()
+                .msg,
+            "integer out of range"
+        );
+        assert_eq!(
+            MData::BigInt(i64::MIN)
+                .apply_minus(MData::BigInt(1))
+                .unwrap_err()
+                .msg,
+            "integer out of range"
+        );
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        assert_eq!(m_int!(1).apply_plus(m_int!(2)).unwrap(), m_int!(3));
+        assert_eq!(m_int!(1).apply_minus(m_int!(2)).unwrap(), m_int!(-1));
+        assert_eq!(
+            MData::Integer(i32::MAX)
+                .apply_plus(m_int!(1))
+                .unwrap_err()
+                .msg,
+            "integer out of range"
+        );
+        assert_eq!(
+            MData::Integer(i32::MIN)
+                .apply_minus(m_int!(1))
+                .unwrap_err()
+                .msg,
+            "integer out of range"
+        );
+        assert_eq!(
+            MData::Float(1.5).apply_plus(m_int!(1)).unwrap(),
+            MData::Float(2.5)
+        );
+        assert_eq!(
+            m_int!(1).apply_minus(MData::Float(0.25)).unwrap(),
+            MData::Float(0.75)
+        );
+        assert_eq!(
+            MData::Float(f64::MAX)
+                .apply_plus(MData::Float(f64::MAX))
+                .unwrap_err()
+                .msg,
+            "float out of range"
+        );
+        assert_eq!(
+            m_varchar!("a").apply_minus(m_int!(1)).unwrap_err().code,
+            sqlstate::DATATYPE_MISMATCH
+        );
+
+        assert_eq!(MData::Null.apply_plus(m_int!(1)).unwrap(), MData::Null);
+        assert_eq!(m_int!(1).apply_minus(MData::Null).unwrap(), MData::Null);
+    }
+
+    #[test]
+    fn test_to_mdata() {
+        assert_eq!(5.to_mdata(), m_int!(5));
+        assert_eq!("foo".to_mdata(), m_varchar!("foo"));
+        assert_eq!(String::from("foo").to_mdata(), m_varchar!("foo"));
+        assert_eq!(Some(1).to_mdata(), m_int!(1));
+        assert_eq!(true.to_mdata(), MData::Bool(true));
+        assert_eq!(None::<i32>.to_mdata(), MData::Null);
+        assert_eq!(MData::Null.to_mdata(), MData::Null);
+
+        let params: &[&dyn ToMData] = &[&1, &"foo", &None::<String>];
+        let converted: Vec<MData> = params.iter().map(|p| p.to_mdata()).collect();
+        assert_eq!(converted, vec![m_int!(1), m_varchar!("foo"), MData::Null]);
     }
 
     #[test]
@@ -178,4 +708,122 @@ mod serialization_tests {
             panic!("Integer deserialized to something else than varchar");
         }
     }
+
+    #[test]
+    fn test_serialize_and_deserialize_bool() {
+        for value in [true, false] {
+            let bytes = MData::Bool(value).bytes();
+            let deserialized = deserialize_data_column(TYPE_BYTE_BOOL, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Bool(value));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_BOOL, &[]).is_err());
+        assert!(deserialize_data_column(TYPE_BYTE_BOOL, &[2]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_float() {
+        for value in [0.0, -1.5, 3.25e100, f64::MIN_POSITIVE] {
+            let bytes = MData::Float(value).bytes();
+            assert_eq!(bytes, value.to_be_bytes());
+            let deserialized = deserialize_data_column(TYPE_BYTE_FLOAT, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Float(value));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_FLOAT, &[0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_date() {
+        for value in [0, -1, 19723] {
+            let bytes = MData::Date(value).bytes();
+            assert_eq!(bytes, value.to_be_bytes());
+            let deserialized = deserialize_data_column(TYPE_BYTE_DATE, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Date(value));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_DATE, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        assert_eq!(
+            MData::Decimal(1050, 2)
+                .apply_plus(MData::Decimal(5, 3))
+                .unwrap(),
+            MData::Decimal(10505, 3)
+        );
+        assert_eq!(
+            m_int!(1).apply_minus(MData::Decimal(1, 1)).unwrap(),
+            MData::Decimal(9, 1)
+        );
+        // 0.1 + 0.2 is exactly 0.3 unlike with floats
+        assert_eq!(
+            MData::Decimal(1, 1)
+                .apply_plus(MData::Decimal(2, 1))
+                .unwrap(),
+            MData::Decimal(3, 1)
+        );
+        assert_eq!(
+            MData::Decimal(15, 1).apply_plus(MData::Float(1.0)).unwrap(),
+            MData::Float(2.5)
+        );
+        assert_eq!(
+            MData::Decimal(10i128.pow(37) * 9, 0)
+                .apply_plus(MData::Decimal(10i128.pow(37) * 9, 0))
+                .unwrap_err()
+                .msg,
+            "numeric out of range"
+        );
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_decimal() {
+        for (value, scale) in [(0, 0), (-1234, 2), (i128::MAX, 38)] {
+            let bytes = MData::Decimal(value, scale).bytes();
+            assert_eq!(bytes.len(), 17);
+            let deserialized = deserialize_data_column(TYPE_BYTE_DECIMAL, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Decimal(value, scale));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_DECIMAL, &[2, 0, 0]).is_err());
+        assert!(deserialize_data_column(TYPE_BYTE_DECIMAL, &[]).is_err());
+        let mut bytes = MData::Decimal(1, 0).bytes();
+        bytes[0] = MAX_PRECISION + 1;
+        assert!(deserialize_data_column(TYPE_BYTE_DECIMAL, &bytes).is_err());
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_bigint() {
+        for value in [0, -1, i64::MAX, i64::MIN] {
+            let bytes = MData::BigInt(value).bytes();
+            assert_eq!(bytes, value.to_be_bytes());
+            let deserialized = deserialize_data_column(TYPE_BYTE_BIGINT, &bytes).unwrap();
+            assert_eq!(deserialized, MData::BigInt(value));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_BIGINT, &[0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_timestamp() {
+        for value in [0, -1, 1_704_067_200_000_000, i64::MAX] {
+            let bytes = MData::Timestamp(value).bytes();
+            assert_eq!(bytes, value.to_be_bytes());
+            let deserialized = deserialize_data_column(TYPE_BYTE_TIMESTAMP, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Timestamp(value));
+        }
+        assert!(deserialize_data_column(TYPE_BYTE_TIMESTAMP, &[0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_and_deserialize_bytes() {
+        for value in [vec![], vec![0], vec![0xde, 0xad, 0xbe, 0xef]] {
+            let bytes = MData::Bytes(value.clone()).bytes();
+            assert_eq!(bytes, value);
+            let deserialized = deserialize_data_column(TYPE_BYTE_BYTES, &bytes).unwrap();
+            assert_eq!(deserialized, MData::Bytes(value));
+        }
+    }
+
+    #[test]
+    fn test_deserialize_invalid_integer() {
+        assert!(deserialize_data_column(TYPE_BYTE_INTEGER, &[]).is_err());
+        assert!(deserialize_data_column(TYPE_BYTE_INTEGER, &[0, 0, 0, 0, 1]).is_err());
+    }
 }
@@ -0,0 +1,227 @@
+//! Conversions between calendar dates and days since 1970-01-01, and timestamps and
+//! microseconds since 1970-01-01 00:00:00 UTC, which is how they are stored and sent
+//! over the wire.
+//!
+//! ```
+//! use microbat_protocol::data::date::{format_date, format_timestamp, parse_date};
+//!
+//! assert_eq!(parse_date("1970-01-02"), Some(1));
+//! assert_eq!(format_date(-1), "1969-12-31");
+//! assert_eq!(format_timestamp(1_500_000), "1970-01-01 00:00:01.5");
+//! ```
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// Days since 1970-01-01 of given date in the proleptic Gregorian calendar
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> i32 {
+    // Years start from March so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march as i32 + 2) / 5 + day as i32 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Year, month and day of given days since 1970-01-01
+pub fn civil_from_days(days: i32) -> (i32, u32, u32) {
+    let days = i64::from(days) + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as i32, month, day)
+}
+
+/// Parses an ISO-8601 date, `YYYY-MM-DD`, into days since 1970-01-01
+pub fn parse_date(value: &str) -> Option<i32> {
+    let mut parts = value.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    if ![year, month, day]
+        .iter()
+        .all(|part| part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+    let (year, month, day): (i32, u32, u32) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// Formats days since 1970-01-01 as an ISO-8601 date
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Parses an ISO-8601 timestamp, `YYYY-MM-DD HH:MM:SS[.ffffff]`, into microseconds since
+/// 1970-01-01 00:00:00 UTC. The date and time can be separated with `T` too and a date
+/// without a time is midnight.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    let (date, time) = match value.find([' ', 'T']) {
+        Some(index) => (&value[..index], Some(&value[index + 1..])),
+        None => (value, None),
+    };
+    let days = i64::from(parse_date(date)?);
+    let micros = match time {
+        Some(time) => parse_time(time)?,
+        None => 0,
+    };
+    Some(days * MICROS_PER_DAY + micros)
+}
+
+/// Formats microseconds since 1970-01-01 00:00:00 UTC as an ISO-8601 timestamp. Fractions
+/// of a second are left out when there are none.
+pub fn format_timestamp(micros: i64) -> String {
+    // Timestamps before 1970 have negative days but the time of day is always positive
+    let days = micros.div_euclid(MICROS_PER_DAY) as i32;
+    let micros = micros.rem_euclid(MICROS_PER_DAY);
+    let seconds = micros / MICROS_PER_SECOND;
+    let mut formatted = format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(days),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let fraction = micros % MICROS_PER_SECOND;
+    if fraction > 0 {
+        formatted.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    formatted
+}
+
+// Microseconds since midnight of `HH:MM:SS[.ffffff]`
+fn parse_time(value: &str) -> Option<i64> {
+    let (time, fraction) = match value.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (value, None),
+    };
+    let parts: Vec<&str> = time.split(':').collect();
+    if parts.len() != 3
+        || !parts
+            .iter()
+            .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+    let (hours, minutes, seconds): (i64, i64, i64) = (
+        parts[0].parse().ok()?,
+        parts[1].parse().ok()?,
+        parts[2].parse().ok()?,
+    );
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    let micros = match fraction {
+        Some(fraction)
+            if (1..=6).contains(&fraction.len())
+                && fraction.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            // Pad to six digits, so .5 is half a second
+            format!("{:0<6}", fraction).parse().ok()?
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+    Some(((hours * 60 + minutes) * 60 + seconds) * MICROS_PER_SECOND + micros)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod date_tests {
+    use super::*;
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2024, 1, 1), 19723);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        for days in (-800_000..800_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(format_date(19782), "2024-02-29");
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2000-02-29"), Some(11016));
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-1-01"), None);
+        assert_eq!(parse_date("2024-01-01x"), None);
+        assert_eq!(parse_date("+024-01-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_and_format_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-01-01T12:30:15"),
+            Some(1_704_112_215_000_000)
+        );
+        assert_eq!(parse_timestamp("1969-12-31 23:59:59.999999"), Some(-1));
+        assert_eq!(parse_timestamp("1970-01-01 00:00:00.5"), Some(500_000));
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(-1), "1969-12-31 23:59:59.999999");
+        assert_eq!(
+            format_timestamp(1_704_112_215_000_000),
+            "2024-01-01 12:30:15"
+        );
+        assert_eq!(format_timestamp(1_000_120), "1970-01-01 00:00:01.00012");
+        for invalid in [
+            "2024-01-01 24:00:00",
+            "2024-01-01 12:60:00",
+            "2024-01-01 12:00",
+            "2024-01-01 12:00:00.",
+            "2024-01-01 12:00:00.1234567",
+            "2024-01-01 1:00:00",
+            "2024-02-30 12:00:00",
+            "2024-01-01 12:00:00Z",
+        ] {
+            assert_eq!(
+                parse_timestamp(invalid),
+                None,
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+}
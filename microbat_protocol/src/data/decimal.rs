@@ -0,0 +1,156 @@
+//! Fixed-point decimals, stored as an integer scaled by a power of ten. A decimal with
+//! value 1234 and scale 2 is 12.34.
+//!
+//! ```
+//! use microbat_protocol::data::decimal::{format_decimal, parse_decimal};
+//!
+//! assert_eq!(parse_decimal("-12.340"), Some((-12340, 3)));
+//! assert_eq!(format_decimal(5, 2), "0.05");
+//! ```
+
+use std::cmp::Ordering;
+
+/// Most digits a decimal can have, as many as always fit in the scaled integer
+pub const MAX_PRECISION: u8 = 38;
+
+/// Parses a decimal number like `-12.34` into its scaled value and scale
+pub fn parse_decimal(value: &str) -> Option<(i128, u8)> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (digits, ""),
+    };
+    if integer.is_empty()
+        || integer.len() + fraction.len() > usize::from(MAX_PRECISION)
+        || !integer
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let scaled: i128 = format!("{}{}", integer, fraction).parse().ok()?;
+    Some((
+        if negative { -scaled } else { scaled },
+        fraction.len() as u8,
+    ))
+}
+
+/// Formats a scaled value with as many decimals as its scale
+pub fn format_decimal(value: i128, scale: u8) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let scale = usize::from(scale);
+    // Pad with zeros so there is at least one digit before the point
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    match scale {
+        0 => format!("{}{}", sign, integer),
+        _ => format!("{}{}.{}", sign, integer, fraction),
+    }
+}
+
+/// Changes the scale of a value, rounding half away from zero when decimals are dropped.
+/// Returns None if the value doesn't fit.
+pub fn rescale(value: i128, from: u8, to: u8) -> Option<i128> {
+    match from.cmp(&to) {
+        Ordering::Equal => Some(value),
+        Ordering::Less => value.checked_mul(power_of_ten(to - from)?),
+        Ordering::Greater => {
+            let divisor = power_of_ten(from - to)?;
+            let (quotient, remainder) = (value / divisor, value % divisor);
+            match remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+                true => Some(quotient + value.signum()),
+                false => Some(quotient),
+            }
+        }
+    }
+}
+
+/// Count of digits in a scaled value, which must not exceed the precision of a column
+pub fn digits(value: i128) -> u8 {
+    value
+        .unsigned_abs()
+        .checked_ilog10()
+        .map_or(1, |log| log as u8 + 1)
+}
+
+/// Compares two decimals of possibly different scales
+pub fn compare(left: (i128, u8), right: (i128, u8)) -> Ordering {
+    let scale = left.1.max(right.1);
+    match (
+        rescale(left.0, left.1, scale),
+        rescale(right.0, right.1, scale),
+    ) {
+        (Some(left), Some(right)) => left.cmp(&right),
+        // Only a value of larger magnitude than the other can overflow when its scale grows
+        (None, _) => left.0.cmp(&0),
+        (_, None) => 0.cmp(&right.0),
+    }
+}
+
+/// Float rounded to given scale, if it fits
+pub fn from_float(value: f64, scale: u8) -> Option<i128> {
+    let scaled = (value * 10f64.powi(i32::from(scale))).round();
+    // Casting saturates, so anything at the limits is out of range
+    match scaled.is_finite() && scaled.abs() < i128::MAX as f64 {
+        true => Some(scaled as i128),
+        false => None,
+    }
+}
+
+/// Value of a decimal as a float, which may lose precision
+pub fn to_float(value: i128, scale: u8) -> f64 {
+    value as f64 / 10f64.powi(i32::from(scale))
+}
+
+fn power_of_ten(exponent: u8) -> Option<i128> {
+    10i128.checked_pow(u32::from(exponent))
+}
+
+#[cfg(test)]
+mod decimal_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse_decimal("12.34"), Some((1234, 2)));
+        assert_eq!(parse_decimal("7"), Some((7, 0)));
+        assert_eq!(parse_decimal("0.050"), Some((50, 3)));
+        assert_eq!(format_decimal(1234, 2), "12.34");
+        assert_eq!(format_decimal(-5, 3), "-0.005");
+        assert_eq!(format_decimal(7, 0), "7");
+        assert_eq!(format_decimal(0, 2), "0.00");
+        for invalid in ["", ".5", "1.2.3", "1e5", "+1", "--1", "12a"] {
+            assert_eq!(parse_decimal(invalid), None, "{} should not parse", invalid);
+        }
+        assert_eq!(parse_decimal(&"9".repeat(39)), None);
+    }
+
+    #[test]
+    fn test_rescale() {
+        assert_eq!(rescale(1234, 2, 4), Some(123400));
+        assert_eq!(rescale(1235, 3, 2), Some(124));
+        assert_eq!(rescale(1234, 3, 2), Some(123));
+        assert_eq!(rescale(-1235, 3, 2), Some(-124));
+        assert_eq!(rescale(i128::MAX, 0, 1), None);
+        assert_eq!(from_float(12.34, 2), Some(1234));
+        assert_eq!(from_float(-0.005, 2), Some(-1));
+        assert_eq!(from_float(f64::MAX, 0), None);
+        assert_eq!(from_float(f64::NAN, 0), None);
+    }
+
+    #[test]
+    fn test_digits_and_compare() {
+        assert_eq!(digits(0), 1);
+        assert_eq!(digits(-999), 3);
+        assert_eq!(digits(1000), 4);
+        assert_eq!(compare((150, 2), (15, 1)), Ordering::Equal);
+        assert_eq!(compare((-1, 0), (5, 3)), Ordering::Less);
+        assert_eq!(compare((i128::MAX, 0), (1, 5)), Ordering::Greater);
+        assert_eq!(compare((1, 5), (i128::MIN, 0)), Ordering::Greater);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod data_values;
+pub mod date;
+pub mod decimal;
 pub mod table_model;
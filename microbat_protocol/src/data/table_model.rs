@@ -1,4 +1,7 @@
-use super::data_values::{DataError, MData, MDataType};
+use std::sync::Arc;
+
+use super::data_values::{DataError, FromMData, MData, MDataType};
+use crate::sqlstate;
 
 /// Serializable data description of incoming rows in result set.
 #[derive(PartialEq, Debug)]
@@ -10,6 +13,7 @@ impl TableSchema {
     pub fn new(columns: Vec<Column>) -> Result<Self, DataError> {
         if columns.is_empty() {
             return Err(DataError {
+                code: sqlstate::SYNTAX_ERROR,
                 msg: String::from("Can't build empty schema"),
             });
         }
@@ -18,15 +22,54 @@ impl TableSchema {
 
     pub fn matches_at(&self, index: usize, data_type: MDataType) -> bool {
         match self.columns.get(index) {
-            Some(column) => column.data_type == data_type,
+            Some(column) => match (&column.data_type, &data_type) {
+                // Decimal values don't know the precision of the column, see `MData::matcher`
+                (MDataType::Decimal { .. }, MDataType::Decimal { .. }) => true,
+                (column_type, data_type) => column_type == data_type,
+            },
             None => false, // Ok, this is bad
         }
     }
 
+    /// Position of the column with given name. Names are compared case insensitively, as
+    /// the server folds the case of unquoted identifiers.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
     pub fn len(&self) -> usize {
         self.columns.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Fails unless given row has a value of the type of each column, or null
+    pub fn check_row(&self, row: &[MData]) -> Result<(), DataError> {
+        if row.len() != self.len() {
+            return Err(DataError {
+                code: sqlstate::SYNTAX_ERROR,
+                msg: format!(
+                    "Trying to put {} columns but schema has {} columns",
+                    row.len(),
+                    self.len()
+                ),
+            });
+        }
+        for (index, data) in row.iter().enumerate() {
+            if *data != MData::Null && !self.matches_at(index, data.matcher()) {
+                return Err(DataError {
+                    code: sqlstate::DATATYPE_MISMATCH,
+                    msg: format!("Can't put {:?} into index {}", data.matcher(), index),
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn join(&self, other: TableSchema) -> Result<Self, DataError> {
         let mut columns = vec![];
         for c in self.columns.iter() {
@@ -61,7 +104,7 @@ impl Column {
 }
 
 /// One row in result set
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct DataRow {
     pub columns: Vec<MData>,
 }
@@ -71,6 +114,54 @@ impl DataRow {
     }
 }
 
+/// Row of a result set together with the description of its columns, so values can be read
+/// by column name instead of position, e.g. `row.get::<i32>("id")`.
+///
+/// Rows of the same result share the description.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Row {
+    schema: Arc<TableSchema>,
+    data: DataRow,
+}
+
+impl Row {
+    pub fn new(schema: Arc<TableSchema>, data: DataRow) -> Self {
+        Row { schema, data }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.schema.columns
+    }
+
+    /// Values in the order of the columns
+    pub fn values(&self) -> &[MData] {
+        &self.data.columns
+    }
+
+    /// Value of the column with given name, if there is such column
+    pub fn value(&self, name: &str) -> Option<&MData> {
+        self.schema
+            .index_of(name)
+            .and_then(|index| self.data.columns.get(index))
+    }
+
+    /// Value of the column with given name converted to a rust value. Use an `Option` for
+    /// nullable columns.
+    pub fn get<T: FromMData>(&self, name: &str) -> Result<T, DataError> {
+        match self.value(name) {
+            Some(value) => T::from_mdata(value),
+            None => Err(DataError {
+                code: sqlstate::UNDEFINED_COLUMN,
+                msg: format!("No column named {}", name),
+            }),
+        }
+    }
+
+    pub fn into_values(self) -> Vec<MData> {
+        self.data.columns
+    }
+}
+
 pub struct RelationTable {
     pub schema: TableSchema,
     pub rows: Vec<DataRow>,
@@ -85,22 +176,7 @@ impl RelationTable {
     }
 
     pub fn push_row(&mut self, row: Vec<MData>) -> Result<(), DataError> {
-        if row.len() != self.schema.len() {
-            return Err(DataError {
-                msg: format!(
-                    "Trying to put {} columns but schema has {} columns",
-                    row.len(),
-                    self.schema.len()
-                ),
-            });
-        }
-        for (index, data) in row.iter().enumerate() {
-            if !self.schema.matches_at(index, data.matcher()) {
-                return Err(DataError {
-                    msg: format!("Can't put {:?} into index {}", data.matcher(), index),
-                });
-            }
-        }
+        self.schema.check_row(&row)?;
         self.rows.push(DataRow::new(row));
         Ok(())
     }
@@ -108,6 +184,10 @@ impl RelationTable {
     pub fn len(&self) -> usize {
         self.rows.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -116,10 +196,7 @@ mod tests {
     macro_rules! t_schema {
         ($ ( $e:expr),+ ) => {
             {
-                let mut columns = Vec::new();
-                $(
-                    columns.push($e);
-                )*
+                let columns = vec![$($e),+];
                 TableSchema::new(columns).unwrap()
         }
         };
@@ -183,4 +260,42 @@ mod tests {
             "Expected pushing varchar to int fail but it succeeded"
         );
     }
+
+    #[test]
+    fn test_row_access_by_name() {
+        let schema = Arc::new(t_schema!(
+            column!("ID", MDataType::Integer),
+            column!("NAME", MDataType::Varchar),
+            column!("SCORE", MDataType::Float)
+        ));
+        let row = Row::new(
+            schema,
+            DataRow::new(vec![m_int!(1), m_varchar!("foo"), MData::Null]),
+        );
+        assert_eq!(row.get::<i32>("id").unwrap(), 1);
+        assert_eq!(row.get::<i64>("ID").unwrap(), 1);
+        assert_eq!(row.get::<String>("name").unwrap(), "foo");
+        assert_eq!(row.get::<Option<f64>>("score").unwrap(), None);
+        assert_eq!(row.value("name"), Some(&m_varchar!("foo")));
+        assert_eq!(row.value("missing"), None);
+        assert_eq!(
+            row.get::<i32>("missing").unwrap_err().msg,
+            "No column named missing"
+        );
+        assert_eq!(
+            row.get::<bool>("name").unwrap_err().msg,
+            "Can't convert VARCHAR to bool"
+        );
+        assert!(row.get::<f64>("score").is_err());
+    }
+
+    #[test]
+    fn test_null_fits_any_column() {
+        let mut relation = RelationTable::new(t_schema!(
+            column!("foo", MDataType::Integer),
+            column!("bar", MDataType::Varchar)
+        ));
+        relation.push_row(vec![MData::Null, MData::Null]).unwrap();
+        assert_eq!(relation.len(), 1);
+    }
 }
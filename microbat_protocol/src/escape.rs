@@ -0,0 +1,60 @@
+//! Quoting for building SQL text, so clients and tools don't each escape values on their own.
+//!
+//! ```
+//! use microbat_protocol::escape::{quote_identifier, quote_literal};
+//!
+//! let sql = format!(
+//!     "select string_agg({}, {}) from foo;",
+//!     quote_identifier("order"),
+//!     quote_literal("', '")
+//! );
+//! assert_eq!(sql, r#"select string_agg("order", ''', ''') from foo;"#);
+//! ```
+
+/// Quotes a string literal, 'O''Brien'. Quotes are escaped by doubling them, which
+/// works whether or not the server accepts backslash escapes.
+pub fn quote_literal(value: &str) -> String {
+    quote(value, '\'')
+}
+
+/// Quotes an identifier, "order". Quoted identifiers may contain any characters and
+/// reserved words, but they are case insensitive like unquoted ones.
+pub fn quote_identifier(name: &str) -> String {
+    quote(name, '"')
+}
+
+fn quote(value: &str, quote: char) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push(quote);
+    for char in value.chars() {
+        if char == quote {
+            quoted.push(quote);
+        }
+        quoted.push(char);
+    }
+    quoted.push(quote);
+    quoted
+}
+
+#[cfg(test)]
+mod escape_tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_literal() {
+        assert_eq!(quote_literal(""), "''");
+        assert_eq!(quote_literal("foo"), "'foo'");
+        assert_eq!(quote_literal("O'Brien"), "'O''Brien'");
+        assert_eq!(quote_literal("''"), "''''''");
+        assert_eq!(quote_literal("back\\slash"), "'back\\slash'");
+        assert_eq!(quote_literal("say \"hi\""), "'say \"hi\"'");
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("foo"), "\"foo\"");
+        assert_eq!(quote_identifier("select"), "\"select\"");
+        assert_eq!(quote_identifier("my \"table\""), "\"my \"\"table\"\"\"");
+        assert_eq!(quote_identifier("it's"), "\"it's\"");
+    }
+}
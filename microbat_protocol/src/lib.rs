@@ -1,29 +1,62 @@
 extern crate core;
 
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod arbitrary;
 pub mod data;
+pub mod escape;
 pub mod messages;
+pub mod parameters;
+pub mod sqlstate;
 mod static_values;
+pub mod testing;
 
+use std::io::ErrorKind;
 use std::string::FromUtf8Error;
 
+/// Message of the error of a read or write that timed out
+const TIMED_OUT: &str = "Timed out, peer is not responding";
+
 /// Error for describing protocol errors.
 #[derive(Debug)]
 pub struct MicrobatProtocolError {
     pub msg: String,
+    timed_out: bool,
+}
+
+impl MicrobatProtocolError {
+    pub fn new(msg: impl Into<String>) -> Self {
+        MicrobatProtocolError {
+            msg: msg.into(),
+            timed_out: false,
+        }
+    }
+
+    /// Error of a read or write that timed out
+    pub fn timeout() -> Self {
+        MicrobatProtocolError {
+            msg: String::from(TIMED_OUT),
+            timed_out: true,
+        }
+    }
+
+    /// True if a read or write timed out, see `messages::timeout`
+    pub fn is_timeout(&self) -> bool {
+        self.timed_out
+    }
 }
 
 impl From<std::io::Error> for MicrobatProtocolError {
     fn from(err: std::io::Error) -> Self {
-        MicrobatProtocolError {
-            msg: err.to_string(),
+        // Blocking sockets report an expired timeout as WouldBlock on unix
+        match err.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => MicrobatProtocolError::timeout(),
+            _ => MicrobatProtocolError::new(err.to_string()),
         }
     }
 }
 
 impl From<FromUtf8Error> for MicrobatProtocolError {
     fn from(err: FromUtf8Error) -> Self {
-        MicrobatProtocolError {
-            msg: err.to_string(),
-        }
+        MicrobatProtocolError::new(err.to_string())
     }
 }
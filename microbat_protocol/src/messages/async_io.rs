@@ -0,0 +1,159 @@
+//! Reading messages from async streams, the counterparts of `read_message` and its variants.
+//! Sending is done with `MicrobatMessage::send_async`.
+//!
+//! ```
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! use microbat_protocol::messages::server_messages::{
+//!     deserialize_server_message, MicrobatServerMessage,
+//! };
+//! use microbat_protocol::messages::{read_message_async, MicrobatMessage};
+//!
+//! let (mut client, mut server) = tokio::io::duplex(64);
+//! MicrobatServerMessage::Ready.send_async(&mut server).await.unwrap();
+//! let message = read_message_async(&mut client, deserialize_server_message).await;
+//! assert_eq!(message.unwrap(), MicrobatServerMessage::Ready);
+//! # });
+//! ```
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::checksum::verify_checksum;
+use super::{check_frame_size, unwrap_frame, ProtocolFeatures, MAX_FRAME_SIZE};
+use crate::MicrobatProtocolError;
+
+/// Reads message from given async stream like `read_message`
+pub async fn read_message_async<T>(
+    stream: &mut (impl AsyncRead + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+) -> Result<T, MicrobatProtocolError> {
+    read_message_async_with(stream, deserializer, |_| true, ProtocolFeatures::default()).await
+}
+
+/// Reads message from given async stream like `read_message_with`
+pub async fn read_message_async_with<T>(
+    stream: &mut (impl AsyncRead + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+    features: ProtocolFeatures,
+) -> Result<T, MicrobatProtocolError> {
+    read_message_async_limited(stream, deserializer, is_known, features, MAX_FRAME_SIZE).await
+}
+
+/// Reads message from given async stream like `read_message_limited`
+pub async fn read_message_async_limited<T>(
+    stream: &mut (impl AsyncRead + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+    features: ProtocolFeatures,
+    max_message_size: usize,
+) -> Result<T, MicrobatProtocolError> {
+    loop {
+        let (message_type, payload) = read_frame(stream, features, max_message_size).await?;
+        if is_known(message_type) || !features.skip_unknown_messages {
+            return deserializer(message_type, payload.len(), &payload);
+        }
+    }
+}
+
+/// Reads the type and payload of next message like the blocking `read_frame`
+async fn read_frame(
+    stream: &mut (impl AsyncRead + Unpin),
+    features: ProtocolFeatures,
+    max_message_size: usize,
+) -> Result<(u8, Vec<u8>), MicrobatProtocolError> {
+    let mut message_type = [0];
+    if stream.read(&mut message_type).await? == 0 || message_type[0] == b'\0' {
+        return Err(MicrobatProtocolError::new("unexpected hangup"));
+    }
+    let length = stream.read_u32_le().await? as usize;
+    check_frame_size(length, max_message_size)?;
+
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).await?;
+    if features.checksums {
+        let checksum = stream.read_u32_le().await?;
+        verify_checksum(message_type[0], &payload, checksum)?;
+    }
+    unwrap_frame(message_type[0], payload, max_message_size)
+}
+
+#[cfg(test)]
+mod async_io_tests {
+    use super::*;
+    use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
+    use crate::messages::server_messages::{
+        deserialize_server_message, is_server_message_type, ErrorResponse, MicrobatServerMessage,
+    };
+    use crate::messages::{read_message_with, MicrobatMessage};
+    use crate::sqlstate;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn test_send_and_read_async() {
+        let (mut client, mut server) = duplex(1024);
+        let query = MicrobatClientMessage::Query(String::from("select 1"));
+        let sent = query.send_async(&mut client).await.unwrap();
+        assert_eq!(sent, query.as_bytes().len());
+        assert_eq!(
+            read_message_async(&mut server, deserialize_client_message)
+                .await
+                .unwrap(),
+            query
+        );
+
+        drop(client);
+        let error = read_message_async(&mut server, deserialize_client_message)
+            .await
+            .unwrap_err();
+        assert_eq!(error.msg, "unexpected hangup");
+    }
+
+    #[tokio::test]
+    async fn test_async_framing_matches_blocking() {
+        let features = ProtocolFeatures::all();
+        let wide = MicrobatServerMessage::Error(ErrorResponse::new(
+            sqlstate::INTERNAL_ERROR,
+            "bat ".repeat(1000),
+        ));
+        let mut bytes = vec![];
+        for message in [wide.clone(), MicrobatServerMessage::Ready] {
+            message.send_async_with(&mut bytes, features).await.unwrap();
+        }
+        // Compressed and checksummed frames read the same with either reader
+        let mut stream = std::io::Cursor::new(bytes.clone());
+        let read = |stream: &mut std::io::Cursor<Vec<u8>>| {
+            read_message_with(
+                stream,
+                deserialize_server_message,
+                is_server_message_type,
+                features,
+            )
+            .unwrap()
+        };
+        assert_eq!(read(&mut stream), wide);
+        assert_eq!(read(&mut stream), MicrobatServerMessage::Ready);
+
+        let mut stream = bytes.as_slice();
+        for expected in [wide.clone(), MicrobatServerMessage::Ready] {
+            let message = read_message_async_with(
+                &mut stream,
+                deserialize_server_message,
+                is_server_message_type,
+                features,
+            )
+            .await
+            .unwrap();
+            assert_eq!(message, expected);
+        }
+
+        let error = read_message_async_limited(
+            &mut bytes.as_slice(),
+            deserialize_server_message,
+            is_server_message_type,
+            features,
+            1024,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.msg.contains("limited to 1024 bytes"));
+    }
+}
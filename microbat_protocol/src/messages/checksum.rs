@@ -0,0 +1,59 @@
+use crate::MicrobatProtocolError;
+
+/// CRC32 of a frame, [MESSAGE_ID, LENGTH, ...PAYLOAD], sent after the frame when checksums
+/// are negotiated. The trailer is not counted in the length of the frame.
+pub(crate) fn frame_checksum(message_type: u8, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[message_type]);
+    hasher.update(&(payload.len() as u32).to_le_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Checks the trailer read after given frame. A mismatch means that the frame was corrupted,
+/// or that the stream is out of sync and the frame is read from the middle of another one.
+pub(crate) fn verify_checksum(
+    message_type: u8,
+    payload: &[u8],
+    checksum: u32,
+) -> Result<(), MicrobatProtocolError> {
+    let expected = frame_checksum(message_type, payload);
+    if checksum != expected {
+        return Err(MicrobatProtocolError::new(format!(
+                "Checksum of message '{}' is {:#010x} but expecting {:#010x}, stream is corrupted or out of sync",
+                char::from(message_type),
+                checksum,
+                expected
+            )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod checksum_tests {
+    use super::*;
+    use crate::messages::server_messages::MicrobatServerMessage;
+    use crate::messages::MicrobatMessage;
+
+    #[test]
+    fn test_checksum_of_frame() {
+        let bytes = MicrobatServerMessage::InsertResult(42).as_bytes();
+        assert_eq!(
+            frame_checksum(bytes[0], &bytes[5..]),
+            crc32fast::hash(&bytes)
+        );
+        assert!(verify_checksum(bytes[0], &bytes[5..], crc32fast::hash(&bytes)).is_ok());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let bytes = MicrobatServerMessage::InsertResult(42).as_bytes();
+        let checksum = crc32fast::hash(&bytes);
+        let mut corrupted = bytes[5..].to_vec();
+        corrupted[0] ^= 1;
+        let error = verify_checksum(bytes[0], &corrupted, checksum).unwrap_err();
+        assert!(error.msg.contains("corrupted or out of sync"));
+        // Frames of another type don't pass either
+        assert!(verify_checksum(b'?', &bytes[5..], checksum).is_err());
+    }
+}
@@ -1,67 +1,243 @@
 use crate::{static_values as values, MicrobatProtocolError};
 
-use super::MicrobatMessage;
+use super::frame::{FrameReader, FrameWriter};
+use super::{read_handshake, write_handshake, MicrobatMessage, ProtocolFeatures};
+use crate::data::data_values::MData;
+use crate::data::table_model::DataRow;
 
 /// Enum of messages that can originate from the client
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum MicrobatClientMessage {
-    Handshake,
+    /// Starts the session with the protocol version of the client, requesting given optional
+    /// features
+    Handshake(u16, ProtocolFeatures),
+    /// Query of one or more statements, see `split_statements`
     Query(String),
+    /// Query with $1, $2... placeholders and values for them, sent separately from the sql
+    ParameterizedQuery(String, Vec<MData>),
+    /// Logs in after the handshake, server refuses queries until this succeeds
+    Authenticate {
+        user: String,
+        password: String,
+    },
     Disconnect,
+    /// Checks that the connection is alive, server answers with Pong. Needs protocol version
+    /// `PING_PROTOCOL_VERSION`.
+    Ping,
+    /// Parses given sql into a statement stored under given name for the rest of the session,
+    /// replacing an earlier statement of the same name. Server answers with ParseComplete.
+    /// Needs protocol version `EXTENDED_QUERY_PROTOCOL_VERSION`.
+    Parse(String, String),
+    /// Binds values to the $1, $2... placeholders of the named statement, server answers
+    /// with BindComplete
+    Bind(String, Vec<MData>),
+    /// Executes the named statement with its bound values, server answers like to a Query
+    Execute(String),
+    /// Rows of a COPY ... FROM STDIN, sent after the server answered the statement with
+    /// CopyIn. Needs protocol version `COPY_PROTOCOL_VERSION`.
+    CopyData(Vec<DataRow>),
+    /// Ends the rows of a COPY ... FROM STDIN, server answers like to an INSERT
+    CopyDone,
 }
 
 impl MicrobatMessage for MicrobatClientMessage {
     fn as_bytes(&self) -> Vec<u8> {
         match self {
-            MicrobatClientMessage::Handshake => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::CLIENT_MSG_TYPE_HANDSHAKE);
-                bytes.append(&mut self.str_with_length(values::CLIENT_HANDSHAKE_PAYLOAD));
-                bytes
+            MicrobatClientMessage::Handshake(version, features) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_HANDSHAKE);
+                write_handshake(
+                    &mut frame,
+                    values::CLIENT_HANDSHAKE_PAYLOAD,
+                    *version,
+                    features,
+                );
+                frame.finish()
             }
             MicrobatClientMessage::Disconnect => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::CLIENT_MSG_TYPE_DISCONNECT);
-                bytes.append(&mut self.str_with_length(values::CLIENT_DISCONNECT_PAYLOAD));
-                bytes
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_DISCONNECT);
+                frame.put_bytes(values::CLIENT_DISCONNECT_PAYLOAD.as_bytes());
+                frame.finish()
+            }
+            MicrobatClientMessage::Ping => FrameWriter::new(values::CLIENT_MSG_TYPE_PING).finish(),
+            MicrobatClientMessage::Parse(name, query) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_PARSE);
+                frame.put_str(name).put_str(query);
+                frame.finish()
+            }
+            MicrobatClientMessage::Bind(name, parameters) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_BIND);
+                frame.put_str(name);
+                for parameter in parameters {
+                    frame.put_data(parameter);
+                }
+                frame.finish()
+            }
+            MicrobatClientMessage::Execute(name) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_EXECUTE);
+                frame.put_str(name);
+                frame.finish()
+            }
+            MicrobatClientMessage::CopyData(rows) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_COPY_DATA);
+                frame.put_rows(rows);
+                frame.finish()
+            }
+            MicrobatClientMessage::CopyDone => {
+                FrameWriter::new(values::CLIENT_MSG_TYPE_COPY_DONE).finish()
+            }
+            MicrobatClientMessage::Authenticate { user, password } => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_AUTHENTICATE);
+                frame.put_str(user).put_str(password);
+                frame.finish()
             }
             MicrobatClientMessage::Query(query) => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::CLIENT_MSG_TYPE_QUERY);
-                bytes.append(&mut self.str_with_length(query));
-                bytes
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_QUERY);
+                frame.put_bytes(query.as_bytes());
+                frame.finish()
+            }
+            MicrobatClientMessage::ParameterizedQuery(query, parameters) => {
+                let mut frame = FrameWriter::new(values::CLIENT_MSG_TYPE_PARAMETERIZED_QUERY);
+                frame.put_str(query);
+                for parameter in parameters {
+                    frame.put_data(parameter);
+                }
+                frame.finish()
             }
         }
     }
 }
 
+/// Splits a query into statements terminated by `;`, ignoring terminators in string literals
+/// and quoted identifiers.
+///
+/// Server executes the statements of a Query message one by one and responds to each of them
+/// separately, ending every response with Ready. Clients use this to know how many Ready
+/// messages to expect. Blank statements are skipped, but a query always has at least one
+/// statement, so a blank query still gets a response.
+pub fn split_statements(query: &str) -> Vec<&str> {
+    let mut statements = vec![];
+    // Quote of the literal or identifier being read. Doubled quotes close and open it again.
+    let mut quote = None;
+    let mut start = 0;
+    for (index, char) in query.char_indices() {
+        match (quote, char) {
+            (Some(open), char) if char == open => quote = None,
+            (None, '\'' | '"') => quote = Some(char),
+            (None, ';') => {
+                statements.push(&query[start..=index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&query[start..]);
+    let statements: Vec<&str> = statements
+        .into_iter()
+        .map(|statement| statement.trim())
+        .filter(|statement| !statement.is_empty() && *statement != ";")
+        .collect();
+    if statements.is_empty() {
+        return vec![query];
+    }
+    statements
+}
+
+/// True if given byte is the type of some client message
+pub fn is_client_message_type(message_type: u8) -> bool {
+    matches!(
+        message_type,
+        values::CLIENT_MSG_TYPE_HANDSHAKE
+            | values::CLIENT_MSG_TYPE_QUERY
+            | values::CLIENT_MSG_TYPE_PARAMETERIZED_QUERY
+            | values::CLIENT_MSG_TYPE_DISCONNECT
+            | values::CLIENT_MSG_TYPE_AUTHENTICATE
+            | values::CLIENT_MSG_TYPE_PING
+            | values::CLIENT_MSG_TYPE_PARSE
+            | values::CLIENT_MSG_TYPE_BIND
+            | values::CLIENT_MSG_TYPE_EXECUTE
+            | values::CLIENT_MSG_TYPE_COPY_DATA
+            | values::CLIENT_MSG_TYPE_COPY_DONE
+    )
+}
+
 pub fn deserialize_client_message(
     message_type: u8,
     length: usize,
     bytes: &[u8],
 ) -> Result<MicrobatClientMessage, MicrobatProtocolError> {
     if length != bytes.len() {
-        return Err(MicrobatProtocolError {
-            msg: format!(
-                "Byte mismatch error. Expecting {} bytes but received {} bytes",
-                length,
-                bytes.len()
-            ),
-        });
+        return Err(MicrobatProtocolError::new(format!(
+            "Byte mismatch error. Expecting {} bytes but received {} bytes",
+            length,
+            bytes.len()
+        )));
     }
     match message_type {
-        values::CLIENT_MSG_TYPE_HANDSHAKE => Ok(MicrobatClientMessage::Handshake),
+        values::CLIENT_MSG_TYPE_HANDSHAKE => {
+            let (version, features) = read_handshake(bytes, values::CLIENT_HANDSHAKE_PAYLOAD)?;
+            Ok(MicrobatClientMessage::Handshake(version, features))
+        }
         values::CLIENT_MSG_TYPE_DISCONNECT => Ok(MicrobatClientMessage::Disconnect),
+        values::CLIENT_MSG_TYPE_PING => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatClientMessage::Ping)
+        }
+        values::CLIENT_MSG_TYPE_AUTHENTICATE => {
+            let mut reader = FrameReader::new(bytes);
+            let user = reader.get_str()?;
+            let password = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatClientMessage::Authenticate { user, password })
+        }
         values::CLIENT_MSG_TYPE_QUERY => Ok(MicrobatClientMessage::Query(String::from_utf8(
             bytes.to_vec(),
         )?)),
-        unknown => Err(MicrobatProtocolError {
-            msg: format!(
-                "Received unknown message type: {} (ascii: {})",
-                unknown,
-                char::from(unknown)
-            ),
-        }),
+        values::CLIENT_MSG_TYPE_PARAMETERIZED_QUERY => {
+            let mut reader = FrameReader::new(bytes);
+            let query = reader.get_str()?;
+            let mut parameters = vec![];
+            while !reader.is_empty() {
+                parameters.push(reader.get_data()?);
+            }
+            Ok(MicrobatClientMessage::ParameterizedQuery(query, parameters))
+        }
+        values::CLIENT_MSG_TYPE_PARSE => {
+            let mut reader = FrameReader::new(bytes);
+            let name = reader.get_str()?;
+            let query = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatClientMessage::Parse(name, query))
+        }
+        values::CLIENT_MSG_TYPE_BIND => {
+            let mut reader = FrameReader::new(bytes);
+            let name = reader.get_str()?;
+            let mut parameters = vec![];
+            while !reader.is_empty() {
+                parameters.push(reader.get_data()?);
+            }
+            Ok(MicrobatClientMessage::Bind(name, parameters))
+        }
+        values::CLIENT_MSG_TYPE_EXECUTE => {
+            let mut reader = FrameReader::new(bytes);
+            let name = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatClientMessage::Execute(name))
+        }
+        values::CLIENT_MSG_TYPE_COPY_DATA => {
+            let mut reader = FrameReader::new(bytes);
+            let rows = reader.get_rows()?;
+            reader.finish()?;
+            Ok(MicrobatClientMessage::CopyData(rows))
+        }
+        values::CLIENT_MSG_TYPE_COPY_DONE => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatClientMessage::CopyDone)
+        }
+        unknown => Err(MicrobatProtocolError::new(format!(
+            "Received unknown message type: {} (ascii: {})",
+            unknown,
+            char::from(unknown)
+        ))),
     }
 }
 
@@ -71,14 +247,20 @@ mod client_message_tests {
     use crate::messages::serialization_test_util::assert_serialisation;
 
     use super::*;
+    use crate::messages::PROTOCOL_VERSION;
 
     #[test]
     fn test_client_handshake_deserialization() {
-        let handshake_bytes = MicrobatClientMessage::Handshake.as_bytes();
+        let handshake_bytes =
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+                .as_bytes();
         let length = u32::from_le_bytes(handshake_bytes[1..5].try_into().unwrap()) as usize;
         let deserialized =
             deserialize_client_message(handshake_bytes[0], length, &handshake_bytes[5..]).unwrap();
-        assert_eq!(deserialized, MicrobatClientMessage::Handshake);
+        assert_eq!(
+            deserialized,
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+        );
     }
 
     #[test]
@@ -111,11 +293,152 @@ mod client_message_tests {
         }
     }
 
+    #[test]
+    fn test_client_parameterized_query_deserialization() {
+        let message = MicrobatClientMessage::ParameterizedQuery(
+            String::from("select $1, $2, $3"),
+            vec![
+                MData::Integer(42),
+                MData::Varchar(String::from("it's")),
+                MData::Null,
+            ],
+        );
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], values::CLIENT_MSG_TYPE_PARAMETERIZED_QUERY);
+        let length = u32::from_le_bytes(bytes[1..5].try_into().unwrap()) as usize;
+        assert_eq!(length, bytes.len() - 5);
+        let deserialized = deserialize_client_message(bytes[0], length, &bytes[5..]).unwrap();
+        assert_eq!(deserialized, message);
+
+        let no_parameters =
+            MicrobatClientMessage::ParameterizedQuery(String::from("select 1"), vec![]);
+        let bytes = no_parameters.as_bytes();
+        assert_eq!(
+            deserialize_client_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            no_parameters
+        );
+
+        for end in 5..bytes.len() - 1 {
+            assert!(deserialize_client_message(bytes[0], end - 5, &bytes[5..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_client_authenticate_deserialization() {
+        let message = MicrobatClientMessage::Authenticate {
+            user: String::from("juho"),
+            password: String::from("sekret"),
+        };
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], values::CLIENT_MSG_TYPE_AUTHENTICATE);
+        assert_eq!(
+            deserialize_client_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            message
+        );
+        for end in 5..bytes.len() - 1 {
+            assert!(deserialize_client_message(bytes[0], end - 5, &bytes[5..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_client_ping_deserialization() {
+        let bytes = MicrobatClientMessage::Ping.as_bytes();
+        assert_eq!(bytes, vec![values::CLIENT_MSG_TYPE_PING, 0, 0, 0, 0]);
+        assert_eq!(
+            deserialize_client_message(bytes[0], 0, &[]).unwrap(),
+            MicrobatClientMessage::Ping
+        );
+        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_PING, 1, b"x").is_err());
+    }
+
+    #[test]
+    fn test_client_extended_query_deserialization() {
+        let messages = [
+            MicrobatClientMessage::Parse(String::from("s1"), String::from("select $1")),
+            MicrobatClientMessage::Bind(String::from("s1"), vec![MData::Integer(42), MData::Null]),
+            MicrobatClientMessage::Bind(String::from("s1"), vec![]),
+            MicrobatClientMessage::Execute(String::from("s1")),
+        ];
+        for message in messages {
+            let bytes = message.as_bytes();
+            assert!(is_client_message_type(bytes[0]));
+            assert_eq!(
+                deserialize_client_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+                message
+            );
+            // Bind cut between its values is a valid Bind of fewer values
+            for end in 5..bytes.len() - 1 {
+                assert_ne!(
+                    deserialize_client_message(bytes[0], end - 5, &bytes[5..end]).ok(),
+                    Some(message.clone())
+                );
+            }
+        }
+        let bytes =
+            MicrobatClientMessage::Parse(String::from("s1"), String::from("select 1")).as_bytes();
+        for end in 5..bytes.len() - 1 {
+            assert!(deserialize_client_message(bytes[0], end - 5, &bytes[5..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_client_copy_deserialization() {
+        let data = MicrobatClientMessage::CopyData(vec![
+            DataRow::new(vec![MData::Integer(1), MData::Varchar(String::from("foo"))]),
+            DataRow::new(vec![MData::Null, MData::Varchar(String::new())]),
+        ]);
+        let bytes = data.as_bytes();
+        assert_eq!(bytes[0], values::CLIENT_MSG_TYPE_COPY_DATA);
+        assert_eq!(
+            deserialize_client_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            data
+        );
+        for end in 5..bytes.len() - 1 {
+            assert!(deserialize_client_message(bytes[0], end - 5, &bytes[5..end]).is_err());
+        }
+
+        let bytes = MicrobatClientMessage::CopyDone.as_bytes();
+        assert_eq!(bytes, vec![values::CLIENT_MSG_TYPE_COPY_DONE, 0, 0, 0, 0]);
+        assert_eq!(
+            deserialize_client_message(bytes[0], 0, &[]).unwrap(),
+            MicrobatClientMessage::CopyDone
+        );
+        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_COPY_DONE, 1, b"x").is_err());
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(split_statements("select 1;"), vec!["select 1;"]);
+        assert_eq!(split_statements("select 1"), vec!["select 1"]);
+        assert_eq!(
+            split_statements("select 1; select 2;\nshow tables"),
+            vec!["select 1;", "select 2;", "show tables"]
+        );
+        assert_eq!(
+            split_statements("select 'a;b', 'it''s;'; select 2;"),
+            vec!["select 'a;b', 'it''s;';", "select 2;"]
+        );
+        assert_eq!(
+            split_statements("create table \"a;b\" (x integer); select \"say \"\"hi;\"\"\", '\"';"),
+            vec![
+                "create table \"a;b\" (x integer);",
+                "select \"say \"\"hi;\"\"\", '\"';"
+            ]
+        );
+        assert_eq!(
+            split_statements("select 1;; ;\n select 2;  "),
+            vec!["select 1;", "select 2;"]
+        );
+        assert_eq!(split_statements(""), vec![""]);
+        assert_eq!(split_statements(" ; "), vec![" ; "]);
+    }
+
     #[test]
     fn test_client_message_serialisation() {
         assert_serialisation(
             "client handshake",
-            MicrobatClientMessage::Handshake.as_bytes(),
+            // Version 1 without features is the handshake of peers that predate them
+            MicrobatClientMessage::Handshake(1, ProtocolFeatures::default()).as_bytes(),
             values::CLIENT_MSG_TYPE_HANDSHAKE,
             values::CLIENT_HANDSHAKE_PAYLOAD.len(),
             Some(values::CLIENT_HANDSHAKE_PAYLOAD),
@@ -147,8 +470,8 @@ mod client_message_tests {
     fn test_invalid_client_deserialization() {
         assert!(deserialize_client_message(b'\0', 0, &[]).is_err());
         assert!(deserialize_client_message(b'h', 0, &[]).is_err());
-        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_HANDSHAKE, 0, &[b't']).is_err());
-        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_HANDSHAKE, 5, &[b't']).is_err());
+        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_HANDSHAKE, 0, b"t").is_err());
+        assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_HANDSHAKE, 5, b"t").is_err());
         assert!(deserialize_client_message(values::CLIENT_MSG_TYPE_QUERY, 2, &[0, 159]).is_err());
     }
 
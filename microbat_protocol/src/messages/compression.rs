@@ -0,0 +1,111 @@
+use crate::{static_values as values, MicrobatProtocolError};
+
+use super::frame::{FrameReader, FrameWriter};
+/// Smallest frame, header included, that is compressed when compression is negotiated.
+/// Compressing smaller frames costs more time than it saves bandwidth.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Wraps given frame in a compressed frame, [COMPRESSED, LENGTH, MESSAGE_ID,
+/// PAYLOAD_LENGTH, ...LZ4_BLOCK]. None if compression would not make the frame smaller.
+pub(crate) fn compress_frame(bytes: &[u8]) -> Option<Vec<u8>> {
+    let payload = &bytes[5..];
+    let compressed = lz4_flex::block::compress(payload);
+    // Header, message id and payload length of the compressed frame
+    if compressed.len() + 10 >= bytes.len() {
+        return None;
+    }
+    let mut frame = FrameWriter::new(values::MSG_TYPE_COMPRESSED);
+    frame
+        .put_u8(bytes[0])
+        .put_u32(payload.len() as u32)
+        .put_bytes(&compressed);
+    Some(frame.finish())
+}
+
+/// Message type and payload of the frame wrapped in the payload of a compressed frame, which
+/// must not decompress to a frame larger than given size
+pub(crate) fn decompress_frame(
+    bytes: &[u8],
+    max_frame_size: usize,
+) -> Result<(u8, Vec<u8>), MicrobatProtocolError> {
+    let mut reader = FrameReader::new(bytes);
+    let message_type = reader.get_u8()?;
+    let length = reader.get_u32()? as usize;
+    if message_type == values::MSG_TYPE_COMPRESSED {
+        return Err(MicrobatProtocolError::new(
+            "Compressed frame wraps another compressed frame",
+        ));
+    }
+    // Checked before decompressing, so a small frame can't claim a huge payload
+    if length + 5 > max_frame_size {
+        return Err(MicrobatProtocolError::new(format!(
+            "Message decompresses to {} bytes but frames are limited to {} bytes",
+            length + 5,
+            max_frame_size
+        )));
+    }
+    let payload = lz4_flex::block::decompress(reader.get_rest(), length)
+        .map_err(|err| MicrobatProtocolError::new(format!("Can't decompress message: {}", err)))?;
+    if payload.len() != length {
+        return Err(MicrobatProtocolError::new(format!(
+            "Message decompressed to {} bytes but expecting {} bytes",
+            payload.len(),
+            length
+        )));
+    }
+    Ok((message_type, payload))
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+    use crate::data::data_values::MData;
+    use crate::data::table_model::DataRow;
+    use crate::messages::server_messages::MicrobatServerMessage;
+    use crate::messages::{MicrobatMessage, MAX_FRAME_SIZE};
+
+    fn wide_row() -> Vec<u8> {
+        MicrobatServerMessage::DataRow(DataRow::new(vec![
+            MData::Varchar("microbat ".repeat(200));
+            4
+        ]))
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let bytes = wide_row();
+        let compressed = compress_frame(&bytes).unwrap();
+        assert_eq!(compressed[0], values::MSG_TYPE_COMPRESSED);
+        assert!(compressed.len() < bytes.len() / 10);
+
+        let (message_type, payload) = decompress_frame(&compressed[5..], MAX_FRAME_SIZE).unwrap();
+        assert_eq!(message_type, values::SERVER_MSG_TYPE_DATA_ROW);
+        assert_eq!(payload, &bytes[5..]);
+    }
+
+    #[test]
+    fn test_incompressible_frame_is_not_compressed() {
+        assert!(compress_frame(&MicrobatServerMessage::InsertResult(1).as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_invalid_compressed_frames() {
+        let compressed = compress_frame(&wide_row()).unwrap();
+        let payload = &compressed[5..];
+        assert!(decompress_frame(&payload[..payload.len() - 1], MAX_FRAME_SIZE).is_err());
+        assert!(decompress_frame(&payload[..3], MAX_FRAME_SIZE).is_err());
+
+        let mut nested = payload.to_vec();
+        nested[0] = values::MSG_TYPE_COMPRESSED;
+        assert!(decompress_frame(&nested, MAX_FRAME_SIZE).is_err());
+
+        // Payload larger than a frame is refused without decompressing it
+        let mut bomb = payload.to_vec();
+        bomb[1..5].copy_from_slice(&(MAX_FRAME_SIZE as u32).to_le_bytes());
+        assert!(decompress_frame(&bomb, MAX_FRAME_SIZE)
+            .unwrap_err()
+            .msg
+            .contains("frames are limited"));
+    }
+}
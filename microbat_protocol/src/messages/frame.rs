@@ -0,0 +1,364 @@
+use crate::data::data_values::{deserialize_data_column, MData, MDataType};
+use crate::data::decimal::MAX_PRECISION;
+use crate::data::table_model::DataRow;
+use crate::static_values::{
+    TYPE_BYTE_BIGINT, TYPE_BYTE_BOOL, TYPE_BYTE_BYTES, TYPE_BYTE_DATE, TYPE_BYTE_DECIMAL,
+    TYPE_BYTE_FLOAT, TYPE_BYTE_INTEGER, TYPE_BYTE_NULL, TYPE_BYTE_TIMESTAMP, TYPE_BYTE_VARCHAR,
+};
+use crate::MicrobatProtocolError;
+
+/// Builds the bytes of a message frame, i.e [MESSAGE_ID, LENGTH, ...PAYLOAD].
+///
+/// Payload is written with typed put methods and the frame length is computed
+/// when the frame is finished, so messages never do length arithmetic by hand.
+///
+/// ```
+/// use microbat_protocol::messages::frame::{FrameReader, FrameWriter};
+///
+/// let mut writer = FrameWriter::new(b'z');
+/// writer.put_str("foo").put_u32(42);
+/// let bytes = writer.finish();
+///
+/// let mut reader = FrameReader::new(&bytes[5..]);
+/// assert_eq!(reader.get_str().unwrap(), "foo");
+/// assert_eq!(reader.get_u32().unwrap(), 42);
+/// assert!(reader.finish().is_ok());
+/// ```
+pub struct FrameWriter {
+    message_type: u8,
+    payload: Vec<u8>,
+}
+
+impl FrameWriter {
+    pub fn new(message_type: u8) -> Self {
+        FrameWriter {
+            message_type,
+            payload: vec![],
+        }
+    }
+
+    pub fn put_u8(&mut self, value: u8) -> &mut Self {
+        self.payload.push(value);
+        self
+    }
+
+    /// Puts u16 as two little endian bytes
+    pub fn put_u16(&mut self, value: u16) -> &mut Self {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Puts u32 as four little endian bytes
+    pub fn put_u32(&mut self, value: u32) -> &mut Self {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Puts bytes as is, without length
+    pub fn put_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.payload.extend_from_slice(bytes);
+        self
+    }
+
+    /// Puts string prefixed with its length, [LENGTH, ...STR_BYTES]
+    pub fn put_str(&mut self, value: &str) -> &mut Self {
+        self.put_u32(value.len() as u32).put_bytes(value.as_bytes())
+    }
+
+    /// Puts a data value as [TYPE_BYTE, LENGTH, ...VALUE_BYTES]
+    pub fn put_data(&mut self, value: &MData) -> &mut Self {
+        let bytes = value.bytes();
+        self.put_u8(value.type_byte())
+            .put_u32(bytes.len() as u32)
+            .put_bytes(&bytes)
+    }
+
+    /// Puts rows as [ROW_COUNT, ...ROWS], each row as [COLUMN_COUNT, ...VALUES]
+    pub fn put_rows(&mut self, rows: &[DataRow]) -> &mut Self {
+        self.put_u32(rows.len() as u32);
+        for row in rows {
+            self.put_u32(row.columns.len() as u32);
+            for column in &row.columns {
+                self.put_data(column);
+            }
+        }
+        self
+    }
+
+    /// Puts a data type as its type byte. Decimal is followed by its precision and scale.
+    pub fn put_type(&mut self, data_type: &MDataType) -> &mut Self {
+        self.put_u8(data_type.type_byte());
+        if let MDataType::Decimal { precision, scale } = data_type {
+            self.put_u8(*precision).put_u8(*scale);
+        }
+        self
+    }
+
+    /// Returns the whole frame with message type and payload length
+    pub fn finish(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.payload.len() + 5);
+        bytes.push(self.message_type);
+        bytes.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        bytes.extend(self.payload);
+        bytes
+    }
+}
+
+/// Reads typed values from the payload of a message frame.
+///
+/// Every get method checks the remaining length first and returns an error
+/// instead of panicking if the payload is truncated. Lengths and counts read from the
+/// payload are checked against what is left of it before they are used, so nested values
+/// never claim more than the frame holds.
+pub struct FrameReader<'a> {
+    bytes: &'a [u8],
+    pointer: usize,
+}
+
+impl<'a> FrameReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        FrameReader { bytes, pointer: 0 }
+    }
+
+    /// True if the whole payload has been read
+    pub fn is_empty(&self) -> bool {
+        self.pointer >= self.bytes.len()
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, MicrobatProtocolError> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    /// Gets two little endian bytes as u16
+    pub fn get_u16(&mut self) -> Result<u16, MicrobatProtocolError> {
+        Ok(u16::from_le_bytes(self.get_bytes(2)?.try_into().unwrap()))
+    }
+
+    /// Gets four little endian bytes as u32
+    pub fn get_u32(&mut self) -> Result<u32, MicrobatProtocolError> {
+        Ok(u32::from_le_bytes(self.get_bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Gets given amount of bytes as is
+    pub fn get_bytes(&mut self, length: usize) -> Result<&'a [u8], MicrobatProtocolError> {
+        let bytes = self
+            .pointer
+            .checked_add(length)
+            .and_then(|end| self.bytes.get(self.pointer..end))
+            .ok_or(MicrobatProtocolError::new(format!(
+                "Message is truncated, expecting {} bytes at {} but message is {} bytes",
+                length,
+                self.pointer,
+                self.bytes.len()
+            )))?;
+        self.pointer += length;
+        Ok(bytes)
+    }
+
+    /// Count of bytes that have not been read yet
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.pointer)
+    }
+
+    /// Gets a length of the value following it, failing if the value would not fit in the
+    /// rest of the payload
+    pub fn get_length(&mut self) -> Result<usize, MicrobatProtocolError> {
+        let length = self.get_u32()? as usize;
+        if length > self.remaining() {
+            return Err(MicrobatProtocolError::new(format!(
+                "Value of {} bytes at {} exceeds the {} bytes left in the message",
+                length,
+                self.pointer,
+                self.remaining()
+            )));
+        }
+        Ok(length)
+    }
+
+    /// Gets a count of the items following it, each taking at least `min_size` bytes,
+    /// failing if that many items would not fit in the rest of the payload
+    pub fn get_count(&mut self, min_size: usize) -> Result<usize, MicrobatProtocolError> {
+        let count = self.get_u32()? as usize;
+        if count.saturating_mul(min_size) > self.remaining() {
+            return Err(MicrobatProtocolError::new(format!(
+                "Count of {} items at {} exceeds the {} bytes left in the message",
+                count,
+                self.pointer,
+                self.remaining()
+            )));
+        }
+        Ok(count)
+    }
+
+    /// Gets all bytes that have not been read yet
+    pub fn get_rest(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.pointer.min(self.bytes.len())..];
+        self.pointer = self.bytes.len();
+        bytes
+    }
+
+    /// Gets a string written with `FrameWriter::put_str`
+    pub fn get_str(&mut self) -> Result<String, MicrobatProtocolError> {
+        let length = self.get_length()?;
+        Ok(String::from_utf8(self.get_bytes(length)?.to_vec())?)
+    }
+
+    /// Gets a data value written with `FrameWriter::put_data`
+    pub fn get_data(&mut self) -> Result<MData, MicrobatProtocolError> {
+        let type_byte = self.get_u8()?;
+        let length = self.get_length()?;
+        deserialize_data_column(type_byte, self.get_bytes(length)?)
+    }
+
+    /// Gets rows written with `FrameWriter::put_rows`
+    pub fn get_rows(&mut self) -> Result<Vec<DataRow>, MicrobatProtocolError> {
+        let mut rows = vec![];
+        // Count of columns, and the type byte and length of each value
+        for _ in 0..self.get_count(4)? {
+            let mut row = DataRow { columns: vec![] };
+            for _ in 0..self.get_count(5)? {
+                row.columns.push(self.get_data()?);
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Gets a data type written with `FrameWriter::put_type`
+    pub fn get_type(&mut self) -> Result<MDataType, MicrobatProtocolError> {
+        match self.get_u8()? {
+            TYPE_BYTE_NULL => Ok(MDataType::Null),
+            TYPE_BYTE_INTEGER => Ok(MDataType::Integer),
+            TYPE_BYTE_BIGINT => Ok(MDataType::BigInt),
+            TYPE_BYTE_VARCHAR => Ok(MDataType::Varchar),
+            TYPE_BYTE_BOOL => Ok(MDataType::Bool),
+            TYPE_BYTE_FLOAT => Ok(MDataType::Float),
+            TYPE_BYTE_DECIMAL => {
+                let precision = self.get_u8()?;
+                let scale = self.get_u8()?;
+                if !(1..=MAX_PRECISION).contains(&precision) || scale > precision {
+                    return Err(MicrobatProtocolError::new(format!(
+                        "Invalid decimal type DECIMAL({},{})",
+                        precision, scale
+                    )));
+                }
+                Ok(MDataType::Decimal { precision, scale })
+            }
+            TYPE_BYTE_DATE => Ok(MDataType::Date),
+            TYPE_BYTE_TIMESTAMP => Ok(MDataType::Timestamp),
+            TYPE_BYTE_BYTES => Ok(MDataType::Bytes),
+            unknown => Err(MicrobatProtocolError::new(format!(
+                "Unknown data type marker {}",
+                char::from(unknown)
+            ))),
+        }
+    }
+
+    /// Returns an error if some of the payload was not read
+    pub fn finish(&self) -> Result<(), MicrobatProtocolError> {
+        if !self.is_empty() {
+            return Err(MicrobatProtocolError::new(format!(
+                "Message has {} unexpected trailing bytes",
+                self.bytes.len() - self.pointer
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod frame_tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_frame() {
+        let mut writer = FrameWriter::new(b'z');
+        writer
+            .put_u8(7)
+            .put_u32(1024)
+            .put_str("hello")
+            .put_data(&MData::Integer(-1))
+            .put_data(&MData::Null)
+            .put_type(&MDataType::Varchar)
+            .put_type(&MDataType::Decimal {
+                precision: 10,
+                scale: 2,
+            })
+            .put_bytes(b"rest");
+        let bytes = writer.finish();
+        assert_eq!(bytes[0], b'z');
+        assert_eq!(
+            u32::from_le_bytes(bytes[1..5].try_into().unwrap()) as usize,
+            bytes.len() - 5
+        );
+
+        let mut reader = FrameReader::new(&bytes[5..]);
+        assert_eq!(reader.get_u8().unwrap(), 7);
+        assert_eq!(reader.get_u32().unwrap(), 1024);
+        assert_eq!(reader.get_str().unwrap(), "hello");
+        assert_eq!(reader.get_data().unwrap(), MData::Integer(-1));
+        assert_eq!(reader.get_data().unwrap(), MData::Null);
+        assert_eq!(reader.get_type().unwrap(), MDataType::Varchar);
+        assert_eq!(
+            reader.get_type().unwrap(),
+            MDataType::Decimal {
+                precision: 10,
+                scale: 2
+            }
+        );
+        assert!(reader.finish().is_err());
+        assert_eq!(reader.get_rest(), b"rest");
+        assert!(reader.is_empty());
+        assert!(reader.finish().is_ok());
+        assert_eq!(reader.get_rest(), b"");
+    }
+
+    #[test]
+    fn test_truncated_reads_fail() {
+        assert!(FrameReader::new(&[]).get_u8().is_err());
+        assert!(FrameReader::new(&[1, 0, 0]).get_u32().is_err());
+        assert!(FrameReader::new(&[5, 0, 0, 0, b'a']).get_str().is_err());
+        assert!(FrameReader::new(&[u8::MAX; 4]).get_str().is_err());
+        assert!(FrameReader::new(b"i\x04\x00\x00\x00\x00")
+            .get_data()
+            .is_err());
+        assert!(FrameReader::new(b"?").get_type().is_err());
+        assert!(FrameReader::new(b"m\x0a").get_type().is_err());
+        assert!(FrameReader::new(b"m\x00\x00").get_type().is_err());
+        assert!(FrameReader::new(b"m\x0a\x0b").get_type().is_err());
+
+        let mut reader = FrameReader::new(&[1, 2]);
+        assert!(reader.get_u32().is_err());
+        assert_eq!(reader.get_u8().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_nested_lengths_must_fit_in_frame() {
+        let error = FrameReader::new(b"\x05\x00\x00\x00abcd")
+            .get_str()
+            .unwrap_err();
+        assert_eq!(
+            error.msg,
+            "Value of 5 bytes at 4 exceeds the 4 bytes left in the message"
+        );
+        assert!(FrameReader::new(b"v\xff\xff\xff\xffab").get_data().is_err());
+
+        // A handful of bytes can't claim billions of rows or columns
+        let mut writer = FrameWriter::new(b'z');
+        writer.put_u32(u32::MAX).put_u32(0);
+        let bytes = writer.finish();
+        let error = FrameReader::new(&bytes[5..]).get_rows().unwrap_err();
+        assert!(error.msg.starts_with("Count of 4294967295 items at 4"));
+        let mut writer = FrameWriter::new(b'z');
+        writer.put_u32(1).put_u32(2).put_data(&MData::Null);
+        let bytes = writer.finish();
+        assert!(FrameReader::new(&bytes[5..]).get_rows().is_err());
+
+        let mut writer = FrameWriter::new(b'z');
+        writer.put_rows(&[DataRow::new(vec![MData::Null, MData::Integer(1)])]);
+        let bytes = writer.finish();
+        let mut reader = FrameReader::new(&bytes[5..]);
+        assert_eq!(reader.get_rows().unwrap().len(), 1);
+        assert_eq!(reader.remaining(), 0);
+    }
+}
@@ -1,9 +1,96 @@
+#[cfg(feature = "tokio")]
+mod async_io;
+mod checksum;
 pub mod client_messages;
+pub mod compression;
+pub mod frame;
 pub mod server_messages;
+pub mod timeout;
+pub mod writer;
 
-use crate::MicrobatProtocolError;
+#[cfg(feature = "tokio")]
+pub use async_io::{read_message_async, read_message_async_limited, read_message_async_with};
+
+use crate::{static_values as values, MicrobatProtocolError};
+use checksum::{frame_checksum, verify_checksum};
+use compression::{compress_frame, decompress_frame, COMPRESSION_THRESHOLD};
+use frame::{FrameReader, FrameWriter};
+#[cfg(feature = "tokio")]
+use std::future::Future;
 use std::io::{Read, Write};
-use std::str;
+use std::time::Duration;
+use timeout::Timeouts;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Largest frame, header included, that peers send. Larger messages fail to send
+/// instead of being written to the stream, and larger frames fail to read before their
+/// payload is allocated. Readers may limit frames further with `read_message_limited`.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Largest row sent in DataRowChunk messages, clients refuse to reassemble larger rows
+pub const MAX_CHUNKED_ROW_SIZE: usize = 256 * 1024 * 1024;
+
+/// Version of the protocol spoken by this crate, sent in the handshake. Version 1 is the
+/// protocol of peers that predate versions and send handshakes without one.
+pub const PROTOCOL_VERSION: u16 = 12;
+
+/// First protocol version with Ping and Pong. Older servers drop the connection on a Ping.
+pub const PING_PROTOCOL_VERSION: u16 = 3;
+
+/// First protocol version with DataRowBatch. Servers send rows one by one to older clients.
+pub const BATCH_PROTOCOL_VERSION: u16 = 4;
+
+/// First protocol version with DataRowChunk. Rows too large for a frame fail for older
+/// clients.
+pub const CHUNK_PROTOCOL_VERSION: u16 = 5;
+
+/// First protocol version with Parse, Bind and Execute. Older servers drop the connection
+/// on a Parse.
+pub const EXTENDED_QUERY_PROTOCOL_VERSION: u16 = 6;
+
+/// First protocol version with Notice. Servers don't tell older clients of warnings.
+pub const NOTICE_PROTOCOL_VERSION: u16 = 7;
+
+/// First protocol version with CopyIn, CopyData and CopyDone. Servers refuse COPY FROM
+/// STDIN from older clients.
+pub const COPY_PROTOCOL_VERSION: u16 = 8;
+
+/// First protocol version with BackendKeyData, which servers send to newer clients after
+/// the Handshake.
+pub const BACKEND_KEY_PROTOCOL_VERSION: u16 = 9;
+
+/// First protocol version with ParameterStatus. Servers don't tell older clients of their
+/// parameters.
+pub const PARAMETER_STATUS_PROTOCOL_VERSION: u16 = 10;
+
+/// First protocol version with ShuttingDown. Servers shutting down end the sessions of older
+/// clients with a fatal error instead.
+pub const SHUTDOWN_PROTOCOL_VERSION: u16 = 11;
+
+/// First protocol version with CommandComplete. Servers answer statements that neither
+/// return nor insert rows with InsertResult(0) to older clients instead.
+pub const COMMAND_COMPLETE_PROTOCOL_VERSION: u16 = 12;
+
+/// Oldest protocol version this crate still speaks
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Version to use when the peer speaks given version, which is the older one of the two.
+/// None if the peer only speaks versions older than `MIN_PROTOCOL_VERSION`.
+///
+/// ```
+/// use microbat_protocol::messages::{negotiate_version, PROTOCOL_VERSION};
+///
+/// assert_eq!(negotiate_version(1), Some(1));
+/// assert_eq!(negotiate_version(PROTOCOL_VERSION + 1), Some(PROTOCOL_VERSION));
+/// assert_eq!(negotiate_version(0), None);
+/// ```
+pub fn negotiate_version(version: u16) -> Option<u16> {
+    match version {
+        version if version < MIN_PROTOCOL_VERSION => None,
+        version => Some(version.min(PROTOCOL_VERSION)),
+    }
+}
 
 /// Defines MicrobatMessage and offers utility methods for message deserialization and serialization.
 ///
@@ -18,30 +105,199 @@ pub trait MicrobatMessage {
         &self,
         stream: &mut (impl Read + Write + Unpin),
     ) -> Result<usize, MicrobatProtocolError> {
-        let bytes = self.as_bytes();
+        self.send_with(stream, ProtocolFeatures::default())
+    }
+
+    /// Sends this message like `send`, compressing it if compression is negotiated in given
+    /// features and the frame is larger than `COMPRESSION_THRESHOLD`, and following it with a
+    /// checksum if checksums are negotiated. Returns the amount of bytes written.
+    fn send_with(
+        &self,
+        stream: &mut (impl Read + Write + Unpin),
+        features: ProtocolFeatures,
+    ) -> Result<usize, MicrobatProtocolError> {
+        let bytes = frame_message(self.as_bytes(), features)?;
         // println!(
         //     ">> Sending {} bytes, msgId: {}",
         //     bytes.len(),
         //     char::from(bytes[0])
         // );
-        stream.write(bytes.as_slice())?;
+        stream.write_all(bytes.as_slice())?;
         Ok(bytes.len())
     }
 
+    /// Sends this message like `send_with`, failing if the peer doesn't take it in given
+    /// time. The timeout stays set on the stream for later writes.
+    fn send_timeout(
+        &self,
+        stream: &mut (impl Read + Write + Unpin + Timeouts),
+        features: ProtocolFeatures,
+        timeout: Option<Duration>,
+    ) -> Result<usize, MicrobatProtocolError> {
+        stream.set_write_timeout(timeout)?;
+        self.send_with(stream, features)
+    }
+
+    /// Sends this message like `send` to an async stream, e.g a `tokio::net::TcpStream`.
+    /// Available with the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    fn send_async<'a, W: AsyncWrite + Unpin + Send>(
+        &self,
+        stream: &'a mut W,
+    ) -> impl Future<Output = Result<usize, MicrobatProtocolError>> + Send + 'a {
+        self.send_async_with(stream, ProtocolFeatures::default())
+    }
+
+    /// Sends this message like `send_with` to an async stream. The message is framed before
+    /// the returned future is polled, so the future borrows only the stream.
+    #[cfg(feature = "tokio")]
+    fn send_async_with<'a, W: AsyncWrite + Unpin + Send>(
+        &self,
+        stream: &'a mut W,
+        features: ProtocolFeatures,
+    ) -> impl Future<Output = Result<usize, MicrobatProtocolError>> + Send + 'a {
+        let bytes = frame_message(self.as_bytes(), features);
+        async move {
+            let bytes = bytes?;
+            stream.write_all(&bytes).await?;
+            Ok(bytes.len())
+        }
+    }
+
     /// Implementations must define how given message is serialized as bytes. The implementation
     /// must return the whole byte stream, i.e [MESSAGE_ID, LENGTH, ...BYTES_OF_LENGTH]
     fn as_bytes(&self) -> Vec<u8>;
+}
+
+/// Frames the bytes of a message as they are sent with given features, see
+/// `MicrobatMessage::send_with`
+pub(crate) fn frame_message(
+    mut bytes: Vec<u8>,
+    features: ProtocolFeatures,
+) -> Result<Vec<u8>, MicrobatProtocolError> {
+    // Limited before compression, so the peer never has to decompress a larger frame
+    if bytes.len() > MAX_FRAME_SIZE {
+        return Err(MicrobatProtocolError::new(format!(
+            "Message is {} bytes but frames are limited to {} bytes",
+            bytes.len(),
+            MAX_FRAME_SIZE
+        )));
+    }
+    if features.compression && bytes.len() > COMPRESSION_THRESHOLD {
+        if let Some(compressed) = compress_frame(&bytes) {
+            bytes = compressed;
+        }
+    }
+    // Covers the frame as sent, so a compressed frame is checked before decompressing it
+    if features.checksums {
+        let checksum = frame_checksum(bytes[0], &bytes[5..]);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Optional protocol features, negotiated in the handshake.
+///
+/// Client sends the features it wants in its handshake and server responds with the ones it
+/// also supports, so a feature is in use only if both peers know it. Features are encoded as
+/// bit flags after the handshake greeting, followed by the protocol version. Peers that
+/// predate features don't send the flags and ignore them, which reads as no features.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ProtocolFeatures {
+    /// Messages of unknown type are skipped using their length instead of failing,
+    /// so older peers can ignore messages introduced in newer protocol versions.
+    pub skip_unknown_messages: bool,
+    /// Server tells the client of tables created by any session with `SchemaChanged`
+    /// messages, so caches of the catalog can be refreshed without polling.
+    pub schema_notifications: bool,
+    /// Frames larger than `COMPRESSION_THRESHOLD` may be sent lz4 compressed, see
+    /// `MicrobatMessage::send_with`. Peers that negotiated it read compressed frames
+    /// transparently.
+    pub compression: bool,
+    /// Every frame after the handshake negotiating it is followed by a CRC32 of the frame,
+    /// which `read_message_with` verifies. Corrupted or out of sync streams then fail with a
+    /// protocol error instead of being read as garbage messages.
+    pub checksums: bool,
+}
+
+impl ProtocolFeatures {
+    /// All features supported by this version of the protocol
+    pub fn all() -> Self {
+        ProtocolFeatures {
+            skip_unknown_messages: true,
+            schema_notifications: true,
+            compression: true,
+            checksums: true,
+        }
+    }
+
+    /// Features enabled in both self and other
+    pub fn intersection(&self, other: &ProtocolFeatures) -> Self {
+        ProtocolFeatures {
+            skip_unknown_messages: self.skip_unknown_messages && other.skip_unknown_messages,
+            schema_notifications: self.schema_notifications && other.schema_notifications,
+            compression: self.compression && other.compression,
+            checksums: self.checksums && other.checksums,
+        }
+    }
 
-    /// Utility method for serialising &str with length
-    /// Returns [LENGTH, STR_BYTES]
-    fn str_with_length(&self, payload: &str) -> Vec<u8> {
-        let mut bytes: Vec<u8> = vec![];
-        bytes.append(&mut (payload.len() as u32).to_le_bytes().to_vec());
-        bytes.append(&mut payload.as_bytes().to_vec());
-        bytes
+    pub(crate) fn bits(&self) -> u32 {
+        let mut bits = 0;
+        if self.skip_unknown_messages {
+            bits |= values::PROTOCOL_FEATURE_SKIP_UNKNOWN_MESSAGES;
+        }
+        if self.schema_notifications {
+            bits |= values::PROTOCOL_FEATURE_SCHEMA_NOTIFICATIONS;
+        }
+        if self.compression {
+            bits |= values::PROTOCOL_FEATURE_COMPRESSION;
+        }
+        if self.checksums {
+            bits |= values::PROTOCOL_FEATURE_CHECKSUMS;
+        }
+        bits
     }
 }
 
+/// Writes the handshake payload after the greeting. Flags and version are left out when they
+/// have their defaults, so peers that predate them can read the handshake.
+pub(crate) fn write_handshake(
+    frame: &mut FrameWriter,
+    greeting: &str,
+    version: u16,
+    features: &ProtocolFeatures,
+) {
+    frame.put_bytes(greeting.as_bytes());
+    if version != 1 {
+        frame.put_u32(features.bits()).put_u16(version);
+    } else if features.bits() != 0 {
+        frame.put_u32(features.bits());
+    }
+}
+
+/// Reads the protocol version and features following the greeting in a handshake payload.
+/// Unknown feature bits are ignored.
+pub(crate) fn read_handshake(
+    bytes: &[u8],
+    greeting: &str,
+) -> Result<(u16, ProtocolFeatures), MicrobatProtocolError> {
+    let mut reader = FrameReader::new(bytes);
+    if reader.get_bytes(greeting.len()).is_err() || reader.is_empty() {
+        return Ok((1, ProtocolFeatures::default()));
+    }
+    let bits = reader.get_u32()?;
+    let features = ProtocolFeatures {
+        skip_unknown_messages: bits & values::PROTOCOL_FEATURE_SKIP_UNKNOWN_MESSAGES != 0,
+        schema_notifications: bits & values::PROTOCOL_FEATURE_SCHEMA_NOTIFICATIONS != 0,
+        compression: bits & values::PROTOCOL_FEATURE_COMPRESSION != 0,
+        checksums: bits & values::PROTOCOL_FEATURE_CHECKSUMS != 0,
+    };
+    if reader.is_empty() {
+        return Ok((1, features));
+    }
+    Ok((reader.get_u16()?, features))
+}
+
 /// Reads message from given stream using given deserializer
 ///
 /// Returns generic type of Result<T, MicrobatProtocolError> in which T
@@ -52,18 +308,84 @@ pub fn read_message<T>(
     stream: &mut (impl Read + Write + Unpin),
     deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
 ) -> Result<T, MicrobatProtocolError> {
+    read_message_with(stream, deserializer, |_| true, ProtocolFeatures::default())
+}
+
+/// Reads message like `read_message`, but skips messages whose type is not known.
+///
+/// Use this when skipping unknown messages is negotiated in `ProtocolFeatures`. Known
+/// message types are given with `is_known`, e.g `is_server_message_type`.
+pub fn read_known_message<T>(
+    stream: &mut (impl Read + Write + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+) -> Result<T, MicrobatProtocolError> {
+    let features = ProtocolFeatures {
+        skip_unknown_messages: true,
+        ..ProtocolFeatures::default()
+    };
+    read_message_with(stream, deserializer, is_known, features)
+}
+
+/// Reads message like `read_message`, following the framing negotiated in given features.
+/// Checksums are verified if negotiated, and messages whose type is not known by `is_known`
+/// are skipped if skipping unknown messages is negotiated.
+pub fn read_message_with<T>(
+    stream: &mut (impl Read + Write + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+    features: ProtocolFeatures,
+) -> Result<T, MicrobatProtocolError> {
+    read_message_limited(stream, deserializer, is_known, features, MAX_FRAME_SIZE)
+}
+
+/// Reads message like `read_message_with`, failing on frames larger than given size, header
+/// included, instead of reading them. The length of a frame is checked before its payload is
+/// allocated, so a peer can't have the reader allocate more than given size. Compressed
+/// frames are limited by the size they decompress to.
+pub fn read_message_limited<T>(
+    stream: &mut (impl Read + Write + Unpin),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+    features: ProtocolFeatures,
+    max_message_size: usize,
+) -> Result<T, MicrobatProtocolError> {
+    loop {
+        let (message_type, message_buffer) = read_frame(stream, features, max_message_size)?;
+        if is_known(message_type) || !features.skip_unknown_messages {
+            return deserializer(
+                message_type,
+                message_buffer.len(),
+                message_buffer.as_slice(),
+            );
+        }
+    }
+}
+
+/// Reads the type and payload of next message, verifying its checksum if checksums are
+/// negotiated and decompressing it if it is compressed
+fn read_frame(
+    stream: &mut (impl Read + Write + Unpin),
+    features: ProtocolFeatures,
+    max_message_size: usize,
+) -> Result<(u8, Vec<u8>), MicrobatProtocolError> {
     let message_type = read_message_type(stream)?;
     if message_type == b'\0' {
         println!("Received null byte");
-        return Err(MicrobatProtocolError {
-            msg: String::from("unexpected hangup"),
-        });
+        return Err(MicrobatProtocolError::new("unexpected hangup"));
     }
 
     let length = read_message_length(stream)?;
+    check_frame_size(length, max_message_size)?;
 
     let mut message_buffer = vec![0; length];
-    stream.read_exact(&mut message_buffer).unwrap();
+    stream.read_exact(&mut message_buffer)?;
+
+    if features.checksums {
+        let mut checksum = [0; 4];
+        stream.read_exact(&mut checksum)?;
+        verify_checksum(message_type, &message_buffer, u32::from_le_bytes(checksum))?;
+    }
 
     // println!(
     // ">> Reading {} bytes, msgId: {}",
@@ -71,7 +393,79 @@ pub fn read_message<T>(
     // char::from(message_type)
     // );
 
-    deserializer(message_type, length, message_buffer.as_slice())
+    unwrap_frame(message_type, message_buffer, max_message_size)
+}
+
+/// Type and payload of the message in given frame, decompressing it if it is compressed
+fn unwrap_frame(
+    message_type: u8,
+    payload: Vec<u8>,
+    max_message_size: usize,
+) -> Result<(u8, Vec<u8>), MicrobatProtocolError> {
+    if message_type == values::MSG_TYPE_COMPRESSED {
+        return decompress_frame(&payload, max_message_size);
+    }
+    Ok((message_type, payload))
+}
+
+/// Fails if a frame with a payload of given length is larger than given size
+fn check_frame_size(length: usize, max_message_size: usize) -> Result<(), MicrobatProtocolError> {
+    if length.saturating_add(5) > max_message_size {
+        return Err(MicrobatProtocolError::new(format!(
+            "Message is {} bytes but messages are limited to {} bytes",
+            length.saturating_add(5),
+            max_message_size
+        )));
+    }
+    Ok(())
+}
+
+/// Parses one message from the beginning of given bytes without doing any I/O.
+///
+/// Returns the message and the amount of bytes it took from the input, or None if
+/// the bytes do not yet contain a whole message. Malformed bytes never panic but
+/// produce an error, so this is the entry point for fuzzing the deserializers.
+pub fn parse_frame<T>(
+    bytes: &[u8],
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+) -> Result<Option<(T, usize)>, MicrobatProtocolError> {
+    parse_frame_with(bytes, deserializer, ProtocolFeatures::default())
+}
+
+/// Parses one message like `parse_frame`, verifying its checksum if checksums are negotiated
+/// in given features. Frames larger than `MAX_FRAME_SIZE` fail without waiting for the rest
+/// of their bytes.
+pub fn parse_frame_with<T>(
+    bytes: &[u8],
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    features: ProtocolFeatures,
+) -> Result<Option<(T, usize)>, MicrobatProtocolError> {
+    let (message_type, length_bytes) = match (bytes.first(), bytes.get(1..5)) {
+        (Some(message_type), Some(length_bytes)) => (*message_type, length_bytes),
+        _ => return Ok(None),
+    };
+    let length = u32::from_le_bytes(length_bytes.try_into().unwrap()) as usize;
+    check_frame_size(length, MAX_FRAME_SIZE)?;
+    let payload = match bytes.get(5..5 + length) {
+        Some(payload) => payload,
+        None => return Ok(None),
+    };
+    let mut frame_length = 5 + length;
+    if features.checksums {
+        let checksum = match bytes.get(frame_length..frame_length + 4) {
+            Some(checksum) => u32::from_le_bytes(checksum.try_into().unwrap()),
+            None => return Ok(None),
+        };
+        verify_checksum(message_type, payload, checksum)?;
+        frame_length += 4;
+    }
+    let message = if message_type == values::MSG_TYPE_COMPRESSED {
+        let (message_type, payload) = decompress_frame(payload, MAX_FRAME_SIZE)?;
+        deserializer(message_type, payload.len(), &payload)?
+    } else {
+        deserializer(message_type, length, payload)?
+    };
+    Ok(Some((message, frame_length)))
 }
 
 /// Utility fn for reading next byte as message type.
@@ -79,7 +473,9 @@ fn read_message_type(
     stream: &mut (impl Read + Write + Unpin),
 ) -> Result<u8, MicrobatProtocolError> {
     let mut message_type = [b'\0'];
-    stream.read(&mut message_type)?;
+    if stream.read(&mut message_type)? == 0 {
+        return Ok(b'\0');
+    }
     Ok(message_type[0])
 }
 
@@ -95,61 +491,381 @@ fn read_message_length(
 #[cfg(test)]
 mod mocked_tcp_stream_tests {
     use super::*;
-    use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
-    use std::cmp::min;
+    use crate::messages::client_messages::MicrobatClientMessage;
+    use crate::messages::server_messages::{
+        deserialize_server_message, is_server_message_type, ErrorResponse, MicrobatServerMessage,
+    };
+    use crate::sqlstate;
+    use crate::testing::MockServer;
 
-    struct MockTcpStream {
-        read_data: Vec<u8>,
-        write_data: Vec<u8>,
-    }
+    #[test]
+    fn test_handshake_via_mock_stream() {
+        let server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+            vec![MicrobatServerMessage::Handshake(
+                PROTOCOL_VERSION,
+                ProtocolFeatures::default(),
+            )],
+        );
+        let mut stream = server.clone();
+        let sent = MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+            .send(&mut stream)
+            .unwrap();
+        assert!(sent > 0);
 
-    impl Read for MockTcpStream {
-        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-            let size: usize = min(self.read_data.len(), buf.len());
-            buf[..size].copy_from_slice(&self.read_data[..size]);
-            Ok(size)
+        let result = read_message(&mut stream, deserialize_server_message);
+        assert!(result.is_ok());
+        match result.unwrap() {
+            MicrobatServerMessage::Handshake(..) => (),
+            value => panic!("Expecting Handshake but got {:?}", value),
         }
+        server.assert_done();
     }
 
-    impl Write for MockTcpStream {
-        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-            self.write_data.append(&mut Vec::from(buf));
-            Ok(buf.len())
-        }
+    #[test]
+    fn test_hangup() {
+        let mut stream = MockServer::new();
+        let error = read_message(&mut stream, deserialize_server_message).unwrap_err();
+        assert_eq!(error.msg, "unexpected hangup");
+    }
 
-        fn flush(&mut self) -> std::io::Result<()> {
-            Ok(())
+    #[test]
+    fn test_oversized_messages() {
+        // Only the header of a frame claiming 4 GB is there, it is refused before reading more
+        let mut header = vec![values::SERVER_MSG_TYPE_READY_FOR_QUERY];
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        let error = read_message(
+            &mut std::io::Cursor::new(header.clone()),
+            deserialize_server_message,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.msg,
+            format!(
+                "Message is 4294967300 bytes but messages are limited to {} bytes",
+                MAX_FRAME_SIZE
+            )
+        );
+        assert!(parse_frame(&header, deserialize_server_message).is_err());
+
+        let message = MicrobatServerMessage::Error(ErrorResponse::new(
+            sqlstate::INTERNAL_ERROR,
+            "bat ".repeat(1000),
+        ));
+        let read_limited = |features: ProtocolFeatures, max_message_size: usize| {
+            let mut stream = std::io::Cursor::new(vec![]);
+            message.send_with(&mut stream, features).unwrap();
+            stream.set_position(0);
+            read_message_limited(
+                &mut stream,
+                deserialize_server_message,
+                |_| true,
+                features,
+                max_message_size,
+            )
+        };
+        let size = message.as_bytes().len();
+        assert_eq!(
+            read_limited(ProtocolFeatures::default(), size).unwrap(),
+            message
+        );
+        assert!(read_limited(ProtocolFeatures::default(), size - 1).is_err());
+        // Compressed frame is limited by the size it decompresses to
+        let compressing = ProtocolFeatures {
+            compression: true,
+            ..ProtocolFeatures::default()
+        };
+        let mut stream = std::io::Cursor::new(vec![]);
+        assert!(message.send_with(&mut stream, compressing).unwrap() < size);
+        assert!(read_limited(compressing, size).is_ok());
+        assert!(read_limited(compressing, size - 1).is_err());
+    }
+
+    #[test]
+    fn test_compressed_messages() {
+        let wide = MicrobatServerMessage::Error(ErrorResponse::new(
+            sqlstate::INTERNAL_ERROR,
+            "bat ".repeat(1000),
+        ));
+        let compressing = ProtocolFeatures {
+            compression: true,
+            ..ProtocolFeatures::default()
+        };
+        let mut stream = std::io::Cursor::new(vec![]);
+        let sent = wide.send_with(&mut stream, compressing).unwrap();
+        assert!(sent < wide.as_bytes().len());
+        // Small messages are sent as is
+        let sent = MicrobatServerMessage::Ready
+            .send_with(&mut stream, compressing)
+            .unwrap();
+        assert_eq!(sent, MicrobatServerMessage::Ready.as_bytes().len());
+        // Not compressed unless negotiated
+        let sent = wide
+            .send_with(&mut stream, ProtocolFeatures::default())
+            .unwrap();
+        assert_eq!(sent, wide.as_bytes().len());
+
+        stream.set_position(0);
+        for expected in [wide.clone(), MicrobatServerMessage::Ready, wide] {
+            assert_eq!(
+                read_message(&mut stream, deserialize_server_message).unwrap(),
+                expected
+            );
         }
     }
 
     #[test]
-    fn test_handshake_via_mock_stream() {
-        let mut write_stream = MockTcpStream {
-            read_data: vec![],
-            write_data: vec![],
+    fn test_checksummed_messages() {
+        let checksummed = ProtocolFeatures {
+            checksums: true,
+            compression: true,
+            ..ProtocolFeatures::default()
         };
-        MicrobatClientMessage::Handshake
-            .send(&mut write_stream)
+        let wide = MicrobatServerMessage::Error(ErrorResponse::new(
+            sqlstate::INTERNAL_ERROR,
+            "bat ".repeat(1000),
+        ));
+        let mut stream = std::io::Cursor::new(vec![]);
+        let sent = MicrobatServerMessage::Ready
+            .send_with(&mut stream, checksummed)
             .unwrap();
-        assert!(write_stream.write_data.len() > 0);
+        assert_eq!(sent, MicrobatServerMessage::Ready.as_bytes().len() + 4);
+        wide.send_with(&mut stream, checksummed).unwrap();
+        let bytes = stream.into_inner();
 
-        let mut read_stream = MockTcpStream {
-            read_data: write_stream.write_data,
-            write_data: vec![],
+        let read = |bytes: &[u8], features| {
+            read_message_with(
+                &mut std::io::Cursor::new(bytes.to_vec()),
+                deserialize_server_message,
+                is_server_message_type,
+                features,
+            )
         };
+        assert_eq!(
+            read(&bytes, checksummed).unwrap(),
+            MicrobatServerMessage::Ready
+        );
+        let (message, length) =
+            parse_frame_with(&bytes[sent..], deserialize_server_message, checksummed)
+                .unwrap()
+                .unwrap();
+        assert_eq!(message, wide);
+        assert_eq!(sent + length, bytes.len());
 
-        let result = read_message(&mut read_stream, deserialize_client_message);
-        assert!(result.is_ok());
-        match result.unwrap() {
-            MicrobatClientMessage::Handshake => (),
-            value => panic!("Expecting Handshake but got {:?}", value),
+        // A flipped bit anywhere in the frame or its checksum is noticed
+        for position in 0..sent {
+            let mut corrupted = bytes.clone();
+            corrupted[position] ^= 0x10;
+            assert!(read(&corrupted, checksummed).is_err());
         }
+        // Out of sync peers fail instead of reading the next frame as a checksum
+        let mut unchecked = MicrobatServerMessage::Ready.as_bytes();
+        unchecked.append(&mut MicrobatServerMessage::Ready.as_bytes());
+        let error = read(&unchecked, checksummed).unwrap_err();
+        assert!(error.msg.contains("out of sync"), "{}", error.msg);
+        assert!(read(&bytes[sent - 4..], ProtocolFeatures::default()).is_err());
     }
 }
 
 #[cfg(test)]
-mod serialization_test_util {
+mod parse_frame_tests {
     use super::*;
+    use crate::data::data_values::{MData, MDataType};
+    use crate::data::table_model::{Column, DataRow, TableSchema};
+    use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
+    use crate::messages::server_messages::{
+        deserialize_server_message, ErrorResponse, MicrobatServerMessage,
+    };
+    use crate::sqlstate;
+
+    fn server_messages() -> Vec<MicrobatServerMessage> {
+        vec![
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+            MicrobatServerMessage::Ready,
+            MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::INTERNAL_ERROR, "error")),
+            MicrobatServerMessage::DataDescription(
+                TableSchema::new(vec![
+                    Column::new(String::from("foo"), MDataType::Integer),
+                    Column::new(String::from("bar"), MDataType::Integer),
+                ])
+                .unwrap(),
+            ),
+            MicrobatServerMessage::DataRow(DataRow::new(vec![
+                MData::Integer(1),
+                MData::Null,
+                MData::Varchar(String::from("foo")),
+                MData::Bool(true),
+            ])),
+            MicrobatServerMessage::InsertResult(5),
+        ]
+    }
+
+    #[test]
+    fn test_parse_frames() {
+        let mut bytes = vec![];
+        for message in server_messages() {
+            bytes.append(&mut message.as_bytes());
+        }
+        let mut parsed = vec![];
+        let mut position = 0;
+        while let Some((message, length)) =
+            parse_frame(&bytes[position..], deserialize_server_message).unwrap()
+        {
+            parsed.push(message);
+            position += length;
+        }
+        assert_eq!(position, bytes.len());
+        assert_eq!(parsed, server_messages());
+
+        let bytes = MicrobatClientMessage::Query(String::from("select 1")).as_bytes();
+        let (message, length) = parse_frame(&bytes, deserialize_client_message)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            message,
+            MicrobatClientMessage::Query(String::from("select 1"))
+        );
+        assert_eq!(length, bytes.len());
+    }
+
+    #[test]
+    fn test_incomplete_frames() {
+        let bytes =
+            MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::INTERNAL_ERROR, "error"))
+                .as_bytes();
+        for end in 0..bytes.len() {
+            assert!(parse_frame(&bytes[..end], deserialize_server_message)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_malformed_frames_do_not_panic() {
+        for message in server_messages() {
+            let bytes = message.as_bytes();
+            // Truncate the payload but keep the frame consistent with it
+            for end in 5..bytes.len() {
+                let mut truncated = bytes[..end].to_vec();
+                truncated[1..5].copy_from_slice(&((end - 5) as u32).to_le_bytes());
+                let _ = parse_frame(&truncated, deserialize_server_message);
+                let _ = parse_frame(&truncated, deserialize_client_message);
+            }
+            // Flip every byte to a few interesting values
+            for position in 0..bytes.len() {
+                for value in [0, 1, 4, b'i', b'v', 0x7f, 0xff] {
+                    let mut mutated = bytes.clone();
+                    mutated[position] = value;
+                    let _ = parse_frame(&mutated, deserialize_server_message);
+                    let _ = parse_frame(&mutated, deserialize_client_message);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod compatibility_tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
+    use crate::messages::frame::FrameWriter;
+    use crate::messages::server_messages::{
+        deserialize_server_message, is_server_message_type, MicrobatServerMessage,
+    };
+
+    fn unknown_message_then_ready() -> Cursor<Vec<u8>> {
+        let mut unknown = FrameWriter::new(b'?');
+        unknown.put_str("from the future").put_u32(42);
+        let mut bytes = unknown.finish();
+        bytes.append(&mut MicrobatServerMessage::Ready.as_bytes());
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn test_unknown_messages_are_skipped() {
+        let mut stream = unknown_message_then_ready();
+        assert_eq!(
+            read_known_message(
+                &mut stream,
+                deserialize_server_message,
+                is_server_message_type
+            )
+            .unwrap(),
+            MicrobatServerMessage::Ready
+        );
+    }
+
+    #[test]
+    fn test_unknown_messages_fail_without_skipping() {
+        let mut stream = unknown_message_then_ready();
+        assert!(read_message(&mut stream, deserialize_server_message).is_err());
+    }
+
+    #[test]
+    fn test_handshake_features() {
+        let plain = MicrobatServerMessage::Handshake(1, ProtocolFeatures::default()).as_bytes();
+        assert_eq!(
+            &plain[5..],
+            values::SERVER_HANDSHAKE_PAYLOAD.as_bytes(),
+            "Handshake without features must be readable by peers that predate features"
+        );
+
+        let with_features =
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all()).as_bytes();
+        assert_eq!(
+            deserialize_server_message(
+                with_features[0],
+                with_features.len() - 5,
+                &with_features[5..]
+            )
+            .unwrap(),
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::all())
+        );
+
+        // Unknown feature bits are ignored
+        let mut future = FrameWriter::new(values::SERVER_MSG_TYPE_HANDSHAKE);
+        future
+            .put_bytes(values::SERVER_HANDSHAKE_PAYLOAD.as_bytes())
+            .put_u32(u32::MAX);
+        let future = future.finish();
+        assert_eq!(
+            deserialize_server_message(future[0], future.len() - 5, &future[5..]).unwrap(),
+            MicrobatServerMessage::Handshake(1, ProtocolFeatures::all())
+        );
+
+        assert_eq!(
+            ProtocolFeatures::all().intersection(&ProtocolFeatures::default()),
+            ProtocolFeatures::default()
+        );
+    }
+
+    #[test]
+    fn test_handshake_version() {
+        // Version is sent after the feature bits even when there are no features
+        let versioned = MicrobatClientMessage::Handshake(7, ProtocolFeatures::default()).as_bytes();
+        assert_eq!(
+            &versioned[5 + values::CLIENT_HANDSHAKE_PAYLOAD.len()..],
+            &[0, 0, 0, 0, 7, 0]
+        );
+        assert_eq!(
+            deserialize_client_message(versioned[0], versioned.len() - 5, &versioned[5..]).unwrap(),
+            MicrobatClientMessage::Handshake(7, ProtocolFeatures::default())
+        );
+
+        let unsupported = MicrobatServerMessage::UnsupportedVersion(2, 3).as_bytes();
+        assert_eq!(
+            deserialize_server_message(unsupported[0], unsupported.len() - 5, &unsupported[5..])
+                .unwrap(),
+            MicrobatServerMessage::UnsupportedVersion(2, 3)
+        );
+    }
+}
+
+#[cfg(test)]
+mod serialization_test_util {
+    use std::str;
 
     pub fn assert_serialisation(
         message_name_for_failures: &str,
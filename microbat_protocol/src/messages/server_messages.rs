@@ -1,34 +1,235 @@
 use crate::{
-    data::{
-        data_values::{deserialize_data_column, MDataType},
-        table_model::{Column, DataRow, TableSchema},
-    },
-    static_values as values, MicrobatProtocolError,
+    data::table_model::{Column, DataRow, TableSchema},
+    sqlstate, static_values as values, MicrobatProtocolError,
 };
 use std::fmt::{Display, Formatter};
 
-use super::MicrobatMessage;
+use super::frame::{FrameReader, FrameWriter};
+use super::{read_handshake, write_handshake, MicrobatMessage, ProtocolFeatures};
 
 /// Enum of messages that can originate from the server
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum MicrobatServerMessage {
-    Handshake,
-    Error(String),
+    /// Accepts the session with the protocol version both peers speak and the requested
+    /// optional features that server supports
+    Handshake(u16, ProtocolFeatures),
+    /// Refuses the session when the client speaks none of the protocol versions from the
+    /// first to the second version. Server closes the connection after sending this.
+    UnsupportedVersion(u16, u16),
+    /// Key of the session, sent between Handshake and Ready to clients speaking
+    /// `BACKEND_KEY_PROTOCOL_VERSION` or later
+    BackendKeyData(BackendKey),
+    /// Name and value of a parameter of the server, see `parameters`. Sent between
+    /// Handshake and Ready to clients speaking `PARAMETER_STATUS_PROTOCOL_VERSION` or later.
+    ParameterStatus(String, String),
+    /// Accepts the credentials of Authenticate
+    AuthOk,
+    /// Refuses the credentials of Authenticate with a reason. Server closes the connection
+    /// after sending this.
+    AuthFailed(String),
+    /// Answers a Ping of the client
+    Pong,
+    /// Answers a Parse that stored its statement
+    ParseComplete,
+    /// Answers a Bind that bound its values to the statement
+    BindComplete,
+    /// Tells a client that negotiated schema notifications that given table was created.
+    /// Server sends these before the next Ready or Pong, never in the middle of a result.
+    SchemaChanged(String),
+    Error(ErrorResponse),
+    /// Warning or notice about a statement that does not fail it, e.g. a value rounded to
+    /// fit its column. Sent before the result of the statement to clients speaking
+    /// `NOTICE_PROTOCOL_VERSION` or later.
+    Notice(ErrorResponse),
     DataDescription(TableSchema),
     DataRow(DataRow),
+    /// Rows of a result set in one message, saving the header and a write per row. Sent
+    /// instead of DataRow messages to clients speaking `BATCH_PROTOCOL_VERSION` or later.
+    DataRowBatch(Vec<DataRow>),
+    /// Part of the payload of a DataRow, for rows with values too large to send in one
+    /// message. Parts are sent in order and the row is complete after the last one. Sent to
+    /// clients speaking `CHUNK_PROTOCOL_VERSION` or later, see `row_chunks`.
+    DataRowChunk {
+        bytes: Vec<u8>,
+        last: bool,
+    },
+    /// Answers COPY ... FROM STDIN with the columns of the table, asking the client to send
+    /// the rows in CopyData messages followed by CopyDone. Sent to clients speaking
+    /// `COPY_PROTOCOL_VERSION` or later.
+    CopyIn(TableSchema),
     InsertResult(u32),
+    /// Answers a statement that neither returns nor inserts rows, e.g. CREATE TABLE, with
+    /// the tag of the statement. Sent to clients speaking `COMMAND_COMPLETE_PROTOCOL_VERSION`
+    /// or later.
+    CommandComplete(String),
     Ready,
+    /// Tells that the session ends because the server is shutting down, sent instead of the
+    /// response to the next message of the client. Server closes the connection after
+    /// sending this. Sent to clients speaking `SHUTDOWN_PROTOCOL_VERSION` or later.
+    ShuttingDown,
+}
+
+/// Identifies a session to the server, for acting on it from another connection, e.g.
+/// cancelling its statement. The secret is random, so knowing the id of a session is not
+/// enough for that.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BackendKey {
+    pub session_id: u32,
+    pub secret: u32,
+}
+
+/// How bad an error is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The statement failed, but the session goes on
+    Error,
+    /// The session ends and server closes the connection
+    Fatal,
+    /// Something the user likely wants to know of, sent in a Notice
+    Warning,
+    /// Something the user may want to know of, sent in a Notice
+    Notice,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "ERROR",
+            Severity::Fatal => "FATAL",
+            Severity::Warning => "WARNING",
+            Severity::Notice => "NOTICE",
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Error or notice sent by the server, with a code from `sqlstate` that clients can branch on
+#[derive(Debug, PartialEq, Clone)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Line and column in the sql where the error is, both starting from 1
+    pub position: Option<(u32, u32)>,
+}
+
+impl ErrorResponse {
+    /// Error of given code failing a statement
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            code: String::from(code),
+            severity: Severity::Error,
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    /// Error of given code ending the session
+    pub fn fatal(code: &str, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            severity: Severity::Fatal,
+            ..ErrorResponse::new(code, message)
+        }
+    }
+
+    /// Warning of given code, the statement goes on
+    pub fn warning(code: &str, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            severity: Severity::Warning,
+            ..ErrorResponse::new(code, message)
+        }
+    }
+
+    fn write_payload(&self, frame: &mut FrameWriter) {
+        frame
+            .put_str(&self.code)
+            .put_str(self.severity.as_str())
+            .put_str(&self.message);
+        if let Some((line, column)) = self.position {
+            frame.put_u32(line).put_u32(column);
+        }
+    }
+
+    /// Reads the payload of an Error or Notice message. Servers that predate error codes send the
+    /// message as is, which reads as an internal error.
+    fn from_payload(bytes: &[u8]) -> Result<Self, MicrobatProtocolError> {
+        let structured = || -> Result<Self, MicrobatProtocolError> {
+            let mut reader = FrameReader::new(bytes);
+            let code = reader.get_str()?;
+            let severity = match reader.get_str()?.as_str() {
+                "ERROR" => Severity::Error,
+                "FATAL" => Severity::Fatal,
+                "WARNING" => Severity::Warning,
+                "NOTICE" => Severity::Notice,
+                severity => {
+                    return Err(MicrobatProtocolError::new(format!(
+                        "Unknown severity {}",
+                        severity
+                    )))
+                }
+            };
+            let message = reader.get_str()?;
+            let mut position = None;
+            if !reader.is_empty() {
+                position = Some((reader.get_u32()?, reader.get_u32()?));
+            }
+            reader.finish()?;
+            Ok(ErrorResponse {
+                code,
+                severity,
+                message,
+                position,
+            })
+        };
+        structured().or_else(|_| {
+            Ok(ErrorResponse::new(
+                sqlstate::INTERNAL_ERROR,
+                String::from_utf8(bytes.to_vec())?,
+            ))
+        })
+    }
+}
+
+/// Message followed by the position, like errors are shown to users
+impl Display for ErrorResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        match self.position {
+            Some((line, column)) => write!(f, " at line {} column {}", line, column),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Display for MicrobatServerMessage {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            MicrobatServerMessage::Handshake => write!(f, "Handshake"),
+            MicrobatServerMessage::Handshake(..) => write!(f, "Handshake"),
+            MicrobatServerMessage::UnsupportedVersion(..) => write!(f, "UnsupportedVersion"),
+            MicrobatServerMessage::BackendKeyData(_) => write!(f, "BackendKeyData"),
+            MicrobatServerMessage::ParameterStatus(..) => write!(f, "ParameterStatus"),
+            MicrobatServerMessage::AuthOk => write!(f, "AuthOk"),
+            MicrobatServerMessage::AuthFailed(_) => write!(f, "AuthFailed"),
+            MicrobatServerMessage::Pong => write!(f, "Pong"),
+            MicrobatServerMessage::ParseComplete => write!(f, "ParseComplete"),
+            MicrobatServerMessage::BindComplete => write!(f, "BindComplete"),
+            MicrobatServerMessage::SchemaChanged(_) => write!(f, "SchemaChanged"),
             MicrobatServerMessage::Error(_) => write!(f, "Error"),
+            MicrobatServerMessage::Notice(_) => write!(f, "Notice"),
             MicrobatServerMessage::DataDescription(_) => write!(f, "DataDescription"),
             MicrobatServerMessage::DataRow(_) => write!(f, "DataRow"),
+            MicrobatServerMessage::DataRowBatch(_) => write!(f, "DataRowBatch"),
+            MicrobatServerMessage::DataRowChunk { .. } => write!(f, "DataRowChunk"),
+            MicrobatServerMessage::CopyIn(_) => write!(f, "CopyIn"),
             MicrobatServerMessage::InsertResult(_) => write!(f, "InsertResult"),
+            MicrobatServerMessage::CommandComplete(_) => write!(f, "CommandComplete"),
             MicrobatServerMessage::Ready => write!(f, "Ready"),
+            MicrobatServerMessage::ShuttingDown => write!(f, "ShuttingDown"),
         }
     }
 }
@@ -36,61 +237,212 @@ impl Display for MicrobatServerMessage {
 impl MicrobatMessage for MicrobatServerMessage {
     fn as_bytes(&self) -> Vec<u8> {
         match self {
-            MicrobatServerMessage::Handshake => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_HANDSHAKE);
-                bytes.append(&mut self.str_with_length(values::SERVER_HANDSHAKE_PAYLOAD));
-                bytes
+            MicrobatServerMessage::Handshake(version, features) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_HANDSHAKE);
+                write_handshake(
+                    &mut frame,
+                    values::SERVER_HANDSHAKE_PAYLOAD,
+                    *version,
+                    features,
+                );
+                frame.finish()
+            }
+            MicrobatServerMessage::UnsupportedVersion(min, max) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_UNSUPPORTED_VERSION);
+                frame.put_u16(*min).put_u16(*max);
+                frame.finish()
+            }
+            MicrobatServerMessage::BackendKeyData(key) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_BACKEND_KEY_DATA);
+                frame.put_u32(key.session_id).put_u32(key.secret);
+                frame.finish()
+            }
+            MicrobatServerMessage::ParameterStatus(name, value) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_PARAMETER_STATUS);
+                frame.put_str(name).put_str(value);
+                frame.finish()
+            }
+            MicrobatServerMessage::AuthOk => {
+                FrameWriter::new(values::SERVER_MSG_TYPE_AUTH_OK).finish()
+            }
+            MicrobatServerMessage::AuthFailed(reason) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_AUTH_FAILED);
+                frame.put_str(reason);
+                frame.finish()
+            }
+            MicrobatServerMessage::Pong => FrameWriter::new(values::SERVER_MSG_TYPE_PONG).finish(),
+            MicrobatServerMessage::ShuttingDown => {
+                FrameWriter::new(values::SERVER_MSG_TYPE_SHUTTING_DOWN).finish()
+            }
+            MicrobatServerMessage::ParseComplete => {
+                FrameWriter::new(values::SERVER_MSG_TYPE_PARSE_COMPLETE).finish()
+            }
+            MicrobatServerMessage::BindComplete => {
+                FrameWriter::new(values::SERVER_MSG_TYPE_BIND_COMPLETE).finish()
+            }
+            MicrobatServerMessage::SchemaChanged(table) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_SCHEMA_CHANGED);
+                frame.put_str(table);
+                frame.finish()
             }
             MicrobatServerMessage::Ready => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_READY_FOR_QUERY);
-                bytes.append(&mut self.str_with_length(values::SERVER_READY_PAYLOAD));
-                bytes
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_READY_FOR_QUERY);
+                frame.put_bytes(values::SERVER_READY_PAYLOAD.as_bytes());
+                frame.finish()
             }
             MicrobatServerMessage::Error(error) => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_ERROR);
-                bytes.append(&mut self.str_with_length(error));
-                bytes
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_ERROR);
+                error.write_payload(&mut frame);
+                frame.finish()
+            }
+            MicrobatServerMessage::Notice(notice) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_NOTICE);
+                notice.write_payload(&mut frame);
+                frame.finish()
             }
             MicrobatServerMessage::DataDescription(row_descriptption) => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_ROW_DESCRIPTION);
-
-                let mut column_bytes: Vec<u8> = vec![];
-                for column in &row_descriptption.columns {
-                    column_bytes.append(&mut self.str_with_length(&column.name));
-                }
-                bytes.append(&mut (column_bytes.len() as u32).to_le_bytes().to_vec());
-                bytes.append(&mut column_bytes);
-                bytes
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_ROW_DESCRIPTION);
+                write_schema(&mut frame, row_descriptption);
+                frame.finish()
             }
             MicrobatServerMessage::DataRow(data_row) => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_DATA_ROW);
-
-                let mut column_bytes: Vec<u8> = vec![];
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_DATA_ROW);
                 for column in &data_row.columns {
-                    let mut data_bytes = column.bytes();
-                    column_bytes.push(column.type_byte());
-                    column_bytes.append(&mut (data_bytes.len() as u32).to_le_bytes().to_vec());
-                    column_bytes.append(&mut data_bytes);
+                    frame.put_data(column);
                 }
-                bytes.append(&mut (column_bytes.len() as u32).to_le_bytes().to_vec());
-                bytes.append(&mut column_bytes);
-                bytes
+                frame.finish()
+            }
+            MicrobatServerMessage::DataRowChunk { bytes, last } => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_DATA_ROW_CHUNK);
+                frame.put_u8(u8::from(*last)).put_bytes(bytes);
+                frame.finish()
+            }
+            MicrobatServerMessage::DataRowBatch(rows) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_DATA_ROW_BATCH);
+                frame.put_rows(rows);
+                frame.finish()
+            }
+            MicrobatServerMessage::CopyIn(schema) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_COPY_IN);
+                write_schema(&mut frame, schema);
+                frame.finish()
             }
             MicrobatServerMessage::InsertResult(size) => {
-                let mut bytes: Vec<u8> = vec![];
-                bytes.push(values::SERVER_MSG_TYPE_INSERT_RESULT);
-                let byte_arr = size.to_le_bytes();
-                bytes.append(&mut (byte_arr.len() as u32).to_le_bytes().to_vec());
-                bytes.append(&mut byte_arr.to_vec());
-                bytes
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_INSERT_RESULT);
+                frame.put_u32(*size);
+                frame.finish()
             }
+            MicrobatServerMessage::CommandComplete(tag) => {
+                let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_COMMAND_COMPLETE);
+                frame.put_str(tag);
+                frame.finish()
+            }
+        }
+    }
+}
+
+/// Writes the columns of a schema as [NAME, TYPE] pairs filling the payload
+fn write_schema(frame: &mut FrameWriter, schema: &TableSchema) {
+    for column in &schema.columns {
+        frame.put_str(&column.name).put_type(&column.data_type);
+    }
+}
+
+fn read_schema(bytes: &[u8]) -> Result<TableSchema, MicrobatProtocolError> {
+    let mut schema = TableSchema { columns: vec![] };
+    let mut reader = FrameReader::new(bytes);
+    while !reader.is_empty() {
+        schema.columns.push(Column {
+            name: reader.get_str()?,
+            data_type: reader.get_type()?,
+        });
+    }
+    Ok(schema)
+}
+
+/// Computes the frame size of given row without serializing it, so rows too large to send
+/// can be refused before building them. The error names the column that takes the row over
+/// `max_frame_size`.
+pub fn check_row_size(
+    row: &DataRow,
+    columns: &[Column],
+    max_frame_size: usize,
+) -> Result<usize, MicrobatProtocolError> {
+    // Message type and length
+    let mut size = 5;
+    for (index, value) in row.columns.iter().enumerate() {
+        // Type byte, length and the value
+        size += 5 + value.byte_len();
+        if size > max_frame_size {
+            let name = columns
+                .get(index)
+                .map_or(format!("at index {}", index), |column| column.name.clone());
+            return Err(MicrobatProtocolError::new(format!(
+                    "Row is too large to send, column {} takes it to {} bytes but frames are limited to {} bytes",
+                    name, size, max_frame_size
+                )));
         }
     }
+    Ok(size)
+}
+
+/// Splits the payload of a DataRow to DataRowChunk messages of at most `chunk_size` bytes,
+/// for sending a row that has values too large to send in one message
+pub fn row_chunks(row: &DataRow, chunk_size: usize) -> Vec<MicrobatServerMessage> {
+    let mut frame = FrameWriter::new(values::SERVER_MSG_TYPE_DATA_ROW);
+    for column in &row.columns {
+        frame.put_data(column);
+    }
+    let payload = frame.finish().split_off(5);
+    let chunks = payload.len().div_ceil(chunk_size.max(1)).max(1);
+    (0..chunks)
+        .map(|index| {
+            let start = index * chunk_size;
+            MicrobatServerMessage::DataRowChunk {
+                bytes: payload[start.min(payload.len())..(start + chunk_size).min(payload.len())]
+                    .to_vec(),
+                last: index == chunks - 1,
+            }
+        })
+        .collect()
+}
+
+/// Reads the payload of a DataRow, e.g one reassembled of DataRowChunk messages
+pub fn deserialize_data_row(bytes: &[u8]) -> Result<DataRow, MicrobatProtocolError> {
+    let mut row = DataRow { columns: vec![] };
+    let mut reader = FrameReader::new(bytes);
+    while !reader.is_empty() {
+        row.columns.push(reader.get_data()?);
+    }
+    Ok(row)
+}
+
+/// True if given byte is the type of some server message
+pub fn is_server_message_type(message_type: u8) -> bool {
+    matches!(
+        message_type,
+        values::SERVER_MSG_TYPE_HANDSHAKE
+            | values::SERVER_MSG_TYPE_READY_FOR_QUERY
+            | values::SERVER_MSG_TYPE_ERROR
+            | values::SERVER_MSG_TYPE_NOTICE
+            | values::SERVER_MSG_TYPE_COPY_IN
+            | values::SERVER_MSG_TYPE_ROW_DESCRIPTION
+            | values::SERVER_MSG_TYPE_DATA_ROW
+            | values::SERVER_MSG_TYPE_DATA_ROW_BATCH
+            | values::SERVER_MSG_TYPE_DATA_ROW_CHUNK
+            | values::SERVER_MSG_TYPE_INSERT_RESULT
+            | values::SERVER_MSG_TYPE_COMMAND_COMPLETE
+            | values::SERVER_MSG_TYPE_UNSUPPORTED_VERSION
+            | values::SERVER_MSG_TYPE_BACKEND_KEY_DATA
+            | values::SERVER_MSG_TYPE_PARAMETER_STATUS
+            | values::SERVER_MSG_TYPE_SHUTTING_DOWN
+            | values::SERVER_MSG_TYPE_AUTH_OK
+            | values::SERVER_MSG_TYPE_PONG
+            | values::SERVER_MSG_TYPE_PARSE_COMPLETE
+            | values::SERVER_MSG_TYPE_BIND_COMPLETE
+            | values::SERVER_MSG_TYPE_SCHEMA_CHANGED
+            | values::SERVER_MSG_TYPE_AUTH_FAILED
+    )
 }
 
 pub fn deserialize_server_message(
@@ -99,62 +451,126 @@ pub fn deserialize_server_message(
     bytes: &[u8],
 ) -> Result<MicrobatServerMessage, MicrobatProtocolError> {
     if length != bytes.len() {
-        return Err(MicrobatProtocolError {
-            msg: format!(
-                "Byte mismatch error. Expecting {} bytes but received {} bytes",
-                length,
-                bytes.len()
-            ),
-        });
+        return Err(MicrobatProtocolError::new(format!(
+            "Byte mismatch error. Expecting {} bytes but received {} bytes",
+            length,
+            bytes.len()
+        )));
     }
     match message_type {
-        values::SERVER_MSG_TYPE_HANDSHAKE => Ok(MicrobatServerMessage::Handshake),
+        values::SERVER_MSG_TYPE_HANDSHAKE => {
+            let (version, features) = read_handshake(bytes, values::SERVER_HANDSHAKE_PAYLOAD)?;
+            Ok(MicrobatServerMessage::Handshake(version, features))
+        }
+        values::SERVER_MSG_TYPE_UNSUPPORTED_VERSION => {
+            let mut reader = FrameReader::new(bytes);
+            let min = reader.get_u16()?;
+            let max = reader.get_u16()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::UnsupportedVersion(min, max))
+        }
+        values::SERVER_MSG_TYPE_BACKEND_KEY_DATA => {
+            let mut reader = FrameReader::new(bytes);
+            let key = BackendKey {
+                session_id: reader.get_u32()?,
+                secret: reader.get_u32()?,
+            };
+            reader.finish()?;
+            Ok(MicrobatServerMessage::BackendKeyData(key))
+        }
+        values::SERVER_MSG_TYPE_PARAMETER_STATUS => {
+            let mut reader = FrameReader::new(bytes);
+            let name = reader.get_str()?;
+            let value = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::ParameterStatus(name, value))
+        }
         values::SERVER_MSG_TYPE_READY_FOR_QUERY => Ok(MicrobatServerMessage::Ready),
-        values::SERVER_MSG_TYPE_ERROR => Ok(MicrobatServerMessage::Error(String::from_utf8(
-            bytes.to_vec(),
-        )?)),
+        values::SERVER_MSG_TYPE_AUTH_OK => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatServerMessage::AuthOk)
+        }
+        values::SERVER_MSG_TYPE_PONG => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatServerMessage::Pong)
+        }
+        values::SERVER_MSG_TYPE_SHUTTING_DOWN => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatServerMessage::ShuttingDown)
+        }
+        values::SERVER_MSG_TYPE_PARSE_COMPLETE => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatServerMessage::ParseComplete)
+        }
+        values::SERVER_MSG_TYPE_BIND_COMPLETE => {
+            FrameReader::new(bytes).finish()?;
+            Ok(MicrobatServerMessage::BindComplete)
+        }
+        values::SERVER_MSG_TYPE_SCHEMA_CHANGED => {
+            let mut reader = FrameReader::new(bytes);
+            let table = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::SchemaChanged(table))
+        }
+        values::SERVER_MSG_TYPE_AUTH_FAILED => {
+            let mut reader = FrameReader::new(bytes);
+            let reason = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::AuthFailed(reason))
+        }
+        values::SERVER_MSG_TYPE_ERROR => Ok(MicrobatServerMessage::Error(
+            ErrorResponse::from_payload(bytes)?,
+        )),
+        values::SERVER_MSG_TYPE_NOTICE => Ok(MicrobatServerMessage::Notice(
+            ErrorResponse::from_payload(bytes)?,
+        )),
         values::SERVER_MSG_TYPE_ROW_DESCRIPTION => {
-            let mut rows = TableSchema { columns: vec![] };
-            let mut pointer: usize = 0;
-            while pointer < bytes.len() {
-                let column_length =
-                    u32::from_le_bytes(bytes[pointer..pointer + 4].try_into().unwrap()) as usize;
-                let name =
-                    String::from_utf8(bytes[pointer + 4..(pointer + 4 + column_length)].to_vec())?;
-                rows.columns.push(Column {
-                    name,
-                    data_type: MDataType::Integer,
-                }); // TODO: this is WRONG!s
-                pointer += column_length + 4;
-            }
-            Ok(MicrobatServerMessage::DataDescription(rows))
+            Ok(MicrobatServerMessage::DataDescription(read_schema(bytes)?))
         }
         values::SERVER_MSG_TYPE_DATA_ROW => {
-            let mut rows = DataRow { columns: vec![] };
-            let mut pointer: usize = 0;
-            while pointer < bytes.len() {
-                let column_type = bytes[pointer];
-                let column_length =
-                    u32::from_le_bytes(bytes[pointer + 1..pointer + 5].try_into().unwrap())
-                        as usize;
-                rows.columns.push(deserialize_data_column(
-                    column_type,
-                    &bytes[pointer + 5..(pointer + 5 + column_length)],
-                )?);
-                pointer += column_length + 5;
-            }
-            Ok(MicrobatServerMessage::DataRow(rows))
+            Ok(MicrobatServerMessage::DataRow(deserialize_data_row(bytes)?))
         }
-        values::SERVER_MSG_TYPE_INSERT_RESULT => Ok(MicrobatServerMessage::InsertResult(
-            u32::from_le_bytes(bytes.try_into().unwrap()),
-        )),
-        unknown => Err(MicrobatProtocolError {
-            msg: format!(
-                "Received unknown message type: {} (ascii: {})",
-                unknown,
-                char::from(unknown)
-            ),
-        }),
+        values::SERVER_MSG_TYPE_DATA_ROW_CHUNK => {
+            let mut reader = FrameReader::new(bytes);
+            let last = match reader.get_u8()? {
+                0 => false,
+                1 => true,
+                flag => {
+                    return Err(MicrobatProtocolError::new(format!(
+                        "Invalid last chunk flag {}",
+                        flag
+                    )))
+                }
+            };
+            Ok(MicrobatServerMessage::DataRowChunk {
+                bytes: reader.get_rest().to_vec(),
+                last,
+            })
+        }
+        values::SERVER_MSG_TYPE_DATA_ROW_BATCH => {
+            let mut reader = FrameReader::new(bytes);
+            let rows = reader.get_rows()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::DataRowBatch(rows))
+        }
+        values::SERVER_MSG_TYPE_COPY_IN => Ok(MicrobatServerMessage::CopyIn(read_schema(bytes)?)),
+        values::SERVER_MSG_TYPE_INSERT_RESULT => {
+            let mut reader = FrameReader::new(bytes);
+            let size = reader.get_u32()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::InsertResult(size))
+        }
+        values::SERVER_MSG_TYPE_COMMAND_COMPLETE => {
+            let mut reader = FrameReader::new(bytes);
+            let tag = reader.get_str()?;
+            reader.finish()?;
+            Ok(MicrobatServerMessage::CommandComplete(tag))
+        }
+        unknown => Err(MicrobatProtocolError::new(format!(
+            "Received unknown message type: {} (ascii: {})",
+            unknown,
+            char::from(unknown)
+        ))),
     }
 }
 
@@ -162,16 +578,20 @@ pub fn deserialize_server_message(
 mod server_message_tests {
 
     use crate::{
-        data::data_values::MData, messages::serialization_test_util::assert_serialisation,
+        data::data_values::{MData, MDataType},
+        messages::{serialization_test_util::assert_serialisation, MAX_FRAME_SIZE},
     };
 
     use super::*;
+    use crate::messages::PROTOCOL_VERSION;
+    use crate::parameters;
 
     #[test]
     fn test_server_message_serialisation() {
         assert_serialisation(
             "server handshake",
-            MicrobatServerMessage::Handshake.as_bytes(),
+            // Version 1 without features is the handshake of peers that predate them
+            MicrobatServerMessage::Handshake(1, ProtocolFeatures::default()).as_bytes(),
             values::SERVER_MSG_TYPE_HANDSHAKE,
             values::SERVER_HANDSHAKE_PAYLOAD.len(),
             Some(values::SERVER_HANDSHAKE_PAYLOAD),
@@ -185,10 +605,11 @@ mod server_message_tests {
         );
         assert_serialisation(
             "server error",
-            MicrobatServerMessage::Error(String::from("error")).as_bytes(),
+            MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::SYNTAX_ERROR, "error"))
+                .as_bytes(),
             values::SERVER_MSG_TYPE_ERROR,
-            5,
-            Some("error"),
+            27, // Length prefixed code, severity and message
+            None,
         );
         assert_serialisation(
             "server row description",
@@ -200,7 +621,7 @@ mod server_message_tests {
             })
             .as_bytes(),
             values::SERVER_MSG_TYPE_ROW_DESCRIPTION,
-            7, // We just know this expected size of 7 bytes
+            8, // Length and name of the column and its type
             None,
         );
         assert_serialisation(
@@ -232,17 +653,209 @@ mod server_message_tests {
         )
     }
 
+    #[test]
+    fn test_server_error_deserialisation() {
+        let mut positioned = ErrorResponse::new(sqlstate::SYNTAX_ERROR, "unexpected token FROM");
+        positioned.position = Some((1, 26));
+        for error in [
+            positioned,
+            ErrorResponse::fatal(sqlstate::PROTOCOL_VIOLATION, "bad message"),
+        ] {
+            let bytes = MicrobatServerMessage::Error(error.clone()).as_bytes();
+            assert_eq!(
+                deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+                MicrobatServerMessage::Error(error)
+            );
+        }
+
+        // Servers that predate error codes send plain text
+        assert_eq!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_ERROR, 7, b"no good").unwrap(),
+            MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::INTERNAL_ERROR, "no good"))
+        );
+    }
+
+    #[test]
+    fn test_server_notice_deserialisation() {
+        let mut notice = ErrorResponse::warning(sqlstate::WARNING, "value rounded");
+        let bytes = MicrobatServerMessage::Notice(notice.clone()).as_bytes();
+        assert_eq!(bytes[0], values::SERVER_MSG_TYPE_NOTICE);
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            MicrobatServerMessage::Notice(notice.clone())
+        );
+
+        notice.severity = Severity::Notice;
+        notice.position = Some((2, 3));
+        let bytes = MicrobatServerMessage::Notice(notice.clone()).as_bytes();
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            MicrobatServerMessage::Notice(notice)
+        );
+    }
+
+    #[test]
+    fn test_server_auth_deserialisation() {
+        for message in [
+            MicrobatServerMessage::AuthOk,
+            MicrobatServerMessage::AuthFailed(String::from("wrong password")),
+            MicrobatServerMessage::SchemaChanged(String::from("people")),
+            MicrobatServerMessage::CommandComplete(String::from("CREATE TABLE")),
+        ] {
+            let bytes = message.as_bytes();
+            assert_eq!(
+                deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_server_pong_deserialisation() {
+        let bytes = MicrobatServerMessage::Pong.as_bytes();
+        assert_eq!(bytes, vec![values::SERVER_MSG_TYPE_PONG, 0, 0, 0, 0]);
+        assert_eq!(
+            deserialize_server_message(bytes[0], 0, &[]).unwrap(),
+            MicrobatServerMessage::Pong
+        );
+        assert!(deserialize_server_message(values::SERVER_MSG_TYPE_PONG, 1, b"x").is_err());
+    }
+
+    #[test]
+    fn test_server_shutting_down_deserialisation() {
+        let bytes = MicrobatServerMessage::ShuttingDown.as_bytes();
+        assert_eq!(
+            bytes,
+            vec![values::SERVER_MSG_TYPE_SHUTTING_DOWN, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            deserialize_server_message(bytes[0], 0, &[]).unwrap(),
+            MicrobatServerMessage::ShuttingDown
+        );
+        assert!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_SHUTTING_DOWN, 1, b"x").is_err()
+        );
+    }
+
+    #[test]
+    fn test_server_backend_key_data_deserialisation() {
+        let message = MicrobatServerMessage::BackendKeyData(BackendKey {
+            session_id: 7,
+            secret: 0xdeadbeef,
+        });
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], values::SERVER_MSG_TYPE_BACKEND_KEY_DATA);
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            message
+        );
+        assert!(
+            deserialize_server_message(bytes[0], bytes.len() - 6, &bytes[5..bytes.len() - 1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_server_parameter_status_deserialisation() {
+        let message = MicrobatServerMessage::ParameterStatus(
+            String::from(parameters::SERVER_VERSION),
+            String::from("0.1.0"),
+        );
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], values::SERVER_MSG_TYPE_PARAMETER_STATUS);
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            message
+        );
+        assert!(
+            deserialize_server_message(bytes[0], bytes.len() - 6, &bytes[5..bytes.len() - 1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_server_copy_in_deserialisation() {
+        let message = MicrobatServerMessage::CopyIn(
+            TableSchema::new(vec![
+                Column::new(String::from("id"), MDataType::Integer),
+                Column::new(
+                    String::from("price"),
+                    MDataType::Decimal {
+                        precision: 6,
+                        scale: 2,
+                    },
+                ),
+            ])
+            .unwrap(),
+        );
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], values::SERVER_MSG_TYPE_COPY_IN);
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            message
+        );
+        assert!(
+            deserialize_server_message(bytes[0], bytes.len() - 6, &bytes[5..bytes.len() - 1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_server_extended_query_deserialisation() {
+        for message in [
+            MicrobatServerMessage::ParseComplete,
+            MicrobatServerMessage::BindComplete,
+        ] {
+            let bytes = message.as_bytes();
+            assert_eq!(bytes.len(), 5);
+            assert!(is_server_message_type(bytes[0]));
+            assert_eq!(
+                deserialize_server_message(bytes[0], 0, &[]).unwrap(),
+                message
+            );
+            assert!(deserialize_server_message(bytes[0], 1, b"x").is_err());
+        }
+    }
+
     #[test]
     fn test_server_handshake_deserialisation() {
-        let handshake_bytes = MicrobatServerMessage::Handshake.as_bytes();
+        let handshake_bytes =
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+                .as_bytes();
         let length = u32::from_le_bytes(handshake_bytes[1..5].try_into().unwrap()) as usize;
         let deserialized =
             deserialize_server_message(handshake_bytes[0], length, &handshake_bytes[5..]).unwrap();
-        assert_eq!(deserialized, MicrobatServerMessage::Handshake);
+        assert_eq!(
+            deserialized,
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+        );
     }
 
     // TODO: cleanly assert all serialize->deserialize streams...
 
+    #[test]
+    fn test_server_row_description_deserialization() {
+        let description = MicrobatServerMessage::DataDescription(
+            TableSchema::new(vec![
+                Column::new(String::from("id"), MDataType::BigInt),
+                Column::new(String::from("name"), MDataType::Varchar),
+                Column::new(
+                    String::from("price"),
+                    MDataType::Decimal {
+                        precision: 6,
+                        scale: 2,
+                    },
+                ),
+            ])
+            .unwrap(),
+        );
+        let bytes = description.as_bytes();
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            description
+        );
+    }
+
     #[test]
     fn test_server_datarow_deserialization_varchar() {
         let data_row = DataRow {
@@ -279,15 +892,112 @@ mod server_message_tests {
         );
     }
 
+    #[test]
+    fn test_server_datarow_batch_deserialization() {
+        let batch = MicrobatServerMessage::DataRowBatch(vec![
+            DataRow::new(vec![MData::Integer(1), MData::Varchar(String::from("foo"))]),
+            DataRow::new(vec![MData::Null, MData::Varchar(String::new())]),
+            DataRow::new(vec![]),
+        ]);
+        let bytes = batch.as_bytes();
+        assert_eq!(
+            deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            batch
+        );
+
+        // Batch of two rows carrying only one
+        let truncated =
+            MicrobatServerMessage::DataRowBatch(vec![DataRow::new(vec![MData::Integer(1)])])
+                .as_bytes();
+        let mut bytes = truncated[5..].to_vec();
+        bytes[0] = 2;
+        assert!(deserialize_server_message(
+            values::SERVER_MSG_TYPE_DATA_ROW_BATCH,
+            bytes.len(),
+            &bytes
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_row_chunks() {
+        let row = DataRow::new(vec![
+            MData::Integer(1),
+            MData::Varchar("x".repeat(100)),
+            MData::Null,
+        ]);
+        let chunks = row_chunks(&row, 30);
+        // Payload of 9 + 105 + 5 bytes
+        assert_eq!(chunks.len(), 4);
+        let mut payload = vec![];
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let bytes = chunk.as_bytes();
+            let deserialized =
+                deserialize_server_message(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap();
+            assert_eq!(deserialized, chunk);
+            match chunk {
+                MicrobatServerMessage::DataRowChunk { bytes, last } => {
+                    assert_eq!(last, index == 3);
+                    payload.extend(bytes);
+                }
+                message => panic!("Expecting DataRowChunk but got {}", message),
+            }
+        }
+        assert_eq!(deserialize_data_row(&payload).unwrap(), row);
+
+        assert_eq!(
+            row_chunks(&DataRow::new(vec![]), 30),
+            vec![MicrobatServerMessage::DataRowChunk {
+                bytes: vec![],
+                last: true
+            }]
+        );
+        assert!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_DATA_ROW_CHUNK, 1, &[2]).is_err()
+        );
+    }
+
     #[test]
     fn test_invalid_server_deserialization() {
         assert!(deserialize_server_message(b'\0', 0, &[]).is_err());
         assert!(deserialize_server_message(b'h', 0, &[]).is_err());
-        assert!(deserialize_server_message(values::SERVER_MSG_TYPE_HANDSHAKE, 0, &[b't']).is_err());
-        assert!(deserialize_server_message(values::SERVER_MSG_TYPE_HANDSHAKE, 5, &[b't']).is_err());
+        assert!(deserialize_server_message(values::SERVER_MSG_TYPE_HANDSHAKE, 0, b"t").is_err());
+        assert!(deserialize_server_message(values::SERVER_MSG_TYPE_HANDSHAKE, 5, b"t").is_err());
         assert!(deserialize_server_message(values::SERVER_MSG_TYPE_ERROR, 2, &[0, 159]).is_err());
     }
 
+    #[test]
+    fn test_truncated_server_messages() {
+        assert!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_ROW_DESCRIPTION, 2, &[5, 0])
+                .is_err()
+        );
+        assert!(deserialize_server_message(
+            values::SERVER_MSG_TYPE_ROW_DESCRIPTION,
+            6,
+            &[5, 0, 0, 0, b'a', b'b']
+        )
+        .is_err());
+        assert!(deserialize_server_message(
+            values::SERVER_MSG_TYPE_ROW_DESCRIPTION,
+            6,
+            &[2, 0, 0, 0, b'i', b'd']
+        )
+        .is_err());
+        assert!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_DATA_ROW, 3, &[b'i', 4, 0]).is_err()
+        );
+        assert!(deserialize_server_message(
+            values::SERVER_MSG_TYPE_DATA_ROW,
+            7,
+            &[b'i', 4, 0, 0, 0, 1, 2]
+        )
+        .is_err());
+        assert!(
+            deserialize_server_message(values::SERVER_MSG_TYPE_INSERT_RESULT, 2, &[1, 2]).is_err()
+        );
+    }
+
     #[test]
     fn test_deserialization_fails_if_length_and_bytes_do_not_match() {
         assert!(
@@ -307,4 +1017,42 @@ mod server_message_tests {
             deserialize_server_message(values::CLIENT_MSG_TYPE_DISCONNECT, 5, &[b'0', 5]).is_err()
         );
     }
+
+    #[test]
+    fn test_check_row_size() {
+        let row = DataRow::new(vec![
+            MData::Integer(1),
+            MData::Varchar(String::from("hello")),
+            MData::Null,
+        ]);
+        let columns = vec![
+            Column::new(String::from("id"), MDataType::Integer),
+            Column::new(String::from("name"), MDataType::Varchar),
+        ];
+        let size = row_size(&row);
+        assert_eq!(check_row_size(&row, &columns, size).unwrap(), size);
+        assert_eq!(
+            check_row_size(&row, &columns, 15).unwrap_err().msg,
+            "Row is too large to send, column name takes it to 24 bytes but frames are limited to 15 bytes"
+        );
+        assert_eq!(
+            check_row_size(&row, &columns, size - 1).unwrap_err().msg,
+            "Row is too large to send, column at index 2 takes it to 29 bytes but frames are limited to 28 bytes"
+        );
+    }
+
+    fn row_size(row: &DataRow) -> usize {
+        MicrobatServerMessage::DataRow(row.clone()).as_bytes().len()
+    }
+
+    #[test]
+    fn test_too_large_message_is_not_sent() {
+        let mut stream = std::io::Cursor::new(vec![]);
+        let error = MicrobatServerMessage::Error(ErrorResponse::new(
+            sqlstate::INTERNAL_ERROR,
+            "x".repeat(MAX_FRAME_SIZE),
+        ));
+        assert!(error.send(&mut stream).is_err());
+        assert!(stream.get_ref().is_empty());
+    }
 }
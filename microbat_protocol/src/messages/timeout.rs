@@ -0,0 +1,136 @@
+//! Timeouts of reads and writes, so a stalled peer can't block a connection forever.
+//!
+//! Timeouts are set on the stream, usually once per connection with
+//! `ConnectionTimeouts::apply`. A read or write that times out fails with an error whose
+//! `is_timeout` is true, and the connection is then out of sync, so it should be closed.
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::{read_message_with, ProtocolFeatures};
+use crate::testing::MockServer;
+use crate::MicrobatProtocolError;
+
+/// Streams whose reads and writes can time out, like a TcpStream. None waits forever.
+pub trait Timeouts {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Timeouts for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Reads from MockServer never block, so there is nothing to time out
+impl Timeouts for MockServer {
+    fn set_read_timeout(&self, _: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Timeouts of a connection, None waits forever
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ConnectionTimeouts {
+    /// Longest wait for the next bytes of the peer, so also for its next message
+    pub read: Option<Duration>,
+    /// Longest wait for the peer to take the bytes sent to it
+    pub write: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    /// Sets these timeouts on given stream for all of its later reads and writes
+    pub fn apply(&self, stream: &impl Timeouts) -> Result<(), MicrobatProtocolError> {
+        stream.set_read_timeout(self.read)?;
+        stream.set_write_timeout(self.write)?;
+        Ok(())
+    }
+}
+
+/// Reads message like `read_message_with`, failing if it doesn't arrive in given time. The
+/// timeout stays set on the stream for later reads.
+pub fn read_message_timeout<T>(
+    stream: &mut (impl Read + Write + Unpin + Timeouts),
+    deserializer: fn(u8, usize, &[u8]) -> Result<T, MicrobatProtocolError>,
+    is_known: fn(u8) -> bool,
+    features: ProtocolFeatures,
+    timeout: Option<Duration>,
+) -> Result<T, MicrobatProtocolError> {
+    stream.set_read_timeout(timeout)?;
+    read_message_with(stream, deserializer, is_known, features)
+}
+
+#[cfg(test)]
+mod timeout_tests {
+    use super::*;
+    use crate::messages::server_messages::{deserialize_server_message, MicrobatServerMessage};
+    use crate::messages::MicrobatMessage;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn test_read_times_out() {
+        let (mut client, mut server) = connection();
+        let start = Instant::now();
+        let error = read_message_timeout(
+            &mut client,
+            deserialize_server_message,
+            |_| true,
+            ProtocolFeatures::default(),
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap_err();
+        assert!(error.is_timeout(), "{}", error.msg);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        MicrobatServerMessage::Ready
+            .send_timeout(
+                &mut server,
+                ProtocolFeatures::default(),
+                Some(Duration::from_secs(5)),
+            )
+            .unwrap();
+        let message = read_message_timeout(
+            &mut client,
+            deserialize_server_message,
+            |_| true,
+            ProtocolFeatures::default(),
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap();
+        assert_eq!(message, MicrobatServerMessage::Ready);
+    }
+
+    #[test]
+    fn test_connection_timeouts() {
+        let (client, _server) = connection();
+        let timeouts = ConnectionTimeouts {
+            read: Some(Duration::from_secs(1)),
+            write: None,
+        };
+        timeouts.apply(&client).unwrap();
+        assert_eq!(client.read_timeout().unwrap(), timeouts.read);
+        assert_eq!(client.write_timeout().unwrap(), None);
+        assert!(
+            !MicrobatProtocolError::from(std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+                .is_timeout()
+        );
+    }
+}
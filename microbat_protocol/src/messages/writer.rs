@@ -0,0 +1,189 @@
+use std::io::Write;
+
+use super::{frame_message, MicrobatMessage, ProtocolFeatures};
+use crate::MicrobatProtocolError;
+
+/// Bytes buffered by `MessageWriter::new` before they are written to the stream
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Buffers sent messages and writes them to the stream together, cutting the writes of a
+/// response of many small messages, like rows, to a few large ones.
+///
+/// Messages are framed with given features as `MicrobatMessage::send_with` frames them.
+/// Buffered messages are written when the buffer fills up and when `flush` is called, which
+/// must be done before waiting for the peer to respond. Dropping the writer flushes it too,
+/// but ignores errors.
+///
+/// ```
+/// use microbat_protocol::messages::server_messages::MicrobatServerMessage;
+/// use microbat_protocol::messages::writer::MessageWriter;
+/// use microbat_protocol::messages::{MicrobatMessage, ProtocolFeatures};
+///
+/// let mut stream = vec![];
+/// let mut writer = MessageWriter::new(&mut stream, ProtocolFeatures::default());
+/// writer.send(&MicrobatServerMessage::InsertResult(1)).unwrap();
+/// writer.send(&MicrobatServerMessage::Ready).unwrap();
+/// writer.flush().unwrap();
+/// drop(writer);
+/// assert_eq!(
+///     stream,
+///     [
+///         MicrobatServerMessage::InsertResult(1).as_bytes(),
+///         MicrobatServerMessage::Ready.as_bytes()
+///     ]
+///     .concat()
+/// );
+/// ```
+pub struct MessageWriter<W: Write> {
+    stream: W,
+    features: ProtocolFeatures,
+    buffer: Vec<u8>,
+    capacity: usize,
+}
+
+impl<W: Write> MessageWriter<W> {
+    /// Writer buffering up to `DEFAULT_WRITE_BUFFER_SIZE` bytes
+    pub fn new(stream: W, features: ProtocolFeatures) -> Self {
+        MessageWriter::with_capacity(stream, features, DEFAULT_WRITE_BUFFER_SIZE)
+    }
+
+    /// Writer buffering up to given amount of bytes. Larger messages are written as is.
+    pub fn with_capacity(stream: W, features: ProtocolFeatures, capacity: usize) -> Self {
+        MessageWriter {
+            stream,
+            features,
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Buffers given message, writing the buffer if it fills up. Returns the amount of bytes
+    /// the message takes in the stream.
+    pub fn send(&mut self, message: &impl MicrobatMessage) -> Result<usize, MicrobatProtocolError> {
+        let bytes = frame_message(message.as_bytes(), self.features)?;
+        if self.buffer.len() + bytes.len() > self.capacity {
+            self.write_buffer()?;
+        }
+        if bytes.len() > self.capacity {
+            self.stream.write_all(&bytes)?;
+        } else {
+            self.buffer.extend_from_slice(&bytes);
+        }
+        Ok(bytes.len())
+    }
+
+    /// Writes the buffered messages and flushes the stream
+    pub fn flush(&mut self) -> Result<(), MicrobatProtocolError> {
+        self.write_buffer()?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Amount of bytes waiting to be written
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn write_buffer(&mut self) -> Result<(), MicrobatProtocolError> {
+        if !self.buffer.is_empty() {
+            // Cleared even if writing fails, the stream is broken then anyway
+            let result = self.stream.write_all(&self.buffer);
+            self.buffer.clear();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for MessageWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod writer_tests {
+    use super::*;
+    use crate::data::data_values::MData;
+    use crate::data::table_model::DataRow;
+    use crate::messages::read_message_with;
+    use crate::messages::server_messages::{deserialize_server_message, MicrobatServerMessage};
+
+    /// Stream counting the writes to it
+    #[derive(Default)]
+    struct CountingStream {
+        bytes: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingStream {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn row(value: i32) -> MicrobatServerMessage {
+        MicrobatServerMessage::DataRow(DataRow::new(vec![MData::Integer(value)]))
+    }
+
+    #[test]
+    fn test_messages_are_written_together() {
+        let mut stream = CountingStream::default();
+        let mut writer = MessageWriter::new(&mut stream, ProtocolFeatures::all());
+        for value in 0..100 {
+            writer.send(&row(value)).unwrap();
+        }
+        assert!(writer.buffered() > 0);
+        writer.flush().unwrap();
+        assert_eq!(writer.buffered(), 0);
+        drop(writer);
+        assert_eq!(stream.writes, 1);
+
+        let mut bytes = std::io::Cursor::new(stream.bytes);
+        for value in 0..100 {
+            let message = read_message_with(
+                &mut bytes,
+                deserialize_server_message,
+                |_| true,
+                ProtocolFeatures::all(),
+            )
+            .unwrap();
+            assert_eq!(message, row(value));
+        }
+    }
+
+    #[test]
+    fn test_full_buffer_is_written() {
+        let size = row(0).as_bytes().len();
+        let mut stream = CountingStream::default();
+        let mut writer =
+            MessageWriter::with_capacity(&mut stream, ProtocolFeatures::default(), size * 2);
+        for value in 0..5 {
+            writer.send(&row(value)).unwrap();
+        }
+        assert_eq!(writer.buffered(), size);
+        // Larger than the buffer, written after the buffered row
+        let wide = MicrobatServerMessage::SchemaChanged("bat".repeat(100));
+        writer.send(&wide).unwrap();
+        assert_eq!(writer.buffered(), 0);
+        drop(writer);
+        assert_eq!(stream.writes, 4);
+        assert!(stream.bytes.ends_with(&wide.as_bytes()));
+        assert_eq!(stream.bytes.len(), size * 5 + wide.as_bytes().len());
+    }
+
+    #[test]
+    fn test_dropping_writer_flushes() {
+        let mut stream = vec![];
+        MessageWriter::new(&mut stream, ProtocolFeatures::default())
+            .send(&MicrobatServerMessage::Ready)
+            .unwrap();
+        assert_eq!(stream, MicrobatServerMessage::Ready.as_bytes());
+    }
+}
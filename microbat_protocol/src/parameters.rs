@@ -0,0 +1,26 @@
+//! Names of the parameters servers report in `ParameterStatus` messages.
+//!
+//! Servers send their parameters after the handshake, so clients can adapt to the server
+//! without querying it. Clients should ignore parameters they don't know, as newer servers
+//! may report more of them.
+//!
+//! Sessions change some of the parameters with `SET name = value`, and servers tell of the
+//! new value with `ParameterStatus` too.
+
+/// Version of the server, e.g. `0.1.0`
+pub const SERVER_VERSION: &str = "server_version";
+
+/// Database the session uses
+pub const DATABASE: &str = "database";
+
+/// Encoding of the text the server sends and expects, always `UTF8`
+pub const CLIENT_ENCODING: &str = "client_encoding";
+
+/// Text clients show for null values, empty by default
+pub const NULL_DISPLAY: &str = "null_display";
+
+/// Format of dates and timestamps, always `ISO`
+pub const DATE_STYLE: &str = "datestyle";
+
+/// Milliseconds a statement may run before it is cancelled, 0 for no limit
+pub const STATEMENT_TIMEOUT: &str = "statement_timeout";
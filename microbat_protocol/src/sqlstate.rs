@@ -0,0 +1,74 @@
+//! Error codes sent in `ErrorResponse`, following SQLSTATE of PostgreSQL.
+//!
+//! A code has five characters and the first two of them tell the class of the error, so
+//! clients can branch on e.g. syntax errors without knowing every code.
+//!
+//! ```
+//! use microbat_protocol::sqlstate::{self, class};
+//!
+//! assert_eq!(class(sqlstate::UNDEFINED_TABLE), class(sqlstate::SYNTAX_ERROR));
+//! assert_eq!(class(sqlstate::DIVISION_BY_ZERO), "22");
+//! ```
+
+/// Class of warnings, sent in notices of statements that succeed
+pub const WARNING: &str = "01000";
+
+/// Class of features the server or the client doesn't support
+pub const FEATURE_NOT_SUPPORTED: &str = "0A000";
+
+/// Class of connection errors, like messages that can't be read
+pub const PROTOCOL_VIOLATION: &str = "08P01";
+
+/// Class of data exceptions, i.e values that can't be computed or stored
+pub const DATA_EXCEPTION: &str = "22000";
+pub const NUMERIC_VALUE_OUT_OF_RANGE: &str = "22003";
+pub const INVALID_DATETIME_FORMAT: &str = "22007";
+pub const DIVISION_BY_ZERO: &str = "22012";
+pub const INVALID_PARAMETER_VALUE: &str = "22023";
+pub const INVALID_TEXT_REPRESENTATION: &str = "22P02";
+pub const BAD_COPY_FILE_FORMAT: &str = "22P04";
+
+/// Class of references to prepared statements that don't exist
+pub const INVALID_SQL_STATEMENT_NAME: &str = "26000";
+
+/// Class of authorization errors
+pub const INVALID_AUTHORIZATION_SPECIFICATION: &str = "28000";
+
+/// Class of syntax errors, references to things that don't exist and access denied to them
+pub const INSUFFICIENT_PRIVILEGE: &str = "42501";
+pub const SYNTAX_ERROR: &str = "42601";
+pub const DUPLICATE_COLUMN: &str = "42701";
+pub const UNDEFINED_COLUMN: &str = "42703";
+pub const GROUPING_ERROR: &str = "42803";
+pub const UNDEFINED_OBJECT: &str = "42704";
+pub const DATATYPE_MISMATCH: &str = "42804";
+pub const WRONG_OBJECT_TYPE: &str = "42809";
+pub const UNDEFINED_FUNCTION: &str = "42883";
+pub const UNDEFINED_TABLE: &str = "42P01";
+pub const UNDEFINED_PARAMETER: &str = "42P02";
+pub const DUPLICATE_TABLE: &str = "42P07";
+
+/// Class of resources running out, like connections of the server
+pub const TOO_MANY_CONNECTIONS: &str = "53300";
+
+/// Class of limits of the implementation, like the size of a message
+pub const PROGRAM_LIMIT_EXCEEDED: &str = "54000";
+
+/// Class of objects in a state that doesn't allow the operation
+pub const CANT_CHANGE_RUNTIME_PARAM: &str = "55P02";
+
+/// Class of operator intervention, like the server shutting down
+pub const QUERY_CANCELED: &str = "57014";
+pub const ADMIN_SHUTDOWN: &str = "57P01";
+pub const IDLE_SESSION_TIMEOUT: &str = "57P05";
+
+/// Class of errors of the system around the database, like files that can't be written
+pub const IO_ERROR: &str = "58030";
+
+/// Class of bugs and errors of peers that predate error codes
+pub const INTERNAL_ERROR: &str = "XX000";
+
+/// Class of the code, its first two characters
+pub fn class(code: &str) -> &str {
+    code.get(..2).unwrap_or(code)
+}
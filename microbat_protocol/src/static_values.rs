@@ -2,7 +2,15 @@
 
 pub const CLIENT_MSG_TYPE_HANDSHAKE: u8 = b'a';
 pub const CLIENT_MSG_TYPE_QUERY: u8 = b'q';
+pub const CLIENT_MSG_TYPE_PARAMETERIZED_QUERY: u8 = b'p';
 pub const CLIENT_MSG_TYPE_DISCONNECT: u8 = b'd';
+pub const CLIENT_MSG_TYPE_AUTHENTICATE: u8 = b'u';
+pub const CLIENT_MSG_TYPE_PING: u8 = b'g';
+pub const CLIENT_MSG_TYPE_PARSE: u8 = b'r';
+pub const CLIENT_MSG_TYPE_BIND: u8 = b'v';
+pub const CLIENT_MSG_TYPE_EXECUTE: u8 = b'e';
+pub const CLIENT_MSG_TYPE_COPY_DATA: u8 = b'c';
+pub const CLIENT_MSG_TYPE_COPY_DONE: u8 = b'f';
 
 pub const CLIENT_HANDSHAKE_PAYLOAD: &str = "hello microbat";
 pub const CLIENT_DISCONNECT_PAYLOAD: &str = "bye and so on";
@@ -12,11 +20,41 @@ pub const SERVER_MSG_TYPE_READY_FOR_QUERY: u8 = b'x';
 pub const SERVER_MSG_TYPE_ERROR: u8 = b'e';
 pub const SERVER_MSG_TYPE_ROW_DESCRIPTION: u8 = b'r';
 pub const SERVER_MSG_TYPE_DATA_ROW: u8 = b'd';
+pub const SERVER_MSG_TYPE_DATA_ROW_BATCH: u8 = b'w';
+pub const SERVER_MSG_TYPE_DATA_ROW_CHUNK: u8 = b'c';
 pub const SERVER_MSG_TYPE_INSERT_RESULT: u8 = b'i';
+pub const SERVER_MSG_TYPE_UNSUPPORTED_VERSION: u8 = b'u';
+pub const SERVER_MSG_TYPE_AUTH_OK: u8 = b'k';
+pub const SERVER_MSG_TYPE_AUTH_FAILED: u8 = b'f';
+pub const SERVER_MSG_TYPE_PONG: u8 = b'o';
+pub const SERVER_MSG_TYPE_SCHEMA_CHANGED: u8 = b's';
+pub const SERVER_MSG_TYPE_PARSE_COMPLETE: u8 = b'1';
+pub const SERVER_MSG_TYPE_BIND_COMPLETE: u8 = b'2';
+pub const SERVER_MSG_TYPE_NOTICE: u8 = b'n';
+pub const SERVER_MSG_TYPE_COPY_IN: u8 = b'y';
+pub const SERVER_MSG_TYPE_BACKEND_KEY_DATA: u8 = b'K';
+pub const SERVER_MSG_TYPE_PARAMETER_STATUS: u8 = b'S';
+pub const SERVER_MSG_TYPE_SHUTTING_DOWN: u8 = b'D';
+pub const SERVER_MSG_TYPE_COMMAND_COMPLETE: u8 = b'C';
+
+// Sent by both peers, wraps a compressed frame of any other type
+pub const MSG_TYPE_COMPRESSED: u8 = b'z';
 
 pub const SERVER_HANDSHAKE_PAYLOAD: &str = "hello client";
 pub const SERVER_READY_PAYLOAD: &str = "shoot";
 
+pub const PROTOCOL_FEATURE_SKIP_UNKNOWN_MESSAGES: u32 = 1;
+pub const PROTOCOL_FEATURE_SCHEMA_NOTIFICATIONS: u32 = 2;
+pub const PROTOCOL_FEATURE_COMPRESSION: u32 = 4;
+pub const PROTOCOL_FEATURE_CHECKSUMS: u32 = 8;
+
 pub const TYPE_BYTE_NULL: u8 = b'n';
 pub const TYPE_BYTE_INTEGER: u8 = b'i';
+pub const TYPE_BYTE_BIGINT: u8 = b'l';
 pub const TYPE_BYTE_VARCHAR: u8 = b'v';
+pub const TYPE_BYTE_BOOL: u8 = b'b';
+pub const TYPE_BYTE_FLOAT: u8 = b'f';
+pub const TYPE_BYTE_DECIMAL: u8 = b'm';
+pub const TYPE_BYTE_DATE: u8 = b'd';
+pub const TYPE_BYTE_TIMESTAMP: u8 = b't';
+pub const TYPE_BYTE_BYTES: u8 = b'x';
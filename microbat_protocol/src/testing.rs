@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::messages::client_messages::{deserialize_client_message, MicrobatClientMessage};
+use crate::messages::server_messages::MicrobatServerMessage;
+use crate::messages::{frame_message, parse_frame_with, MicrobatMessage, ProtocolFeatures};
+
+/// Scripted in-memory microbat server for testing clients without a real server.
+///
+/// The script is a list of expected client messages, each answered with canned server
+/// messages. MockServer implements Read + Write, so it can be used in place of a TcpStream.
+/// Writing a message that does not match the next expectation fails with an io error.
+/// Messages after a scripted Handshake response are framed with the features it carries,
+/// like a real server frames them.
+///
+/// MockServer is a cheap handle to shared state, so a clone can be given to the client while
+/// the test keeps the original for `assert_done()`.
+///
+/// ```
+/// use microbat_protocol::messages::client_messages::MicrobatClientMessage;
+/// use microbat_protocol::messages::server_messages::MicrobatServerMessage;
+/// use microbat_protocol::messages::{ProtocolFeatures, PROTOCOL_VERSION};
+/// use microbat_protocol::testing::MockServer;
+///
+/// let server = MockServer::new().expect(
+///     MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+///     vec![
+///         MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+///         MicrobatServerMessage::Ready,
+///     ],
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct MockServer {
+    state: Arc<Mutex<MockServerState>>,
+}
+
+#[derive(Default)]
+struct MockServerState {
+    script: VecDeque<(MicrobatClientMessage, Vec<MicrobatServerMessage>)>,
+    // Bytes written by the client that do not yet form a complete message
+    incoming: Vec<u8>,
+    // Bytes of responses waiting to be read by the client
+    outgoing: VecDeque<u8>,
+    // Features of the last Handshake response
+    features: ProtocolFeatures,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a step to the script: expect given client message and respond with given messages.
+    pub fn expect(
+        self,
+        message: MicrobatClientMessage,
+        responses: Vec<MicrobatServerMessage>,
+    ) -> Self {
+        self.lock().script.push_back((message, responses));
+        self
+    }
+
+    /// Queues messages the client can read without sending anything first.
+    pub fn respond(self, responses: Vec<MicrobatServerMessage>) -> Self {
+        {
+            let mut state = self.lock();
+            for response in responses {
+                state
+                    .send(response)
+                    .expect("MockServer can't frame response");
+            }
+        }
+        self
+    }
+
+    /// Number of bytes of responses the client has not read yet.
+    pub fn unread(&self) -> usize {
+        self.lock().outgoing.len()
+    }
+
+    /// Panics if some expected client message was not received or responses were not read.
+    pub fn assert_done(&self) {
+        let state = self.lock();
+        assert!(
+            state.script.is_empty(),
+            "MockServer still expects {} messages, next is {:?}",
+            state.script.len(),
+            state.script.front().map(|(message, _)| message)
+        );
+        assert!(
+            state.incoming.is_empty(),
+            "MockServer received {} bytes of incomplete message",
+            state.incoming.len()
+        );
+        assert!(
+            state.outgoing.is_empty(),
+            "Client did not read {} bytes of responses",
+            state.outgoing.len()
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockServerState> {
+        self.state.lock().expect("MockServer lock poisoned")
+    }
+}
+
+impl MockServerState {
+    /// Consumes complete messages from incoming bytes and plays the script for them.
+    fn receive(&mut self) -> std::io::Result<()> {
+        while let Some((message, length)) =
+            parse_frame_with(&self.incoming, deserialize_client_message, self.features)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err.msg))?
+        {
+            self.incoming.drain(..length);
+            match self.script.pop_front() {
+                Some((expected, responses)) if expected == message => {
+                    for response in responses {
+                        self.send(response)?;
+                    }
+                }
+                Some((expected, _)) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("MockServer expected {:?} but got {:?}", expected, message),
+                    ))
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "MockServer did not expect any message but got {:?}",
+                            message
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues given response, switching to the features of a Handshake after framing it
+    fn send(&mut self, response: MicrobatServerMessage) -> std::io::Result<()> {
+        let bytes = frame_message(response.as_bytes(), self.features)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err.msg))?;
+        self.outgoing.extend(bytes);
+        if let MicrobatServerMessage::Handshake(_, features) = response {
+            self.features = features;
+        }
+        Ok(())
+    }
+}
+
+impl Read for MockServer {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.lock();
+        let size = buf.len().min(state.outgoing.len());
+        for (index, byte) in state.outgoing.drain(..size).enumerate() {
+            buf[index] = byte;
+        }
+        Ok(size)
+    }
+}
+
+impl Write for MockServer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.lock();
+        state.incoming.extend_from_slice(buf);
+        state.receive()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod mock_server_tests {
+    use super::*;
+    use crate::messages::server_messages::{deserialize_server_message, ErrorResponse};
+    use crate::messages::{read_message, ProtocolFeatures, PROTOCOL_VERSION};
+    use crate::sqlstate;
+
+    #[test]
+    fn test_scripted_conversation() {
+        let server = MockServer::new()
+            .expect(
+                MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+                vec![
+                    MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+                    MicrobatServerMessage::Ready,
+                ],
+            )
+            .expect(
+                MicrobatClientMessage::Query(String::from("select 1")),
+                vec![MicrobatServerMessage::Error(ErrorResponse::new(
+                    sqlstate::INTERNAL_ERROR,
+                    "nope",
+                ))],
+            );
+        let mut client = server.clone();
+
+        MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+            .send(&mut client)
+            .unwrap();
+        assert_eq!(
+            read_message(&mut client, deserialize_server_message).unwrap(),
+            MicrobatServerMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+        );
+        assert_eq!(
+            read_message(&mut client, deserialize_server_message).unwrap(),
+            MicrobatServerMessage::Ready
+        );
+        MicrobatClientMessage::Query(String::from("select 1"))
+            .send(&mut client)
+            .unwrap();
+        assert_eq!(
+            read_message(&mut client, deserialize_server_message).unwrap(),
+            MicrobatServerMessage::Error(ErrorResponse::new(sqlstate::INTERNAL_ERROR, "nope"))
+        );
+        server.assert_done();
+    }
+
+    #[test]
+    fn test_unexpected_message_fails() {
+        let mut server = MockServer::new().expect(
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+            vec![],
+        );
+        assert!(MicrobatClientMessage::Disconnect.send(&mut server).is_err());
+
+        let mut server = MockServer::new();
+        assert!(MicrobatClientMessage::Disconnect.send(&mut server).is_err());
+    }
+
+    #[test]
+    fn test_messages_can_be_written_in_pieces() {
+        let mut server = MockServer::new().expect(
+            MicrobatClientMessage::Disconnect,
+            vec![MicrobatServerMessage::Ready],
+        );
+        let bytes = MicrobatClientMessage::Disconnect.as_bytes();
+        server.write_all(&bytes[..3]).unwrap();
+        server.write_all(&bytes[3..]).unwrap();
+        assert_eq!(
+            read_message(&mut server, deserialize_server_message).unwrap(),
+            MicrobatServerMessage::Ready
+        );
+        server.assert_done();
+    }
+
+    #[test]
+    #[should_panic(expected = "MockServer still expects 1 messages")]
+    fn test_assert_done_fails_on_pending_expectations() {
+        MockServer::new()
+            .expect(
+                MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default()),
+                vec![],
+            )
+            .assert_done();
+    }
+
+    #[test]
+    fn test_unsolicited_responses() {
+        let mut server = MockServer::new().respond(vec![MicrobatServerMessage::Ready]);
+        assert!(server.unread() > 0);
+        assert_eq!(
+            read_message(&mut server, deserialize_server_message).unwrap(),
+            MicrobatServerMessage::Ready
+        );
+        assert_eq!(server.unread(), 0);
+        server.assert_done();
+    }
+}
@@ -0,0 +1,131 @@
+//! Benchmarks of the executor over the demo tables, generated in a few sizes.
+//!
+//! Run with `cargo bench --features bench`.
+use std::sync::{Arc, RwLock};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use microbat_server::db::demo::create_demo_tables;
+use microbat_server::db::execute_sql;
+use microbat_server::db::manager::{DatabaseManager, InMemoryManager};
+use microbat_server::sql::lexer::Lexer;
+use microbat_server::sql::parser::{parse_sql, SqlClause};
+
+/// Amounts of people in the generated people table
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+const QUERY: &str = "select name, age + 1 as next_age from people \
+    where age > 30 and name <> 'Juho' order by age desc, name limit 10;";
+
+fn database(people: usize) -> Arc<RwLock<InMemoryManager>> {
+    let mut manager = InMemoryManager::new();
+    create_demo_tables(&mut manager, people).unwrap();
+    Arc::new(RwLock::new(manager))
+}
+
+fn execute(sql: &str, database: &Arc<RwLock<InMemoryManager>>) {
+    if execute_sql(String::from(sql), vec![], database).is_err() {
+        panic!("{} failed", sql);
+    }
+}
+
+fn lexing(c: &mut Criterion) {
+    c.bench_function("lex", |b| {
+        b.iter(|| {
+            let mut lexer = Lexer::with_input(String::from(black_box(QUERY))).unwrap();
+            while lexer.has_next() {
+                lexer.next();
+            }
+        })
+    });
+}
+
+fn parsing(c: &mut Criterion) {
+    c.bench_function("parse", |b| {
+        b.iter(|| parse_sql(String::from(black_box(QUERY))).is_ok())
+    });
+}
+
+fn expressions(c: &mut Criterion) {
+    let database = database(1_000);
+    let database = database.read().unwrap();
+    let schema = &database.get_table_meta("people").unwrap().schema;
+    let rows = database.fetch("people").unwrap();
+    let select = match parse_sql(String::from(
+        "select id + age, age > 30 and name <> 'Juho', (id, age) in ((1, 40), (2, 19)) from people;",
+    )) {
+        Ok(SqlClause::Select(select)) => select,
+        _ => panic!("Expecting a select"),
+    };
+    c.bench_function("evaluate 1000 rows", |b| {
+        b.iter(|| {
+            for row in rows.iter() {
+                for expression in select.projection.iter() {
+                    black_box(expression.eval(schema, row).unwrap());
+                }
+            }
+        })
+    });
+}
+
+fn scans(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    for people in SIZES {
+        let database = database(people);
+        group.bench_with_input(BenchmarkId::new("filter", people), &database, |b, db| {
+            b.iter(|| execute("select name from people where age > 50;", db))
+        });
+        group.bench_with_input(BenchmarkId::new("sort", people), &database, |b, db| {
+            b.iter(|| execute(QUERY, db))
+        });
+        group.bench_with_input(BenchmarkId::new("group", people), &database, |b, db| {
+            b.iter(|| execute("select age, count(*) from people group by age;", db))
+        });
+    }
+    group.finish();
+}
+
+fn parallel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel");
+    for workers in [1, 4] {
+        let mut manager = InMemoryManager::new();
+        create_demo_tables(&mut manager, 100_000).unwrap();
+        manager.set_max_parallel_workers(workers);
+        let database = Arc::new(RwLock::new(manager));
+        group.bench_with_input(BenchmarkId::new("group", workers), &database, |b, db| {
+            b.iter(|| {
+                execute(
+                    "select age, count(*), max(name) from people where age > 30 group by age;",
+                    db,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn joins(c: &mut Criterion) {
+    let mut group = c.benchmark_group("join");
+    for people in SIZES {
+        let database = database(people);
+        group.bench_with_input(BenchmarkId::new("equi", people), &database, |b, db| {
+            b.iter(|| {
+                execute(
+                    "select name, name_dep from people, departments where id = id_dep;",
+                    db,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    lexing,
+    parsing,
+    expressions,
+    scans,
+    parallel,
+    joins
+);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+create table people (id integer, name varchar, age integer);
+insert into people values
+    (1, 'Juho', 40),
+    (2, 'Simo', 19),
+    (3, 'Hermanni', 48),
+    (4, 'Taavetti', 32),
+    (5, 'Metusalem', 85);
+
+create table departments (id_dep integer, name_dep varchar);
+insert into departments values
+    (1, 'Rustland'),
+    (2, 'Goland'),
+    (3, 'Javaland'),
+    (4, 'Cppland'),
+    (5, 'Nodejsland');
+
+create table modes (id_mode integer, name_mode varchar);
+insert into modes values (1, 'soft'), (2, 'medium'), (3, 'hard');
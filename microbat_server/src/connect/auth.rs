@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+
+/// User the server boots with, until users can be managed with sql
+pub const DEFAULT_USER: &str = "microbat";
+pub const DEFAULT_PASSWORD: &str = "microbat";
+
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+const HASH_ROUNDS: u32 = 10_000;
+
+/// Password stored as a salted PBKDF2-HMAC-SHA256 hash, never as plain text
+struct PasswordHash {
+    salt: [u8; SALT_LENGTH],
+    hash: [u8; HASH_LENGTH],
+}
+
+impl PasswordHash {
+    fn new(password: &str) -> Self {
+        let mut salt = [0; SALT_LENGTH];
+        getrandom::getrandom(&mut salt).expect("No randomness for password salt");
+        PasswordHash {
+            salt,
+            hash: hash(password, &salt),
+        }
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        // Compares every byte so the time taken doesn't tell how much of the hash matched
+        hash(password, &self.salt)
+            .iter()
+            .zip(self.hash.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+    }
+}
+
+fn hash(password: &str, salt: &[u8]) -> [u8; HASH_LENGTH] {
+    let mut hash = [0; HASH_LENGTH];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, HASH_ROUNDS, &mut hash);
+    hash
+}
+
+/// Users allowed to log in to the server
+#[derive(Default)]
+pub struct UserStore {
+    users: HashMap<String, PasswordHash>,
+}
+
+impl UserStore {
+    pub fn new() -> Self {
+        UserStore::default()
+    }
+
+    /// Store with only the default user
+    pub fn with_default_user() -> Self {
+        let mut store = UserStore::new();
+        store.add_user(DEFAULT_USER, DEFAULT_PASSWORD);
+        store
+    }
+
+    /// Adds a user, replacing the password of an existing one
+    pub fn add_user(&mut self, user: &str, password: &str) {
+        self.users
+            .insert(String::from(user), PasswordHash::new(password));
+    }
+
+    /// True if the user exists and has given password
+    pub fn authenticate(&self, user: &str, password: &str) -> bool {
+        match self.users.get(user) {
+            Some(stored) => stored.matches(password),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use super::*;
+
+    #[test]
+    fn test_authenticate() {
+        let mut store = UserStore::with_default_user();
+        store.add_user("juho", "sekret");
+        assert!(store.authenticate("juho", "sekret"));
+        assert!(store.authenticate(DEFAULT_USER, DEFAULT_PASSWORD));
+        assert!(!store.authenticate("juho", "Sekret"));
+        assert!(!store.authenticate("juho", ""));
+        assert!(!store.authenticate("JUHO", "sekret"));
+        assert!(!store.authenticate("nobody", "sekret"));
+
+        store.add_user("juho", "changed");
+        assert!(!store.authenticate("juho", "sekret"));
+        assert!(store.authenticate("juho", "changed"));
+    }
+
+    #[test]
+    fn test_passwords_are_salted() {
+        let first = PasswordHash::new("same");
+        let second = PasswordHash::new("same");
+        assert_ne!(first.salt, second.salt);
+        assert_ne!(first.hash, second.hash);
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::db::manager::DatabaseManager;
+use crate::db::write_lock;
+use crate::log::{log, LogLevel};
+
+/// Thread checkpointing the database periodically, so that the log of changes a database in
+/// files replays when opened stays short. Checkpoints like the CHECKPOINT statement, holding
+/// the database locked while writing it.
+pub(crate) struct Checkpointer {
+    // Dropped for stopping the thread
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Checkpointer {
+    /// Starts checkpointing given database once per given interval
+    pub(crate) fn start(
+        database: Arc<RwLock<dyn DatabaseManager + Send + Sync>>,
+        interval: Duration,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(String::from("microbat-checkpointer"))
+            .spawn(move || {
+                while stopped.recv_timeout(interval) == Err(RecvTimeoutError::Timeout) {
+                    if let Err(err) = write_lock(&database).checkpoint() {
+                        log!(LogLevel::Error, "Checkpoint failed: {}", err.msg);
+                    }
+                }
+            })
+            .expect("Thread spawn failure");
+        Checkpointer { stop, thread }
+    }
+
+    /// Stops checkpointing, waiting for a checkpoint in progress to finish
+    pub(crate) fn stop(self) {
+        drop(self.stop);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod checkpointer_tests {
+    use super::*;
+    use crate::db::file_manager::FileManager;
+    use crate::sql::lexer::CaseFolding;
+    use microbat_protocol::data::data_values::{MData, MDataType};
+    use microbat_protocol::data::table_model::Column;
+    use std::time::Instant;
+
+    #[test]
+    fn test_database_is_checkpointed_periodically() {
+        let directory =
+            std::env::temp_dir().join(format!("microbat-checkpointer-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut files = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        files
+            .create_table(
+                String::from("foo"),
+                vec![Column::new(String::from("id"), MDataType::Integer)],
+            )
+            .unwrap();
+        files.insert("foo", vec![MData::Integer(1)]).unwrap();
+        let database: Arc<RwLock<dyn DatabaseManager + Send + Sync>> = Arc::new(RwLock::new(files));
+
+        let checkpointer = Checkpointer::start(database, Duration::from_millis(10));
+        let snapshot = directory.join("snapshot-000001.mbt");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !snapshot.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        checkpointer.stop();
+        assert!(snapshot.exists());
+        assert!(!directory.join("wal-000001.mbt").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use microbat_protocol::messages::timeout::ConnectionTimeouts;
+use serde::Deserialize;
+
+use super::{
+    MicrobatServerOpts, DEFAULT_CHECKPOINT_INTERVAL, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_CONNECTIONS,
+    DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_MAX_PARALLEL_WORKERS, DEFAULT_ROW_BATCH_SIZE,
+    DEFAULT_SHUTDOWN_TIMEOUT, DEFAULT_TIMEOUTS,
+};
+use crate::log::LogLevel;
+use crate::sql::lexer::CaseFolding;
+
+/// Address listened on by default, reachable only from the same machine
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Options of the server binary, printed with `--help`
+pub const HELP: &str = "\
+Usage: microbat_server [options]
+
+Options:
+  --config <path.toml>             Read settings from a TOML file, overridden by the
+                                   options below
+  --host <address>                 Listen on given address [default: 127.0.0.1]
+  --port <port>                    Listen on given port [default: 7878]
+  --data-dir <path>                Store the tables in given directory instead of only in
+                                   memory, MICROBAT_DATA_DIR by default
+  --file-dir <path>                Let statements read and write files on the server in given
+                                   directory, which they can't without one [default: none]
+  --init-file <path.sql>           Execute a script of statements when the database has no
+                                   tables
+  --max-connections <count>        Most sessions served at once, clients beyond them are
+                                   refused [default: 100]
+  --log-level <level>              error, warn, info or debug [default: info]
+  --max-parallel-workers <count>   Most workers reading a large table in parallel
+                                   [default: 4]
+  --idle-timeout <seconds>         End sessions of clients idle this long, 0 for never
+                                   [default: 3600]
+  --write-timeout <seconds>        End sessions of clients not taking responses for this
+                                   long, 0 for never [default: 60]
+  --statement-timeout <millis>     Cancel statements taking longer, unless their session sets
+                                   statement_timeout, 0 for never [default: 0]
+  --checkpoint-interval <seconds>  Checkpoint the database this often, 0 only when shutting
+                                   down and with CHECKPOINT [default: 300]
+  --shutdown-timeout <seconds>     Wait this long for sessions to finish their statements
+                                   when interrupted or terminated, 0 for as long as they
+                                   take [default: 30]
+  --metrics-address <host:port>    Serve metrics for Prometheus over HTTP at /metrics of
+                                   given address [default: none]
+  -h, --help                       Print this help
+
+Settings of the config file are named like the options, with underscores, e.g.
+log_level = \"debug\".";
+
+/// Settings of the server from a config file or the command line. Settings not given are
+/// the defaults.
+#[derive(Default, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub data_dir: Option<PathBuf>,
+    pub file_dir: Option<PathBuf>,
+    pub init_file: Option<PathBuf>,
+    pub log_level: Option<LogLevel>,
+    pub max_connections: Option<usize>,
+    pub max_parallel_workers: Option<usize>,
+    /// Seconds, 0 for no limit
+    pub idle_timeout: Option<u64>,
+    /// Seconds, 0 for no limit
+    pub write_timeout: Option<u64>,
+    /// Milliseconds, 0 for no limit
+    pub statement_timeout: Option<u64>,
+    /// Seconds, 0 for checkpointing only when shutting down and with CHECKPOINT
+    pub checkpoint_interval: Option<u64>,
+    /// Seconds, 0 for waiting as long as the statements take
+    pub shutdown_timeout: Option<u64>,
+    /// Address of the HTTP listener serving metrics, e.g. `127.0.0.1:9187`
+    pub metrics_address: Option<String>,
+}
+
+/// What the command line asks the server to do
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Serve with given settings
+    Run(Box<ServerConfig>),
+    /// Print the options and exit
+    Help,
+}
+
+impl ServerConfig {
+    /// Reads the settings of a TOML file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Can't read config file {}: {}", path.display(), err))?;
+        toml::from_str(&text)
+            .map_err(|err| format!("Invalid config file {}: {}", path.display(), err.message()))
+    }
+
+    /// These settings with the ones given in `overrides` replacing them
+    pub fn overridden_by(self, overrides: ServerConfig) -> Self {
+        ServerConfig {
+            host: overrides.host.or(self.host),
+            port: overrides.port.or(self.port),
+            data_dir: overrides.data_dir.or(self.data_dir),
+            file_dir: overrides.file_dir.or(self.file_dir),
+            init_file: overrides.init_file.or(self.init_file),
+            log_level: overrides.log_level.or(self.log_level),
+            max_connections: overrides.max_connections.or(self.max_connections),
+            max_parallel_workers: overrides.max_parallel_workers.or(self.max_parallel_workers),
+            idle_timeout: overrides.idle_timeout.or(self.idle_timeout),
+            write_timeout: overrides.write_timeout.or(self.write_timeout),
+            statement_timeout: overrides.statement_timeout.or(self.statement_timeout),
+            checkpoint_interval: overrides.checkpoint_interval.or(self.checkpoint_interval),
+            shutdown_timeout: overrides.shutdown_timeout.or(self.shutdown_timeout),
+            metrics_address: overrides.metrics_address.or(self.metrics_address),
+        }
+    }
+
+    /// Options of a server with these settings and the defaults for the rest
+    pub fn into_opts(self) -> MicrobatServerOpts {
+        let host = self.host.unwrap_or_else(|| String::from(DEFAULT_HOST));
+        let port = self.port.unwrap_or(DEFAULT_PORT);
+        let seconds = |seconds: Option<u64>, default: Option<Duration>| match seconds {
+            Some(0) => None,
+            Some(seconds) => Some(Duration::from_secs(seconds)),
+            None => default,
+        };
+        MicrobatServerOpts {
+            // IPv6 addresses are bracketed to tell them from the port
+            bind: match host.contains(':') {
+                true => format!("[{}]:{}", host, port),
+                false => format!("{}:{}", host, port),
+            },
+            case_folding: CaseFolding::default(),
+            row_batch_size: DEFAULT_ROW_BATCH_SIZE,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            timeouts: ConnectionTimeouts {
+                read: seconds(self.idle_timeout, DEFAULT_TIMEOUTS.read),
+                write: seconds(self.write_timeout, DEFAULT_TIMEOUTS.write),
+            },
+            data_dir: self.data_dir,
+            file_dir: self.file_dir,
+            checkpoint_interval: seconds(
+                self.checkpoint_interval,
+                Some(DEFAULT_CHECKPOINT_INTERVAL),
+            ),
+            max_parallel_workers: self
+                .max_parallel_workers
+                .unwrap_or(DEFAULT_MAX_PARALLEL_WORKERS),
+            init_file: self.init_file,
+            log_level: self.log_level.unwrap_or(LogLevel::Info),
+            max_connections: self.max_connections.unwrap_or(DEFAULT_MAX_CONNECTIONS),
+            statement_timeout: self
+                .statement_timeout
+                .filter(|millis| *millis > 0)
+                .map(Duration::from_millis),
+            metrics_bind: self.metrics_address,
+            shutdown_timeout: seconds(self.shutdown_timeout, Some(DEFAULT_SHUTDOWN_TIMEOUT)),
+        }
+    }
+}
+
+/// Parses the arguments of the server binary, without the name of the binary. Settings of
+/// the config file named with `--config` are overridden by the other options.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut config_file = None;
+    let mut overrides = ServerConfig::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => config_file = Some(PathBuf::from(value()?)),
+            "--host" => overrides.host = Some(value()?),
+            "--port" => overrides.port = Some(number(&arg, &value()?)?),
+            "--data-dir" => overrides.data_dir = Some(PathBuf::from(value()?)),
+            "--file-dir" => overrides.file_dir = Some(PathBuf::from(value()?)),
+            "--init-file" => overrides.init_file = Some(PathBuf::from(value()?)),
+            "--max-connections" => overrides.max_connections = Some(number(&arg, &value()?)?),
+            "--log-level" => overrides.log_level = Some(LogLevel::from_str(&value()?)?),
+            "--max-parallel-workers" => {
+                overrides.max_parallel_workers = Some(number(&arg, &value()?)?)
+            }
+            "--idle-timeout" => overrides.idle_timeout = Some(number(&arg, &value()?)?),
+            "--write-timeout" => overrides.write_timeout = Some(number(&arg, &value()?)?),
+            "--statement-timeout" => overrides.statement_timeout = Some(number(&arg, &value()?)?),
+            "--shutdown-timeout" => overrides.shutdown_timeout = Some(number(&arg, &value()?)?),
+            "--metrics-address" => overrides.metrics_address = Some(value()?),
+            "--checkpoint-interval" => {
+                overrides.checkpoint_interval = Some(number(&arg, &value()?)?)
+            }
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+    let config = match config_file {
+        Some(path) => ServerConfig::load(&path)?,
+        None => ServerConfig::default(),
+    };
+    Ok(Command::Run(Box::new(config.overridden_by(overrides))))
+}
+
+/// Value of an option expecting a number
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, not {}", option, value))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| String::from(*arg)))
+    }
+
+    #[test]
+    fn test_config_file() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            host = "0.0.0.0"
+            port = 5433
+            data_dir = "/var/lib/microbat"
+            log_level = "debug"
+            idle_timeout = 0
+            write_timeout = 10
+            statement_timeout = 1500
+            metrics_address = "0.0.0.0:9187"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.log_level, Some(LogLevel::Debug));
+        let opts = config.into_opts();
+        assert_eq!(opts.bind, "0.0.0.0:5433");
+        assert_eq!(opts.data_dir, Some(PathBuf::from("/var/lib/microbat")));
+        assert_eq!(opts.timeouts.read, None);
+        assert_eq!(opts.timeouts.write, Some(Duration::from_secs(10)));
+        assert_eq!(opts.statement_timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(opts.metrics_bind.as_deref(), Some("0.0.0.0:9187"));
+        assert_eq!(opts.checkpoint_interval, Some(DEFAULT_CHECKPOINT_INTERVAL));
+
+        assert!(toml::from_str::<ServerConfig>("bind = \"0.0.0.0\"").is_err());
+        assert!(toml::from_str::<ServerConfig>("log_level = \"loud\"").is_err());
+    }
+
+    #[test]
+    fn test_options_override_config_file() {
+        let path = std::env::temp_dir().join(format!("microbat-{}.toml", std::process::id()));
+        fs::write(&path, "port = 5433\nlog_level = \"warn\"\n").unwrap();
+        let parsed = args(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--log-level",
+            "ERROR",
+            "--checkpoint-interval",
+            "0",
+            "--max-connections",
+            "8",
+            "--file-dir",
+            "/srv/files",
+        ]);
+        fs::remove_file(&path).unwrap();
+        let Ok(Command::Run(config)) = parsed else {
+            panic!("Expecting settings");
+        };
+        assert_eq!(config.port, Some(5433));
+        assert_eq!(config.log_level, Some(LogLevel::Error));
+        let opts = (*config).into_opts();
+        assert_eq!(opts.bind, "127.0.0.1:5433");
+        assert_eq!(opts.checkpoint_interval, None);
+        assert_eq!(opts.max_parallel_workers, DEFAULT_MAX_PARALLEL_WORKERS);
+        assert_eq!(opts.max_connections, 8);
+        assert_eq!(opts.file_dir, Some(PathBuf::from("/srv/files")));
+        assert_eq!(opts.statement_timeout, None);
+        assert_eq!(opts.shutdown_timeout, Some(DEFAULT_SHUTDOWN_TIMEOUT));
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        let Ok(Command::Run(config)) = args(&["--host", "0.0.0.0", "--port", "5433"]) else {
+            panic!("Expecting settings");
+        };
+        assert_eq!((*config).into_opts().bind, "0.0.0.0:5433");
+        let Ok(Command::Run(config)) = args(&["--host", "::1"]) else {
+            panic!("Expecting settings");
+        };
+        assert_eq!((*config).into_opts().bind, "[::1]:7878");
+        assert_eq!(
+            args(&["--port", "70000"]),
+            Err(String::from("--port expects a number, not 70000"))
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(args(&["--log-level", "debug", "--help"]), Ok(Command::Help));
+        assert_eq!(args(&[]), Ok(Command::Run(Box::default())));
+        assert_eq!(
+            args(&["--data-dir"]),
+            Err(String::from("--data-dir needs a value"))
+        );
+        assert_eq!(
+            args(&["--idle-timeout", "soon"]),
+            Err(String::from("--idle-timeout expects a number, not soon"))
+        );
+        assert_eq!(
+            args(&["--verbose"]),
+            Err(String::from("Unknown argument --verbose"))
+        );
+        assert!(args(&["--config", "/nonexistent/microbat.toml"]).is_err());
+    }
+}
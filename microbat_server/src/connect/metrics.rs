@@ -0,0 +1,278 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::db::{MicrobatQueryError, QueryResult};
+use crate::log::{log, LogLevel};
+
+/// Upper bounds of the buckets of query latency in seconds, the default buckets of Prometheus
+/// clients with a millisecond bucket for the fast queries of a database
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Scrapers taking longer than this to send their request are disconnected, so that one
+/// can't keep the others waiting
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters of a server collected by its sessions, served in the text format of Prometheus
+#[derive(Default)]
+pub struct Metrics {
+    connections: AtomicU64,
+    refused_connections: AtomicU64,
+    queries: AtomicU64,
+    errors: AtomicU64,
+    rows: AtomicU64,
+    latency: Histogram,
+}
+
+/// Observations counted in the bucket of the smallest bound they fit in, not cumulatively
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts a session the server accepted
+    pub fn connection_accepted(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection refused for exceeding the most sessions served at once
+    pub fn connection_refused(&self) {
+        self.refused_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an executed statement with the time executing it took and the rows it returned
+    pub fn statement_executed(
+        &self,
+        elapsed: Duration,
+        result: &Result<QueryResult, MicrobatQueryError>,
+    ) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        let rows = match result {
+            Ok(QueryResult::Table(_, rows)) => rows.len() as u64,
+            Ok(QueryResult::Streamed(count)) => *count,
+            Ok(_) => 0,
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                0
+            }
+        };
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+        self.latency.observe(elapsed);
+    }
+
+    /// Metrics in the text exposition format of Prometheus
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let counters = [
+            (
+                "microbat_connections_total",
+                "Sessions accepted",
+                &self.connections,
+            ),
+            (
+                "microbat_connections_refused_total",
+                "Connections refused for exceeding max_connections",
+                &self.refused_connections,
+            ),
+            (
+                "microbat_queries_total",
+                "Statements executed",
+                &self.queries,
+            ),
+            (
+                "microbat_query_errors_total",
+                "Statements that failed",
+                &self.errors,
+            ),
+            (
+                "microbat_rows_returned_total",
+                "Rows returned to clients",
+                &self.rows,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            let _ = writeln!(text, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+        self.latency.render(
+            &mut text,
+            "microbat_query_duration_seconds",
+            "Time executing statements took",
+        );
+        text
+    }
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    fn render(&self, text: &mut String, name: &str, help: &str) {
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(text, "{}_sum {}", name, sum);
+        let _ = writeln!(text, "{}_count {}", name, count);
+    }
+}
+
+/// Thread answering HTTP requests for `/metrics` with the metrics of a server, one request
+/// at a time
+pub(crate) struct MetricsListener {
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl MetricsListener {
+    /// Starts serving given metrics to the connections of given listener
+    pub(crate) fn start(listener: TcpListener, metrics: Arc<Metrics>) -> std::io::Result<Self> {
+        let address = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopping = Arc::clone(&stopped);
+        let thread = thread::Builder::new()
+            .name(String::from("microbat-metrics"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => respond(stream, &metrics),
+                        Err(err) => log!(LogLevel::Warn, "Can't accept scraper: {}", err),
+                    }
+                }
+            })
+            .expect("Thread spawn failure");
+        Ok(MetricsListener {
+            address,
+            stopped,
+            thread,
+        })
+    }
+
+    /// Stops serving, waiting for a request in progress to be answered
+    pub(crate) fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wakes the thread from accepting
+        let _ = TcpStream::connect(self.address);
+        let _ = self.thread.join();
+    }
+}
+
+/// Answers a request for the metrics. Other paths are not found, and the connection is closed
+/// after the response.
+fn respond(mut stream: TcpStream, metrics: &Metrics) {
+    let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // Headers are read so that closing doesn't reset the connection before the response
+    let mut header = String::new();
+    while matches!(reader.read_line(&mut header), Ok(read) if read > 0 && header.trim() != "") {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", String::from("Not found, try /metrics\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("Only GET is allowed\n"),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.connection_accepted();
+        metrics.statement_executed(Duration::from_millis(3), &Ok(QueryResult::Streamed(7)));
+        metrics.statement_executed(Duration::from_secs(20), &Ok(QueryResult::Inserted(2)));
+        metrics.statement_executed(
+            Duration::from_micros(500),
+            &Err(MicrobatQueryError::new("42601", String::from("bad"))),
+        );
+        let text = metrics.render();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE microbat_connections_total counter"));
+        assert!(lines.contains(&"microbat_connections_total 1"));
+        assert!(lines.contains(&"microbat_connections_refused_total 0"));
+        assert!(lines.contains(&"microbat_queries_total 3"));
+        assert!(lines.contains(&"microbat_query_errors_total 1"));
+        assert!(lines.contains(&"microbat_rows_returned_total 7"));
+        assert!(lines.contains(&"# TYPE microbat_query_duration_seconds histogram"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_bucket{le=\"0.001\"} 1"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_bucket{le=\"0.005\"} 2"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_bucket{le=\"10\"} 2"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_sum 20.0035"));
+        assert!(lines.contains(&"microbat_query_duration_seconds_count 3"));
+    }
+
+    #[test]
+    fn test_listener() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.connection_refused();
+        let listener = MetricsListener::start(
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            Arc::clone(&metrics),
+        )
+        .unwrap();
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(listener.address).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nmicrobat_connections_refused_total 1\n"));
+        let response = get("GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = get("POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        listener.stop();
+    }
+}
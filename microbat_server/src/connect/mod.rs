@@ -1,212 +1,1486 @@
-use microbat_protocol::data::data_values::{MData, MDataType};
-use microbat_protocol::data::table_model::Column;
+use microbat_protocol::data::data_values::{DataError, MData};
+use microbat_protocol::data::table_model::{Column, DataRow, TableSchema};
 use microbat_protocol::messages::client_messages::{
-    deserialize_client_message, MicrobatClientMessage,
+    deserialize_client_message, is_client_message_type, split_statements, MicrobatClientMessage,
 };
-use microbat_protocol::messages::server_messages::MicrobatServerMessage;
-use microbat_protocol::messages::{read_message, MicrobatMessage};
-use std::net::{TcpListener, TcpStream};
+use microbat_protocol::messages::server_messages::{
+    check_row_size, row_chunks, BackendKey, ErrorResponse, MicrobatServerMessage,
+};
+use microbat_protocol::messages::timeout::ConnectionTimeouts;
+use microbat_protocol::messages::writer::MessageWriter;
+use microbat_protocol::messages::{
+    negotiate_version, read_message_limited, MicrobatMessage, ProtocolFeatures,
+    BACKEND_KEY_PROTOCOL_VERSION, BATCH_PROTOCOL_VERSION, CHUNK_PROTOCOL_VERSION,
+    COMMAND_COMPLETE_PROTOCOL_VERSION, COPY_PROTOCOL_VERSION, MAX_CHUNKED_ROW_SIZE, MAX_FRAME_SIZE,
+    MIN_PROTOCOL_VERSION, NOTICE_PROTOCOL_VERSION, PARAMETER_STATUS_PROTOCOL_VERSION,
+    PROTOCOL_VERSION, SHUTDOWN_PROTOCOL_VERSION,
+};
+use microbat_protocol::sqlstate;
+use microbat_protocol::MicrobatProtocolError;
+use std::collections::HashMap;
+use std::fs;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use self::auth::UserStore;
+use self::checkpointer::Checkpointer;
+use self::metrics::{Metrics, MetricsListener};
+use self::shutdown::ShutdownHandle;
+use crate::db::catalog::SessionActivity;
+use crate::db::file_manager::FileManager;
 use crate::db::manager::{DatabaseManager, InMemoryManager};
-use crate::db::{execute_sql, QueryResult};
+use crate::db::session::Session;
+use crate::db::{
+    copy_rows, execute_prepared, execute_sql, execute_sql_with_notices, prepare_sql, read_lock,
+    write_lock, MicrobatQueryError, PreparedStatement, QueryNotice, QueryResult, RowReceiver,
+};
+use crate::log::{self, log, LogLevel};
+use crate::sql::lexer::CaseFolding;
+
+pub mod auth;
+mod checkpointer;
+pub mod config;
+pub mod metrics;
+pub mod shutdown;
+
+/// Rows sent in one message by default
+pub const DEFAULT_ROW_BATCH_SIZE: usize = 256;
+/// Size of the chunks large values are sent in by default
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+/// Largest message read from clients by default
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = MAX_FRAME_SIZE;
+/// Sessions end after an hour without messages from the client by default, and when the
+/// client takes no responses for a minute
+pub const DEFAULT_TIMEOUTS: ConnectionTimeouts = ConnectionTimeouts {
+    read: Some(Duration::from_secs(60 * 60)),
+    write: Some(Duration::from_secs(60)),
+};
+/// Database is checkpointed every five minutes by default
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Large tables are read by up to four workers by default
+pub const DEFAULT_MAX_PARALLEL_WORKERS: usize = 4;
+/// Shutting down waits half a minute for sessions to finish their statements by default
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Connections beyond a hundred sessions are refused by default
+pub const DEFAULT_MAX_CONNECTIONS: usize = 100;
 
 pub struct MicrobatServerOpts {
     pub bind: String,
+    /// How unquoted identifiers are folded, lower case by default
+    pub case_folding: CaseFolding,
+    /// Most rows sent in one message, 1 sends every row in its own message
+    pub row_batch_size: usize,
+    /// Rows with values larger than this are sent in chunks of this size, limited to what
+    /// fits in a frame
+    pub chunk_size: usize,
+    /// Sessions of clients sending larger messages, header included, end with a protocol
+    /// violation. Limited to `MAX_FRAME_SIZE`, which clients never exceed.
+    pub max_message_size: usize,
+    /// Timeouts of every connection. Read timeout ends sessions of idle clients, which can
+    /// ping to keep their sessions.
+    pub timeouts: ConnectionTimeouts,
+    /// Directory the tables are stored in, None keeps them only in memory. Tables in the
+    /// directory are loaded when the server starts.
+    pub data_dir: Option<PathBuf>,
+    /// Directory statements read and write files on the server in, with `IMPORT CSV` and
+    /// `SELECT ... INTO OUTFILE`. None lets them use no files, as any client that logs in
+    /// could otherwise read and write any file the server can.
+    pub file_dir: Option<PathBuf>,
+    /// How often the database is checkpointed in the background, None only when shutting
+    /// down and with CHECKPOINT. Checkpoints shorten the log of changes replayed when the
+    /// server starts, and do nothing for tables kept only in memory.
+    pub checkpoint_interval: Option<Duration>,
+    /// Most workers filtering and aggregating the rows of a large table in parallel for a
+    /// query, 1 reads every table in the thread of the session
+    pub max_parallel_workers: usize,
+    /// Script of statements separated by semicolons executed when the database has no tables,
+    /// i.e. when the server starts the first time with a data directory and every time
+    /// without one. Without a script the database starts empty.
+    pub init_file: Option<PathBuf>,
+    /// Least important messages logged, shared by every server of the process
+    pub log_level: LogLevel,
+    /// Most sessions served at once. Clients connecting beyond them are told that there are
+    /// too many connections and disconnected.
+    pub max_connections: usize,
+    /// Time a statement may take before it is canceled, unless the session sets
+    /// statement_timeout itself. None lets statements run until they are done.
+    pub statement_timeout: Option<Duration>,
+    /// Address of an HTTP listener serving the metrics of the server at `/metrics` in the
+    /// text format of Prometheus, None for no listener
+    pub metrics_bind: Option<String>,
+    /// How long shutting down waits for sessions to finish the statements they are executing,
+    /// None for as long as they take. Sessions still executing are abandoned without a
+    /// checkpoint, so their changes are replayed from the log when the server starts again.
+    pub shutdown_timeout: Option<Duration>,
+}
+
+/// How results are sent to a client, as far as its protocol version and features allow, and
+/// how messages are read from it
+#[derive(Clone, Copy)]
+struct ResultFormat {
+    /// Most rows sent in one message
+    batch_size: usize,
+    /// Size of the chunks rows with large values are sent in, None if the client can't
+    /// reassemble them
+    chunk_size: Option<usize>,
+    /// False if the client can't read notices
+    notices: bool,
+    /// False if the client can't send rows for COPY ... FROM STDIN
+    copy: bool,
+    /// False if the client can't read ShuttingDown
+    shutting_down: bool,
+    /// False if the client can't read ParameterStatus
+    parameters: bool,
+    /// False if the client can't read CommandComplete
+    commands: bool,
+    /// Largest message read from the client
+    max_message_size: usize,
+    features: ProtocolFeatures,
+}
+
+impl ResultFormat {
+    /// Rows one by one and whole, as clients that predate the handshake expect them, reading
+    /// messages of at most given size
+    fn basic(max_message_size: usize) -> Self {
+        ResultFormat {
+            batch_size: 1,
+            chunk_size: None,
+            notices: false,
+            copy: false,
+            shutting_down: false,
+            parameters: false,
+            commands: false,
+            max_message_size,
+            features: ProtocolFeatures::default(),
+        }
+    }
+
+    /// This format limited to what clients of given version and features support
+    fn negotiate(&self, version: u16, features: ProtocolFeatures) -> Self {
+        ResultFormat {
+            batch_size: match version >= BATCH_PROTOCOL_VERSION {
+                true => self.batch_size,
+                false => 1,
+            },
+            chunk_size: self
+                .chunk_size
+                .filter(|_| version >= CHUNK_PROTOCOL_VERSION),
+            notices: self.notices && version >= NOTICE_PROTOCOL_VERSION,
+            copy: self.copy && version >= COPY_PROTOCOL_VERSION,
+            shutting_down: self.shutting_down && version >= SHUTDOWN_PROTOCOL_VERSION,
+            parameters: self.parameters && version >= PARAMETER_STATUS_PROTOCOL_VERSION,
+            commands: self.commands && version >= COMMAND_COMPLETE_PROTOCOL_VERSION,
+            max_message_size: self.max_message_size,
+            features,
+        }
+    }
 }
 
+/// Serves until the process is interrupted or terminated, then shuts down like
+/// `ShutdownHandle::shut_down`. A second signal exits right away.
 pub fn run_microbat(server_opts: MicrobatServerOpts) {
-    let listener = TcpListener::bind(server_opts.bind).expect("Can't start microbat");
-    println!("Microbat is running");
-    let database = Arc::new(RwLock::new(InMemoryManager::new()));
-    let mut init_db = database.write().unwrap();
-    init_db
-        .create_table(
-            String::from("PEOPLE"),
-            vec![
-                Column::new(String::from("id"), MDataType::Integer),
-                Column::new(String::from("name"), MDataType::Varchar),
-                Column::new(String::from("age"), MDataType::Integer),
-            ],
-        )
-        .unwrap();
-    init_db
-        .insert(
-            "PEOPLE",
-            vec![
-                MData::Integer(1),
-                MData::Varchar(String::from("Juho")),
-                MData::Integer(40),
-            ],
-        )
-        .unwrap();
-    init_db
-        .insert(
-            "PEOPLE",
-            vec![
-                MData::Integer(2),
-                MData::Varchar(String::from("Simo")),
-                MData::Integer(19),
-            ],
-        )
-        .unwrap();
-    init_db
-        .insert(
-            "PEOPLE",
-            vec![
-                MData::Integer(3),
-                MData::Varchar(String::from("Hermanni")),
-                MData::Integer(48),
-            ],
-        )
-        .unwrap();
-    init_db
-        .insert(
-            "PEOPLE",
-            vec![
-                MData::Integer(4),
-                MData::Varchar(String::from("Taavetti")),
-                MData::Integer(32),
-            ],
+    let server = MicrobatServer::bind(server_opts).expect("Can't start microbat");
+    let shutdown = server.shutdown_handle();
+    let handled = ctrlc::set_handler(move || {
+        if shutdown.is_requested() {
+            log!(LogLevel::Warn, "Exiting without waiting for sessions");
+            std::process::exit(1);
+        }
+        log!(LogLevel::Info, "Received termination signal");
+        shutdown.shut_down();
+    });
+    if let Err(err) = handled {
+        log!(LogLevel::Warn, "Can't handle termination signals: {}", err);
+    }
+    server.run();
+}
+
+/// Microbat bound to its address, serving connections once `run`
+pub struct MicrobatServer {
+    listener: TcpListener,
+    database: Arc<RwLock<dyn DatabaseManager + Send + Sync>>,
+    users: Arc<UserStore>,
+    format: ResultFormat,
+    timeouts: ConnectionTimeouts,
+    checkpoint_interval: Option<Duration>,
+    shutdown: ShutdownHandle,
+    max_connections: usize,
+    statement_timeout: Option<Duration>,
+    file_dir: Option<PathBuf>,
+    // Sessions being served
+    connections: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+    metrics_listener: Option<TcpListener>,
+    shutdown_timeout: Option<Duration>,
+}
+
+impl MicrobatServer {
+    /// Binds the address of given options and opens the database, executing the init file
+    /// if it has no tables yet
+    pub fn bind(server_opts: MicrobatServerOpts) -> std::io::Result<Self> {
+        log::set_level(server_opts.log_level);
+        let listener = TcpListener::bind(&server_opts.bind)?;
+        let metrics_listener = match &server_opts.metrics_bind {
+            Some(address) => Some(TcpListener::bind(address)?),
+            None => None,
+        };
+        let case_folding = server_opts.case_folding;
+        // A directory that doesn't exist fails now rather than every statement using a file
+        let file_dir = match &server_opts.file_dir {
+            Some(directory) => Some(std::fs::canonicalize(directory)?),
+            None => None,
+        };
+        let database: Arc<RwLock<dyn DatabaseManager + Send + Sync>> = match server_opts.data_dir {
+            Some(directory) => {
+                let mut files = FileManager::open(directory, case_folding).map_err(io_error)?;
+                files.set_max_parallel_workers(server_opts.max_parallel_workers);
+                Arc::new(RwLock::new(files))
+            }
+            None => {
+                let mut memory = InMemoryManager::with_case_folding(case_folding);
+                memory.set_max_parallel_workers(server_opts.max_parallel_workers);
+                Arc::new(RwLock::new(memory))
+            }
+        };
+        if let Some(init_file) = &server_opts.init_file {
+            initialize(&database, init_file)?;
+        }
+        let format = ResultFormat {
+            batch_size: server_opts.row_batch_size,
+            // Chunk type, flag and the header
+            chunk_size: Some(server_opts.chunk_size.clamp(1, MAX_FRAME_SIZE - 6)),
+            notices: true,
+            copy: true,
+            shutting_down: true,
+            parameters: true,
+            commands: true,
+            max_message_size: server_opts.max_message_size.min(MAX_FRAME_SIZE),
+            features: ProtocolFeatures::all(),
+        };
+        let shutdown = ShutdownHandle::new(listener.local_addr()?);
+        Ok(MicrobatServer {
+            listener,
+            database,
+            users: Arc::new(UserStore::with_default_user()),
+            format,
+            timeouts: server_opts.timeouts,
+            checkpoint_interval: server_opts.checkpoint_interval,
+            shutdown,
+            max_connections: server_opts.max_connections,
+            statement_timeout: server_opts.statement_timeout,
+            file_dir,
+            connections: Arc::new(AtomicUsize::new(0)),
+            metrics: Arc::new(Metrics::default()),
+            metrics_listener,
+            shutdown_timeout: server_opts.shutdown_timeout,
+        })
+    }
+
+    /// Address the server listens on, e.g. for the port bound when binding port 0
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Address the metrics are served on, if the server has a metrics listener
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+    }
+
+    /// Handle for shutting the server down from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Serves every connection in its own thread. Returns after shutting down, once every
+    /// session has told its client of it and the database is checkpointed, or once the
+    /// shutdown timeout has passed.
+    pub fn run(mut self) {
+        log!(LogLevel::Info, "Microbat is running");
+        let checkpointer = self
+            .checkpoint_interval
+            .map(|interval| Checkpointer::start(Arc::clone(&self.database), interval));
+        let metrics_listener = match self.metrics_listener.take() {
+            Some(listener) => match MetricsListener::start(listener, Arc::clone(&self.metrics)) {
+                Ok(metrics_listener) => Some(metrics_listener),
+                Err(err) => {
+                    log!(LogLevel::Error, "Can't serve metrics: {}", err);
+                    None
+                }
+            },
+            None => None,
+        };
+        for (thread_id, stream) in (1..).zip(self.listener.incoming()) {
+            if self.shutdown.is_requested() {
+                break;
+            }
+            let stream = stream.unwrap();
+            if let Err(err) = self.timeouts.apply(&stream) {
+                log!(
+                    LogLevel::Warn,
+                    "Can't set timeouts of connection: {}",
+                    err.msg
+                );
+            }
+            let Some(slot) = ConnectionSlot::take(&self.connections, self.max_connections) else {
+                log!(
+                    LogLevel::Warn,
+                    "Refusing connection, serving {} sessions already",
+                    self.max_connections
+                );
+                self.metrics.connection_refused();
+                let max_message_size = self.format.max_message_size;
+                thread::Builder::new()
+                    .name(format!("microbat-refused-{}", thread_id))
+                    .spawn(move || refuse_connection(stream, max_message_size))
+                    .expect("Thread spawn failure");
+                continue;
+            };
+            self.metrics.connection_accepted();
+            let db_arc = Arc::clone(&self.database);
+            let users = Arc::clone(&self.users);
+            let metrics = Arc::clone(&self.metrics);
+            let format = self.format;
+            let shutdown = self.shutdown.clone();
+            let mut session = Session::with_statement_timeout(self.statement_timeout);
+            session.set_file_dir(self.file_dir.clone());
+            // Sessions are numbered like the threads serving them
+            let key = backend_key(thread_id);
+            thread::Builder::new()
+                .name(format!("microbat-t-{}", thread_id))
+                .spawn(move || {
+                    handle_connection(
+                        stream, &db_arc, &users, format, key, session, &metrics, &shutdown,
+                    );
+                    drop(slot);
+                })
+                .expect("Thread spawn failure");
+        }
+        log!(LogLevel::Info, "Microbat is shutting down");
+        let ended = self.shutdown.wait_for_sessions(self.shutdown_timeout);
+        if let Some(metrics_listener) = metrics_listener {
+            metrics_listener.stop();
+        }
+        if !ended {
+            // Checkpointing would wait for the statements to release the database
+            log!(
+                LogLevel::Warn,
+                "Sessions are still executing statements, exiting without a checkpoint"
+            );
+            return;
+        }
+        if let Some(checkpointer) = checkpointer {
+            checkpointer.stop();
+        }
+        if let Err(err) = write_lock(&self.database).checkpoint() {
+            log!(LogLevel::Error, "Checkpoint failed: {}", err.msg);
+        }
+    }
+}
+
+/// Executes the statements of given script in the database, unless it has tables already.
+/// Starting the server fails if a statement fails.
+fn initialize(
+    database: &Arc<RwLock<dyn DatabaseManager + Send + Sync>>,
+    path: &Path,
+) -> std::io::Result<()> {
+    if !read_lock(database).schema_changes().is_empty() {
+        return Ok(());
+    }
+    let script = fs::read_to_string(path).map_err(|err| {
+        std::io::Error::new(
+            err.kind(),
+            format!("Can't read init file {}: {}", path.display(), err),
         )
-        .unwrap();
-    init_db
-        .insert(
-            "PEOPLE",
-            vec![
-                MData::Integer(5),
-                MData::Varchar(String::from("Metusalem")),
-                MData::Integer(85),
-            ],
+    })?;
+    for statement in split_statements(&script) {
+        // A script of only whitespace is a single blank statement
+        if statement.trim().is_empty() {
+            continue;
+        }
+        if let Err(err) = execute_sql(String::from(statement), vec![], database) {
+            return Err(std::io::Error::other(format!(
+                "Statement of init file {} failed: {}: {}",
+                path.display(),
+                statement,
+                err.msg
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Error of opening the database as an error of starting the server
+fn io_error(err: DataError) -> std::io::Error {
+    std::io::Error::other(err.msg)
+}
+
+/// Session counted against the most sessions served at once, until dropped
+struct ConnectionSlot {
+    connections: Arc<AtomicUsize>,
+}
+
+impl ConnectionSlot {
+    /// Slot for a new session, None if `max` sessions are served already
+    fn take(connections: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()?;
+        Some(ConnectionSlot {
+            connections: Arc::clone(connections),
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// What a session is doing, reported for the sessions view until dropped
+struct ActivityReport<'a, M: DatabaseManager + ?Sized> {
+    manager: &'a Arc<RwLock<M>>,
+    session_id: u32,
+    activity: SessionActivity,
+}
+
+impl<'a, M: DatabaseManager + ?Sized> ActivityReport<'a, M> {
+    fn start(manager: &'a Arc<RwLock<M>>, session_id: u32) -> Self {
+        let report = ActivityReport {
+            manager,
+            session_id,
+            activity: SessionActivity::new(),
+        };
+        report.send();
+        report
+    }
+
+    fn logged_in(&mut self, user: &str) {
+        self.activity.user = Some(String::from(user));
+        self.send();
+    }
+
+    /// Reports given statement as executing while it is executed by `execute`
+    fn executing<T>(&mut self, query: String, execute: impl FnOnce() -> T) -> T {
+        self.activity.query = Some(query);
+        self.send();
+        let result = execute();
+        self.activity.query = None;
+        self.send();
+        result
+    }
+
+    fn send(&self) {
+        read_lock(self.manager).report_session(self.session_id, Some(self.activity.clone()));
+    }
+}
+
+impl<M: DatabaseManager + ?Sized> Drop for ActivityReport<'_, M> {
+    fn drop(&mut self) {
+        read_lock(self.manager).report_session(self.session_id, None);
+    }
+}
+
+/// Tells a client connecting beyond the most sessions served at once that there are too many
+/// connections. Its handshake is read first, as closing a connection with unread messages
+/// may reset it before the client reads the error.
+fn refuse_connection(mut stream: TcpStream, max_message_size: usize) {
+    let format = ResultFormat::basic(max_message_size);
+    if read_client_message(&mut stream, format).is_err() {
+        return;
+    }
+    let _ = MicrobatServerMessage::Error(ErrorResponse::fatal(
+        sqlstate::TOO_MANY_CONNECTIONS,
+        "Sorry, too many clients already",
+    ))
+    .send_with(&mut stream, format.features);
+}
+
+/// Key of the session of given id, with a random secret
+fn backend_key(session_id: u32) -> BackendKey {
+    let mut secret = [0; 4];
+    getrandom::getrandom(&mut secret).expect("No randomness for session secret");
+    BackendKey {
+        session_id,
+        secret: u32::from_le_bytes(secret),
+    }
+}
+
+// Each session gets the parts of the server it needs rather than the server itself
+#[allow(clippy::too_many_arguments)]
+fn handle_connection(
+    mut stream: TcpStream,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    users: &UserStore,
+    server_format: ResultFormat,
+    key: BackendKey,
+    session: Session,
+    metrics: &Metrics,
+    shutdown: &ShutdownHandle,
+) {
+    let mut format = ResultFormat::basic(server_format.max_message_size);
+    let Some(_registration) = shutdown.register(key.session_id, &stream) else {
+        send_shutting_down(&mut stream, format);
+        return;
+    };
+    let served = serve_session(
+        &mut stream,
+        &mut format,
+        manager,
+        users,
+        server_format,
+        key,
+        session,
+        metrics,
+        shutdown,
+    );
+    if let Err(err) = served {
+        end_session(&mut stream, err, format, shutdown);
+    }
+}
+
+/// Answers the messages of a client until it disconnects or the server shuts down. Fails if
+/// the client breaks the protocol, lets reading time out or doesn't take its responses, with
+/// `format` left as negotiated for telling it why the session ends.
+#[allow(clippy::too_many_arguments)]
+fn serve_session(
+    stream: &mut TcpStream,
+    format: &mut ResultFormat,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    users: &UserStore,
+    server_format: ResultFormat,
+    key: BackendKey,
+    mut session: Session,
+    metrics: &Metrics,
+    shutdown: &ShutdownHandle,
+) -> Result<(), MicrobatProtocolError> {
+    let mut features = ProtocolFeatures::default();
+    let mut activity = ActivityReport::start(manager, key.session_id);
+    // Queries are refused until the client has logged in
+    let mut user = None;
+    // Count of schema changes told to the client, if it negotiated schema notifications
+    let mut schema_changes_seen = None;
+    // Statements of Parse messages by their names, kept for the session
+    let mut statements = HashMap::new();
+    loop {
+        // Checked before reading, as a message may have arrived before shutting down
+        if shutdown.is_requested() {
+            send_shutting_down(stream, *format);
+            return Ok(());
+        }
+        match read_client_message(stream, *format)? {
+            MicrobatClientMessage::Handshake(version, requested) => {
+                log!(
+                    LogLevel::Info,
+                    "Received handshake, protocol version {}",
+                    version
+                );
+                let Some(version) = negotiate_version(version) else {
+                    MicrobatServerMessage::UnsupportedVersion(
+                        MIN_PROTOCOL_VERSION,
+                        PROTOCOL_VERSION,
+                    )
+                    .send_with(stream, features)?;
+                    return Ok(());
+                };
+                let negotiated = requested.intersection(&ProtocolFeatures::all());
+                // Framed like the client's handshake, with the features negotiated before
+                MicrobatServerMessage::Handshake(version, negotiated)
+                    .send_with(stream, features)?;
+                features = negotiated;
+                *format = server_format.negotiate(version, features);
+                schema_changes_seen = features
+                    .schema_notifications
+                    .then(|| read_lock(manager).schema_changes().len());
+                if version >= BACKEND_KEY_PROTOCOL_VERSION {
+                    MicrobatServerMessage::BackendKeyData(key).send_with(stream, features)?;
+                }
+                if format.parameters {
+                    for (name, value) in session.parameters() {
+                        MicrobatServerMessage::ParameterStatus(
+                            String::from(name),
+                            String::from(value),
+                        )
+                        .send_with(stream, features)?;
+                    }
+                }
+                MicrobatServerMessage::Ready.send_with(stream, features)?;
+            }
+            MicrobatClientMessage::Authenticate {
+                user: name,
+                password,
+            } => {
+                if !users.authenticate(&name, &password) {
+                    log!(LogLevel::Warn, "Authentication failed for {}", name);
+                    MicrobatServerMessage::AuthFailed(format!(
+                        "Password authentication failed for user {}",
+                        name
+                    ))
+                    .send_with(stream, features)?;
+                    return Ok(());
+                }
+                log!(LogLevel::Info, "Authenticated {}", name);
+                activity.logged_in(&name);
+                user = Some(name);
+                MicrobatServerMessage::AuthOk.send_with(stream, features)?;
+            }
+            // Answered before login too, liveness tells nothing about the database
+            MicrobatClientMessage::Ping => {
+                send_schema_changes(stream, manager, &mut schema_changes_seen, features)?;
+                MicrobatServerMessage::Pong.send_with(stream, features)?;
+            }
+            MicrobatClientMessage::Disconnect => {
+                log!(LogLevel::Info, "Disconnect");
+                return Ok(());
+            }
+            MicrobatClientMessage::Query(query) => {
+                for statement in split_statements(&query) {
+                    match user {
+                        Some(_) => activity.executing(statement.to_owned(), || {
+                            execute_query(
+                                stream,
+                                statement.to_owned(),
+                                vec![],
+                                manager,
+                                &mut session,
+                                *format,
+                                metrics,
+                            )
+                        })?,
+                        None => refuse_query(stream, features)?,
+                    }
+                    send_ready(stream, manager, &mut schema_changes_seen, features)?;
+                }
+            }
+            MicrobatClientMessage::ParameterizedQuery(query, parameters) => {
+                match user {
+                    Some(_) => activity.executing(query.clone(), || {
+                        execute_query(
+                            stream,
+                            query,
+                            parameters,
+                            manager,
+                            &mut session,
+                            *format,
+                            metrics,
+                        )
+                    })?,
+                    None => refuse_query(stream, features)?,
+                }
+                send_ready(stream, manager, &mut schema_changes_seen, features)?;
+            }
+            MicrobatClientMessage::Parse(name, query) => {
+                match user {
+                    Some(_) => {
+                        log!(LogLevel::Debug, "Preparing {}: {}", name, query);
+                        match prepare_sql(query, manager) {
+                            Ok(statement) => {
+                                statements.insert(name, statement);
+                                MicrobatServerMessage::ParseComplete.send_with(stream, features)?;
+                            }
+                            Err(err) => send_query_error(stream, err, features)?,
+                        }
+                    }
+                    None => refuse_query(stream, features)?,
+                }
+                send_ready(stream, manager, &mut schema_changes_seen, features)?;
+            }
+            MicrobatClientMessage::Bind(name, parameters) => {
+                match user {
+                    Some(_) => match find_statement(&mut statements, &name)
+                        .and_then(|statement| statement.bind(&parameters))
+                    {
+                        Ok(()) => {
+                            MicrobatServerMessage::BindComplete.send_with(stream, features)?;
+                        }
+                        Err(err) => send_query_error(stream, err, features)?,
+                    },
+                    None => refuse_query(stream, features)?,
+                }
+                send_ready(stream, manager, &mut schema_changes_seen, features)?;
+            }
+            MicrobatClientMessage::Execute(name) => {
+                match user {
+                    Some(_) => {
+                        log!(LogLevel::Debug, "Executing prepared {}", name);
+                        let mut notices = vec![];
+                        let mut rows = RowSender::new(stream, *format);
+                        let query = statements
+                            .get(&name)
+                            .map_or_else(|| name.clone(), |statement| statement.sql().to_owned());
+                        let start = Instant::now();
+                        let result = activity.executing(query, || {
+                            find_statement(&mut statements, &name).and_then(|statement| {
+                                execute_prepared(
+                                    statement,
+                                    manager,
+                                    &mut session,
+                                    &mut notices,
+                                    &mut rows,
+                                )
+                            })
+                        });
+                        let sent = rows.finish();
+                        metrics.statement_executed(start.elapsed(), &result);
+                        sent?;
+                        send_result(stream, result, notices, manager, *format)?;
+                        send_parameter_changes(stream, &mut session, *format)?;
+                    }
+                    None => refuse_query(stream, features)?,
+                }
+                send_ready(stream, manager, &mut schema_changes_seen, features)?;
+            }
+            MicrobatClientMessage::CopyData(_) | MicrobatClientMessage::CopyDone => {
+                return Err(MicrobatProtocolError::new(
+                    "Received copy data outside of COPY",
+                ));
+            }
+        }
+    }
+}
+
+/// Reads next message, skipping unknown messages and verifying checksums if the client
+/// negotiated those. Messages larger than the format allows end the session.
+fn read_client_message(
+    stream: &mut TcpStream,
+    format: ResultFormat,
+) -> Result<MicrobatClientMessage, MicrobatProtocolError> {
+    read_message_limited(
+        stream,
+        deserialize_client_message,
+        is_client_message_type,
+        format.features,
+        format.max_message_size,
+    )
+}
+
+/// Tells the client why the session ends after it broke the protocol or let reading time out.
+/// Reading fails for every session when shutting down, and those are told of the shutdown
+/// instead.
+fn end_session(
+    stream: &mut TcpStream,
+    err: MicrobatProtocolError,
+    format: ResultFormat,
+    shutdown: &ShutdownHandle,
+) {
+    if shutdown.is_requested() {
+        send_shutting_down(stream, format);
+        return;
+    }
+    log!(LogLevel::Warn, "{:?}", err);
+    let error = match err.is_timeout() {
+        true => ErrorResponse::fatal(
+            sqlstate::IDLE_SESSION_TIMEOUT,
+            "Terminating session, client has been idle for too long",
+        ),
+        false => ErrorResponse::fatal(sqlstate::PROTOCOL_VIOLATION, err.msg),
+    };
+    // The peer may be gone already, so failing to tell it is no concern
+    let _ = MicrobatServerMessage::Error(error).send_with(stream, format.features);
+}
+
+/// Tells the client that the session ends because the server is shutting down, with a fatal
+/// error if it can't read ShuttingDown
+fn send_shutting_down(stream: &mut TcpStream, format: ResultFormat) {
+    let message = match format.shutting_down {
+        true => MicrobatServerMessage::ShuttingDown,
+        false => MicrobatServerMessage::Error(ErrorResponse::fatal(
+            sqlstate::ADMIN_SHUTDOWN,
+            "Server is shutting down",
+        )),
+    };
+    // Like ending the session for other reasons, the peer may be gone already
+    let _ = message.send_with(stream, format.features);
+}
+
+/// Statement of given name, or an error telling the client that no Parse stored one
+fn find_statement<'a>(
+    statements: &'a mut HashMap<String, PreparedStatement>,
+    name: &str,
+) -> Result<&'a mut PreparedStatement, MicrobatQueryError> {
+    statements.get_mut(name).ok_or_else(|| {
+        MicrobatQueryError::new(
+            sqlstate::INVALID_SQL_STATEMENT_NAME,
+            format!("Prepared statement {} does not exist", name),
         )
-        .unwrap();
+    })
+}
 
-    init_db
-        .create_table(
-            String::from("DEPARTMENTS"),
-            vec![
-                Column::new(String::from("id_dep"), MDataType::Integer),
-                Column::new(String::from("name_dep"), MDataType::Varchar),
-            ],
+/// Responds to a statement sent before authentication like to a failing one
+fn refuse_query(
+    stream: &mut TcpStream,
+    features: ProtocolFeatures,
+) -> Result<(), MicrobatProtocolError> {
+    MicrobatServerMessage::Error(ErrorResponse::new(
+        sqlstate::INVALID_AUTHORIZATION_SPECIFICATION,
+        "Authentication required",
+    ))
+    .send_with(stream, features)?;
+    Ok(())
+}
+
+/// Sends the rows of a result as they are received in DataRowBatch messages of at most the
+/// batch size of given format that fit in a frame, and rows with large values in chunks if
+/// the client can reassemble them. A row too large to send ends the result with an error
+/// after the rows before it. Rows are buffered and written to the stream together, not with
+/// a write of their own, and the buffer is written by `finish`. Rows are dropped once writing
+/// fails, and `finish` returns the error.
+struct RowSender<'a> {
+    writer: MessageWriter<&'a mut TcpStream>,
+    format: ResultFormat,
+    columns: Vec<Column>,
+    batch: Vec<DataRow>,
+    // Bytes the batch takes in a frame
+    batch_bytes: usize,
+    // Set once a row was too large to send, so the rest are dropped
+    failed: bool,
+    // First error writing to the stream
+    error: Option<MicrobatProtocolError>,
+}
+
+impl<'a> RowSender<'a> {
+    fn new(stream: &'a mut TcpStream, format: ResultFormat) -> Self {
+        RowSender {
+            writer: MessageWriter::new(stream, format.features),
+            format,
+            columns: vec![],
+            batch: vec![],
+            // Header and the count of rows
+            batch_bytes: 9,
+            failed: false,
+            error: None,
+        }
+    }
+
+    /// Sends the rows still in the batch and writes the buffered messages to the stream.
+    /// Fails with the first error writing to the stream.
+    fn finish(mut self) -> Result<(), MicrobatProtocolError> {
+        self.flush_rows();
+        match self.error {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+
+    /// Buffers a message in the writer, unless writing has failed already
+    fn send(&mut self, message: &MicrobatServerMessage) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.writer.send(message) {
+            self.error = Some(err);
+            self.failed = true;
+        }
+    }
+
+    /// Buffers the collected rows in the writer, a single row as a DataRow so it always fits
+    /// in a frame. Rows are compressed if the client negotiated compression.
+    fn flush_rows(&mut self) {
+        self.batch_bytes = 9;
+        let message = match self.batch.len() {
+            0 => return,
+            1 => MicrobatServerMessage::DataRow(self.batch.remove(0)),
+            _ => MicrobatServerMessage::DataRowBatch(std::mem::take(&mut self.batch)),
+        };
+        self.send(&message);
+    }
+}
+
+impl RowReceiver for RowSender<'_> {
+    fn describe(&mut self, schema: TableSchema) {
+        self.columns = schema.columns.clone();
+        self.send(&MicrobatServerMessage::DataDescription(schema));
+    }
+
+    fn receive(&mut self, row: DataRow) {
+        if self.failed {
+            return;
+        }
+        let max_row_size = match self.format.chunk_size {
+            Some(_) => MAX_CHUNKED_ROW_SIZE,
+            None => MAX_FRAME_SIZE,
+        };
+        let size = match check_row_size(&row, &self.columns, max_row_size) {
+            Ok(size) => size,
+            Err(err) => {
+                self.flush_rows();
+                self.send(&MicrobatServerMessage::Error(ErrorResponse::new(
+                    sqlstate::PROGRAM_LIMIT_EXCEEDED,
+                    err.msg,
+                )));
+                self.failed = true;
+                return;
+            }
+        };
+        if let Some(chunk_size) = self.format.chunk_size {
+            if size > MAX_FRAME_SIZE
+                || row
+                    .columns
+                    .iter()
+                    .any(|value| value.byte_len() > chunk_size)
+            {
+                self.flush_rows();
+                for chunk in row_chunks(&row, chunk_size) {
+                    self.send(&chunk);
+                }
+                return;
+            }
+        }
+        // In a batch the header of the row is replaced with its count of columns
+        if !self.batch.is_empty() && self.batch_bytes + size - 1 > MAX_FRAME_SIZE {
+            self.flush_rows();
+        }
+        self.batch_bytes += size - 1;
+        self.batch.push(row);
+        if self.batch.len() >= self.format.batch_size {
+            self.flush_rows();
+        }
+    }
+}
+
+/// Sends `SchemaChanged` for the tables created since the client was last told, if it
+/// negotiated schema notifications. Sent only between responses, so a client that is idle
+/// hears of changes when it sends its next query or ping.
+fn send_schema_changes(
+    stream: &mut TcpStream,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    seen: &mut Option<usize>,
+    features: ProtocolFeatures,
+) -> Result<(), MicrobatProtocolError> {
+    let Some(seen) = seen else {
+        return Ok(());
+    };
+    // Copied out so the lock isn't held while writing to a slow client
+    let changes = read_lock(manager).schema_changes()[*seen..].to_vec();
+    *seen += changes.len();
+    for table in changes {
+        MicrobatServerMessage::SchemaChanged(table).send_with(stream, features)?;
+    }
+    Ok(())
+}
+
+/// Sends schema changes and Ready, ending the response to a message
+fn send_ready(
+    stream: &mut TcpStream,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    seen: &mut Option<usize>,
+    features: ProtocolFeatures,
+) -> Result<(), MicrobatProtocolError> {
+    send_schema_changes(stream, manager, seen, features)?;
+    MicrobatServerMessage::Ready.send_with(stream, features)?;
+    Ok(())
+}
+
+fn execute_query(
+    stream: &mut TcpStream,
+    query: String,
+    parameters: Vec<MData>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    session: &mut Session,
+    format: ResultFormat,
+    metrics: &Metrics,
+) -> Result<(), MicrobatProtocolError> {
+    log!(LogLevel::Debug, "Executing {}", query);
+    let mut notices = vec![];
+    let mut rows = RowSender::new(stream, format);
+    let start = Instant::now();
+    let result =
+        execute_sql_with_notices(query, parameters, manager, session, &mut notices, &mut rows);
+    let sent = rows.finish();
+    metrics.statement_executed(start.elapsed(), &result);
+    sent?;
+    send_result(stream, result, notices, manager, format)?;
+    send_parameter_changes(stream, session, format)
+}
+
+/// Tells the client of the parameters the session changed with SET, if it can read
+/// ParameterStatus
+fn send_parameter_changes(
+    stream: &mut TcpStream,
+    session: &mut Session,
+    format: ResultFormat,
+) -> Result<(), MicrobatProtocolError> {
+    for (name, value) in session.take_changes() {
+        if format.parameters {
+            MicrobatServerMessage::ParameterStatus(name, value)
+                .send_with(stream, format.features)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends the notices of a statement followed by its result, or the error it failed with.
+/// Rows of COPY ... FROM STDIN are read from the client before answering. Fails if the client
+/// breaks the protocol while sending them or doesn't take the response.
+fn send_result(
+    stream: &mut TcpStream,
+    result: Result<QueryResult, MicrobatQueryError>,
+    notices: Vec<QueryNotice>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    format: ResultFormat,
+) -> Result<(), MicrobatProtocolError> {
+    if format.notices {
+        for notice in notices {
+            MicrobatServerMessage::Notice(ErrorResponse::warning(notice.code, notice.msg))
+                .send_with(stream, format.features)?;
+        }
+    }
+    match result {
+        Ok(result) => match result {
+            QueryResult::Table(description, data) => {
+                let mut rows = RowSender::new(stream, format);
+                rows.describe(description);
+                for row in data {
+                    rows.receive(row);
+                }
+                rows.finish()?;
+            }
+            // Sent while the select was executed
+            QueryResult::Streamed(_) => {}
+            QueryResult::Inserted(rows) => {
+                MicrobatServerMessage::InsertResult(rows).send_with(stream, format.features)?;
+            }
+            QueryResult::Command(tag) => {
+                let message = match format.commands {
+                    true => MicrobatServerMessage::CommandComplete(String::from(tag)),
+                    false => MicrobatServerMessage::InsertResult(0),
+                };
+                message.send_with(stream, format.features)?;
+            }
+            QueryResult::CopyIn(table, schema) => {
+                if !format.copy {
+                    return send_query_error(
+                        stream,
+                        MicrobatQueryError::new(
+                            sqlstate::FEATURE_NOT_SUPPORTED,
+                            format!(
+                                "COPY FROM STDIN needs protocol version {}",
+                                COPY_PROTOCOL_VERSION
+                            ),
+                        ),
+                        format.features,
+                    );
+                }
+                MicrobatServerMessage::CopyIn(schema).send_with(stream, format.features)?;
+                let rows = receive_copy(stream, format)?;
+                log!(LogLevel::Debug, "Copying {} rows to {}", rows.len(), table);
+                let mut notices = vec![];
+                let result = copy_rows(&table, rows, manager, &mut notices);
+                return send_result(stream, result, notices, manager, format);
+            }
+        },
+        Err(err) => send_query_error(stream, err, format.features)?,
+    }
+    Ok(())
+}
+
+/// Reads the rows of COPY ... FROM STDIN up to CopyDone. Client must finish the copy before
+/// sending anything else.
+fn receive_copy(
+    stream: &mut TcpStream,
+    format: ResultFormat,
+) -> Result<Vec<DataRow>, MicrobatProtocolError> {
+    let mut rows = vec![];
+    loop {
+        match read_client_message(stream, format)? {
+            MicrobatClientMessage::CopyData(data) => rows.extend(data),
+            MicrobatClientMessage::CopyDone => return Ok(rows),
+            _ => {
+                return Err(MicrobatProtocolError::new(
+                    "Expecting CopyData or CopyDone during COPY",
+                ))
+            }
+        }
+    }
+}
+
+fn send_query_error(
+    stream: &mut TcpStream,
+    err: MicrobatQueryError,
+    features: ProtocolFeatures,
+) -> Result<(), MicrobatProtocolError> {
+    let mut response = ErrorResponse::new(err.code, err.msg);
+    response.position = err
+        .position
+        .map(|position| (position.line as u32, position.column as u32));
+    MicrobatServerMessage::Error(response).send_with(stream, features)?;
+    Ok(())
+}
+
+/// Helpers for testing the server over real connections
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+    use microbat_protocol::messages::read_message_with;
+    use microbat_protocol::messages::server_messages::deserialize_server_message;
+
+    /// Options of a server on a free port of localhost
+    pub(crate) fn test_opts() -> MicrobatServerOpts {
+        MicrobatServerOpts {
+            bind: String::from("127.0.0.1:0"),
+            case_folding: CaseFolding::default(),
+            row_batch_size: DEFAULT_ROW_BATCH_SIZE,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            timeouts: DEFAULT_TIMEOUTS,
+            data_dir: None,
+            file_dir: None,
+            checkpoint_interval: None,
+            max_parallel_workers: DEFAULT_MAX_PARALLEL_WORKERS,
+            init_file: None,
+            log_level: LogLevel::Info,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            statement_timeout: None,
+            metrics_bind: None,
+            shutdown_timeout: Some(DEFAULT_SHUTDOWN_TIMEOUT),
+        }
+    }
+
+    pub(crate) fn read(stream: &mut TcpStream) -> MicrobatServerMessage {
+        read_message_with(
+            stream,
+            deserialize_server_message,
+            |_| true,
+            ProtocolFeatures::default(),
         )
+        .unwrap()
+    }
+
+    /// Connects and handshakes with given protocol version, reading up to Ready
+    pub(crate) fn session(address: SocketAddr, version: u16) -> TcpStream {
+        let mut stream = TcpStream::connect(address).unwrap();
+        MicrobatClientMessage::Handshake(version, ProtocolFeatures::default())
+            .send_with(&mut stream, ProtocolFeatures::default())
+            .unwrap();
+        while read(&mut stream) != MicrobatServerMessage::Ready {}
+        stream
+    }
+}
+
+#[cfg(test)]
+mod connect_tests {
+    use super::test_util::{read, session, test_opts};
+    use super::*;
+    use microbat_protocol::data::data_values::MDataType;
+
+    #[test]
+    fn test_idle_sessions_time_out() {
+        let server = MicrobatServer::bind(MicrobatServerOpts {
+            timeouts: ConnectionTimeouts {
+                read: Some(Duration::from_millis(50)),
+                write: None,
+            },
+            ..test_opts()
+        })
         .unwrap();
-    init_db
-        .insert(
-            "DEPARTMENTS",
-            vec![MData::Integer(1), MData::Varchar(String::from("Rustland"))],
-        )
+        let address = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut stream = session(address, PROTOCOL_VERSION);
+        match read(&mut stream) {
+            MicrobatServerMessage::Error(error) => {
+                assert_eq!(error.code, sqlstate::IDLE_SESSION_TIMEOUT);
+            }
+            message => panic!("Expecting a timeout error but got {}", message),
+        }
+        shutdown.shut_down();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_clients_not_taking_responses_end_their_sessions() {
+        let server = MicrobatServer::bind(MicrobatServerOpts {
+            timeouts: ConnectionTimeouts {
+                read: None,
+                write: Some(Duration::from_millis(50)),
+            },
+            ..test_opts()
+        })
         .unwrap();
-    init_db
-        .insert(
-            "DEPARTMENTS",
-            vec![MData::Integer(2), MData::Varchar(String::from("Goland"))],
-        )
+        {
+            let mut database = write_lock(&server.database);
+            database
+                .create_table(
+                    String::from("large"),
+                    vec![Column::new(String::from("value"), MDataType::Varchar)],
+                )
+                .unwrap();
+            let rows = vec![vec![MData::Varchar("x".repeat(1024 * 1024))]; 32];
+            database.insert_rows("large", rows).unwrap();
+        }
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let (stream, _) = server.listener.accept().unwrap();
+        server.timeouts.apply(&stream).unwrap();
+        let serving = thread::spawn(move || {
+            handle_connection(
+                stream,
+                &server.database,
+                &server.users,
+                server.format,
+                backend_key(1),
+                Session::new(),
+                &server.metrics,
+                &server.shutdown,
+            )
+        });
+
+        MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+            .send(&mut client)
+            .unwrap();
+        MicrobatClientMessage::Authenticate {
+            user: String::from(auth::DEFAULT_USER),
+            password: String::from(auth::DEFAULT_PASSWORD),
+        }
+        .send(&mut client)
         .unwrap();
-    init_db
-        .insert(
-            "DEPARTMENTS",
-            vec![MData::Integer(3), MData::Varchar(String::from("Javaland"))],
-        )
+        // More than the buffers of the connection hold, and none of it is read
+        MicrobatClientMessage::Query(String::from("select value from large;"))
+            .send(&mut client)
+            .unwrap();
+        assert!(
+            serving.join().is_ok(),
+            "Session should end without panicking"
+        );
+    }
+
+    #[test]
+    fn test_connections_beyond_max_are_refused() {
+        let server = MicrobatServer::bind(MicrobatServerOpts {
+            max_connections: 1,
+            ..test_opts()
+        })
         .unwrap();
-    init_db
-        .insert(
-            "DEPARTMENTS",
-            vec![MData::Integer(4), MData::Varchar(String::from("Cppland"))],
-        )
+        let address = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let first = session(address, PROTOCOL_VERSION);
+        let mut refused = TcpStream::connect(address).unwrap();
+        MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+            .send(&mut refused)
+            .unwrap();
+        match read(&mut refused) {
+            MicrobatServerMessage::Error(error) => {
+                assert_eq!(error.code, sqlstate::TOO_MANY_CONNECTIONS);
+            }
+            message => panic!("Expecting too many connections but got {}", message),
+        }
+        drop(first);
+        // The slot of the first session is freed once its thread sees it disconnect
+        let mut attempts = 0;
+        loop {
+            let mut stream = TcpStream::connect(address).unwrap();
+            MicrobatClientMessage::Handshake(PROTOCOL_VERSION, ProtocolFeatures::default())
+                .send(&mut stream)
+                .unwrap();
+            match read(&mut stream) {
+                MicrobatServerMessage::Handshake(..) => break,
+                _ if attempts < 100 => attempts += 1,
+                message => panic!("Expecting a handshake but got {}", message),
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        shutdown.shut_down();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_set_tells_of_parameter() {
+        let server = MicrobatServer::bind(test_opts()).unwrap();
+        let address = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        // Older clients are told that the statement inserted nothing instead
+        for (version, completed) in [
+            (
+                PROTOCOL_VERSION,
+                MicrobatServerMessage::CommandComplete(String::from("SET")),
+            ),
+            (
+                COMMAND_COMPLETE_PROTOCOL_VERSION - 1,
+                MicrobatServerMessage::InsertResult(0),
+            ),
+        ] {
+            let mut stream = session(address, version);
+            MicrobatClientMessage::Authenticate {
+                user: String::from(auth::DEFAULT_USER),
+                password: String::from(auth::DEFAULT_PASSWORD),
+            }
+            .send(&mut stream)
+            .unwrap();
+            assert_eq!(read(&mut stream), MicrobatServerMessage::AuthOk);
+            MicrobatClientMessage::Query(String::from("set statement_timeout = 250;"))
+                .send(&mut stream)
+                .unwrap();
+            assert_eq!(read(&mut stream), completed);
+            assert_eq!(
+                read(&mut stream),
+                MicrobatServerMessage::ParameterStatus(
+                    String::from("statement_timeout"),
+                    String::from("250")
+                )
+            );
+            assert_eq!(read(&mut stream), MicrobatServerMessage::Ready);
+        }
+        shutdown.shut_down();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_sessions_are_listed() {
+        let server = MicrobatServer::bind(test_opts()).unwrap();
+        let address = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let waiting = session(address, PROTOCOL_VERSION);
+        let mut stream = session(address, PROTOCOL_VERSION);
+        MicrobatClientMessage::Authenticate {
+            user: String::from(auth::DEFAULT_USER),
+            password: String::from(auth::DEFAULT_PASSWORD),
+        }
+        .send(&mut stream)
         .unwrap();
-    init_db
-        .insert(
-            "DEPARTMENTS",
+        assert_eq!(read(&mut stream), MicrobatServerMessage::AuthOk);
+        let query = "select user_name, state, query from microbat_sessions order by session_id;";
+        MicrobatClientMessage::Query(String::from(query))
+            .send(&mut stream)
+            .unwrap();
+        let mut rows = vec![];
+        loop {
+            match read(&mut stream) {
+                MicrobatServerMessage::DataRow(row) => rows.push(row.columns),
+                MicrobatServerMessage::DataRowBatch(batch) => {
+                    rows.extend(batch.into_iter().map(|row| row.columns))
+                }
+                MicrobatServerMessage::Ready => break,
+                _ => {}
+            }
+        }
+        assert_eq!(
+            rows,
             vec![
-                MData::Integer(5),
-                MData::Varchar(String::from("Nodejsland")),
-            ],
-        )
+                vec![
+                    MData::Null,
+                    MData::Varchar(String::from("idle")),
+                    MData::Null
+                ],
+                vec![
+                    MData::Varchar(String::from(auth::DEFAULT_USER)),
+                    MData::Varchar(String::from("active")),
+                    MData::Varchar(String::from(query))
+                ],
+            ]
+        );
+        drop(waiting);
+        shutdown.shut_down();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_metrics_are_served() {
+        use std::io::{Read, Write};
+
+        let server = MicrobatServer::bind(MicrobatServerOpts {
+            metrics_bind: Some(String::from("127.0.0.1:0")),
+            ..test_opts()
+        })
         .unwrap();
-    init_db
-        .create_table(
-            String::from("MODES"),
-            vec![
-                Column::new(String::from("id_mode"), MDataType::Integer),
-                Column::new(String::from("name_mode"), MDataType::Varchar),
-            ],
-        )
+        let address = server.local_addr().unwrap();
+        let metrics_address = server.metrics_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut stream = session(address, PROTOCOL_VERSION);
+        MicrobatClientMessage::Authenticate {
+            user: String::from(auth::DEFAULT_USER),
+            password: String::from(auth::DEFAULT_PASSWORD),
+        }
+        .send(&mut stream)
         .unwrap();
-    init_db
-        .insert(
-            "MODES",
-            vec![MData::Integer(1), MData::Varchar(String::from("soft"))],
-        )
+        assert_eq!(read(&mut stream), MicrobatServerMessage::AuthOk);
+        MicrobatClientMessage::Query(String::from(
+            "create table foo (id integer); select nope from foo;",
+        ))
+        .send(&mut stream)
         .unwrap();
-    init_db
-        .insert(
-            "MODES",
-            vec![MData::Integer(2), MData::Varchar(String::from("medium"))],
+        let mut readies = 0;
+        while readies < 2 {
+            if read(&mut stream) == MicrobatServerMessage::Ready {
+                readies += 1;
+            }
+        }
+
+        let mut scraper = TcpStream::connect(metrics_address).unwrap();
+        scraper
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        scraper.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("\nmicrobat_connections_total 1\n"));
+        assert!(response.contains("\nmicrobat_queries_total 2\n"));
+        assert!(response.contains("\nmicrobat_query_errors_total 1\n"));
+        assert!(response.contains("\nmicrobat_query_duration_seconds_count 2\n"));
+        shutdown.shut_down();
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_init_file_is_executed_once() {
+        let directory =
+            std::env::temp_dir().join(format!("microbat-server-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let init_file = directory.join("init.sql");
+        std::fs::write(
+            &init_file,
+            "create table modes (id integer, name varchar);\n\
+             insert into modes values (1, 'soft'), (2, 'hard;er');\n",
         )
         .unwrap();
-    init_db
-        .insert(
-            "MODES",
-            vec![MData::Integer(3), MData::Varchar(String::from("hard"))],
+        let opts = || MicrobatServerOpts {
+            data_dir: Some(directory.join("data")),
+            init_file: Some(init_file.clone()),
+            ..test_opts()
+        };
+        let rows = |server: &MicrobatServer| read_lock(&server.database).fetch("modes").unwrap();
+        let server = MicrobatServer::bind(opts()).unwrap();
+        assert_eq!(rows(&server).len(), 2);
+        drop(server);
+        // Tables are loaded instead of created again
+        let server = MicrobatServer::bind(opts()).unwrap();
+        assert_eq!(read_lock(&server.database).schema_changes().len(), 1);
+        assert_eq!(rows(&server).len(), 2);
+        drop(server);
+
+        // Without an init file the database starts empty
+        let server = MicrobatServer::bind(test_opts()).unwrap();
+        assert!(read_lock(&server.database).schema_changes().is_empty());
+
+        std::fs::write(
+            &init_file,
+            "create table foo (id integer);\nselect nope from foo;",
         )
         .unwrap();
-    drop(init_db);
-    let mut thread_id = 1;
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        let db_arc = Arc::clone(&database);
-        thread::Builder::new()
-            .name(format!("microbat-t-{}", thread_id))
-            .spawn(move || {
-                handle_connection(stream, &db_arc);
-            })
-            .expect("Thread spawn failure");
-        thread_id = thread_id + 1;
-    }
-}
-
-fn handle_connection(mut stream: TcpStream, manager: &Arc<RwLock<impl DatabaseManager>>) {
-    loop {
-        match read_message(&mut stream, deserialize_client_message) {
-            Ok(message) => match message {
-                MicrobatClientMessage::Handshake => {
-                    println!("Received handshake");
-                    MicrobatServerMessage::Handshake.send(&mut stream).unwrap();
-                    MicrobatServerMessage::Ready.send(&mut stream).unwrap();
-                }
-                MicrobatClientMessage::Disconnect => {
-                    println!("Disconnect");
-                    break;
-                }
-                MicrobatClientMessage::Query(query) => {
-                    println!("Executing {}", query);
-                    match execute_sql(query, manager) {
-                        Ok(result) => match result {
-                            QueryResult::Table(description, data) => {
-                                MicrobatServerMessage::DataDescription(description)
-                                    .send(&mut stream)
-                                    .unwrap();
-                                for row in data.into_iter() {
-                                    MicrobatServerMessage::DataRow(row)
-                                        .send(&mut stream)
-                                        .unwrap();
-                                }
-                            }
-                        },
-                        Err(err) => {
-                            MicrobatServerMessage::Error(err.msg)
-                                .send(&mut stream)
-                                .unwrap();
-                        }
-                    }
-                    MicrobatServerMessage::Ready.send(&mut stream).unwrap();
-                }
-            },
-            Err(err) => {
-                println!("{:?}", err);
-                break;
-            }
+        let failing = MicrobatServer::bind(MicrobatServerOpts {
+            init_file: Some(init_file.clone()),
+            ..test_opts()
+        });
+        match failing {
+            Err(err) => assert!(err.to_string().contains("select nope from foo")),
+            Ok(_) => panic!("Failing init file should fail starting"),
         }
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 }
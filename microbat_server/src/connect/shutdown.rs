@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Shuts down a running server from another thread, see `MicrobatServer::shutdown_handle`.
+///
+/// Shutting down stops accepting connections and closes the reading half of the connection
+/// of every session, which wakes the sessions waiting for a message. Sessions finish the
+/// statement they are executing and tell their client that the server is shutting down
+/// instead of reading the next message.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+struct ShutdownState {
+    requested: AtomicBool,
+    // Connections of the sessions by their ids
+    sessions: Mutex<HashMap<u32, TcpStream>>,
+    // Notified when a session ends
+    session_ended: Condvar,
+    // Address the server listens on, connected to for waking it from accepting
+    address: SocketAddr,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(address: SocketAddr) -> Self {
+        ShutdownHandle {
+            state: Arc::new(ShutdownState {
+                requested: AtomicBool::new(false),
+                sessions: Mutex::new(HashMap::new()),
+                session_ended: Condvar::new(),
+                address,
+            }),
+        }
+    }
+
+    /// Starts shutting down the server, returning without waiting for the sessions to end
+    pub fn shut_down(&self) {
+        // Set before the sessions are locked, so a session registering later sees it
+        if self.state.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for stream in self.sessions().values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+        let _ = TcpStream::connect(self.state.address);
+    }
+
+    /// True once the server is shutting down
+    pub fn is_requested(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+    }
+
+    /// Registers the connection of a session for waking it on shutdown, until the returned
+    /// registration is dropped. None if the server is already shutting down, in which case
+    /// the session should end right away.
+    pub(crate) fn register(&self, session_id: u32, stream: &TcpStream) -> Option<Registration> {
+        let mut sessions = self.sessions();
+        if self.is_requested() {
+            return None;
+        }
+        if let Ok(stream) = stream.try_clone() {
+            sessions.insert(session_id, stream);
+        }
+        Some(Registration {
+            handle: self.clone(),
+            session_id,
+        })
+    }
+
+    /// Blocks until every registered session has ended, or at most given time. False if
+    /// sessions were still running when the time ran out.
+    pub(crate) fn wait_for_sessions(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut sessions = self.sessions();
+        while !sessions.is_empty() {
+            let ended = &self.state.session_ended;
+            sessions = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return false;
+                    }
+                    ended
+                        .wait_timeout(sessions, left)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => ended.wait(sessions).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+        true
+    }
+
+    fn unregister(&self, session_id: u32) {
+        self.sessions().remove(&session_id);
+        self.state.session_ended.notify_all();
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<u32, TcpStream>> {
+        self.state
+            .sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Session registered for shutting down, unregistered when dropped so that a panicking session
+/// doesn't keep the server waiting for it
+pub(crate) struct Registration {
+    handle: ShutdownHandle,
+    session_id: u32,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.handle.unregister(self.session_id);
+    }
+}
+
+#[cfg(test)]
+mod shutdown_tests {
+    use super::*;
+    use crate::connect::test_util::{read, session, test_opts};
+    use crate::connect::MicrobatServer;
+    use microbat_protocol::messages::server_messages::{ErrorResponse, MicrobatServerMessage};
+    use microbat_protocol::messages::{PROTOCOL_VERSION, SHUTDOWN_PROTOCOL_VERSION};
+    use microbat_protocol::sqlstate;
+    use std::thread;
+
+    #[test]
+    fn test_sessions_are_told_of_shutting_down() {
+        let server = MicrobatServer::bind(test_opts()).unwrap();
+        let address = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        let mut current = session(address, PROTOCOL_VERSION);
+        let mut older = session(address, SHUTDOWN_PROTOCOL_VERSION - 1);
+        shutdown.shut_down();
+        assert_eq!(read(&mut current), MicrobatServerMessage::ShuttingDown);
+        // Older client can't read ShuttingDown, so it gets a fatal error
+        assert_eq!(
+            read(&mut older),
+            MicrobatServerMessage::Error(ErrorResponse::fatal(
+                sqlstate::ADMIN_SHUTDOWN,
+                "Server is shutting down"
+            ))
+        );
+        running.join().unwrap();
+        assert!(shutdown.is_requested());
+    }
+
+    #[test]
+    fn test_waiting_for_sessions_is_bounded() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shutdown = ShutdownHandle::new(address);
+        let stream = TcpStream::connect(address).unwrap();
+        let registration = shutdown.register(1, &stream).unwrap();
+        shutdown.shut_down();
+        // Session is still executing a statement, never reading the shutdown
+        let start = Instant::now();
+        assert!(!shutdown.wait_for_sessions(Some(Duration::from_millis(50))));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        drop(registration);
+        assert!(shutdown.wait_for_sessions(Some(Duration::from_millis(50))));
+        assert!(shutdown.wait_for_sessions(None));
+    }
+}
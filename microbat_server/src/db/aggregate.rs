@@ -0,0 +1,236 @@
+use std::cmp::Ordering;
+
+use microbat_protocol::data::data_values::MData;
+use microbat_protocol::sqlstate;
+
+use crate::db::sort::{compare_values, SortOptions};
+use crate::sql::expression::{AggregateFunction, EvaluationError};
+
+/// Running state of an aggregate function over the rows of one group.
+///
+/// Null values are skipped by every aggregate, so an aggregate of only nulls is null,
+/// except for count, which is zero.
+pub enum Accumulator {
+    Count(i32),
+    Sum(Option<MData>),
+    Min(Option<MData>),
+    Max(Option<MData>),
+    StringAgg {
+        separator: String,
+        value: Option<String>,
+    },
+}
+
+impl Accumulator {
+    pub fn new(function: &AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(None),
+            AggregateFunction::Min => Accumulator::Min(None),
+            AggregateFunction::Max => Accumulator::Max(None),
+            AggregateFunction::StringAgg(separator) => Accumulator::StringAgg {
+                separator: separator.clone(),
+                value: None,
+            },
+        }
+    }
+
+    pub fn push(&mut self, value: MData) -> Result<(), EvaluationError> {
+        if value == MData::Null {
+            return Ok(());
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => match (sum.take(), value) {
+                // Integers are summed as big integers, like PostgreSQL sums them
+                (None, MData::Integer(value)) => *sum = Some(MData::BigInt(i64::from(value))),
+                // Any number can be summed, as_float is some only for numbers
+                (None, value) if value.as_float().is_some() => *sum = Some(value),
+                (Some(MData::BigInt(current)), value @ (MData::Integer(_) | MData::BigInt(_))) => {
+                    *sum = Some(MData::BigInt(
+                        current
+                            .checked_add(value.as_bigint().unwrap())
+                            .ok_or(EvaluationError {
+                                code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+                                msg: String::from("Integer overflow in sum"),
+                            })?,
+                    ))
+                }
+                (Some(current), value) if value.as_float().is_some() => {
+                    *sum = Some(current.apply_plus(value)?)
+                }
+                (_, value) => {
+                    return Err(EvaluationError {
+                        code: sqlstate::DATATYPE_MISMATCH,
+                        msg: format!("Can't sum {:?}", value),
+                    })
+                }
+            },
+            Accumulator::Min(min) => keep(min, value, Ordering::Less),
+            Accumulator::Max(max) => keep(max, value, Ordering::Greater),
+            Accumulator::StringAgg {
+                separator,
+                value: current,
+            } => match (current, value) {
+                (Some(current), MData::Varchar(value)) => {
+                    current.push_str(separator);
+                    current.push_str(&value);
+                }
+                (current, MData::Varchar(value)) => *current = Some(value),
+                (_, value) => {
+                    return Err(EvaluationError {
+                        code: sqlstate::DATATYPE_MISMATCH,
+                        msg: format!("Can't concatenate {:?}", value),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Merges the state of this aggregate over earlier rows with its state over the rows
+    /// after them, e.g. over partitions of a table aggregated by different workers
+    pub fn merge(&mut self, later: Accumulator) -> Result<(), EvaluationError> {
+        match (self, later) {
+            (Accumulator::Count(count), Accumulator::Count(later)) => {
+                *count += later;
+                Ok(())
+            }
+            // Other aggregates are values like the ones they aggregate, null if nothing was
+            (accumulator, later) => accumulator.push(later.finish()),
+        }
+    }
+
+    pub fn finish(self) -> MData {
+        match self {
+            Accumulator::Count(count) => MData::Integer(count),
+            Accumulator::Sum(sum) => sum.unwrap_or(MData::Null),
+            Accumulator::Min(value) | Accumulator::Max(value) => value.unwrap_or(MData::Null),
+            Accumulator::StringAgg { value, .. } => value.map_or(MData::Null, MData::Varchar),
+        }
+    }
+}
+
+/// Replaces current value if the new one compares as `wanted` to it
+pub fn keep(current: &mut Option<MData>, value: MData, wanted: Ordering) {
+    let replace = match current {
+        Some(current) => compare_values(&value, current, &SortOptions::default()) == wanted,
+        None => true,
+    };
+    if replace {
+        *current = Some(value);
+    }
+}
+
+#[cfg(test)]
+mod aggregate_tests {
+    use super::*;
+
+    fn aggregate(function: AggregateFunction, values: Vec<MData>) -> MData {
+        let mut accumulator = Accumulator::new(&function);
+        for value in values {
+            accumulator.push(value).unwrap();
+        }
+        accumulator.finish()
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let values = vec![MData::Integer(2), MData::Null, MData::Integer(5)];
+        assert_eq!(
+            aggregate(AggregateFunction::Count, values.clone()),
+            MData::Integer(2)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, values.clone()),
+            MData::BigInt(7)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Min, values.clone()),
+            MData::Integer(2)
+        );
+        assert_eq!(aggregate(AggregateFunction::Max, values), MData::Integer(5));
+    }
+
+    #[test]
+    fn test_aggregates_of_nulls() {
+        assert_eq!(
+            aggregate(AggregateFunction::Count, vec![MData::Null]),
+            MData::Integer(0)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, vec![MData::Null]),
+            MData::Null
+        );
+        assert_eq!(aggregate(AggregateFunction::Max, vec![]), MData::Null);
+    }
+
+    #[test]
+    fn test_string_agg() {
+        let function = AggregateFunction::StringAgg(String::from(", "));
+        let varchar = |value: &str| MData::Varchar(String::from(value));
+        assert_eq!(
+            aggregate(
+                function.clone(),
+                vec![varchar("a"), MData::Null, varchar("b"), varchar("c")]
+            ),
+            varchar("a, b, c")
+        );
+        assert_eq!(aggregate(function.clone(), vec![MData::Null]), MData::Null);
+        assert!(Accumulator::new(&function).push(MData::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let merged = |function: AggregateFunction, earlier: Vec<MData>, later: Vec<MData>| {
+            let mut accumulator = Accumulator::new(&function);
+            for value in earlier {
+                accumulator.push(value).unwrap();
+            }
+            let mut partial = Accumulator::new(&function);
+            for value in later {
+                partial.push(value).unwrap();
+            }
+            accumulator.merge(partial).unwrap();
+            accumulator.finish()
+        };
+        let values = vec![MData::Integer(2), MData::Null, MData::Integer(5)];
+        assert_eq!(
+            merged(AggregateFunction::Count, values.clone(), values.clone()),
+            MData::Integer(4)
+        );
+        assert_eq!(
+            merged(AggregateFunction::Sum, values.clone(), vec![MData::Null]),
+            MData::BigInt(7)
+        );
+        assert_eq!(
+            merged(AggregateFunction::Min, vec![], values.clone()),
+            MData::Integer(2)
+        );
+        let varchar = |value: &str| MData::Varchar(String::from(value));
+        assert_eq!(
+            merged(
+                AggregateFunction::StringAgg(String::from("-")),
+                vec![varchar("a"), varchar("b")],
+                vec![varchar("c")]
+            ),
+            varchar("a-b-c")
+        );
+    }
+
+    #[test]
+    fn test_sum_errors() {
+        let mut accumulator = Accumulator::new(&AggregateFunction::Sum);
+        assert!(accumulator.push(MData::Varchar(String::from("a"))).is_err());
+        // Integers don't overflow before big integers do
+        accumulator.push(MData::Integer(i32::MAX)).unwrap();
+        accumulator.push(MData::Integer(1)).unwrap();
+        assert_eq!(accumulator.finish(), MData::BigInt(i64::from(i32::MAX) + 1));
+        let mut accumulator = Accumulator::new(&AggregateFunction::Sum);
+        accumulator.push(MData::BigInt(i64::MAX)).unwrap();
+        assert_eq!(
+            accumulator.push(MData::Integer(1)).unwrap_err().msg,
+            "Integer overflow in sum"
+        );
+    }
+}
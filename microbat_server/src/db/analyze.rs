@@ -0,0 +1,60 @@
+use microbat_protocol::data::data_values::MData;
+
+use crate::db::sort::{compare_values, SortOptions};
+
+/// Statistics of the values of a table, collected by ANALYZE for the planner to estimate
+/// how many rows the steps of a plan produce
+pub struct TableStatistics {
+    /// Count of rows when analyzed
+    pub rows: usize,
+    /// Count of distinct values other than null in each column
+    pub distinct: Vec<usize>,
+}
+
+impl TableStatistics {
+    /// Statistics of given rows of a table with given count of columns
+    pub fn collect(rows: &[Vec<MData>], columns: usize) -> Self {
+        let options = SortOptions::default();
+        let distinct = (0..columns)
+            .map(|column| {
+                let mut values: Vec<&MData> = rows
+                    .iter()
+                    .map(|row| &row[column])
+                    .filter(|value| **value != MData::Null)
+                    .collect();
+                values.sort_by(|left, right| compare_values(left, right, &options));
+                values.dedup_by(|left, right| compare_values(left, right, &options).is_eq());
+                values.len()
+            })
+            .collect();
+        TableStatistics {
+            rows: rows.len(),
+            distinct,
+        }
+    }
+}
+
+#[cfg(test)]
+mod analyze_tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let rows: Vec<Vec<MData>> = [(1, Some("a")), (2, Some("a")), (2, None), (3, Some("b"))]
+            .into_iter()
+            .map(|(id, name)| {
+                vec![
+                    MData::Integer(id),
+                    name.map_or(MData::Null, |name| MData::Varchar(String::from(name))),
+                ]
+            })
+            .collect();
+        let statistics = TableStatistics::collect(&rows, 2);
+        assert_eq!(statistics.rows, 4);
+        assert_eq!(statistics.distinct, vec![3, 2]);
+
+        let statistics = TableStatistics::collect(&[], 2);
+        assert_eq!(statistics.rows, 0);
+        assert_eq!(statistics.distinct, vec![0, 0]);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use microbat_protocol::data::{
+    data_values::{DataError, MData, MDataType},
+    table_model::{Column, TableSchema},
+};
+
+use crate::db::manager::DatabaseManager;
+use crate::db::stats::{StatementStatistics, STAT_STATEMENTS_VIEW};
+use crate::sql::lexer::CaseFolding;
+
+/// Name of the system view listing the tables and system views
+pub const TABLES_VIEW: &str = "microbat_tables";
+/// Name of the system view listing the indexes of tables
+pub const INDEXES_VIEW: &str = "microbat_indexes";
+/// Name of the system view listing the sessions of the server
+pub const SESSIONS_VIEW: &str = "microbat_sessions";
+
+/// Views of the database itself, listed with the tables but with rows built when read.
+/// They can't be written, indexed or analyzed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemView {
+    StatStatements,
+    Tables,
+    Indexes,
+    Sessions,
+}
+
+/// What a session is doing, reported by the server for the sessions view
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionActivity {
+    /// User the session logged in as, None until it has
+    pub user: Option<String>,
+    /// Statement the session is executing, None while it waits for the next one
+    pub query: Option<String>,
+    /// Microseconds since 1970-01-01 00:00:00 UTC when the session started
+    pub started: i64,
+}
+
+impl SystemView {
+    pub const ALL: [SystemView; 4] = [
+        SystemView::StatStatements,
+        SystemView::Tables,
+        SystemView::Indexes,
+        SystemView::Sessions,
+    ];
+
+    /// View of given name as folded by given policy, if it is a system view
+    pub fn named(case_folding: CaseFolding, name: &str) -> Option<Self> {
+        SystemView::ALL
+            .into_iter()
+            .find(|view| case_folding.fold(view.name()) == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemView::StatStatements => STAT_STATEMENTS_VIEW,
+            SystemView::Tables => TABLES_VIEW,
+            SystemView::Indexes => INDEXES_VIEW,
+            SystemView::Sessions => SESSIONS_VIEW,
+        }
+    }
+
+    /// Schema of the view, column names folded like identifiers
+    pub fn schema(&self, case_folding: CaseFolding) -> TableSchema {
+        let column = |name: &str, data_type| Column::new(case_folding.fold(name), data_type);
+        let columns = match self {
+            SystemView::StatStatements => return StatementStatistics::schema(case_folding),
+            SystemView::Tables => vec![
+                column("table_name", MDataType::Varchar),
+                column("table_type", MDataType::Varchar),
+                column("column_count", MDataType::Integer),
+                column("row_count", MDataType::Integer),
+            ],
+            SystemView::Indexes => vec![
+                column("index_name", MDataType::Varchar),
+                column("table_name", MDataType::Varchar),
+                column("column_name", MDataType::Varchar),
+            ],
+            SystemView::Sessions => vec![
+                column("session_id", MDataType::BigInt),
+                column("user_name", MDataType::Varchar),
+                column("state", MDataType::Varchar),
+                column("query", MDataType::Varchar),
+                column("started", MDataType::Timestamp),
+            ],
+        };
+        TableSchema::new(columns).expect("System view columns are unique")
+    }
+}
+
+impl SessionActivity {
+    /// Activity of a session starting now, before it has logged in
+    pub fn new() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock is before 1970");
+        SessionActivity {
+            user: None,
+            query: None,
+            started: since_epoch.as_micros() as i64,
+        }
+    }
+}
+
+impl Default for SessionActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rows of the tables view, one per table and system view in order of their names. System
+/// views have no row count, as their rows are built when read.
+pub fn table_rows(
+    database: &(impl DatabaseManager + ?Sized),
+) -> Result<Vec<Vec<MData>>, DataError> {
+    let mut rows = vec![];
+    for table in database.get_tables()? {
+        let columns = database.get_table_meta(&table)?.schema.len();
+        let (table_type, row_count) = match database.stored_rows(&table) {
+            Some(stored) => ("table", count(stored.len())),
+            None => ("system view", MData::Null),
+        };
+        rows.push(vec![
+            MData::Varchar(table),
+            MData::Varchar(String::from(table_type)),
+            count(columns),
+            row_count,
+        ]);
+    }
+    Ok(rows)
+}
+
+/// Rows of the indexes view, one per index in order of the names of their tables
+pub fn index_rows(
+    database: &(impl DatabaseManager + ?Sized),
+) -> Result<Vec<Vec<MData>>, DataError> {
+    let mut rows = vec![];
+    for table in database.get_tables()? {
+        let schema = &database.get_table_meta(&table)?.schema;
+        for index in database.indexes(&table) {
+            rows.push(vec![
+                MData::Varchar(index.name.clone()),
+                MData::Varchar(table.clone()),
+                MData::Varchar(schema.columns[index.column].name.clone()),
+            ]);
+        }
+    }
+    Ok(rows)
+}
+
+/// Rows of the sessions view from the activity of each session by its id, in order of the ids
+pub fn session_rows(sessions: &BTreeMap<u32, SessionActivity>) -> Vec<Vec<MData>> {
+    sessions
+        .iter()
+        .map(|(session_id, activity)| {
+            let optional = |value: &Option<String>| match value {
+                Some(value) => MData::Varchar(value.clone()),
+                None => MData::Null,
+            };
+            let state = match activity.query {
+                Some(_) => "active",
+                None => "idle",
+            };
+            vec![
+                MData::BigInt(i64::from(*session_id)),
+                optional(&activity.user),
+                MData::Varchar(String::from(state)),
+                optional(&activity.query),
+                MData::Timestamp(activity.started),
+            ]
+        })
+        .collect()
+}
+
+// Counts larger than an integer column can hold are capped
+fn count(value: usize) -> MData {
+    MData::Integer(i32::try_from(value).unwrap_or(i32::MAX))
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        assert_eq!(
+            SystemView::named(CaseFolding::Lower, "microbat_tables"),
+            Some(SystemView::Tables)
+        );
+        assert_eq!(
+            SystemView::named(CaseFolding::Upper, "MICROBAT_SESSIONS"),
+            Some(SystemView::Sessions)
+        );
+        assert_eq!(
+            SystemView::named(CaseFolding::Upper, "microbat_tables"),
+            None
+        );
+        assert_eq!(SystemView::named(CaseFolding::Lower, "tables"), None);
+    }
+
+    #[test]
+    fn test_session_rows() {
+        let mut sessions = BTreeMap::new();
+        sessions.insert(
+            2,
+            SessionActivity {
+                user: Some(String::from("admin")),
+                query: Some(String::from("select 1;")),
+                started: 20,
+            },
+        );
+        sessions.insert(
+            1,
+            SessionActivity {
+                user: None,
+                query: None,
+                started: 10,
+            },
+        );
+        assert_eq!(
+            session_rows(&sessions),
+            vec![
+                vec![
+                    MData::BigInt(1),
+                    MData::Null,
+                    MData::Varchar(String::from("idle")),
+                    MData::Null,
+                    MData::Timestamp(10),
+                ],
+                vec![
+                    MData::BigInt(2),
+                    MData::Varchar(String::from("admin")),
+                    MData::Varchar(String::from("active")),
+                    MData::Varchar(String::from("select 1;")),
+                    MData::Timestamp(20),
+                ],
+            ]
+        );
+    }
+}
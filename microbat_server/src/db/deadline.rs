@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+use microbat_protocol::data::data_values::DataError;
+use microbat_protocol::sqlstate;
+
+/// Time a statement has to be executed by, after which producing its rows fails. Executing
+/// checks it for every row read from a table or joined.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// Statements without a deadline run until they are done
+    pub const NONE: Deadline = Deadline(None);
+
+    /// Deadline of a statement starting now, given the statement_timeout of its session
+    pub fn after(timeout: Option<Duration>) -> Self {
+        Deadline(timeout.and_then(|timeout| Instant::now().checked_add(timeout)))
+    }
+
+    /// Fails the statement once the deadline has passed
+    pub fn check(&self) -> Result<(), DataError> {
+        match self.0 {
+            Some(deadline) if Instant::now() >= deadline => Err(DataError {
+                code: sqlstate::QUERY_CANCELED,
+                msg: String::from("Canceling statement due to statement timeout"),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod deadline_tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        assert!(Deadline::NONE.check().is_ok());
+        assert!(Deadline::after(None).check().is_ok());
+        assert!(Deadline::after(Some(Duration::from_secs(60)))
+            .check()
+            .is_ok());
+        let error = Deadline::after(Some(Duration::ZERO)).check().unwrap_err();
+        assert_eq!(error.code, sqlstate::QUERY_CANCELED);
+    }
+}
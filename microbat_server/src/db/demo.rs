@@ -0,0 +1,108 @@
+use microbat_protocol::data::{
+    data_values::{DataError, MData, MDataType},
+    table_model::Column,
+};
+
+use super::manager::DatabaseManager;
+
+/// People of the demo script, followed by generated ones when more are asked for
+const PEOPLE: [(&str, i32); 5] = [
+    ("Juho", 40),
+    ("Simo", 19),
+    ("Hermanni", 48),
+    ("Taavetti", 32),
+    ("Metusalem", 85),
+];
+const DEPARTMENTS: [&str; 5] = ["Rustland", "Goland", "Javaland", "Cppland", "Nodejsland"];
+const MODES: [&str; 3] = ["soft", "medium", "hard"];
+
+/// Creates the tables people, departments and modes of the demo script `demo.sql` with
+/// given amount of people.
+///
+/// The first five people are always the same and the rest are generated, so benchmarks can
+/// run the same queries the demo data is explored with on tables of any size.
+pub fn create_demo_tables(
+    database: &mut impl DatabaseManager,
+    people: usize,
+) -> Result<(), DataError> {
+    let case_folding = database.case_folding();
+    let fold = |name: &str| case_folding.fold(name);
+    let people_table = fold("people");
+    database.create_table(
+        people_table.clone(),
+        vec![
+            Column::new(fold("id"), MDataType::Integer),
+            Column::new(fold("name"), MDataType::Varchar),
+            Column::new(fold("age"), MDataType::Integer),
+        ],
+    )?;
+    for id in 1..=people as i32 {
+        let (name, age) = match PEOPLE.get(id as usize - 1) {
+            Some((name, age)) => (String::from(*name), *age),
+            None => (format!("Person {}", id), 18 + id * 7 % 70),
+        };
+        database.insert(
+            &people_table,
+            vec![
+                MData::Integer(id),
+                MData::Varchar(name),
+                MData::Integer(age),
+            ],
+        )?;
+    }
+
+    let departments_table = fold("departments");
+    database.create_table(
+        departments_table.clone(),
+        vec![
+            Column::new(fold("id_dep"), MDataType::Integer),
+            Column::new(fold("name_dep"), MDataType::Varchar),
+        ],
+    )?;
+    for (id, name) in (1..).zip(DEPARTMENTS) {
+        database.insert(
+            &departments_table,
+            vec![MData::Integer(id), MData::Varchar(String::from(name))],
+        )?;
+    }
+
+    let modes_table = fold("modes");
+    database.create_table(
+        modes_table.clone(),
+        vec![
+            Column::new(fold("id_mode"), MDataType::Integer),
+            Column::new(fold("name_mode"), MDataType::Varchar),
+        ],
+    )?;
+    for (id, name) in (1..).zip(MODES) {
+        database.insert(
+            &modes_table,
+            vec![MData::Integer(id), MData::Varchar(String::from(name))],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod demo_tests {
+    use super::*;
+    use crate::db::manager::InMemoryManager;
+
+    #[test]
+    fn test_generated_people() {
+        let mut manager = InMemoryManager::new();
+        create_demo_tables(&mut manager, 100).unwrap();
+        let people = manager.fetch("people").unwrap();
+        assert_eq!(people.len(), 100);
+        assert_eq!(people[0][1], MData::Varchar(String::from("Juho")));
+        assert_eq!(
+            people[99],
+            vec![
+                MData::Integer(100),
+                MData::Varchar(String::from("Person 100")),
+                MData::Integer(18)
+            ]
+        );
+        assert_eq!(manager.fetch("departments").unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::BTreeMap;
+use std::iter;
+use std::panic;
+use std::thread;
+
+use microbat_protocol::data::{
+    data_values::{DataError, MData},
+    table_model::TableSchema,
+};
+use microbat_protocol::sqlstate;
+
+use crate::db::deadline::Deadline;
+use crate::db::group::{group_rows, partial_groups, GroupedRows, PartialGroups};
+use crate::db::index::IndexKey;
+use crate::db::manager::DatabaseManager;
+use crate::db::planner::{plan_select, Plan, Step};
+use crate::db::sort::{compare_rows, SortOptions};
+use crate::db::summary::summarized_table;
+use crate::db::table_function::scan;
+use crate::db::window::evaluate_window;
+use crate::sql::expression::{Expression, Truth};
+use crate::sql::parser::SelectClause;
+
+/// Rows produced one at a time as they are pulled. Producing a row can fail, which ends the
+/// rows for the consumer.
+pub type Rows<'a> = Box<dyn Iterator<Item = Result<Vec<MData>, DataError>> + 'a>;
+
+/// Rows of a table a worker filters or aggregates
+type Partition<'a> = &'a [Vec<MData>];
+
+/// Executes a select, producing its rows as they are pulled with their schema.
+///
+/// Rows stream from the tables through filters and joins to the result, and reading stops
+/// once LIMIT is reached. Only grouping, window functions and sorting, which need every row
+/// before producing any, and the inner inputs of joins keep rows in memory. So do the rows
+/// of tables read in parallel, which workers filter a partition each of.
+///
+/// Reading or joining a row after the deadline fails with a timeout.
+pub fn execute_select<'a>(
+    select: &'a SelectClause,
+    database: &'a (impl DatabaseManager + ?Sized),
+    deadline: Deadline,
+) -> Result<(TableSchema, Rows<'a>), DataError> {
+    if let Some(summarized) = summarized(select, database)? {
+        return Ok(summarized);
+    }
+    let plan = plan_select(select, database)?;
+    let relation = plan.relation();
+
+    let projection = &select.projection;
+    let grouped = select.group_by.is_some() || projection.iter().any(|e| e.aggregate().is_some());
+    let blocking =
+        grouped || !select.order_by.is_empty() || projection.iter().any(|e| e.window().is_some());
+    let (schema, rows) = match blocking {
+        true => {
+            let (schema, rows) = match grouped {
+                true => {
+                    let (schema, groups) = execute_groups(select, relation, database, deadline)?;
+                    materialize(select, &schema, vec![], Some(groups))?
+                }
+                false => {
+                    let (schema, rows) = execute_from(relation, database, deadline)?;
+                    materialize(select, &schema, rows.collect::<Result<_, _>>()?, None)?
+                }
+            };
+            let rows = Box::new(rows.into_iter().map(Ok));
+            (schema, limited(rows, select.offset, select.limit))
+        }
+        false => {
+            let (schema, rows) = execute_from(relation, database, deadline)?;
+            let mut columns = vec![];
+            for (index, expr) in projection.iter().enumerate() {
+                columns.push(expr.schema_column(&schema, index)?);
+            }
+            // Skipped rows aren't projected
+            let rows = limited(rows, select.offset, select.limit).map(move |row| {
+                let row = row?;
+                let mut projected = vec![];
+                for expr in projection.iter() {
+                    projected.push(expr.eval(&schema, &row)?);
+                }
+                Ok(projected)
+            });
+            (TableSchema::new(columns)?, Box::new(rows) as Rows)
+        }
+    };
+    let columns = TableSchema {
+        columns: schema.columns.clone(),
+    };
+    let rows = rows.map(move |row| {
+        let row = row?;
+        columns.check_row(&row)?;
+        Ok(row)
+    });
+    Ok((schema, Box::new(rows)))
+}
+
+/// Rows of FROM with their schema, none for a select without FROM
+fn execute_from<'a>(
+    relation: Option<&Plan<'a>>,
+    database: &'a (impl DatabaseManager + ?Sized),
+    deadline: Deadline,
+) -> Result<(TableSchema, Rows<'a>), DataError> {
+    match relation {
+        Some(relation) => execute(relation, database, deadline),
+        None => Ok((TableSchema::new(vec![])?, Box::new(iter::empty()))),
+    }
+}
+
+/// Groups of the rows of FROM for a select grouping them, with the schema of the rows.
+/// Partitions of a table read in parallel are filtered and grouped by workers, and their
+/// groups merged in the order of the partitions.
+fn execute_groups<'a>(
+    select: &SelectClause,
+    relation: Option<&Plan<'a>>,
+    database: &'a (impl DatabaseManager + ?Sized),
+    deadline: Deadline,
+) -> Result<(TableSchema, GroupedRows), DataError> {
+    let group_by = select.group_by.as_ref();
+    let projection = &select.projection;
+    let (scan, conditions) = match relation {
+        Some(Plan {
+            step: Step::Filter(conditions),
+            inputs,
+            ..
+        }) => (Some(&inputs[0]), &conditions[..]),
+        relation => (relation, &[][..]),
+    };
+    let partitioned = match scan {
+        Some(scan) => partitions(scan, database)?,
+        None => None,
+    };
+    let Some((schema, partitions)) = partitioned else {
+        let (schema, rows) = execute_from(relation, database, deadline)?;
+        let rows: Vec<Vec<MData>> = rows.collect::<Result<_, _>>()?;
+        let groups = group_rows(group_by, projection, &schema, &rows)?;
+        return Ok((schema, groups));
+    };
+    let partials = in_parallel(&partitions, |rows| -> Result<PartialGroups, DataError> {
+        let rows: Vec<Vec<MData>> = filtered(conditions, &schema, rows, deadline)
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+        Ok(partial_groups(group_by, projection, &schema, &rows)?)
+    });
+    let mut partials = partials.into_iter();
+    let mut merged = match partials.next() {
+        Some(partial) => partial?,
+        None => partial_groups(group_by, projection, &schema, &[])?,
+    };
+    for partial in partials {
+        merged.merge(partial?)?;
+    }
+    let groups = merged.finish(group_by, projection, &schema)?;
+    Ok((schema, groups))
+}
+
+/// Rows a step of a plan reading and joining the relations of FROM produces, with their
+/// schema
+fn execute<'a>(
+    plan: &Plan<'a>,
+    database: &'a (impl DatabaseManager + ?Sized),
+    deadline: Deadline,
+) -> Result<(TableSchema, Rows<'a>), DataError> {
+    match &plan.step {
+        // Without conditions the workers would have nothing to do but copy the rows
+        Step::Scan(table) | Step::ParallelScan(table, _) => {
+            let columns = database.get_table_meta(table)?.schema.columns.clone();
+            let rows = checked(database.scan(table)?, deadline);
+            Ok((TableSchema { columns }, rows))
+        }
+        Step::IndexScan(table, index, range) => {
+            let columns = database.get_table_meta(table)?.schema.columns.clone();
+            let rows = database.scan_at(table, index.lookup(range))?;
+            Ok((TableSchema { columns }, checked(rows, deadline)))
+        }
+        Step::FunctionScan(call) => {
+            let (columns, rows) = scan(call)?;
+            Ok((
+                TableSchema::new(columns)?,
+                checked(Box::new(rows.into_iter().map(Ok)), deadline),
+            ))
+        }
+        Step::Filter(conditions) => {
+            if let Some((schema, partitions)) = partitions(&plan.inputs[0], database)? {
+                let rows = in_parallel(&partitions, |rows| {
+                    filtered(conditions, &schema, rows, deadline)
+                });
+                return Ok((schema, Box::new(rows.into_iter().flatten())));
+            }
+            let (schema, rows) = execute(&plan.inputs[0], database, deadline)?;
+            let conditions = conditions.clone();
+            let columns = TableSchema {
+                columns: schema.columns.clone(),
+            };
+            let rows = rows.filter_map(move |row| {
+                let kept = row.and_then(|row| match kept(&conditions, &columns, &row)? {
+                    true => Ok(Some(row)),
+                    false => Ok(None),
+                });
+                kept.transpose()
+            });
+            Ok((schema, Box::new(rows)))
+        }
+        Step::NestedLoop(columns) => {
+            let (left_schema, left) = execute(&plan.inputs[0], database, deadline)?;
+            let (right_schema, right) = execute(&plan.inputs[1], database, deadline)?;
+            // Rows of the second input are read once and kept for every row of the first
+            let right: Vec<Vec<MData>> = right.collect::<Result<_, _>>()?;
+            let schema = columns.join(&left_schema.columns, &right_schema.columns);
+            let columns = columns.clone();
+            let rows = left.flat_map(move |row| match row {
+                Ok(row) => right
+                    .iter()
+                    .map(|right_row| Ok(columns.join(&row, right_row)))
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            });
+            Ok((TableSchema::new(schema)?, checked(Box::new(rows), deadline)))
+        }
+        Step::HashJoin(columns, keys) => {
+            let (left_schema, left) = execute(&plan.inputs[0], database, deadline)?;
+            let (right_schema, right) = execute(&plan.inputs[1], database, deadline)?;
+            let (left_keys, right_keys): (Vec<_>, Vec<_>) = keys.iter().copied().unzip();
+            let mut hashed: BTreeMap<Vec<IndexKey>, Vec<Vec<MData>>> = BTreeMap::new();
+            for row in right {
+                let row = row?;
+                if let Some(key) = join_key(&right_keys, &right_schema, &row)? {
+                    hashed.entry(key).or_default().push(row);
+                }
+            }
+            let schema = columns.join(&left_schema.columns, &right_schema.columns);
+            let columns = columns.clone();
+            let rows = left.flat_map(move |row| {
+                let row = match row {
+                    Ok(row) => row,
+                    Err(err) => return vec![Err(err)],
+                };
+                match join_key(&left_keys, &left_schema, &row) {
+                    Ok(Some(key)) => hashed
+                        .get(&key)
+                        .into_iter()
+                        .flatten()
+                        .map(|right_row| Ok(columns.join(&row, right_row)))
+                        .collect(),
+                    Ok(None) => vec![],
+                    Err(err) => vec![Err(err)],
+                }
+            });
+            Ok((TableSchema::new(schema)?, checked(Box::new(rows), deadline)))
+        }
+        _ => Err(DataError {
+            code: sqlstate::INTERNAL_ERROR,
+            msg: format!("{} doesn't read relations", plan.name()),
+        }),
+    }
+}
+
+/// Schema and partitions of the rows of a table read in parallel by given step, one for
+/// each worker, None if the step doesn't read a table in parallel
+fn partitions<'a>(
+    plan: &Plan,
+    database: &'a (impl DatabaseManager + ?Sized),
+) -> Result<Option<(TableSchema, Vec<Partition<'a>>)>, DataError> {
+    let Step::ParallelScan(table, workers) = &plan.step else {
+        return Ok(None);
+    };
+    // System views build their rows when fetched
+    let Some(rows) = database.stored_rows(table) else {
+        return Ok(None);
+    };
+    let columns = database.get_table_meta(table)?.schema.columns.clone();
+    let size = rows.len().div_ceil(*workers).max(1);
+    Ok(Some((TableSchema { columns }, rows.chunks(size).collect())))
+}
+
+/// Results of given work on each partition, each worked on by a worker thread of its own,
+/// in the order of the partitions
+fn in_parallel<T: Send>(partitions: &[Partition], work: impl Fn(Partition) -> T + Sync) -> Vec<T> {
+    let work = &work;
+    thread::scope(|scope| {
+        let workers: Vec<_> = partitions
+            .iter()
+            .map(|partition| scope.spawn(move || work(partition)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
+/// Rows of a partition every condition is true for, up to and including the failure of the
+/// first row that fails, as the rows after it aren't produced when read in order
+fn filtered(
+    conditions: &[&dyn Expression],
+    schema: &TableSchema,
+    rows: Partition,
+    deadline: Deadline,
+) -> Vec<Result<Vec<MData>, DataError>> {
+    let mut filtered = vec![];
+    for row in rows {
+        match deadline.check().and_then(|_| kept(conditions, schema, row)) {
+            Ok(true) => filtered.push(Ok(row.clone())),
+            Ok(false) => {}
+            Err(err) => {
+                filtered.push(Err(err));
+                break;
+            }
+        }
+    }
+    filtered
+}
+
+/// Rows failing once the deadline has passed, after the rows read before it
+fn checked(rows: Rows<'_>, deadline: Deadline) -> Rows<'_> {
+    Box::new(rows.map(move |row| {
+        deadline.check()?;
+        row
+    }))
+}
+
+/// True if every condition is true for given row. WHERE keeps only the rows where the
+/// condition is true, not false or unknown.
+fn kept(
+    conditions: &[&dyn Expression],
+    schema: &TableSchema,
+    row: &[MData],
+) -> Result<bool, DataError> {
+    let mut truth = Truth::True;
+    for condition in conditions {
+        truth = truth.and(Truth::from_mdata(&condition.eval(schema, row)?)?);
+    }
+    Ok(truth == Truth::True)
+}
+
+/// Key of a row for a hash join, None if a value of it is null and so equals nothing.
+/// Keys are compared like index keys, which are equal when the values are.
+fn join_key(
+    keys: &[&dyn Expression],
+    schema: &TableSchema,
+    row: &[MData],
+) -> Result<Option<Vec<IndexKey>>, DataError> {
+    let mut key = vec![];
+    for expression in keys {
+        match expression.eval(schema, row)? {
+            MData::Null => return Ok(None),
+            value => key.push(IndexKey(value)),
+        }
+    }
+    Ok(Some(key))
+}
+
+/// Rows after skipping `offset` rows and with at most `limit` rows. Failures pass through
+/// without counting as rows.
+fn limited(rows: Rows<'_>, offset: usize, limit: Option<usize>) -> Rows<'_> {
+    let mut skipped = 0;
+    let rows = rows
+        .filter(move |row| match row {
+            Ok(_) if skipped < offset => {
+                skipped += 1;
+                false
+            }
+            _ => true,
+        })
+        .take(limit.unwrap_or(usize::MAX));
+    Box::new(rows)
+}
+
+/// Result of the select from the summary of its table, if it can be answered without
+/// scanning the rows
+fn summarized<'a>(
+    select: &SelectClause,
+    database: &(impl DatabaseManager + ?Sized),
+) -> Result<Option<(TableSchema, Rows<'a>)>, DataError> {
+    // System views have no summary
+    let (table, summary) =
+        match summarized_table(select).and_then(|table| Some((table, database.summary(table)?))) {
+            Some(summarized) => summarized,
+            None => return Ok(None),
+        };
+    let schema = &database.get_table_meta(table)?.schema;
+    let row = match summary.aggregate(select, schema) {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let mut columns = vec![];
+    for (index, expr) in select.projection.iter().enumerate() {
+        columns.push(expr.schema_column(schema, index)?);
+    }
+    let rows = match select.offset == 0 && select.limit != Some(0) {
+        true => vec![Ok(row)],
+        false => vec![],
+    };
+    Ok(Some((
+        TableSchema::new(columns)?,
+        Box::new(rows.into_iter()),
+    )))
+}
+
+/// Projects given rows of FROM, or given groups of them, when the select groups, has window
+/// functions or sorts them, which needs all the rows before any is produced. Returns the
+/// projected rows in order, before OFFSET and LIMIT.
+fn materialize(
+    select: &SelectClause,
+    query_schema: &TableSchema,
+    data: Vec<Vec<MData>>,
+    grouped: Option<GroupedRows>,
+) -> Result<(TableSchema, Vec<Vec<MData>>), DataError> {
+    let projection = &select.projection;
+    // Grouped queries select from the groups, where only GROUP BY keys are available
+    let (schema, data) = match &grouped {
+        Some(grouped) => (&grouped.schema, &grouped.rows),
+        None => (query_schema, &data),
+    };
+
+    let mut evaled_columns = vec![];
+    for (index, expr) in projection.iter().enumerate() {
+        match expr.aggregate() {
+            Some(_) => evaled_columns.push(expr.schema_column(query_schema, index)?),
+            None => evaled_columns.push(expr.schema_column(schema, index)?),
+        }
+    }
+
+    let mut rows = vec![];
+    for (row_index, row) in data.iter().enumerate() {
+        let mut relation_row = vec![];
+        for (index, expr) in projection.iter().enumerate() {
+            if expr.aggregate().is_some() {
+                let aggregates = &grouped.as_ref().unwrap().aggregates;
+                relation_row.push(aggregates[row_index][index].clone());
+            } else if expr.window().is_some() {
+                // Window values are filled in below once all rows are known
+                relation_row.push(MData::Null);
+            } else {
+                relation_row.push(expr.eval(schema, row)?);
+            }
+        }
+        let mut sort_key = vec![];
+        for order_by in select.order_by.iter() {
+            sort_key.push(order_by.expression.eval(schema, row)?);
+        }
+        rows.push((sort_key, relation_row));
+    }
+
+    for (index, expr) in projection.iter().enumerate() {
+        if let Some(window) = expr.window() {
+            let values = evaluate_window(window, schema, data)?;
+            for ((_, relation_row), value) in rows.iter_mut().zip(values) {
+                relation_row[index] = value;
+            }
+        }
+    }
+
+    let sort_options: Vec<SortOptions> =
+        select.order_by.iter().map(|order| order.options).collect();
+    rows.sort_by(|(left, _), (right, _)| compare_rows(left, right, &sort_options));
+    let rows = rows.into_iter().map(|(_, row)| row).collect();
+    Ok((TableSchema::new(evaled_columns)?, rows))
+}
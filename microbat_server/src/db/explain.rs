@@ -0,0 +1,48 @@
+use microbat_protocol::data::{
+    data_values::{DataError, MData, MDataType},
+    table_model::{Column, DataRow, TableSchema},
+};
+
+use crate::db::manager::DatabaseManager;
+use crate::db::planner::{plan_select, Plan};
+use crate::sql::parser::SelectClause;
+
+/// Schema of the rows EXPLAIN returns. Each row is a step in the plan and refers to the
+/// step consuming its rows with parent_id, which is null for the step producing the result.
+pub fn schema() -> TableSchema {
+    TableSchema::new(vec![
+        Column::new(String::from("node_id"), MDataType::Integer),
+        Column::new(String::from("parent_id"), MDataType::Integer),
+        Column::new(String::from("node"), MDataType::Varchar),
+        Column::new(String::from("detail"), MDataType::Varchar),
+        Column::new(String::from("estimated_rows"), MDataType::Integer),
+    ])
+    .expect("Plan columns are unique")
+}
+
+/// Describes the steps `DatabaseManager::query` takes to execute given select
+pub fn explain(
+    select: &SelectClause,
+    database: &(impl DatabaseManager + ?Sized),
+) -> Result<Vec<DataRow>, DataError> {
+    let mut rows = vec![];
+    flatten(&plan_select(select, database)?, None, &mut rows);
+    Ok(rows)
+}
+
+// Appends the step and its inputs depth first, so a parent is before its children
+fn flatten(plan: &Plan, parent: Option<i32>, rows: &mut Vec<DataRow>) {
+    let id = rows.len() as i32 + 1;
+    rows.push(DataRow::new(vec![
+        MData::Integer(id),
+        parent.map_or(MData::Null, MData::Integer),
+        MData::Varchar(plan.name().to_owned()),
+        plan.detail.clone().map_or(MData::Null, MData::Varchar),
+        plan.rows
+            .and_then(|rows| i32::try_from(rows).ok())
+            .map_or(MData::Null, MData::Integer),
+    ]));
+    for input in plan.inputs.iter() {
+        flatten(input, Some(id), rows);
+    }
+}
@@ -0,0 +1,833 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use microbat_protocol::data::{
+    data_values::{DataError, MData},
+    table_model::{Column, DataRow, TableSchema},
+};
+use microbat_protocol::messages::server_messages::check_row_size;
+use microbat_protocol::messages::writer::MessageWriter;
+use microbat_protocol::messages::{
+    read_message_with, MicrobatMessage, ProtocolFeatures, MAX_FRAME_SIZE,
+};
+use microbat_protocol::{sqlstate, MicrobatProtocolError};
+
+use crate::db::analyze::TableStatistics;
+use crate::db::catalog::SessionActivity;
+use crate::db::deadline::Deadline;
+use crate::db::executor::Rows;
+use crate::db::index::TableIndex;
+use crate::db::manager::{DatabaseManager, InMemoryManager, TableMetadata};
+use crate::db::summary::TableSummary;
+use crate::db::wal_record::{
+    check_header, deserialize_wal_record, file_header, WalRecord, HEADER_LENGTH,
+};
+use crate::log::{log, LogLevel};
+use crate::sql::lexer::CaseFolding;
+use crate::sql::parser::SelectClause;
+
+/// Extension of the snapshot and log files
+const FILE_EXTENSION: &str = "mbt";
+const SNAPSHOT_PREFIX: &str = "snapshot";
+const WAL_PREFIX: &str = "wal";
+
+/// Records of logs are framed like messages with checksums, so a corrupted log fails to load
+/// instead of loading garbage rows
+const WAL_FEATURES: ProtocolFeatures = ProtocolFeatures {
+    skip_unknown_messages: false,
+    schema_notifications: false,
+    compression: false,
+    checksums: true,
+};
+
+/// Snapshots are written at once, so their rows are compressed too
+const SNAPSHOT_FEATURES: ProtocolFeatures = ProtocolFeatures {
+    compression: true,
+    ..WAL_FEATURES
+};
+
+/// Rows of a snapshot are written in batches of about this many bytes
+const SNAPSHOT_BATCH_SIZE: usize = 1024 * 1024;
+
+/// Manager keeping the tables in memory like `InMemoryManager` and in files under a data
+/// directory, from where they are loaded when the manager is opened again.
+///
+/// Tables are stored in a snapshot of every table, written by `checkpoint`, and in a
+/// write-ahead log of the changes made since. Snapshots and logs are numbered, and snapshot
+/// N has the changes of the logs up to N. A checkpoint starts log N + 1 before writing
+/// snapshot N, and removes the files the snapshot covers only once it is written, so every
+/// change is in some file all along. Opening loads the latest snapshot and replays the logs
+/// after it.
+///
+/// Both start with a header telling the version of their format, followed by `WalRecord`s.
+/// Changes are logged as they are made, so they survive the server stopping but not
+/// necessarily the machine crashing. A change only partly logged when the server stopped is
+/// dropped when the log is loaded.
+pub struct FileManager {
+    memory: InMemoryManager,
+    directory: PathBuf,
+    // Log changes are appended to
+    wal: File,
+    wal_number: u32,
+    // Table of the latest records logged, named again when records of another table follow
+    wal_table: Option<String>,
+    // True once changes are logged after the latest snapshot
+    wal_changed: bool,
+}
+
+impl FileManager {
+    /// Opens the database in given directory, creating the directory if it doesn't exist
+    pub fn open(
+        directory: impl Into<PathBuf>,
+        case_folding: CaseFolding,
+    ) -> Result<Self, DataError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|err| io_error(&directory, err))?;
+        let mut snapshot = 0;
+        let mut wals = vec![];
+        for entry in fs::read_dir(&directory).map_err(|err| io_error(&directory, err))? {
+            let path = entry.map_err(|err| io_error(&directory, err))?.path();
+            match numbered_file(&path) {
+                Some((SNAPSHOT_PREFIX, number)) => snapshot = snapshot.max(number),
+                Some((_, number)) => wals.push(number),
+                None => {}
+            }
+        }
+        // Logs the latest snapshot covers are left over from a checkpoint that didn't finish
+        wals.retain(|number| *number > snapshot);
+        wals.sort();
+
+        let mut memory = InMemoryManager::with_case_folding(case_folding);
+        if snapshot > 0 {
+            let path = numbered_path(&directory, SNAPSHOT_PREFIX, snapshot);
+            let (records, torn) = read_records(&path, SNAPSHOT_FEATURES)?;
+            if torn.is_some() {
+                return Err(corrupted(&path, "snapshot ends in the middle of a record"));
+            }
+            replay(&mut memory, records, &path)?;
+        }
+        let mut wal_changed = false;
+        for number in &wals {
+            let path = numbered_path(&directory, WAL_PREFIX, *number);
+            let (records, torn) = read_records(&path, WAL_FEATURES)?;
+            wal_changed |= !records.is_empty();
+            replay(&mut memory, records, &path)?;
+            if let Some(length) = torn {
+                log!(
+                    LogLevel::Warn,
+                    "Dropping incomplete change at the end of {}",
+                    path.display()
+                );
+                let file = OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .map_err(|err| io_error(&path, err))?;
+                file.set_len(length).map_err(|err| io_error(&path, err))?;
+            }
+        }
+        remove_covered(&directory, snapshot);
+
+        let wal_number = wals.last().copied().unwrap_or(snapshot + 1);
+        Ok(FileManager {
+            memory,
+            wal: open_wal(&directory, wal_number)?,
+            directory,
+            wal_number,
+            wal_table: None,
+            wal_changed,
+        })
+    }
+
+    /// Lets queries read large tables with at most given count of workers, see
+    /// `InMemoryManager::set_max_parallel_workers`
+    pub fn set_max_parallel_workers(&mut self, workers: usize) {
+        self.memory.set_max_parallel_workers(workers);
+    }
+
+    /// Appends the records of a change to the log before making it in memory, and truncates
+    /// them away if making it fails, so the log has only the changes that were made
+    fn logged(
+        &mut self,
+        table: &str,
+        records: &[WalRecord],
+        change: impl FnOnce(&mut InMemoryManager) -> Result<(), DataError>,
+    ) -> Result<(), DataError> {
+        let path = numbered_path(&self.directory, WAL_PREFIX, self.wal_number);
+        let length = self
+            .wal
+            .metadata()
+            .map_err(|err| io_error(&path, err))?
+            .len();
+        let previous_table = self.wal_table.take();
+        let result = self
+            .write_change(table, previous_table.as_deref(), records)
+            .and_then(|()| change(&mut self.memory));
+        if let Err(err) = result {
+            self.wal
+                .set_len(length)
+                .map_err(|err| io_error(&path, err))?;
+            self.wal_table = previous_table;
+            return Err(err);
+        }
+        self.wal_table = Some(String::from(table));
+        self.wal_changed = true;
+        Ok(())
+    }
+
+    fn write_change(
+        &mut self,
+        table: &str,
+        previous_table: Option<&str>,
+        records: &[WalRecord],
+    ) -> Result<(), DataError> {
+        // Creating a table names it
+        let creates_table = matches!(records.first(), Some(WalRecord::CreateTable(..)));
+        if previous_table != Some(table) && !creates_table {
+            write_record(&mut self.wal, &WalRecord::Table(String::from(table)))?;
+        }
+        for record in records {
+            write_record(&mut self.wal, record)?;
+        }
+        Ok(())
+    }
+
+    /// Writes every table to the snapshot of given number. The snapshot is written under
+    /// another name first, so that a snapshot the server stopped writing is never loaded.
+    fn write_snapshot(&self, number: u32) -> Result<(), DataError> {
+        let path = numbered_path(&self.directory, SNAPSHOT_PREFIX, number);
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary).map_err(|err| io_error(&temporary, err))?;
+        file.write_all(&file_header())
+            .map_err(|err| io_error(&temporary, err))?;
+        let mut writer = MessageWriter::new(&mut file, SNAPSHOT_FEATURES);
+        let mut send = |record: WalRecord| {
+            writer
+                .send(&record)
+                .map(|_| ())
+                .map_err(|err| write_error(&temporary, err))
+        };
+        for table in self.memory.schema_changes() {
+            let schema = self.memory.get_table_meta(table)?.schema.clone();
+            let columns = schema.columns.clone();
+            send(WalRecord::CreateTable(table.clone(), schema))?;
+            let mut batch = vec![];
+            let mut batch_size = 0;
+            for row in self.memory.fetch(table)? {
+                let row = DataRow::new(row);
+                let size = check_row_size(&row, &columns, MAX_FRAME_SIZE)
+                    .map_err(|err| write_error(&temporary, err))?;
+                if !batch.is_empty() && batch_size + size > SNAPSHOT_BATCH_SIZE {
+                    send(batch_record(&mut batch))?;
+                    batch_size = 0;
+                }
+                batch_size += size;
+                batch.push(row);
+            }
+            if !batch.is_empty() {
+                send(batch_record(&mut batch))?;
+            }
+            for index in self.memory.indexes(table) {
+                send(index_record(index, &columns))?;
+            }
+        }
+        writer.flush().map_err(|err| write_error(&temporary, err))?;
+        drop(writer);
+        file.sync_all().map_err(|err| io_error(&temporary, err))?;
+        fs::rename(&temporary, &path).map_err(|err| io_error(&path, err))?;
+        sync_directory(&self.directory);
+        Ok(())
+    }
+}
+
+impl DatabaseManager for FileManager {
+    fn case_folding(&self) -> CaseFolding {
+        self.memory.case_folding()
+    }
+
+    fn max_parallel_workers(&self) -> usize {
+        self.memory.max_parallel_workers()
+    }
+
+    fn get_tables(&self) -> Result<Vec<String>, DataError> {
+        self.memory.get_tables()
+    }
+
+    fn get_table_meta(&self, name: &str) -> Result<&TableMetadata, DataError> {
+        self.memory.get_table_meta(name)
+    }
+
+    fn create_table(&mut self, name: String, columns: Vec<Column>) -> Result<(), DataError> {
+        let record = WalRecord::CreateTable(name.clone(), TableSchema::new(columns.clone())?);
+        self.logged(&name.clone(), &[record], |memory| {
+            memory.create_table(name, columns)
+        })?;
+        // Tables are created rarely enough to wait for them to be on disk, unlike rows
+        let path = numbered_path(&self.directory, WAL_PREFIX, self.wal_number);
+        self.wal.sync_data().map_err(|err| io_error(&path, err))
+    }
+
+    fn schema_changes(&self) -> &[String] {
+        self.memory.schema_changes()
+    }
+
+    fn insert(&mut self, table_name: &str, colums: Vec<MData>) -> Result<(), DataError> {
+        // System views are not logged, and the manager in memory refuses to insert into them
+        if !self
+            .memory
+            .schema_changes()
+            .iter()
+            .any(|table| table == table_name)
+        {
+            return self.memory.insert(table_name, colums);
+        }
+        let row = WalRecord::Row(DataRow::new(colums.clone()));
+        self.logged(table_name, &[row], |memory| {
+            memory.insert(table_name, colums)
+        })
+    }
+
+    fn insert_rows(&mut self, table_name: &str, rows: Vec<Vec<MData>>) -> Result<(), DataError> {
+        if !self
+            .memory
+            .schema_changes()
+            .iter()
+            .any(|table| table == table_name)
+        {
+            return self.memory.insert_rows(table_name, rows);
+        }
+        let records: Vec<WalRecord> = rows
+            .iter()
+            .map(|row| WalRecord::Row(DataRow::new(row.clone())))
+            .collect();
+        self.logged(table_name, &records, |memory| {
+            memory.insert_rows(table_name, rows)
+        })
+    }
+
+    fn create_index(
+        &mut self,
+        name: String,
+        table_name: &str,
+        column: &str,
+    ) -> Result<(), DataError> {
+        let record = WalRecord::Index(name.clone(), String::from(column));
+        self.logged(table_name, &[record], |memory| {
+            memory.create_index(name, table_name, column)
+        })?;
+        let path = numbered_path(&self.directory, WAL_PREFIX, self.wal_number);
+        self.wal.sync_data().map_err(|err| io_error(&path, err))
+    }
+
+    fn indexes(&self, table_name: &str) -> &[TableIndex] {
+        self.memory.indexes(table_name)
+    }
+
+    /// Statistics are only kept in memory, so tables are analyzed again after opening
+    fn analyze(&mut self, table_name: Option<&str>) -> Result<(), DataError> {
+        self.memory.analyze(table_name)
+    }
+
+    fn statistics(&self, table_name: &str) -> Option<&TableStatistics> {
+        self.memory.statistics(table_name)
+    }
+
+    fn fetch(&self, table_name: &str) -> Result<Vec<Vec<MData>>, DataError> {
+        self.memory.fetch(table_name)
+    }
+
+    fn scan(&self, table_name: &str) -> Result<Rows<'_>, DataError> {
+        self.memory.scan(table_name)
+    }
+
+    fn scan_at(&self, table_name: &str, positions: Vec<usize>) -> Result<Rows<'_>, DataError> {
+        self.memory.scan_at(table_name, positions)
+    }
+
+    fn stored_rows(&self, table_name: &str) -> Option<&[Vec<MData>]> {
+        self.memory.stored_rows(table_name)
+    }
+
+    fn query<'a>(
+        &'a self,
+        select: &'a SelectClause,
+        deadline: Deadline,
+    ) -> Result<(TableSchema, Rows<'a>), DataError> {
+        self.memory.query(select, deadline)
+    }
+
+    fn summary(&self, table_name: &str) -> Option<&TableSummary> {
+        self.memory.summary(table_name)
+    }
+
+    fn record_statement(&self, fingerprint: String, elapsed: Duration, rows: Option<u64>) {
+        self.memory.record_statement(fingerprint, elapsed, rows)
+    }
+
+    fn report_session(&self, session_id: u32, activity: Option<SessionActivity>) {
+        self.memory.report_session(session_id, activity)
+    }
+
+    /// Starts a new log and writes a snapshot covering the earlier ones, which are removed
+    /// then. Does nothing if nothing changed since the latest snapshot.
+    fn checkpoint(&mut self) -> Result<(), DataError> {
+        if !self.wal_changed {
+            return Ok(());
+        }
+        // Started first, so that changes are never only in a snapshot that failed to write
+        let covered = self.wal_number;
+        self.wal = open_wal(&self.directory, covered + 1)?;
+        self.wal_number = covered + 1;
+        self.wal_table = None;
+        self.write_snapshot(covered)?;
+        self.wal_changed = false;
+        remove_covered(&self.directory, covered);
+        Ok(())
+    }
+}
+
+/// Makes the changes in the records of a snapshot or a log in memory
+fn replay(
+    memory: &mut InMemoryManager,
+    records: Vec<WalRecord>,
+    path: &Path,
+) -> Result<(), DataError> {
+    let mut table = None;
+    for record in records {
+        let rows = match (record, &table) {
+            (WalRecord::Table(name), _) => {
+                table = Some(name);
+                continue;
+            }
+            (WalRecord::CreateTable(name, schema), _) => {
+                memory.create_table(name.clone(), schema.columns)?;
+                table = Some(name);
+                continue;
+            }
+            (WalRecord::Index(name, column), Some(table)) => {
+                memory.create_index(name, table, &column)?;
+                continue;
+            }
+            (WalRecord::Row(row), Some(_)) => vec![row],
+            (WalRecord::Rows(rows), Some(_)) => rows,
+            (_, None) => return Err(corrupted(path, "change before the name of its table")),
+        };
+        let table = table
+            .as_deref()
+            .expect("Rows follow the name of their table");
+        for row in rows {
+            memory.insert(table, row.columns)?;
+        }
+    }
+    Ok(())
+}
+
+/// Records of a file, and the length of the file without its last record if the server
+/// stopped while writing it
+fn read_records(
+    path: &Path,
+    features: ProtocolFeatures,
+) -> Result<(Vec<WalRecord>, Option<u64>), DataError> {
+    let bytes = fs::read(path).map_err(|err| io_error(path, err))?;
+    // Server stopped while writing the header of a new log
+    if bytes.len() < HEADER_LENGTH && file_header().starts_with(&bytes) {
+        return Ok((vec![], Some(0)));
+    }
+    check_header(&bytes).map_err(|reason| corrupted(path, &reason))?;
+    let length = bytes.len() as u64;
+    let mut reader = Cursor::new(bytes);
+    reader.set_position(HEADER_LENGTH as u64);
+    let mut records = vec![];
+    while reader.position() < length {
+        let start = reader.position();
+        match read_message_with(&mut reader, deserialize_wal_record, |_| true, features) {
+            Ok(record) => records.push(record),
+            Err(_) if is_torn(reader.get_ref(), start as usize) => {
+                return Ok((records, Some(start)))
+            }
+            Err(err) => return Err(corrupted(path, &err.msg)),
+        }
+    }
+    Ok((records, None))
+}
+
+/// True if the record at given offset ends past the end of the file, i.e. the server stopped
+/// while writing it
+fn is_torn(bytes: &[u8], offset: usize) -> bool {
+    // Message type and length
+    let Some(header) = bytes.get(offset..offset + 5) else {
+        return true;
+    };
+    let length = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    // Followed by the checksum
+    offset + 5 + length + 4 > bytes.len()
+}
+
+/// Record of an index of a table with given columns
+fn index_record(index: &TableIndex, columns: &[Column]) -> WalRecord {
+    WalRecord::Index(index.name.clone(), columns[index.column].name.clone())
+}
+
+/// Takes the rows of a batch of a snapshot, a single row as is
+fn batch_record(batch: &mut Vec<DataRow>) -> WalRecord {
+    match batch.len() {
+        1 => WalRecord::Row(batch.remove(0)),
+        _ => WalRecord::Rows(std::mem::take(batch)),
+    }
+}
+
+/// Opens the log of given number for appending, creating it with its header if it doesn't
+/// exist or is empty
+fn open_wal(directory: &Path, number: u32) -> Result<File, DataError> {
+    let path = numbered_path(directory, WAL_PREFIX, number);
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .map_err(|err| io_error(&path, err))?;
+    if file.metadata().map_err(|err| io_error(&path, err))?.len() == 0 {
+        file.write_all(&file_header())
+            .map_err(|err| io_error(&path, err))?;
+    }
+    Ok(file)
+}
+
+/// Removes the logs the snapshot of given number covers, the snapshots before it and
+/// snapshots the server stopped writing. Files that can't be removed now are removed when
+/// the database is opened again.
+fn remove_covered(directory: &Path, snapshot: u32) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let covered = match numbered_file(&path) {
+            Some((SNAPSHOT_PREFIX, number)) => number < snapshot,
+            Some((_, number)) => number <= snapshot,
+            None => path.extension().is_some_and(|ext| ext == "tmp"),
+        };
+        if covered {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Makes a renamed file survive the machine crashing, on platforms that can sync directories
+fn sync_directory(directory: &Path) {
+    if let Ok(directory) = File::open(directory) {
+        let _ = directory.sync_all();
+    }
+}
+
+fn numbered_path(directory: &Path, prefix: &str, number: u32) -> PathBuf {
+    directory.join(format!("{}-{:06}.{}", prefix, number, FILE_EXTENSION))
+}
+
+/// Prefix and number of a file named like `wal-000001.mbt`
+fn numbered_file(path: &Path) -> Option<(&'static str, u32)> {
+    if path.extension()? != FILE_EXTENSION {
+        return None;
+    }
+    let (prefix, number) = path.file_stem()?.to_str()?.split_once('-')?;
+    let prefix = [SNAPSHOT_PREFIX, WAL_PREFIX]
+        .into_iter()
+        .find(|known| *known == prefix)?;
+    Some((prefix, number.parse().ok()?))
+}
+
+fn write_record(file: &mut File, record: &WalRecord) -> Result<(), DataError> {
+    record
+        .send_with(file, WAL_FEATURES)
+        .map(|_| ())
+        .map_err(|err| DataError {
+            code: sqlstate::IO_ERROR,
+            msg: format!("Could not write the log: {}", err.msg),
+        })
+}
+
+fn write_error(path: &Path, err: MicrobatProtocolError) -> DataError {
+    DataError {
+        code: sqlstate::IO_ERROR,
+        msg: format!("Could not write {}: {}", path.display(), err.msg),
+    }
+}
+
+fn corrupted(path: &Path, reason: &str) -> DataError {
+    DataError {
+        code: sqlstate::IO_ERROR,
+        msg: format!("{} is corrupted: {}", path.display(), reason),
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> DataError {
+    DataError {
+        code: sqlstate::IO_ERROR,
+        msg: format!("{}: {}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod file_manager_tests {
+    use super::*;
+    use microbat_protocol::data::data_values::MDataType;
+    use microbat_protocol::messages::MicrobatMessage;
+
+    /// Empty directory of its own for every test
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("microbat-files-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn files(directory: &Path) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn create_foo(manager: &mut FileManager) {
+        manager
+            .create_table(
+                String::from("foo"),
+                vec![
+                    Column::new(String::from("id"), MDataType::Integer),
+                    Column::new(String::from("amount"), MDataType::BigInt),
+                ],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tables_survive_reopening() {
+        let directory = directory("reopen");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        manager
+            .insert("foo", vec![MData::Integer(1), MData::Integer(10)])
+            .unwrap();
+        manager
+            .insert("foo", vec![MData::Integer(2), MData::Null])
+            .unwrap();
+        assert!(manager
+            .insert("foo", vec![MData::Varchar(String::from("x")), MData::Null])
+            .is_err());
+        // Rows inserted together are logged all or none
+        assert!(manager
+            .insert_rows(
+                "foo",
+                vec![
+                    vec![MData::Integer(4), MData::Integer(40)],
+                    vec![MData::Varchar(String::from("x")), MData::Null],
+                ],
+            )
+            .is_err());
+        manager
+            .create_table(
+                String::from("bar"),
+                vec![Column::new(String::from("name"), MDataType::Varchar)],
+            )
+            .unwrap();
+        assert!(manager.create_table(String::from("foo"), vec![]).is_err());
+        manager
+            .insert("foo", vec![MData::Integer(3), MData::Integer(30)])
+            .unwrap();
+        drop(manager);
+
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.schema_changes(), ["foo", "bar"]);
+        assert_eq!(
+            manager.fetch("foo").unwrap(),
+            vec![
+                vec![MData::Integer(1), MData::BigInt(10)],
+                vec![MData::Integer(2), MData::Null],
+                vec![MData::Integer(3), MData::BigInt(30)],
+            ]
+        );
+        assert!(manager.fetch("bar").unwrap().is_empty());
+        manager
+            .insert("bar", vec![MData::Varchar(String::from("baz"))])
+            .unwrap();
+        drop(manager);
+
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.fetch("bar").unwrap().len(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let directory = directory("checkpoint");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        for id in 0..1000 {
+            manager
+                .insert("foo", vec![MData::Integer(id), MData::Integer(id * 10)])
+                .unwrap();
+        }
+        manager.checkpoint().unwrap();
+        assert_eq!(files(&directory), ["snapshot-000001.mbt", "wal-000002.mbt"]);
+        // Nothing changed since, so there is nothing to write
+        manager.checkpoint().unwrap();
+        assert_eq!(files(&directory), ["snapshot-000001.mbt", "wal-000002.mbt"]);
+        manager
+            .insert("foo", vec![MData::Integer(1000), MData::Null])
+            .unwrap();
+        drop(manager);
+
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        let rows = manager.fetch("foo").unwrap();
+        assert_eq!(rows.len(), 1001);
+        assert_eq!(rows[999], vec![MData::Integer(999), MData::BigInt(9990)]);
+        assert_eq!(rows[1000], vec![MData::Integer(1000), MData::Null]);
+        manager.checkpoint().unwrap();
+        assert_eq!(files(&directory), ["snapshot-000002.mbt", "wal-000003.mbt"]);
+        drop(manager);
+
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.fetch("foo").unwrap().len(), 1001);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_indexes_survive_reopening() {
+        let directory = directory("indexes");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        manager
+            .create_index(String::from("foo_amount"), "foo", "amount")
+            .unwrap();
+        assert!(manager
+            .create_index(String::from("foo_amount"), "foo", "id")
+            .is_err());
+        drop(manager);
+
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        let indexes: Vec<(&str, usize)> = manager
+            .indexes("foo")
+            .iter()
+            .map(|index| (index.name.as_str(), index.column))
+            .collect();
+        assert_eq!(indexes, [("foo_amount", 1)]);
+        manager.checkpoint().unwrap();
+        drop(manager);
+
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.indexes("foo")[0].name, "foo_amount");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_checkpoint_is_ignored() {
+        let directory = directory("unfinished");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        manager
+            .insert("foo", vec![MData::Integer(1), MData::Integer(10)])
+            .unwrap();
+        manager.checkpoint().unwrap();
+        drop(manager);
+
+        // Server stopped before removing the log the snapshot covers, and while writing the
+        // next snapshot
+        fs::write(directory.join("wal-000001.mbt"), b"covered").unwrap();
+        fs::write(directory.join("snapshot-000002.tmp"), b"unfinished").unwrap();
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.fetch("foo").unwrap().len(), 1);
+        assert_eq!(files(&directory), ["snapshot-000001.mbt", "wal-000002.mbt"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_incomplete_row_is_dropped() {
+        let directory = directory("torn");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        manager
+            .insert("foo", vec![MData::Integer(1), MData::Integer(10)])
+            .unwrap();
+        drop(manager);
+
+        // Server stopped halfway through writing a row
+        let path = directory.join("wal-000001.mbt");
+        let row = WalRecord::Row(DataRow::new(vec![MData::Integer(2), MData::BigInt(20)]));
+        let bytes = row.as_bytes();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&bytes[..bytes.len() / 2]).unwrap();
+        drop(file);
+
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.fetch("foo").unwrap().len(), 1);
+        manager
+            .insert("foo", vec![MData::Integer(3), MData::Integer(30)])
+            .unwrap();
+        drop(manager);
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.fetch("foo").unwrap().len(), 2);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_file_fails_to_open() {
+        let directory = directory("corrupted");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        manager
+            .insert("foo", vec![MData::Integer(1), MData::Integer(10)])
+            .unwrap();
+        drop(manager);
+
+        let path = directory.join("wal-000001.mbt");
+        let mut bytes = fs::read(&path).unwrap();
+        // Flips a bit in the value of the row, which its checksum catches
+        let last = bytes.len() - 6;
+        bytes[last] ^= 1;
+        fs::write(&path, bytes).unwrap();
+        let error = FileManager::open(&directory, CaseFolding::default())
+            .err()
+            .unwrap();
+        assert_eq!(error.code, sqlstate::IO_ERROR);
+        assert!(error.msg.contains("is corrupted"), "{}", error.msg);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+    #[test]
+    fn test_files_of_another_format_fail_to_open() {
+        let directory = directory("format");
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        drop(manager);
+
+        let path = directory.join("wal-000001.mbt");
+        let mut bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(&file_header()));
+        bytes[HEADER_LENGTH - 2] += 1;
+        fs::write(&path, bytes).unwrap();
+        let error = FileManager::open(&directory, CaseFolding::default())
+            .err()
+            .unwrap();
+        assert!(
+            error.msg.contains("written in format version 2"),
+            "{}",
+            error.msg
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_incomplete_header_is_dropped() {
+        let directory = directory("header");
+        fs::create_dir_all(&directory).unwrap();
+        // Server stopped while writing the header of a new log
+        fs::write(directory.join("wal-000001.mbt"), &file_header()[..3]).unwrap();
+        let mut manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        create_foo(&mut manager);
+        drop(manager);
+
+        let manager = FileManager::open(&directory, CaseFolding::default()).unwrap();
+        assert_eq!(manager.schema_changes(), ["foo"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+use microbat_protocol::data::{
+    data_values::MData,
+    table_model::{Column, TableSchema},
+};
+
+use crate::db::aggregate::Accumulator;
+use crate::db::index::IndexKey;
+use crate::sql::expression::{EvaluationError, Expression};
+use crate::sql::parser::GroupBy;
+
+/// Result of grouping, one row per group
+pub struct GroupedRows {
+    /// Columns of the GROUP BY keys
+    pub schema: TableSchema,
+    /// Key values of each group. Keys not in the grouping set of the group are null.
+    pub rows: Vec<Vec<MData>>,
+    /// Values of aggregate select columns of each group by select column index,
+    /// null for columns that are not aggregates
+    pub aggregates: Vec<Vec<MData>>,
+}
+
+/// Groups of some of the rows with the state of their aggregates, which can be merged with
+/// the groups of the rows after them before the aggregates are finished
+pub struct PartialGroups {
+    /// Groups of each grouping set by their keys, with the state of each select column
+    sets: Vec<BTreeMap<Vec<IndexKey>, Vec<AggregateState>>>,
+}
+
+/// State of a select column in a group
+enum AggregateState {
+    /// Not an aggregate
+    Value,
+    Accumulating(Accumulator),
+    /// Values of a DISTINCT aggregate in the order they came, aggregated only when finished
+    /// as merged groups may have seen the same values
+    Distinct(Accumulator, Vec<MData>),
+}
+
+/// Groups rows by every grouping set of `group_by` and computes the aggregates of
+/// `projection` for each group. Without GROUP BY all rows form a single group.
+///
+/// Groups of each set come in the order of their keys, and sets in the order they were given.
+pub fn group_rows(
+    group_by: Option<&GroupBy>,
+    projection: &[Box<dyn Expression>],
+    schema: &TableSchema,
+    rows: &[Vec<MData>],
+) -> Result<GroupedRows, EvaluationError> {
+    partial_groups(group_by, projection, schema, rows)?.finish(group_by, projection, schema)
+}
+
+/// Groups given rows like `group_rows`, leaving the aggregates unfinished so that the
+/// groups can be merged with the groups of other rows
+pub fn partial_groups(
+    group_by: Option<&GroupBy>,
+    projection: &[Box<dyn Expression>],
+    schema: &TableSchema,
+    rows: &[Vec<MData>],
+) -> Result<PartialGroups, EvaluationError> {
+    let (expressions, sets) = grouping(group_by);
+    let mut partial = PartialGroups {
+        sets: sets.iter().map(|_| BTreeMap::new()).collect(),
+    };
+    for row in rows {
+        let mut key = vec![];
+        for expression in expressions.iter() {
+            key.push(expression.eval(schema, row)?);
+        }
+        let mut values = vec![];
+        for expression in projection.iter() {
+            values.push(match expression.aggregate() {
+                Some(aggregate) => match &aggregate.argument {
+                    Some(argument) => argument.eval(schema, row)?,
+                    // COUNT(*) counts every row
+                    None => MData::Bool(true),
+                },
+                None => MData::Null,
+            });
+        }
+        for (set, groups) in sets.iter().zip(partial.sets.iter_mut()) {
+            let set_key = key
+                .iter()
+                .enumerate()
+                .map(|(position, value)| match set.contains(&position) {
+                    true => IndexKey(value.clone()),
+                    false => IndexKey(MData::Null),
+                })
+                .collect();
+            let states = groups
+                .entry(set_key)
+                .or_insert_with(|| initial_states(projection));
+            for (state, value) in states.iter_mut().zip(values.iter()) {
+                state.push(value)?;
+            }
+        }
+    }
+    Ok(partial)
+}
+
+impl PartialGroups {
+    /// Merges the groups of the rows after the rows of these groups into these
+    pub fn merge(&mut self, later: PartialGroups) -> Result<(), EvaluationError> {
+        for (groups, later) in self.sets.iter_mut().zip(later.sets) {
+            for (key, states) in later {
+                match groups.entry(key) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(states);
+                    }
+                    Entry::Occupied(mut entry) => {
+                        for (state, later) in entry.get_mut().iter_mut().zip(states) {
+                            state.merge(later)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes the aggregates of the groups, which were grouped with the same arguments
+    pub fn finish(
+        self,
+        group_by: Option<&GroupBy>,
+        projection: &[Box<dyn Expression>],
+        schema: &TableSchema,
+    ) -> Result<GroupedRows, EvaluationError> {
+        let (expressions, sets) = grouping(group_by);
+        let mut columns: Vec<Column> = vec![];
+        for (index, expression) in expressions.iter().enumerate() {
+            columns.push(expression.schema_column(schema, index)?);
+        }
+
+        let mut grouped = GroupedRows {
+            schema: TableSchema { columns },
+            rows: vec![],
+            aggregates: vec![],
+        };
+        for (set, groups) in sets.iter().zip(self.sets) {
+            if groups.is_empty() && set.is_empty() {
+                // Grand total is there even if there is nothing to aggregate
+                grouped.rows.push(vec![MData::Null; expressions.len()]);
+                grouped
+                    .aggregates
+                    .push(finish_states(initial_states(projection))?);
+            }
+            for (key, states) in groups {
+                grouped
+                    .rows
+                    .push(key.into_iter().map(|key| key.0).collect());
+                grouped.aggregates.push(finish_states(states)?);
+            }
+        }
+        Ok(grouped)
+    }
+}
+
+impl AggregateState {
+    fn push(&mut self, value: &MData) -> Result<(), EvaluationError> {
+        match self {
+            AggregateState::Value => Ok(()),
+            AggregateState::Accumulating(accumulator) => accumulator.push(value.clone()),
+            AggregateState::Distinct(_, values) => {
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn merge(&mut self, later: AggregateState) -> Result<(), EvaluationError> {
+        match (self, later) {
+            (AggregateState::Accumulating(accumulator), AggregateState::Accumulating(later)) => {
+                accumulator.merge(later)
+            }
+            (state, AggregateState::Distinct(_, values)) => {
+                for value in values.iter() {
+                    state.push(value)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(self) -> Result<MData, EvaluationError> {
+        match self {
+            AggregateState::Value => Ok(MData::Null),
+            AggregateState::Accumulating(accumulator) => Ok(accumulator.finish()),
+            AggregateState::Distinct(mut accumulator, values) => {
+                for value in values {
+                    accumulator.push(value)?;
+                }
+                Ok(accumulator.finish())
+            }
+        }
+    }
+}
+
+/// Expressions grouped by and the grouping sets of them. Without GROUP BY there are no
+/// expressions and a single empty set.
+fn grouping(group_by: Option<&GroupBy>) -> (&[Box<dyn Expression>], Vec<Vec<usize>>) {
+    match group_by {
+        Some(group_by) => (&group_by.expressions[..], group_by.sets.clone()),
+        None => (&[][..], vec![vec![]]),
+    }
+}
+
+/// States of the select columns of a group before any row
+fn initial_states(projection: &[Box<dyn Expression>]) -> Vec<AggregateState> {
+    projection
+        .iter()
+        .map(|expression| match expression.aggregate() {
+            Some(aggregate) if aggregate.distinct => {
+                AggregateState::Distinct(Accumulator::new(&aggregate.function), vec![])
+            }
+            Some(aggregate) => AggregateState::Accumulating(Accumulator::new(&aggregate.function)),
+            None => AggregateState::Value,
+        })
+        .collect()
+}
+
+/// Values of the aggregate select columns of a group, null for the other columns
+fn finish_states(states: Vec<AggregateState>) -> Result<Vec<MData>, EvaluationError> {
+    states.into_iter().map(AggregateState::finish).collect()
+}
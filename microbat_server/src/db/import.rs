@@ -0,0 +1,290 @@
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+use microbat_protocol::data::data_values::{MData, MDataType};
+use microbat_protocol::data::date::{parse_date, parse_timestamp};
+use microbat_protocol::data::decimal::parse_decimal;
+use microbat_protocol::data::table_model::TableSchema;
+use microbat_protocol::sqlstate;
+
+use super::manager::{stored_value, DatabaseManager};
+use super::session::Session;
+use super::{rounding_notices, write_lock, MicrobatQueryError, QueryNotice, QueryResult};
+
+/// Field of a CSV record, remembering if it was quoted to tell an empty string from NULL
+#[derive(Debug, PartialEq)]
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+/// Inserts the rows of a CSV file on the server into a table like INSERT, collecting the
+/// notices it raises to `notices`. Fields are read as the types of the columns in their
+/// order, as `SELECT ... INTO OUTFILE` writes them. No row is inserted if any doesn't fit the
+/// table, and the error tells the line of the row. The file is read from the file directory
+/// of the session.
+pub fn import_csv(
+    path: &str,
+    table: &str,
+    header: bool,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    session: &Session,
+    notices: &mut Vec<QueryNotice>,
+) -> Result<QueryResult, MicrobatQueryError> {
+    let file = session.file_path(path, false)?;
+    // Read before locking, so that a slow disk doesn't keep other sessions waiting
+    let text = fs::read_to_string(file).map_err(|err| {
+        MicrobatQueryError::new(
+            sqlstate::IO_ERROR,
+            format!("Could not read {}: {}", path, err),
+        )
+    })?;
+    let mut database = write_lock(manager);
+    let schema = &database.get_table_meta(table)?.schema;
+    let mut rows = vec![];
+    for (line, record) in records(&text)?.into_iter().skip(usize::from(header)) {
+        let at_line = |err: MicrobatQueryError| {
+            MicrobatQueryError::new(err.code, format!("Line {} of {}: {}", line, path, err.msg))
+        };
+        let row = parse_row(schema, record).map_err(at_line)?;
+        rounding_notices(schema, &row, notices);
+        let row = schema
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| stored_value(column, value))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| at_line(MicrobatQueryError::from(err)))?;
+        rows.push(row);
+    }
+    let count = rows.len() as u32;
+    database.insert_rows(table, rows)?;
+    Ok(QueryResult::Inserted(count))
+}
+
+/// Records of CSV text with the numbers of the lines they start on. Quoted fields may contain
+/// separators, doubled quotes and line breaks, and blank lines are skipped.
+fn records(text: &str) -> Result<Vec<(usize, Vec<Field>)>, MicrobatQueryError> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = Field {
+        text: String::new(),
+        quoted: false,
+    };
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char == '\n' {
+            line += 1;
+        }
+        if in_quotes {
+            match char {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.text.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.text.push(char),
+            }
+            continue;
+        }
+        match char {
+            '"' if field.text.is_empty() && !field.quoted => {
+                in_quotes = true;
+                field.quoted = true;
+            }
+            ',' => record.push(std::mem::replace(
+                &mut field,
+                Field {
+                    text: String::new(),
+                    quoted: false,
+                },
+            )),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !record.is_empty() || !field.text.is_empty() || field.quoted {
+                    record.push(std::mem::replace(
+                        &mut field,
+                        Field {
+                            text: String::new(),
+                            quoted: false,
+                        },
+                    ));
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                start = line;
+            }
+            _ => field.text.push(char),
+        }
+    }
+    if in_quotes {
+        return Err(MicrobatQueryError::new(
+            sqlstate::BAD_COPY_FILE_FORMAT,
+            format!("Unterminated quoted field on line {}", start),
+        ));
+    }
+    if !record.is_empty() || !field.text.is_empty() || field.quoted {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+/// Values of a record as the types of the columns of the table
+fn parse_row(schema: &TableSchema, record: Vec<Field>) -> Result<Vec<MData>, MicrobatQueryError> {
+    if record.len() != schema.len() {
+        return Err(MicrobatQueryError::new(
+            sqlstate::BAD_COPY_FILE_FORMAT,
+            format!("Expecting {} fields but got {}", schema.len(), record.len()),
+        ));
+    }
+    schema
+        .columns
+        .iter()
+        .zip(record)
+        .map(|(column, field)| parse_value(&column.data_type, field))
+        .collect()
+}
+
+/// Value of a field as given type. An empty field is NULL unless it was quoted, as
+/// `SELECT ... INTO OUTFILE` writes NULL and empty strings.
+fn parse_value(data_type: &MDataType, field: Field) -> Result<MData, MicrobatQueryError> {
+    if field.text.is_empty() && !field.quoted {
+        return Ok(MData::Null);
+    }
+    let text = field.text;
+    let value = match data_type {
+        MDataType::Varchar => return Ok(MData::Varchar(text)),
+        MDataType::Integer => text.trim().parse().ok().map(MData::Integer),
+        MDataType::BigInt => text.trim().parse().ok().map(MData::BigInt),
+        MDataType::Float => text.trim().parse().ok().map(MData::Float),
+        MDataType::Decimal { .. } => {
+            parse_decimal(text.trim()).map(|(value, scale)| MData::Decimal(value, scale))
+        }
+        MDataType::Bool => match text.trim().to_lowercase().as_str() {
+            "true" | "t" => Some(MData::Bool(true)),
+            "false" | "f" => Some(MData::Bool(false)),
+            _ => None,
+        },
+        MDataType::Date => parse_date(text.trim()).map(MData::Date),
+        MDataType::Timestamp => parse_timestamp(text.trim()).map(MData::Timestamp),
+        MDataType::Bytes => text
+            .strip_prefix("\\x")
+            .filter(|hex| hex.len() % 2 == 0 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|hex| {
+                MData::Bytes(
+                    (0..hex.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                        .collect(),
+                )
+            }),
+        // Columns of NULL type hold nothing else
+        MDataType::Null => None,
+    };
+    value.ok_or_else(|| {
+        let code = match data_type {
+            MDataType::Date | MDataType::Timestamp => sqlstate::INVALID_DATETIME_FORMAT,
+            _ => sqlstate::INVALID_TEXT_REPRESENTATION,
+        };
+        MicrobatQueryError::new(code, format!("Invalid {}: {}", data_type, text))
+    })
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+    use microbat_protocol::data::table_model::Column;
+
+    fn field(text: &str, quoted: bool) -> Field {
+        Field {
+            text: String::from(text),
+            quoted,
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let text = "id,name\r\n1,\"say \"\"hi\"\", bye\"\n\n2,\"two\nlines\"\n3,\n4,\"\"";
+        assert_eq!(
+            records(text).ok().unwrap(),
+            vec![
+                (1, vec![field("id", false), field("name", false)]),
+                (2, vec![field("1", false), field("say \"hi\", bye", true)]),
+                (4, vec![field("2", false), field("two\nlines", true)]),
+                (6, vec![field("3", false), field("", false)]),
+                (7, vec![field("4", false), field("", true)]),
+            ]
+        );
+        assert_eq!(
+            records("1,\"open\n2,3\n").err().unwrap().msg,
+            "Unterminated quoted field on line 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_row() {
+        let schema = TableSchema::new(vec![
+            Column::new(String::from("i"), MDataType::Integer),
+            Column::new(
+                String::from("d"),
+                MDataType::Decimal {
+                    precision: 5,
+                    scale: 2,
+                },
+            ),
+            Column::new(String::from("b"), MDataType::Bool),
+            Column::new(String::from("t"), MDataType::Timestamp),
+            Column::new(String::from("x"), MDataType::Bytes),
+            Column::new(String::from("v"), MDataType::Varchar),
+        ])
+        .unwrap();
+        let row = |fields: [(&str, bool); 6]| {
+            parse_row(
+                &schema,
+                fields
+                    .iter()
+                    .map(|(text, quoted)| field(text, *quoted))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            row([
+                ("7", false),
+                ("1.5", false),
+                ("true", false),
+                ("1970-01-01 00:00:01", false),
+                ("\\xdead", false),
+                ("", true),
+            ])
+            .ok()
+            .unwrap(),
+            vec![
+                MData::Integer(7),
+                MData::Decimal(15, 1),
+                MData::Bool(true),
+                MData::Timestamp(1_000_000),
+                MData::Bytes(vec![0xde, 0xad]),
+                MData::Varchar(String::new()),
+            ]
+        );
+        assert_eq!(row([("", false); 6]).ok().unwrap(), vec![MData::Null; 6]);
+        let err = row([
+            ("seven", false),
+            ("", false),
+            ("", false),
+            ("", false),
+            ("", false),
+            ("", false),
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(err.code, sqlstate::INVALID_TEXT_REPRESENTATION);
+        assert_eq!(err.msg, "Invalid INTEGER: seven");
+        let err = parse_row(&schema, vec![field("1", false)]).err().unwrap();
+        assert_eq!(err.code, sqlstate::BAD_COPY_FILE_FORMAT);
+        assert_eq!(err.msg, "Expecting 6 fields but got 1");
+    }
+}
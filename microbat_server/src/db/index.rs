@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use microbat_protocol::data::{data_values::MData, table_model::TableSchema};
+
+use crate::db::sort::{compare_values, SortOptions};
+use crate::sql::expression::{Comparison, Expression, Logical};
+
+/// Value of an indexed column, ordered like ORDER BY orders values, so nulls come last
+pub(crate) struct IndexKey(pub(crate) MData);
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_values(&self.0, &other.0, &SortOptions::default())
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for IndexKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for IndexKey {}
+
+/// Ordered index of a column of a table, for finding the rows with values in a range
+/// without scanning the table. Maps the values of the column to the positions of the rows
+/// having them, which don't change as rows are only ever appended.
+pub struct TableIndex {
+    pub name: String,
+    /// Position of the indexed column in the table
+    pub column: usize,
+    entries: BTreeMap<IndexKey, Vec<usize>>,
+}
+
+impl TableIndex {
+    /// Index of given column of the rows of a table
+    pub fn new(name: String, column: usize, rows: &[Vec<MData>]) -> Self {
+        let mut index = TableIndex {
+            name,
+            column,
+            entries: BTreeMap::new(),
+        };
+        for (position, row) in rows.iter().enumerate() {
+            index.push(position, row);
+        }
+        index
+    }
+
+    /// Adds a row appended to the table at given position
+    pub fn push(&mut self, position: usize, row: &[MData]) {
+        self.entries
+            .entry(IndexKey(row[self.column].clone()))
+            .or_default()
+            .push(position);
+    }
+
+    /// Positions of the rows with values in given range, in the order of the rows
+    pub fn lookup(&self, range: &IndexRange) -> Vec<usize> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let bound = |bound: &Bound<MData>| match bound {
+            Bound::Included(value) => Bound::Included(IndexKey(value.clone())),
+            Bound::Excluded(value) => Bound::Excluded(IndexKey(value.clone())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let mut positions: Vec<usize> = self
+            .entries
+            .range((bound(&range.lower), bound(&range.upper)))
+            // Nulls are never in a range, but unbounded ranges reach them
+            .filter(|(key, _)| key.0 != MData::Null)
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// True if values of the column can be compared with given value. Comparing values of
+    /// other types fails, which scanning the table reports and the index must not hide.
+    fn is_comparable(&self, value: &MData) -> bool {
+        match self.entries.keys().next() {
+            // Nulls come last, so the first key is null only if every value is
+            Some(IndexKey(first)) if *first != MData::Null => {
+                Comparison::Equal.compare(first, value).is_ok()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Range of values of an indexed column a filter can be true for
+pub struct IndexRange {
+    pub lower: Bound<MData>,
+    pub upper: Bound<MData>,
+}
+
+impl IndexRange {
+    fn unbounded() -> Self {
+        IndexRange {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    /// Narrows this range to the values for which `value <comparison> given` can be true
+    fn narrow(&mut self, comparison: &Comparison, value: MData) {
+        let (lower, upper) = match comparison {
+            Comparison::Equal => (Bound::Included(value.clone()), Bound::Included(value)),
+            Comparison::LessThan => (Bound::Unbounded, Bound::Excluded(value)),
+            Comparison::LessThanOrEqual => (Bound::Unbounded, Bound::Included(value)),
+            Comparison::GreaterThan => (Bound::Excluded(value), Bound::Unbounded),
+            Comparison::GreaterThanOrEqual => (Bound::Included(value), Bound::Unbounded),
+            Comparison::NotEqual => return,
+        };
+        if tighter(&lower, &self.lower, Ordering::Greater) {
+            self.lower = lower;
+        }
+        if tighter(&upper, &self.upper, Ordering::Less) {
+            self.upper = upper;
+        }
+    }
+
+    /// True if no value is in this range, which is the case when it is bounded by null as
+    /// comparisons with null are never true
+    fn is_empty(&self) -> bool {
+        match (&self.lower, &self.upper) {
+            (Bound::Included(MData::Null) | Bound::Excluded(MData::Null), _)
+            | (_, Bound::Included(MData::Null) | Bound::Excluded(MData::Null)) => true,
+            (Bound::Included(lower), Bound::Included(upper)) => compare(lower, upper).is_gt(),
+            (
+                Bound::Included(lower) | Bound::Excluded(lower),
+                Bound::Included(upper) | Bound::Excluded(upper),
+            ) => compare(lower, upper).is_ge(),
+            _ => false,
+        }
+    }
+
+    /// True if this range has only a single value
+    fn is_point(&self) -> bool {
+        match (&self.lower, &self.upper) {
+            (Bound::Included(lower), Bound::Included(upper)) => compare(lower, upper).is_eq(),
+            _ => false,
+        }
+    }
+}
+
+/// Index of the table given indexes are of to filter it with given conditions, which must
+/// all be true, and the range of values of the indexed column the conditions keep.
+/// Comparisons of an indexed column with a value, alone or combined with AND, narrow the
+/// rows to a range. An index with an equality is preferred, then the first index the
+/// conditions narrow at all.
+///
+/// The conditions still have to be applied to the rows in the range, as they may have
+/// others.
+pub fn choose_index<'a>(
+    indexes: &'a [TableIndex],
+    filter: &[&dyn Expression],
+    schema: &TableSchema,
+) -> Option<(&'a TableIndex, IndexRange)> {
+    let mut conditions = vec![];
+    for condition in filter {
+        collect_conditions(*condition, &mut conditions);
+    }
+    let mut chosen: Option<(&TableIndex, IndexRange)> = None;
+    for index in indexes {
+        let column = &schema.columns[index.column].name;
+        let mut range = IndexRange::unbounded();
+        let mut narrowed = false;
+        for (name, comparison, value) in conditions.iter() {
+            if name != column || !index.is_comparable(value) {
+                continue;
+            }
+            range.narrow(comparison, value.clone());
+            narrowed = true;
+        }
+        let better = match &chosen {
+            None => narrowed,
+            Some((_, chosen)) => range.is_point() && !chosen.is_point(),
+        };
+        if better {
+            chosen = Some((index, range));
+        }
+    }
+    chosen
+}
+
+/// Collects comparisons of a column with a value from the conditions of a filter combined
+/// with AND, as the column, the comparison and the value
+fn collect_conditions(filter: &dyn Expression, conditions: &mut Vec<(String, Comparison, MData)>) {
+    if let Some(logical) = filter.logical() {
+        if matches!(logical.logical, Logical::And) {
+            collect_conditions(logical.left.as_ref(), conditions);
+            collect_conditions(logical.right.as_ref(), conditions);
+        }
+        return;
+    }
+    // Not equal keeps values on both sides of the value, so it doesn't narrow a range
+    let Some(comparison) = filter
+        .comparison()
+        .filter(|comparison| !matches!(comparison.comparison, Comparison::NotEqual))
+    else {
+        return;
+    };
+    let (column, value, flipped) = match (comparison.left.reference(), comparison.right.reference())
+    {
+        (Some(column), None) => (column, constant(comparison.right.as_ref()), false),
+        (None, Some(column)) => (column, constant(comparison.left.as_ref()), true),
+        _ => return,
+    };
+    if let Some(value) = value {
+        let comparison = match flipped {
+            true => comparison.comparison.flipped(),
+            false => comparison.comparison.clone(),
+        };
+        conditions.push((String::from(column), comparison, value));
+    }
+}
+
+/// Value of an expression that doesn't depend on the row, e.g. a literal or a bound
+/// parameter
+fn constant(expression: &dyn Expression) -> Option<MData> {
+    if expression.row_value().is_some()
+        || expression.aggregate().is_some()
+        || expression.window().is_some()
+    {
+        return None;
+    }
+    // Referring to a column fails without columns
+    expression.eval(&TableSchema { columns: vec![] }, &[]).ok()
+}
+
+/// True if bound `candidate` is tighter than `current`, i.e. further in given direction.
+/// Of bounds at the same value the excluding one is tighter.
+fn tighter(candidate: &Bound<MData>, current: &Bound<MData>, direction: Ordering) -> bool {
+    match (candidate, current) {
+        (Bound::Unbounded, _) => false,
+        (_, Bound::Unbounded) => true,
+        (
+            Bound::Included(value) | Bound::Excluded(value),
+            Bound::Included(other) | Bound::Excluded(other),
+        ) => match compare(value, other) {
+            Ordering::Equal => matches!(
+                (candidate, current),
+                (Bound::Excluded(_), Bound::Included(_))
+            ),
+            ordering => ordering == direction,
+        },
+    }
+}
+
+fn compare(left: &MData, right: &MData) -> Ordering {
+    compare_values(left, right, &SortOptions::default())
+}
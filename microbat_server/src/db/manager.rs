@@ -1,32 +1,86 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
 
 use microbat_protocol::data::{
-    data_values::{DataError, MData},
-    table_model::{Column, RelationTable, TableSchema},
+    data_values::{DataError, MData, MDataType},
+    decimal,
+    table_model::{Column, TableSchema},
 };
+use microbat_protocol::sqlstate;
 
-use crate::sql::expression::{EvaluationError, Expression};
+use crate::db::analyze::TableStatistics;
+use crate::db::catalog::{self, SessionActivity, SystemView};
+use crate::db::deadline::Deadline;
+use crate::db::executor::{execute_select, Rows};
+use crate::db::index::TableIndex;
+use crate::db::stats::StatementStatistics;
+use crate::db::summary::TableSummary;
+use crate::sql::expression::EvaluationError;
+use crate::sql::lexer::CaseFolding;
+use crate::sql::parser::SelectClause;
 
 pub trait DatabaseManager {
+    /// How unquoted identifiers are folded for looking up tables and columns by name
+    fn case_folding(&self) -> CaseFolding;
+    /// Most workers reading a large table in parallel for a query, 1 if tables are read by
+    /// the thread executing the query alone
+    fn max_parallel_workers(&self) -> usize;
     fn get_tables(&self) -> Result<Vec<String>, DataError>;
     fn get_table_meta(&self, name: &str) -> Result<&TableMetadata, DataError>;
     fn create_table(&mut self, name: String, columns: Vec<Column>) -> Result<(), DataError>;
+    /// Tables created so far in the order they were created, so sessions can tell their
+    /// clients what changed since they last looked
+    fn schema_changes(&self) -> &[String];
     fn insert(&mut self, table_name: &str, colums: Vec<MData>) -> Result<(), DataError>;
+    /// Inserts rows all or none: every row is checked against the table before any is
+    /// inserted, so a row that doesn't fit leaves the table as it was
+    fn insert_rows(&mut self, table_name: &str, rows: Vec<Vec<MData>>) -> Result<(), DataError>;
+    /// Creates an ordered index of a column of a table, which queries filtering the table by
+    /// the column use instead of scanning every row
+    fn create_index(
+        &mut self,
+        name: String,
+        table_name: &str,
+        column: &str,
+    ) -> Result<(), DataError>;
+    /// Indexes of a table in the order they were created
+    fn indexes(&self, table_name: &str) -> &[TableIndex];
+    /// Collects statistics of the values of given table, or of every table if not given, for
+    /// planning queries
+    fn analyze(&mut self, table_name: Option<&str>) -> Result<(), DataError>;
+    /// Statistics of a table from when it was last analyzed, None if it never was
+    fn statistics(&self, table_name: &str) -> Option<&TableStatistics>;
     fn fetch(&self, table_name: &str) -> Result<Vec<Vec<MData>>, DataError>;
-    fn query(
-        &self,
-        table_name: Vec<String>,
-        projection: Vec<Box<dyn Expression>>,
-    ) -> Result<RelationTable, DataError>;
-    fn carthesian(
-        &self,
-        table: &str,
-        root_data: Vec<Vec<MData>>,
-    ) -> Result<Vec<Vec<MData>>, DataError>;
+    /// Rows of a table one at a time, without copying the whole table like `fetch`
+    fn scan(&self, table_name: &str) -> Result<Rows<'_>, DataError>;
+    /// Rows of a table at given positions, e.g. found with an index
+    fn scan_at(&self, table_name: &str, positions: Vec<usize>) -> Result<Rows<'_>, DataError>;
+    /// Rows of a table as stored, for partitioning them across workers. None for system
+    /// views, whose rows are built when fetched.
+    fn stored_rows(&self, table_name: &str) -> Option<&[Vec<MData>]>;
+    /// Executes a select, producing its rows as they are pulled until the deadline, see
+    /// `execute_select`
+    fn query<'a>(
+        &'a self,
+        select: &'a SelectClause,
+        deadline: Deadline,
+    ) -> Result<(TableSchema, Rows<'a>), DataError>;
+    /// Summary of a table for answering simple aggregates without scanning it. System views
+    /// have no summary.
+    fn summary(&self, table_name: &str) -> Option<&TableSummary>;
+    /// Records an execution of a statement for the statement statistics view
+    fn record_statement(&self, fingerprint: String, elapsed: Duration, rows: Option<u64>);
+    /// Reports what a session is doing for the sessions view, None when the session ended
+    fn report_session(&self, session_id: u32, activity: Option<SessionActivity>);
+    /// Writes the tables to storage so that the changes logged before can be discarded.
+    /// Managers keeping tables only in memory have nothing to write.
+    fn checkpoint(&mut self) -> Result<(), DataError>;
 }
 
 #[derive(Debug)]
 pub struct TableMetadata {
+    #[allow(dead_code)]
     pub name: String,
     pub schema: TableSchema,
 }
@@ -34,23 +88,129 @@ pub struct TableMetadata {
 pub struct InMemoryManager {
     tables: HashMap<String, TableMetadata>,
     data: HashMap<String, Vec<Vec<MData>>>,
+    summaries: HashMap<String, TableSummary>,
+    indexes: HashMap<String, Vec<TableIndex>>,
+    statistics: HashMap<String, TableStatistics>,
+    // Recorded by reading queries too, so behind its own lock
+    statements: Mutex<StatementStatistics>,
+    sessions: Mutex<BTreeMap<u32, SessionActivity>>,
+    case_folding: CaseFolding,
+    max_parallel_workers: usize,
+    schema_changes: Vec<String>,
+}
+
+impl Default for InMemoryManager {
+    fn default() -> Self {
+        InMemoryManager::new()
+    }
 }
 
 impl InMemoryManager {
     pub fn new() -> InMemoryManager {
+        InMemoryManager::with_case_folding(CaseFolding::default())
+    }
+
+    /// Manager folding unquoted identifiers with given policy
+    pub fn with_case_folding(case_folding: CaseFolding) -> InMemoryManager {
+        let mut tables = HashMap::new();
+        // System views are listed with tables but their rows are built when fetched
+        for view in SystemView::ALL {
+            let name = case_folding.fold(view.name());
+            tables.insert(
+                name.clone(),
+                TableMetadata {
+                    name,
+                    schema: view.schema(case_folding),
+                },
+            );
+        }
         InMemoryManager {
-            tables: HashMap::new(),
+            tables,
             data: HashMap::new(),
+            summaries: HashMap::new(),
+            indexes: HashMap::new(),
+            statistics: HashMap::new(),
+            statements: Mutex::new(StatementStatistics::default()),
+            sessions: Mutex::new(BTreeMap::new()),
+            case_folding,
+            max_parallel_workers: 1,
+            schema_changes: vec![],
+        }
+    }
+
+    /// Lets queries read large tables with at most given count of workers. Tables are read
+    /// by the thread executing the query alone by default.
+    pub fn set_max_parallel_workers(&mut self, workers: usize) {
+        self.max_parallel_workers = workers.max(1);
+    }
+
+    fn system_view(&self, table_name: &str) -> Option<SystemView> {
+        SystemView::named(self.case_folding, table_name)
+    }
+
+    /// Row as stored in given table, with values converted to the types of their columns,
+    /// failing if it doesn't fit the table
+    fn checked_row(
+        &self,
+        table_name: &str,
+        mut colums: Vec<MData>,
+    ) -> Result<Vec<MData>, DataError> {
+        let table_metadata = self.get_table_meta(table_name)?;
+        if self.system_view(table_name).is_some() {
+            return Err(DataError {
+                code: sqlstate::WRONG_OBJECT_TYPE,
+                msg: format!("Can't insert into system view {}", table_name),
+            });
         }
+        if colums.len() != table_metadata.schema.len() {
+            return Err(DataError {
+                code: sqlstate::SYNTAX_ERROR,
+                msg: String::from("Column count mismatch"),
+            });
+        }
+        for (index, column) in table_metadata.schema.columns.iter().enumerate() {
+            let data = std::mem::replace(&mut colums[index], MData::Null);
+            let data = stored_value(column, data)?;
+            if data != MData::Null && !table_metadata.schema.matches_at(index, data.matcher()) {
+                return Err(DataError {
+                    code: sqlstate::DATATYPE_MISMATCH,
+                    msg: String::from("Can't put this here"),
+                });
+            }
+            colums[index] = data;
+        }
+        Ok(colums)
+    }
+
+    /// Appends a checked row to its table and the summary and indexes of the table
+    fn append(&mut self, table_name: &str, row: Vec<MData>) {
+        if let Some(summary) = self.summaries.get_mut(table_name) {
+            summary.push(&row);
+        }
+        let rows = self.data.get_mut(table_name).unwrap();
+        for index in self.indexes.get_mut(table_name).into_iter().flatten() {
+            index.push(rows.len(), &row);
+        }
+        rows.push(row);
     }
 }
 
 impl DatabaseManager for InMemoryManager {
+    fn case_folding(&self) -> CaseFolding {
+        self.case_folding
+    }
+
+    fn max_parallel_workers(&self) -> usize {
+        self.max_parallel_workers
+    }
+
     fn get_tables(&self) -> Result<Vec<String>, DataError> {
         let mut tables: Vec<String> = vec![];
-        for (_, table) in self.tables.keys().enumerate() {
+        for table in self.tables.keys() {
             tables.push(table.clone());
         }
+        // Sorted so the listing doesn't change with the hash map's iteration order
+        tables.sort();
         Ok(tables)
     }
 
@@ -58,6 +218,7 @@ impl DatabaseManager for InMemoryManager {
         match self.tables.get(name) {
             Some(table_metadata) => Ok(table_metadata),
             None => Err(DataError {
+                code: sqlstate::UNDEFINED_TABLE,
                 msg: format!("No such table: {}", name),
             }),
         }
@@ -66,6 +227,7 @@ impl DatabaseManager for InMemoryManager {
     fn create_table(&mut self, name: String, columns: Vec<Column>) -> Result<(), DataError> {
         if self.tables.contains_key(&name) {
             return Err(DataError {
+                code: sqlstate::DUPLICATE_TABLE,
                 msg: format!("Table already exists: {}", name),
             });
         }
@@ -73,35 +235,120 @@ impl DatabaseManager for InMemoryManager {
             name: name.clone(),
             schema: TableSchema::new(columns)?,
         };
+        self.summaries
+            .insert(name.clone(), TableSummary::new(table_metadata.schema.len()));
         self.tables.insert(name.clone(), table_metadata);
         self.data.insert(name.clone(), vec![]);
+        self.indexes.insert(name.clone(), vec![]);
+        self.schema_changes.push(name);
         Ok(())
     }
 
+    fn schema_changes(&self) -> &[String] {
+        &self.schema_changes
+    }
+
     fn insert(&mut self, table_name: &str, colums: Vec<MData>) -> Result<(), DataError> {
-        let table_metadata = self.get_table_meta(table_name)?;
-        for (index, column) in table_metadata.schema.columns.iter().enumerate() {
-            match colums.get(index) {
-                Some(data) => {
-                    if column.data_type != data.matcher() {
-                        return Err(DataError {
-                            msg: String::from("Can't put this here"),
-                        });
-                    }
-                }
-                None => {
+        let row = self.checked_row(table_name, colums)?;
+        self.append(table_name, row);
+        Ok(())
+    }
+
+    fn insert_rows(&mut self, table_name: &str, rows: Vec<Vec<MData>>) -> Result<(), DataError> {
+        let rows = rows
+            .into_iter()
+            .map(|row| self.checked_row(table_name, row))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in rows {
+            self.append(table_name, row);
+        }
+        Ok(())
+    }
+
+    fn create_index(
+        &mut self,
+        name: String,
+        table_name: &str,
+        column: &str,
+    ) -> Result<(), DataError> {
+        let schema = &self.get_table_meta(table_name)?.schema;
+        if self.system_view(table_name).is_some() {
+            return Err(DataError {
+                code: sqlstate::WRONG_OBJECT_TYPE,
+                msg: format!("Can't index system view {}", table_name),
+            });
+        }
+        let Some(position) = schema.columns.iter().position(|c| c.name == column) else {
+            return Err(DataError {
+                code: sqlstate::UNDEFINED_COLUMN,
+                msg: format!("No such column {}", column),
+            });
+        };
+        if self
+            .indexes
+            .values()
+            .flatten()
+            .any(|index| index.name == name)
+        {
+            return Err(DataError {
+                code: sqlstate::DUPLICATE_TABLE,
+                msg: format!("Index already exists: {}", name),
+            });
+        }
+        let index = TableIndex::new(name, position, &self.data[table_name]);
+        self.indexes.get_mut(table_name).unwrap().push(index);
+        Ok(())
+    }
+
+    fn indexes(&self, table_name: &str) -> &[TableIndex] {
+        self.indexes.get(table_name).map_or(&[], Vec::as_slice)
+    }
+
+    fn analyze(&mut self, table_name: Option<&str>) -> Result<(), DataError> {
+        let tables = match table_name {
+            Some(table_name) => {
+                self.get_table_meta(table_name)?;
+                if self.system_view(table_name).is_some() {
                     return Err(DataError {
-                        msg: String::from("Column count mismatch"),
-                    })
+                        code: sqlstate::WRONG_OBJECT_TYPE,
+                        msg: format!("Can't analyze system view {}", table_name),
+                    });
                 }
+                vec![table_name.to_owned()]
             }
+            None => self.data.keys().cloned().collect(),
+        };
+        for table in tables {
+            let columns = self.tables[&table].schema.len();
+            let statistics = TableStatistics::collect(&self.data[&table], columns);
+            self.statistics.insert(table, statistics);
         }
-        self.data.get_mut(table_name).unwrap().push(colums);
         Ok(())
     }
 
+    fn statistics(&self, table_name: &str) -> Option<&TableStatistics> {
+        self.statistics.get(table_name)
+    }
+
     fn fetch(&self, table_name: &str) -> Result<Vec<Vec<MData>>, DataError> {
         self.get_table_meta(table_name)?;
+        match self.system_view(table_name) {
+            Some(SystemView::StatStatements) => {
+                return Ok(self
+                    .statements
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .rows())
+            }
+            Some(SystemView::Tables) => return catalog::table_rows(self),
+            Some(SystemView::Indexes) => return catalog::index_rows(self),
+            Some(SystemView::Sessions) => {
+                return Ok(catalog::session_rows(
+                    &self.sessions.lock().unwrap_or_else(PoisonError::into_inner),
+                ))
+            }
+            None => {}
+        }
         let mut result: Vec<Vec<MData>> = vec![];
         for row in self.data.get(table_name).unwrap() {
             let mut clone_row: Vec<MData> = vec![];
@@ -113,70 +360,98 @@ impl DatabaseManager for InMemoryManager {
         Ok(result)
     }
 
-    fn query(
-        &self,
-        tables: Vec<String>,
-        projection: Vec<Box<dyn Expression>>,
-    ) -> Result<RelationTable, DataError> {
-        let mut schema_columns = vec![];
-        let mut data = vec![];
-        for table in tables.iter() {
-            data = self.carthesian(table, data)?;
-            let meta = self.get_table_meta(table)?;
-            for c in meta.schema.columns.iter() {
-                schema_columns.push(c.clone());
-            }
+    fn scan(&self, table_name: &str) -> Result<Rows<'_>, DataError> {
+        if self.system_view(table_name).is_some() {
+            return Ok(Box::new(self.fetch(table_name)?.into_iter().map(Ok)));
         }
-        let query_schema = TableSchema::new(schema_columns)?;
+        self.get_table_meta(table_name)?;
+        Ok(Box::new(self.data[table_name].iter().cloned().map(Ok)))
+    }
 
-        let mut evaled_columns = vec![];
-        for (index, expr) in projection.iter().enumerate() {
-            evaled_columns.push(expr.schema_column(&query_schema, index)?);
-        }
+    fn scan_at(&self, table_name: &str, positions: Vec<usize>) -> Result<Rows<'_>, DataError> {
+        self.get_table_meta(table_name)?;
+        let rows = &self.data[table_name];
+        Ok(Box::new(
+            positions
+                .into_iter()
+                .map(move |position| Ok(rows[position].clone())),
+        ))
+    }
 
-        let mut relation = RelationTable::new(TableSchema::new(evaled_columns)?);
+    fn stored_rows(&self, table_name: &str) -> Option<&[Vec<MData>]> {
+        self.data.get(table_name).map(Vec::as_slice)
+    }
 
-        for row in data.iter() {
-            let mut relation_row = vec![];
-            for expr in projection.iter() {
-                relation_row.push(expr.eval(&query_schema, row)?);
-            }
-            relation.push_row(relation_row)?;
-        }
-        Ok(relation)
+    fn query<'a>(
+        &'a self,
+        select: &'a SelectClause,
+        deadline: Deadline,
+    ) -> Result<(TableSchema, Rows<'a>), DataError> {
+        execute_select(select, self, deadline)
     }
 
-    fn carthesian(
-        &self,
-        table: &str,
-        root_data: Vec<Vec<MData>>,
-    ) -> Result<Vec<Vec<MData>>, DataError> {
-        let data = self.fetch(table)?;
-        if root_data.is_empty() {
-            return Ok(data);
+    fn summary(&self, table_name: &str) -> Option<&TableSummary> {
+        self.summaries.get(table_name)
+    }
+
+    fn record_statement(&self, fingerprint: String, elapsed: Duration, rows: Option<u64>) {
+        self.statements
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(fingerprint, elapsed, rows);
+    }
+
+    fn report_session(&self, session_id: u32, activity: Option<SessionActivity>) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        match activity {
+            Some(activity) => sessions.insert(session_id, activity),
+            None => sessions.remove(&session_id),
+        };
+    }
+
+    fn checkpoint(&mut self) -> Result<(), DataError> {
+        Ok(())
+    }
+}
+
+/// Value as stored in given column. Integers are widened when stored in a big integer or
+/// float column, and numbers stored in a decimal column are rounded to its scale, failing if
+/// they have more digits than it allows. Other values are stored as they are.
+pub fn stored_value(column: &Column, data: MData) -> Result<MData, DataError> {
+    match (&column.data_type, &data) {
+        (MDataType::BigInt, MData::Integer(value)) => Ok(MData::BigInt(i64::from(*value))),
+        (MDataType::Float, MData::Integer(_) | MData::BigInt(_)) => {
+            Ok(MData::Float(data.as_float().unwrap()))
         }
-        let mut new_data = vec![];
-        for row in root_data.iter() {
-            for n_row in data.iter() {
-                let mut c = vec![];
-                for r in row.iter() {
-                    c.push(r.clone());
+        (
+            MDataType::Decimal { precision, scale },
+            MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+        ) => {
+            let value = match &data {
+                MData::Float(value) => decimal::from_float(*value, *scale),
+                _ => {
+                    let (value, from) = data.as_decimal().unwrap();
+                    decimal::rescale(value, from, *scale)
                 }
-                let mut d = vec![];
-                for r in n_row {
-                    d.push(r.clone());
-                }
-                let new_row = vec![c, d].concat();
-                new_data.push(new_row);
+            };
+            match value.filter(|value| decimal::digits(*value) <= *precision) {
+                Some(value) => Ok(MData::Decimal(value, *scale)),
+                None => Err(DataError {
+                    code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+                    msg: format!("Value out of range for {}", column.data_type),
+                }),
             }
         }
-        Ok(new_data)
+        _ => Ok(data),
     }
 }
 
 impl From<EvaluationError> for DataError {
     fn from(value: EvaluationError) -> Self {
-        Self { msg: value.msg }
+        Self {
+            code: value.code,
+            msg: value.msg,
+        }
     }
 }
 
@@ -235,6 +510,7 @@ mod in_memory_db_tests {
         );
         assert!(fails.is_err());
         assert_eq!(fails.unwrap_err().msg, "Table already exists: foo");
+        assert_eq!(manager.schema_changes(), ["foo"]);
     }
 
     #[test]
@@ -273,4 +549,45 @@ mod in_memory_db_tests {
         assert!(insert_result.is_err());
         assert_eq!(insert_result.unwrap_err().msg, "Can't put this here");
     }
+
+    #[test]
+    fn test_insert_rows_all_or_none() {
+        let mut manager = InMemoryManager::new();
+        manager
+            .create_table(
+                String::from("foo"),
+                vec![Column::new(String::from("id"), MDataType::BigInt)],
+            )
+            .unwrap();
+
+        let failed = manager.insert_rows(
+            "foo",
+            vec![vec![MData::Integer(1)], vec![MData::Bool(true)]],
+        );
+        assert_eq!(failed.unwrap_err().msg, "Can't put this here");
+        assert!(manager.fetch("foo").unwrap().is_empty());
+        assert_eq!(manager.summary("foo").unwrap().rows(), 0);
+
+        manager
+            .insert_rows("foo", vec![vec![MData::Integer(1)], vec![MData::Null]])
+            .unwrap();
+        assert_eq!(
+            manager.fetch("foo").unwrap(),
+            vec![vec![MData::BigInt(1)], vec![MData::Null]]
+        );
+    }
+
+    #[test]
+    fn test_insert_null() {
+        let mut manager = InMemoryManager::new();
+        manager
+            .create_table(
+                String::from("foo"),
+                vec![Column::new(String::from("id"), MDataType::Integer)],
+            )
+            .unwrap();
+
+        manager.insert("foo", vec![MData::Null]).unwrap();
+        assert_eq!(manager.fetch("foo").unwrap(), vec![vec![MData::Null]]);
+    }
 }
@@ -1,53 +1,335 @@
+pub mod aggregate;
+pub mod analyze;
+pub mod catalog;
+pub mod deadline;
+pub mod demo;
+pub mod executor;
+pub mod explain;
+pub mod file_manager;
+pub mod group;
+pub mod import;
+pub mod index;
 pub mod manager;
+pub mod planner;
+pub mod session;
+pub mod sink;
+pub mod sort;
+pub mod stats;
+pub mod summary;
+pub mod table_function;
+pub mod wal_record;
+pub mod window;
 
 use std::{
-    sync::{Arc, RwLock},
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Instant,
     vec,
 };
 
 use microbat_protocol::data::{
     data_values::{DataError, MData, MDataType},
+    decimal,
     table_model::{Column, DataRow, TableSchema},
 };
+use microbat_protocol::sqlstate;
 
+use crate::sql::expression::EvaluationError;
+use crate::sql::lexer::{LexerOptions, SourceRef};
+use crate::sql::normalize::fingerprint;
 use crate::sql::parser::{
-    parse_sql, ParseError,
-    SqlClause::{Select, ShowTables},
+    parse_sql_with_options, ParseError, ParseErrorKind, SqlClause,
+    SqlClause::{
+        Analyze, Checkpoint, CopyFrom, CreateIndex, CreateTable, CreateTableAs, Describe, Explain,
+        ImportCsv, Insert, Select, Set, Show, ShowAll, ShowTables,
+    },
 };
 
+use self::deadline::Deadline;
 use self::manager::DatabaseManager;
+use self::session::Session;
+use self::sink::Sink;
 
 pub struct MicrobatQueryError {
+    /// SQLSTATE of the error, see `microbat_protocol::sqlstate`
+    pub code: &'static str,
     pub msg: String,
+    /// Where in the SQL the error is, known for errors of parsing
+    pub position: Option<SourceRef>,
+}
+
+impl MicrobatQueryError {
+    pub fn new(code: &'static str, msg: String) -> Self {
+        MicrobatQueryError {
+            code,
+            msg,
+            position: None,
+        }
+    }
 }
 
 impl From<ParseError> for MicrobatQueryError {
     fn from(value: ParseError) -> Self {
+        let code = match value.kind {
+            ParseErrorKind::UnknownFunction(_) => sqlstate::UNDEFINED_FUNCTION,
+            ParseErrorKind::UnknownCollation(_) | ParseErrorKind::UnknownType(_) => {
+                sqlstate::UNDEFINED_OBJECT
+            }
+            ParseErrorKind::InvalidDate(_) | ParseErrorKind::InvalidTimestamp(_) => {
+                sqlstate::INVALID_DATETIME_FORMAT
+            }
+            ParseErrorKind::InvalidDecimal(_) => sqlstate::INVALID_TEXT_REPRESENTATION,
+            ParseErrorKind::InvalidPrecision(_, _) => sqlstate::INVALID_PARAMETER_VALUE,
+            ParseErrorKind::GroupingExpression => sqlstate::FEATURE_NOT_SUPPORTED,
+            ParseErrorKind::UngroupedColumn(_) => sqlstate::GROUPING_ERROR,
+            _ => sqlstate::SYNTAX_ERROR,
+        };
         MicrobatQueryError {
-            msg: format!("{}", value),
+            code,
+            msg: value.kind.to_string(),
+            position: value.position,
         }
     }
 }
 
+impl From<EvaluationError> for MicrobatQueryError {
+    fn from(value: EvaluationError) -> Self {
+        MicrobatQueryError::new(value.code, value.msg)
+    }
+}
+
 impl From<DataError> for MicrobatQueryError {
     fn from(value: DataError) -> Self {
-        MicrobatQueryError {
-            msg: format!("{}", value.msg),
-        }
+        MicrobatQueryError::new(value.code, value.msg)
     }
 }
 
+/// Warning about a statement that does not fail it, sent to the client in a Notice
+pub struct QueryNotice {
+    /// SQLSTATE of the warning, see `microbat_protocol::sqlstate`
+    pub code: &'static str,
+    pub msg: String,
+}
+
 pub enum QueryResult {
     Table(TableSchema, Vec<DataRow>),
+    /// Count of rows of a select given to the `RowReceiver` as they were produced
+    Streamed(u64),
+    /// Count of inserted rows
+    Inserted(u32),
+    /// Statement that neither returns nor inserts rows completed, with its tag, e.g.
+    /// `CREATE TABLE`
+    Command(&'static str),
+    /// Rows of given table are to be read from the client and inserted with `copy_rows`
+    CopyIn(String, TableSchema),
+}
+
+/// Receiver of the rows of a select as they are produced, so that they don't have to be
+/// kept until the select finishes. Producing a row may still fail after some are received,
+/// which fails the statement.
+pub trait RowReceiver {
+    /// Called with the schema of the rows before any of them
+    fn describe(&mut self, schema: TableSchema);
+    fn receive(&mut self, row: DataRow);
+}
+
+/// Receiver keeping the rows for returning them in `QueryResult::Table`
+#[derive(Default)]
+pub struct CollectedRows {
+    schema: Option<TableSchema>,
+    rows: Vec<DataRow>,
 }
 
+impl CollectedRows {
+    /// Result of a statement with the rows it streamed collected into a table
+    pub fn into_result(self, result: QueryResult) -> QueryResult {
+        match (result, self.schema) {
+            (QueryResult::Streamed(_), Some(schema)) => QueryResult::Table(schema, self.rows),
+            (result, _) => result,
+        }
+    }
+}
+
+impl RowReceiver for CollectedRows {
+    fn describe(&mut self, schema: TableSchema) {
+        self.schema = Some(schema);
+    }
+
+    fn receive(&mut self, row: DataRow) {
+        self.rows.push(row);
+    }
+}
+
+/// Statement parsed once and executed any number of times, with parameters bound anew
+/// before each execution
+pub struct PreparedStatement {
+    clause: SqlClause,
+    // As prepared, for showing what a session executes
+    sql: String,
+    // Of the sql, for the statement statistics
+    fingerprint: Option<String>,
+    // False until every parameter has a value
+    bound: bool,
+}
+
+impl PreparedStatement {
+    /// Binds parameters for the next executions. A statement without parameters is bound
+    /// when it is prepared.
+    pub fn bind(&mut self, parameters: &[MData]) -> Result<(), MicrobatQueryError> {
+        self.bound = false;
+        self.clause.bind(parameters)?;
+        self.bound = true;
+        Ok(())
+    }
+
+    /// Statement as it was prepared
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+}
+
+/// Parses a statement for executing it later with `execute_prepared`
+pub fn prepare_sql(
+    sql: String,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+) -> Result<PreparedStatement, MicrobatQueryError> {
+    let fingerprint = guarded_fingerprint(&sql);
+    let mut clause = catch_panics(|| parse(sql.clone(), manager))?;
+    let bound = clause.bind(&[]).is_ok();
+    Ok(PreparedStatement {
+        clause,
+        sql,
+        fingerprint,
+        bound,
+    })
+}
+
+/// Executes a statement in a session of its own and records it in the statement statistics,
+/// ignoring the notices it raises. Rows of a select are collected into `QueryResult::Table`.
 pub fn execute_sql(
     sql: String,
-    manager: &Arc<RwLock<impl DatabaseManager>>,
+    parameters: Vec<MData>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
 ) -> Result<QueryResult, MicrobatQueryError> {
-    match parse_sql(sql)? {
+    let mut rows = CollectedRows::default();
+    let result = execute_sql_with_notices(
+        sql,
+        parameters,
+        manager,
+        &mut Session::new(),
+        &mut vec![],
+        &mut rows,
+    )?;
+    Ok(rows.into_result(result))
+}
+
+/// Executes a statement of given session like `execute_sql`, collecting the notices it
+/// raises to `notices`. Rows of a select are given to `rows` as they are produced, holding
+/// the database locked for reading until the last one is received.
+pub fn execute_sql_with_notices(
+    sql: String,
+    parameters: Vec<MData>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    session: &mut Session,
+    notices: &mut Vec<QueryNotice>,
+    rows: &mut dyn RowReceiver,
+) -> Result<QueryResult, MicrobatQueryError> {
+    let fingerprint = guarded_fingerprint(&sql);
+    recorded(fingerprint, manager, || {
+        let mut clause = parse(sql, manager)?;
+        clause.bind(&parameters)?;
+        execute_clause(&clause, manager, session, notices, rows)
+    })
+}
+
+/// Executes a prepared statement with the parameters bound to it, like
+/// `execute_sql_with_notices`
+pub fn execute_prepared(
+    statement: &PreparedStatement,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    session: &mut Session,
+    notices: &mut Vec<QueryNotice>,
+    rows: &mut dyn RowReceiver,
+) -> Result<QueryResult, MicrobatQueryError> {
+    if !statement.bound {
+        return Err(MicrobatQueryError::new(
+            sqlstate::UNDEFINED_PARAMETER,
+            String::from("Statement has parameters but none are bound"),
+        ));
+    }
+    recorded(statement.fingerprint.clone(), manager, || {
+        execute_clause(&statement.clause, manager, session, notices, rows)
+    })
+}
+
+/// Fingerprint of a statement, None if lexing it panics. Parsing it then fails the statement
+/// with the panic as an internal error, like any statement panicking.
+fn guarded_fingerprint(sql: &str) -> Option<String> {
+    catch_panics(|| Ok(fingerprint(sql))).unwrap_or(None)
+}
+
+/// Runs a statement, recording it in the statement statistics if it has a fingerprint
+fn recorded(
+    fingerprint: Option<String>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    statement: impl FnOnce() -> Result<QueryResult, MicrobatQueryError>,
+) -> Result<QueryResult, MicrobatQueryError> {
+    let start = Instant::now();
+    let result = catch_panics(statement);
+    if let Some(fingerprint) = fingerprint {
+        let rows = match &result {
+            Ok(QueryResult::Table(_, rows)) => Some(rows.len() as u64),
+            Ok(QueryResult::Streamed(count)) => Some(*count),
+            Ok(QueryResult::Inserted(count)) => Some(u64::from(*count)),
+            Ok(QueryResult::Command(_)) => Some(0),
+            // Rows are not yet copied when the statement ends
+            Ok(QueryResult::CopyIn(..)) => Some(0),
+            Err(_) => None,
+        };
+        read_lock(manager).record_statement(fingerprint, start.elapsed(), rows);
+    }
+    result
+}
+
+/// A bug panicking in one statement fails only that statement instead of the connection
+fn catch_panics<T>(
+    statement: impl FnOnce() -> Result<T, MicrobatQueryError>,
+) -> Result<T, MicrobatQueryError> {
+    panic::catch_unwind(AssertUnwindSafe(statement)).unwrap_or_else(|panic| {
+        let reason = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+            (Some(reason), _) => String::from(*reason),
+            (_, Some(reason)) => reason.clone(),
+            _ => String::from("unknown reason"),
+        };
+        Err(MicrobatQueryError::new(
+            sqlstate::INTERNAL_ERROR,
+            format!("Internal error: {}", reason),
+        ))
+    })
+}
+
+fn parse(
+    sql: String,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+) -> Result<SqlClause, MicrobatQueryError> {
+    let options = LexerOptions {
+        case_folding: read_lock(manager).case_folding(),
+        ..LexerOptions::default()
+    };
+    Ok(parse_sql_with_options(sql, options)?)
+}
+
+fn execute_clause(
+    clause: &SqlClause,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    session: &mut Session,
+    notices: &mut Vec<QueryNotice>,
+    receiver: &mut dyn RowReceiver,
+) -> Result<QueryResult, MicrobatQueryError> {
+    let deadline = Deadline::after(session.statement_timeout());
+    match clause {
         ShowTables => {
-            let database = manager.read().expect("RwLock poisoned");
+            let database = read_lock(manager);
             let mut rows = vec![];
             for table in database.get_tables()? {
                 rows.push(DataRow {
@@ -65,12 +347,2270 @@ pub fn execute_sql(
                 rows,
             ))
         }
-        Select(projection, from) => {
-            let database = manager.read().expect("RwLock poisoned");
+        Show(name) => {
+            let (schema, rows) = session.show(name)?;
+            Ok(QueryResult::Table(schema, rows))
+        }
+        ShowAll => {
+            let (schema, rows) = session.show_all()?;
+            Ok(QueryResult::Table(schema, rows))
+        }
+        Set(name, value) => {
+            session.set(name, value.as_deref())?;
+            Ok(QueryResult::Command("SET"))
+        }
+        Checkpoint => {
+            write_lock(manager).checkpoint()?;
+            Ok(QueryResult::Command("CHECKPOINT"))
+        }
+        Analyze(table) => {
+            write_lock(manager).analyze(table.as_deref())?;
+            Ok(QueryResult::Command("ANALYZE"))
+        }
+        Describe(table) => {
+            let database = read_lock(manager);
+            let meta = database.get_table_meta(table)?;
+            let mut rows = vec![];
+            for column in meta.schema.columns.iter() {
+                rows.push(DataRow {
+                    columns: vec![
+                        MData::Varchar(column.name.clone()),
+                        MData::Varchar(column.data_type.to_string()),
+                        // Columns don't have constraints or defaults yet, so every column is
+                        // nullable and defaults to null
+                        MData::Bool(true),
+                        MData::Null,
+                    ],
+                })
+            }
+
+            Ok(QueryResult::Table(
+                TableSchema::new(vec![
+                    Column::new(String::from("column"), MDataType::Varchar),
+                    Column::new(String::from("type"), MDataType::Varchar),
+                    Column::new(String::from("nullable"), MDataType::Bool),
+                    Column::new(String::from("default"), MDataType::Varchar),
+                ])?,
+                rows,
+            ))
+        }
+        Select(select) => {
+            if let Some(sink) = &select.into {
+                return sink.run(select, manager, session, deadline);
+            }
+            let database = read_lock(manager);
+
+            let (schema, rows) = database.query(select, deadline)?;
+            receiver.describe(schema);
+            let mut count = 0;
+            for row in rows {
+                receiver.receive(DataRow::new(row?));
+                count += 1;
+            }
+            Ok(QueryResult::Streamed(count))
+        }
+        Explain(select) => {
+            let database = read_lock(manager);
+            let rows = explain::explain(select, &*database)?;
+            Ok(QueryResult::Table(explain::schema(), rows))
+        }
+        CreateTable(table, columns) => {
+            check_unique_columns(columns)?;
+            let mut database = write_lock(manager);
+            database.create_table(table.clone(), columns.clone())?;
+            Ok(QueryResult::Command("CREATE TABLE"))
+        }
+        CreateTableAs(table, select) => {
+            Sink::Table(table.clone()).run(select, manager, session, deadline)
+        }
+        CreateIndex(name, table, column) => {
+            write_lock(manager).create_index(name.clone(), table, column)?;
+            Ok(QueryResult::Command("CREATE INDEX"))
+        }
+        CopyFrom(table) => {
+            let database = read_lock(manager);
+            let schema = database.get_table_meta(table)?.schema.clone();
+            Ok(QueryResult::CopyIn(table.clone(), schema))
+        }
+        ImportCsv(path, table, header) => {
+            import::import_csv(path, table, *header, manager, session, notices)
+        }
+        Insert(insert) => {
+            let mut database = write_lock(manager);
 
-            let relation = database.query(from, projection)?;
+            // Values can't refer to any columns
+            let no_columns = TableSchema { columns: vec![] };
+            let mut rows = vec![];
+            for row in insert.rows.iter() {
+                let mut values = vec![];
+                for expression in row.iter() {
+                    values.push(expression.eval(&no_columns, &[])?);
+                }
+                rows.push(values);
+            }
 
-            return Ok(QueryResult::Table(relation.schema, relation.rows));
+            // RETURNING is evaluated of the given values before inserting, and the rows are
+            // inserted all or none, so neither a failing expression nor a row that doesn't
+            // fit leaves rows half inserted
+            let mut returned = None;
+            if !insert.returning.is_empty() {
+                let schema = &database.get_table_meta(&insert.table)?.schema;
+                let mut columns = vec![];
+                for (index, expression) in insert.returning.iter().enumerate() {
+                    columns.push(expression.schema_column(schema, index)?);
+                }
+                let mut returned_rows = vec![];
+                for row in rows.iter() {
+                    let mut values = vec![];
+                    for expression in insert.returning.iter() {
+                        values.push(expression.eval(schema, row)?);
+                    }
+                    returned_rows.push(DataRow::new(values));
+                }
+                returned = Some((TableSchema::new(columns)?, returned_rows));
+            }
+
+            let schema = &database.get_table_meta(&insert.table)?.schema;
+            for row in rows.iter() {
+                rounding_notices(schema, row, notices);
+            }
+            let count = rows.len() as u32;
+            database.insert_rows(&insert.table, rows)?;
+            match returned {
+                Some((schema, rows)) => Ok(QueryResult::Table(schema, rows)),
+                None => Ok(QueryResult::Inserted(count)),
+            }
+        }
+    }
+}
+
+/// Inserts the rows a client sent for COPY ... FROM STDIN like INSERT, all or none,
+/// collecting the notices it raises to `notices`
+pub fn copy_rows(
+    table: &str,
+    rows: Vec<DataRow>,
+    manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+    notices: &mut Vec<QueryNotice>,
+) -> Result<QueryResult, MicrobatQueryError> {
+    catch_panics(|| {
+        let mut database = write_lock(manager);
+        let schema = &database.get_table_meta(table)?.schema;
+        for row in rows.iter() {
+            rounding_notices(schema, &row.columns, notices);
+        }
+        let count = rows.len() as u32;
+        database.insert_rows(table, rows.into_iter().map(|row| row.columns).collect())?;
+        Ok(QueryResult::Inserted(count))
+    })
+}
+
+/// Locks the database for reading. The lock is poisoned when a statement panics while
+/// holding it, but the panic is caught in `execute_sql` and statements change the database
+/// only through the manager, so other connections carry on with it.
+pub(crate) fn read_lock<D: ?Sized>(manager: &RwLock<D>) -> RwLockReadGuard<'_, D> {
+    manager.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks the database for writing, see `read_lock` for poisoning
+pub(crate) fn write_lock<D: ?Sized>(manager: &RwLock<D>) -> RwLockWriteGuard<'_, D> {
+    manager.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Warns of values that lose digits when rounded to the scale of their decimal column.
+/// Rounding is no error, but likely not what the user meant.
+fn rounding_notices(schema: &TableSchema, row: &[MData], notices: &mut Vec<QueryNotice>) {
+    for (column, value) in schema.columns.iter().zip(row) {
+        let MDataType::Decimal { scale, .. } = column.data_type else {
+            continue;
+        };
+        let rounded = match value {
+            MData::Decimal(value, from) if *from > scale => decimal::rescale(*value, *from, scale)
+                .is_some_and(|rounded| decimal::rescale(rounded, scale, *from) != Some(*value))
+                .then(|| decimal::format_decimal(*value, *from)),
+            MData::Float(value) => decimal::from_float(*value, scale)
+                .is_some_and(|rounded| decimal::to_float(rounded, scale) != *value)
+                .then(|| value.to_string()),
+            _ => None,
+        };
+        if let Some(value) = rounded {
+            notices.push(QueryNotice {
+                code: sqlstate::WARNING,
+                msg: format!(
+                    "Value {} of column {} rounded to {} decimals",
+                    value, column.name, scale
+                ),
+            });
+        }
+    }
+}
+
+/// Columns of a new table must have distinct names
+fn check_unique_columns(columns: &[Column]) -> Result<(), MicrobatQueryError> {
+    for (index, column) in columns.iter().enumerate() {
+        if columns[..index].iter().any(|c| c.name == column.name) {
+            return Err(MicrobatQueryError::new(
+                sqlstate::DUPLICATE_COLUMN,
+                format!("Column {} appears more than once", column.name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod execute_sql_tests {
+    use super::*;
+    use crate::db::catalog::SessionActivity;
+    use crate::db::manager::InMemoryManager;
+    use crate::sql::lexer::CaseFolding;
+    use microbat_protocol::data::table_model::Row;
+    use std::time::Duration;
+
+    fn manager() -> Arc<RwLock<InMemoryManager>> {
+        let mut manager = InMemoryManager::new();
+        manager
+            .create_table(
+                String::from("foo"),
+                vec![
+                    Column::new(String::from("id"), MDataType::Integer),
+                    Column::new(String::from("name"), MDataType::Varchar),
+                ],
+            )
+            .unwrap();
+        for (id, name) in [(1, Some("b")), (2, None), (3, Some("A")), (4, Some("a"))] {
+            manager
+                .insert(
+                    "foo",
+                    vec![
+                        MData::Integer(id),
+                        name.map_or(MData::Null, |name| MData::Varchar(String::from(name))),
+                    ],
+                )
+                .unwrap();
+        }
+        Arc::new(RwLock::new(manager))
+    }
+
+    /// Executes a statement in a session reading and writing files in the temp directory
+    fn execute_with_files(
+        sql: String,
+        manager: &Arc<RwLock<InMemoryManager>>,
+    ) -> Result<QueryResult, MicrobatQueryError> {
+        let mut session = Session::new();
+        session.set_file_dir(Some(std::env::temp_dir()));
+        let mut rows = CollectedRows::default();
+        let result =
+            execute_sql_with_notices(sql, vec![], manager, &mut session, &mut vec![], &mut rows)?;
+        Ok(rows.into_result(result))
+    }
+
+    fn rows(sql: &str, manager: &Arc<RwLock<InMemoryManager>>) -> Vec<Vec<MData>> {
+        match execute_sql(String::from(sql), vec![], manager) {
+            Ok(QueryResult::Table(_, rows)) => rows.into_iter().map(|row| row.columns).collect(),
+            Ok(_) => panic!("{} did not return a table", sql),
+            Err(err) => panic!("{} failed: {}", sql, err.msg),
+        }
+    }
+
+    fn named_rows(sql: &str, manager: &Arc<RwLock<InMemoryManager>>) -> Vec<Row> {
+        match execute_sql(String::from(sql), vec![], manager) {
+            Ok(QueryResult::Table(schema, rows)) => {
+                let schema = Arc::new(schema);
+                rows.into_iter()
+                    .map(|row| Row::new(schema.clone(), row))
+                    .collect()
+            }
+            Ok(_) => panic!("{} did not return a table", sql),
+            Err(err) => panic!("{} failed: {}", sql, err.msg),
+        }
+    }
+
+    /// Type of a column as DESCRIBE tells it
+    fn column_type(table: &str, column: &str, manager: &Arc<RwLock<InMemoryManager>>) -> String {
+        named_rows(&format!("describe {};", table), manager)
+            .into_iter()
+            .find(|row| row.get::<String>("column").unwrap() == column)
+            .map(|row| row.get("type").unwrap())
+            .unwrap_or_else(|| panic!("No column {} in {}", column, table))
+    }
+
+    fn ids(sql: &str, manager: &Arc<RwLock<InMemoryManager>>) -> Vec<MData> {
+        rows(sql, manager)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_order_by() {
+        let manager = manager();
+        let order =
+            |ids: &[i32]| -> Vec<MData> { ids.iter().map(|id| MData::Integer(*id)).collect() };
+        assert_eq!(
+            ids("select id from foo order by id desc;", &manager),
+            order(&[4, 3, 2, 1])
+        );
+        assert_eq!(
+            ids("select id from foo order by name;", &manager),
+            order(&[3, 4, 1, 2])
+        );
+        assert_eq!(
+            ids("select id from foo order by name desc;", &manager),
+            order(&[2, 1, 4, 3])
+        );
+        assert_eq!(
+            ids("select id from foo order by name nulls first;", &manager),
+            order(&[2, 3, 4, 1])
+        );
+        assert_eq!(
+            ids(
+                "select id from foo order by name desc nulls last;",
+                &manager
+            ),
+            order(&[1, 4, 3, 2])
+        );
+        assert_eq!(
+            ids(
+                "select id from foo order by name collate nocase, id desc;",
+                &manager
+            ),
+            order(&[4, 3, 1, 2])
+        );
+    }
+
+    #[test]
+    fn test_where() {
+        let manager = manager();
+        let order =
+            |ids: &[i32]| -> Vec<MData> { ids.iter().map(|id| MData::Integer(*id)).collect() };
+        assert_eq!(
+            ids("select id from foo where id > 1 and id <> 3;", &manager),
+            order(&[2, 4])
+        );
+        // Comparison with NULL is unknown, so the row is left out either way
+        assert_eq!(
+            ids("select id from foo where name = 'b';", &manager),
+            order(&[1])
+        );
+        assert_eq!(
+            ids("select id from foo where not name = 'b';", &manager),
+            order(&[3, 4])
+        );
+        assert_eq!(
+            ids("select id from foo where name is null or id = 4;", &manager),
+            order(&[2, 4])
+        );
+        assert_eq!(
+            ids(
+                "select id from foo where id < 3 order by id desc;",
+                &manager
+            ),
+            order(&[2, 1])
+        );
+        match execute_sql(
+            String::from("select id from foo where name;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "Varchar(\"b\") is not a boolean"),
+            Ok(_) => panic!("Varchar should not be a condition"),
+        }
+    }
+
+    #[test]
+    fn test_row_value_predicates() {
+        let manager = manager();
+        let order =
+            |ids: &[i32]| -> Vec<MData> { ids.iter().map(|id| MData::Integer(*id)).collect() };
+        assert_eq!(
+            ids("select id from foo where (id, name) = (3, 'A');", &manager),
+            order(&[3])
+        );
+        assert_eq!(
+            ids(
+                "select id from foo where (id, name) in ((1, 'b'), (2, 'x'), (4, 'a'));",
+                &manager
+            ),
+            order(&[1, 4])
+        );
+        assert_eq!(
+            ids("select id from foo where id not in (1, 3);", &manager),
+            order(&[2, 4])
+        );
+        for (sql, error) in [
+            (
+                "select id from foo where (id, name) = (1, 'b', 2);",
+                "Can't compare rows of 2 and 3 values",
+            ),
+            (
+                "select id from foo where (id, name) = 1;",
+                "Can't compare a row value with a single value",
+            ),
+            (
+                "select (id, name) from foo;",
+                "Row value can only be used in a comparison",
+            ),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.msg, error),
+                Ok(_) => panic!("Expecting {} to fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_limit_and_offset() {
+        let manager = manager();
+        let order =
+            |ids: &[i32]| -> Vec<MData> { ids.iter().map(|id| MData::Integer(*id)).collect() };
+        assert_eq!(
+            ids("select id from foo order by id desc limit 2;", &manager),
+            order(&[4, 3])
+        );
+        assert_eq!(
+            ids("select id from foo order by id limit 2 offset 3;", &manager),
+            order(&[4])
+        );
+        assert_eq!(
+            ids(
+                "select id from foo order by id offset 1 rows fetch first 2 rows only;",
+                &manager
+            ),
+            order(&[2, 3])
+        );
+        assert_eq!(
+            ids("select id from foo offset 10 rows;", &manager),
+            order(&[])
+        );
+    }
+
+    #[test]
+    fn test_row_number() {
+        let manager = manager();
+        let numbers = |sql: &str| -> Vec<MData> {
+            rows(sql, &manager)
+                .into_iter()
+                .map(|row| row[1].clone())
+                .collect()
+        };
+        assert_eq!(
+            numbers("select id, row_number() over () from foo;"),
+            vec![
+                MData::Integer(1),
+                MData::Integer(2),
+                MData::Integer(3),
+                MData::Integer(4)
+            ]
+        );
+        assert_eq!(
+            numbers("select id, row_number() over (order by name) from foo order by id;"),
+            vec![
+                MData::Integer(3),
+                MData::Integer(4),
+                MData::Integer(1),
+                MData::Integer(2)
+            ]
+        );
+        assert_eq!(
+            numbers("select id, row_number() over (order by id) from foo order by id desc;"),
+            vec![
+                MData::Integer(4),
+                MData::Integer(3),
+                MData::Integer(2),
+                MData::Integer(1)
+            ]
+        );
+    }
+
+    fn sales_manager() -> Arc<RwLock<InMemoryManager>> {
+        let mut manager = InMemoryManager::new();
+        manager
+            .create_table(
+                String::from("sales"),
+                vec![
+                    Column::new(String::from("region"), MDataType::Varchar),
+                    Column::new(String::from("product"), MDataType::Varchar),
+                    Column::new(String::from("amount"), MDataType::Integer),
+                ],
+            )
+            .unwrap();
+        for (region, product, amount) in [("north", "x", 1), ("north", "y", 2), ("south", "x", 4)] {
+            manager
+                .insert(
+                    "sales",
+                    vec![
+                        MData::Varchar(String::from(region)),
+                        MData::Varchar(String::from(product)),
+                        MData::Integer(amount),
+                    ],
+                )
+                .unwrap();
+        }
+        Arc::new(RwLock::new(manager))
+    }
+
+    fn varchar(value: &str) -> MData {
+        MData::Varchar(String::from(value))
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let manager = manager();
+        assert_eq!(
+            rows(
+                "select count(*), count(name), sum(id), min(name), max(id) from foo;",
+                &manager
+            ),
+            vec![vec![
+                MData::Integer(4),
+                MData::Integer(3),
+                MData::BigInt(10),
+                varchar("A"),
+                MData::Integer(4)
+            ]]
+        );
+        // Sums of integers are big integers, so they don't overflow on tables of integers
+        assert_eq!(
+            rows("select sum(x) from generate_series(1, 200000) x;", &manager),
+            vec![vec![MData::BigInt(20000100000)]]
+        );
+        assert_eq!(
+            rows(
+                "select name, count(*) as n, max(id) from foo group by name order by name;",
+                &manager
+            ),
+            vec![
+                vec![varchar("A"), MData::Integer(1), MData::Integer(3)],
+                vec![varchar("a"), MData::Integer(1), MData::Integer(4)],
+                vec![varchar("b"), MData::Integer(1), MData::Integer(1)],
+                vec![MData::Null, MData::Integer(1), MData::Integer(2)],
+            ]
+        );
+        match execute_sql(
+            String::from("select id, count(*) from foo group by name;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => {
+                assert_eq!(err.code, sqlstate::GROUPING_ERROR);
+                assert_eq!(
+                    err.msg,
+                    "Column id must appear in the GROUP BY clause or be used in an aggregate function"
+                );
+            }
+            Ok(_) => panic!("Selecting a column not in GROUP BY should fail"),
+        }
+        for (sql, code) in [
+            ("select name, count(*) from foo;", sqlstate::GROUPING_ERROR),
+            (
+                "select id + 1, count(*) from foo group by id + 1;",
+                sqlstate::FEATURE_NOT_SUPPORTED,
+            ),
+            (
+                "select count(*) from foo group by name having count(*) > 1;",
+                sqlstate::SYNTAX_ERROR,
+            ),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.code, code, "{}", sql),
+                Ok(_) => panic!("{} should fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_distinct_aggregates() {
+        let manager = sales_manager();
+        assert_eq!(
+            rows(
+                "select count(distinct product), count(product), string_agg(distinct region, '/') from sales;",
+                &manager
+            ),
+            vec![vec![
+                MData::Integer(2),
+                MData::Integer(3),
+                varchar("north/south")
+            ]]
+        );
+        assert_eq!(
+            rows(
+                "select product, count(distinct region), sum(distinct amount) from sales group by product;",
+                &manager
+            ),
+            vec![
+                vec![varchar("x"), MData::Integer(2), MData::BigInt(5)],
+                vec![varchar("y"), MData::Integer(1), MData::BigInt(2)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grouping_sets() {
+        let manager = sales_manager();
+        let north = || varchar("north");
+        let south = || varchar("south");
+        assert_eq!(
+            rows(
+                "select region, product, sum(amount) from sales group by rollup (region, product);",
+                &manager
+            ),
+            vec![
+                vec![north(), varchar("x"), MData::BigInt(1)],
+                vec![north(), varchar("y"), MData::BigInt(2)],
+                vec![south(), varchar("x"), MData::BigInt(4)],
+                vec![north(), MData::Null, MData::BigInt(3)],
+                vec![south(), MData::Null, MData::BigInt(4)],
+                vec![MData::Null, MData::Null, MData::BigInt(7)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select region, product, sum(amount) from sales group by cube (region, product);",
+                &manager
+            )[3..],
+            vec![
+                vec![north(), MData::Null, MData::BigInt(3)],
+                vec![south(), MData::Null, MData::BigInt(4)],
+                vec![MData::Null, varchar("x"), MData::BigInt(5)],
+                vec![MData::Null, varchar("y"), MData::BigInt(2)],
+                vec![MData::Null, MData::Null, MData::BigInt(7)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select region, count(*) from sales group by grouping sets ((region), ());",
+                &manager
+            ),
+            vec![
+                vec![north(), MData::Integer(2)],
+                vec![south(), MData::Integer(1)],
+                vec![MData::Null, MData::Integer(3)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_agg() {
+        let manager = sales_manager();
+        assert_eq!(
+            rows(
+                "select region, string_agg(product, ', ') from sales group by region;",
+                &manager
+            ),
+            vec![
+                vec![varchar("north"), varchar("x, y")],
+                vec![varchar("south"), varchar("x")],
+            ]
+        );
+        assert_eq!(
+            rows("select group_concat(region) from sales;", &manager),
+            vec![vec![varchar("north,north,south")]]
+        );
+        match execute_sql(
+            String::from("select string_agg(amount, ',') from sales;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "Can't concatenate Integer(1)"),
+            Ok(_) => panic!("Concatenating integers should fail"),
+        }
+    }
+
+    #[test]
+    fn test_insert() {
+        let manager = manager();
+        match execute_sql(
+            String::from("insert into foo values (5, 'e'), (6, null);"),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 2),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(
+            rows("select id, name from foo order by id desc;", &manager)[..2],
+            vec![
+                vec![MData::Integer(6), MData::Null],
+                vec![MData::Integer(5), varchar("e")],
+            ]
+        );
+
+        for (sql, error) in [
+            ("insert into foo values (7);", "Column count mismatch"),
+            ("insert into foo values ('7', 'g');", "Can't put this here"),
+            ("insert into foo values (id, 'g');", "No such column id"),
+            ("insert into bar values (7, 'g');", "No such table: bar"),
+            // Rows are inserted all or none
+            (
+                "insert into foo values (7, 'g'), ('8', 'h');",
+                "Can't put this here",
+            ),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.msg, error),
+                Ok(_) => panic!("{} should fail", sql),
+            }
         }
+        assert_eq!(ids("select id from foo;", &manager).len(), 6);
+    }
+
+    #[test]
+    fn test_insert_returning() {
+        let manager = manager();
+        assert_eq!(
+            rows(
+                "insert into foo values (5, 'e'), (6, 'f') returning id, name as inserted;",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(5), varchar("e")],
+                vec![MData::Integer(6), varchar("f")],
+            ]
+        );
+        match execute_sql(
+            String::from("insert into foo values ($1, $2) returning id + 1;"),
+            vec![MData::Integer(7), varchar("g")],
+            &manager,
+        ) {
+            Ok(QueryResult::Table(schema, rows)) => {
+                assert_eq!(schema.len(), 1);
+                assert_eq!(rows, vec![DataRow::new(vec![MData::Integer(8)])]);
+            }
+            _ => panic!("Expecting returned rows"),
+        }
+        assert_eq!(ids("select id from foo;", &manager).len(), 7);
+
+        // Nothing is inserted if RETURNING fails
+        assert!(execute_sql(
+            String::from("insert into foo values (8, 'h') returning nope;"),
+            vec![],
+            &manager
+        )
+        .is_err());
+        assert_eq!(ids("select id from foo;", &manager).len(), 7);
+    }
+
+    #[test]
+    fn test_create_table_as_select() {
+        let manager = manager();
+        match execute_sql(
+            String::from(
+                "create table named as select id, name as label from foo group by id, name order by id desc;",
+            ),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 4),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(
+            rows("describe named;", &manager)
+                .into_iter()
+                .map(|row| (row[0].clone(), row[1].clone()))
+                .collect::<Vec<(MData, MData)>>(),
+            vec![
+                (varchar("id"), varchar("INTEGER")),
+                (varchar("label"), varchar("VARCHAR")),
+            ]
+        );
+        assert_eq!(
+            rows("select id, label from named;", &manager)[..2],
+            vec![
+                vec![MData::Integer(4), varchar("a")],
+                vec![MData::Integer(3), varchar("A")]
+            ]
+        );
+
+        for (sql, error) in [
+            (
+                "create table named as select id from foo;",
+                "Table already exists: named",
+            ),
+            (
+                "create table twice as select id, id from foo;",
+                "Column id appears more than once",
+            ),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.msg, error),
+                Ok(_) => panic!("{} should fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_show_tables_in_name_order() {
+        let manager = manager();
+        for table in ["zeta", "alpha", "mid"] {
+            assert!(execute_sql(
+                format!("create table {} (id integer);", table),
+                vec![],
+                &manager
+            )
+            .is_ok());
+        }
+        assert_eq!(
+            ids("show tables;", &manager),
+            vec![
+                varchar("alpha"),
+                varchar("foo"),
+                varchar("mb_stat_statements"),
+                varchar("microbat_indexes"),
+                varchar("microbat_sessions"),
+                varchar("microbat_tables"),
+                varchar("mid"),
+                varchar("zeta")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifier_case_folding() {
+        let manager = manager();
+        for sql in [
+            "create table Mixed (Id integer);",
+            "create table \"Mixed\" (\"Id\" integer);",
+            "insert into MIXED values (1);",
+            "insert into \"Mixed\" values (2);",
+        ] {
+            assert!(
+                execute_sql(String::from(sql), vec![], &manager).is_ok(),
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            ids("select ID from mixed;", &manager),
+            vec![MData::Integer(1)]
+        );
+        assert_eq!(
+            ids("select \"Id\" from \"Mixed\";", &manager),
+            vec![MData::Integer(2)]
+        );
+        match execute_sql(String::from("select id from \"Mixed\";"), vec![], &manager) {
+            Err(err) => assert_eq!(err.msg, "No such column id"),
+            Ok(_) => panic!("Quoted column should keep its case"),
+        }
+
+        // Names the server creates itself are folded like identifiers
+        let manager = Arc::new(RwLock::new(InMemoryManager::with_case_folding(
+            CaseFolding::Upper,
+        )));
+        assert_eq!(
+            rows("select calls from mb_stat_statements;", &manager),
+            Vec::<Vec<MData>>::new()
+        );
+        assert!(execute_sql(
+            String::from("create table foo (id integer);"),
+            vec![],
+            &manager
+        )
+        .is_ok());
+        assert_eq!(
+            ids("show tables;", &manager),
+            vec![
+                varchar("FOO"),
+                varchar("MB_STAT_STATEMENTS"),
+                varchar("MICROBAT_INDEXES"),
+                varchar("MICROBAT_SESSIONS"),
+                varchar("MICROBAT_TABLES")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_into() {
+        let manager = manager();
+        match execute_sql(
+            String::from("select id, name into table copied from foo where id > 2;"),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 2),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(
+            ids("select id from copied order by id;", &manager),
+            vec![MData::Integer(3), MData::Integer(4)]
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("microbat-outfile-{}.csv", std::process::id()));
+        let sql = format!(
+            "select id, name into outfile '{}' from foo order by id;",
+            path.display()
+        );
+        // Files are used only in the file directory of the session
+        match execute_sql(sql.clone(), vec![], &manager) {
+            Err(err) => assert_eq!(err.code, sqlstate::INSUFFICIENT_PRIVILEGE),
+            Ok(_) => panic!("File should not be written without a file directory"),
+        }
+        assert!(!path.exists());
+        match execute_with_files(sql.clone(), &manager) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 4),
+            _ => panic!("Expecting insert result"),
+        }
+        let written = std::fs::read_to_string(&path).unwrap();
+        // Existing files are not overwritten
+        let overwritten = execute_with_files(sql, &manager);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "id,name\n1,b\n2,\n3,A\n4,a\n");
+        match overwritten {
+            Err(err) => {
+                assert!(err.msg.starts_with("Could not write"), "{}", err.msg);
+                assert_eq!(err.code, sqlstate::IO_ERROR);
+            }
+            Ok(_) => panic!("Existing file should not be overwritten"),
+        }
+
+        match execute_sql(
+            String::from("create table nested as select id into other from foo;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => {
+                assert_eq!(err.msg, "unexpected token INTO");
+                assert_eq!(
+                    err.position,
+                    Some(SourceRef {
+                        line: 1,
+                        column: 50
+                    })
+                );
+            }
+            Ok(_) => panic!("INTO should not be allowed in CREATE TABLE AS"),
+        }
+    }
+
+    #[test]
+    fn test_poisoned_lock_is_recovered() {
+        let manager = manager();
+        let poisoning = Arc::clone(&manager);
+        let _ = std::thread::spawn(move || {
+            let _database = poisoning.write().unwrap();
+            panic!("Statement failed while holding the lock");
+        })
+        .join();
+        assert!(manager.is_poisoned());
+        assert_eq!(
+            ids("select id from foo order by id limit 1;", &manager),
+            vec![MData::Integer(1)]
+        );
+        assert!(execute_sql(
+            String::from("insert into foo values (5, 'e');"),
+            vec![],
+            &manager
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_message() {
+        match execute_sql(
+            String::from("select id from foo order from;"),
+            vec![],
+            &manager(),
+        ) {
+            Err(err) => {
+                assert_eq!(err.code, sqlstate::SYNTAX_ERROR);
+                assert_eq!(err.msg, "unexpected token FROM");
+                assert_eq!(
+                    err.position,
+                    Some(SourceRef {
+                        line: 1,
+                        column: 26
+                    })
+                );
+            }
+            Ok(_) => panic!("Parsing should fail"),
+        }
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let manager = manager();
+        for (sql, code) in [
+            ("select nothing from foo;", sqlstate::UNDEFINED_COLUMN),
+            ("select id from bar;", sqlstate::UNDEFINED_TABLE),
+            ("create table foo (id integer);", sqlstate::DUPLICATE_TABLE),
+            ("select nope(id) from foo;", sqlstate::UNDEFINED_FUNCTION),
+            (
+                "select id from foo where name;",
+                sqlstate::DATATYPE_MISMATCH,
+            ),
+            ("select $1;", sqlstate::UNDEFINED_PARAMETER),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.code, code, "{}: {}", sql, err.msg),
+                Ok(_) => panic!("{} should fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_table() {
+        let manager = manager();
+        match execute_sql(
+            String::from("create table flags (id integer, enabled boolean);"),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Command(tag)) => assert_eq!(tag, "CREATE TABLE"),
+            _ => panic!("Expecting command result"),
+        }
+        execute_sql(
+            String::from("insert into flags values (1, true), (2, false), (3, null);"),
+            vec![],
+            &manager,
+        )
+        .unwrap_or_else(|err| panic!("Can't insert: {}", err.msg));
+        assert_eq!(column_type("flags", "enabled", &manager), "BOOLEAN");
+        assert_eq!(
+            rows(
+                "select id, enabled = false from flags where enabled or enabled is null;",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(1), MData::Bool(false)],
+                vec![MData::Integer(3), MData::Null],
+            ]
+        );
+        for (sql, error) in [
+            (
+                "create table twice (id integer, id boolean);",
+                "Column id appears more than once",
+            ),
+            ("insert into flags values (4, 1);", "Can't put this here"),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.msg, error),
+                Ok(_) => panic!("{} should fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_table_functions() {
+        let manager = manager();
+        assert_eq!(
+            rows(
+                "select generate_series from generate_series(1, 3);",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(1)],
+                vec![MData::Integer(2)],
+                vec![MData::Integer(3)]
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select name, n from foo, generate_series(1, 2) as g(n) where id = 1;",
+                &manager
+            ),
+            vec![
+                vec![varchar("b"), MData::Integer(1)],
+                vec![varchar("b"), MData::Integer(2)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select x, count(*) from generate_series(10, 0, -5) x group by x order by x;",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(0), MData::Integer(1)],
+                vec![MData::Integer(5), MData::Integer(1)],
+                vec![MData::Integer(10), MData::Integer(1)],
+            ]
+        );
+        assert_eq!(
+            ids("select id from generate_series(1, 0), foo;", &manager),
+            vec![]
+        );
+        match execute_sql(
+            String::from("select a from generate_series(1, 2) as g(a, b);"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(
+                err.msg,
+                "generate_series returns 1 columns but 2 names were given"
+            ),
+            Ok(_) => panic!("Too many column names should fail"),
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let manager = manager();
+        let node =
+            |id: i32, parent: Option<i32>, node: &str, detail: Option<&str>, rows: Option<i32>| {
+                vec![
+                    MData::Integer(id),
+                    parent.map_or(MData::Null, MData::Integer),
+                    varchar(node),
+                    detail.map_or(MData::Null, varchar),
+                    rows.map_or(MData::Null, MData::Integer),
+                ]
+            };
+        assert_eq!(
+            rows(
+                "explain select id, n from foo, generate_series(1, 2) n order by id limit 3 offset 2;",
+                &manager
+            ),
+            vec![
+                node(1, None, "Limit", Some("LIMIT 3 OFFSET 2"), None),
+                node(2, Some(1), "Sort", Some("1 key"), None),
+                node(3, Some(2), "Project", Some("2 columns"), None),
+                node(4, Some(3), "Nested Loop", None, None),
+                node(5, Some(4), "Scan", Some("foo"), Some(4)),
+                node(6, Some(4), "Function Scan", Some("generate_series"), None),
+            ]
+        );
+        assert_eq!(
+            rows(
+                "explain select count(*) from foo where id > 1 limit 5;",
+                &manager
+            ),
+            vec![
+                node(1, None, "Limit", Some("LIMIT 5 OFFSET 0"), Some(1)),
+                node(2, Some(1), "Project", Some("1 column"), Some(1)),
+                node(3, Some(2), "Aggregate", None, Some(1)),
+                node(4, Some(3), "Filter", None, None),
+                node(5, Some(4), "Scan", Some("foo"), Some(4)),
+            ]
+        );
+        match execute_sql(
+            String::from("explain select id from bar;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "No such table: bar"),
+            Ok(_) => panic!("Explaining a missing table should fail"),
+        }
+    }
+
+    #[test]
+    fn test_conditions_are_pushed_down() {
+        let manager = manager();
+        let sql =
+            "select id, n from foo, generate_series(1, 3) n where id = 2 and n > 1 and id < n;";
+        let unindexed = rows(sql, &manager);
+        assert_eq!(unindexed, vec![vec![MData::Integer(2), MData::Integer(3)]]);
+        execute_sql(
+            String::from("create index foo_id on foo (id);"),
+            vec![],
+            &manager,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(rows(sql, &manager), unindexed);
+
+        // Conditions on a single relation filter it before joining, the rest after
+        let plan: Vec<Vec<MData>> = rows(&format!("explain {}", sql), &manager)
+            .into_iter()
+            .map(|row| row[1..4].to_vec())
+            .collect();
+        let node = |parent: i32, node: &str, detail: Option<&str>| {
+            vec![
+                MData::Integer(parent),
+                varchar(node),
+                detail.map_or(MData::Null, varchar),
+            ]
+        };
+        assert_eq!(
+            plan[1..],
+            [
+                node(1, "Filter", None),
+                node(2, "Nested Loop", None),
+                node(3, "Filter", None),
+                node(4, "Index Scan", Some("foo using foo_id")),
+                node(3, "Filter", None),
+                node(6, "Function Scan", Some("generate_series")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_order() {
+        let manager = manager();
+        let execute = |sql: String| {
+            execute_sql(sql, vec![], &manager).ok().unwrap();
+        };
+        execute(String::from(
+            "create table bar (foo_id integer, kind integer, label varchar, name varchar);",
+        ));
+        for id in 0..30 {
+            execute(format!(
+                "insert into bar values ({}, {}, 'bar {}', 'bar');",
+                id % 5,
+                id % 2,
+                id
+            ));
+        }
+        let sql = "select id, name, label from foo, bar where id = foo_id and kind = 1 and label < 'bar 2' order by id, label;";
+        let expected = rows(sql, &manager);
+        assert_eq!(expected.len(), 5);
+        // Relations keep the order of FROM in the joined rows, so name is of foo
+        assert_eq!(
+            expected[0],
+            vec![MData::Integer(1), varchar("b"), varchar("bar 1")]
+        );
+        let nodes = |sql: &str| -> Vec<MData> {
+            rows(&format!("explain {}", sql), &manager)
+                .into_iter()
+                .map(|row| row[2].clone())
+                .collect()
+        };
+        // Filtered bar is estimated smaller than foo until analyzed
+        assert_eq!(
+            nodes(sql)[2..],
+            [
+                varchar("Hash Join"),
+                varchar("Filter"),
+                varchar("Scan"),
+                varchar("Scan"),
+            ]
+        );
+        assert_eq!(
+            rows(&format!("explain {}", sql), &manager)[4][3],
+            varchar("bar")
+        );
+
+        execute(String::from("analyze;"));
+        assert_eq!(
+            read_lock(&manager).statistics("bar").unwrap().distinct,
+            vec![5, 2, 30, 1]
+        );
+        assert_eq!(
+            nodes(sql)[2..],
+            [
+                varchar("Hash Join"),
+                varchar("Scan"),
+                varchar("Filter"),
+                varchar("Scan"),
+            ]
+        );
+        assert_eq!(rows(sql, &manager), expected);
+
+        // Without conditions joining them relations are joined in FROM order
+        assert_eq!(
+            nodes("select id from bar, foo;")[1..],
+            [varchar("Nested Loop"), varchar("Scan"), varchar("Scan")]
+        );
+
+        let error = |sql: &str| {
+            execute_sql(String::from(sql), vec![], &manager)
+                .err()
+                .unwrap()
+                .code
+        };
+        assert_eq!(error("analyze baz;"), sqlstate::UNDEFINED_TABLE);
+        assert_eq!(
+            error("analyze mb_stat_statements;"),
+            sqlstate::WRONG_OBJECT_TYPE
+        );
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let manager = manager();
+        execute_sql(
+            String::from(
+                "create table numbers as select n, n > 17000 as big from generate_series(1, 35000) as g(n);",
+            ),
+            vec![],
+            &manager,
+        )
+        .unwrap_or_else(|err| panic!("Creating numbers failed: {}", err.msg));
+        let queries = [
+            "select n from numbers where n > 9990 and n < 10010 or n = 35000;",
+            "select count(*), sum(n), min(n), max(n) from numbers where n > 100;",
+            "select big, count(n), count(distinct big), max(n) from numbers group by big order by big;",
+            "select big, count(distinct n), sum(distinct n) from numbers where n < 3 or n > 34998 group by big;",
+        ];
+        let serial: Vec<_> = queries.iter().map(|sql| rows(sql, &manager)).collect();
+
+        write_lock(&manager).set_max_parallel_workers(4);
+        let plan = rows("explain select n from numbers where n > 10;", &manager);
+        assert_eq!(
+            plan.last().unwrap()[2..4],
+            [varchar("Parallel Scan"), varchar("numbers, 3 workers")]
+        );
+        for (sql, serial) in queries.iter().zip(serial) {
+            assert_eq!(rows(sql, &manager), serial, "{}", sql);
+        }
+        assert_eq!(rows(queries[0], &manager).len(), 20);
+
+        // Rows of a partition after a failing row are not produced
+        let mut rows = CollectedRows::default();
+        let result = execute_sql_with_notices(
+            String::from("select n from numbers where n + 2147448649 > 0;"),
+            vec![],
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut rows,
+        );
+        assert!(result.is_err());
+        match rows.into_result(QueryResult::Streamed(0)) {
+            QueryResult::Table(_, rows) => assert_eq!(rows.len(), 34998),
+            _ => panic!("Expecting rows"),
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let manager = manager();
+        execute_sql(
+            String::from(
+                "create table numbers as select generate_series as n from generate_series(1, 100);",
+            ),
+            vec![],
+            &manager,
+        )
+        .unwrap_or_else(|err| panic!("Creating numbers failed: {}", err.msg));
+        // Reading stops at LIMIT, before the first row out of range
+        assert_eq!(
+            ids(
+                "select n + 2147483597 from numbers limit 2 offset 5;",
+                &manager
+            ),
+            vec![MData::Integer(2147483603), MData::Integer(2147483604)]
+        );
+        assert_eq!(
+            ids(
+                "select n from numbers where n > 40 limit 3 offset 2;",
+                &manager
+            ),
+            vec![MData::Integer(43), MData::Integer(44), MData::Integer(45)]
+        );
+
+        // Rows before a failing one are received before the failure
+        let mut rows = CollectedRows::default();
+        let result = execute_sql_with_notices(
+            String::from("select n + 2147483597 from numbers;"),
+            vec![],
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut rows,
+        );
+        assert!(result.is_err());
+        match rows.into_result(QueryResult::Streamed(0)) {
+            QueryResult::Table(schema, rows) => {
+                assert_eq!(schema.columns.len(), 1);
+                assert_eq!(rows.len(), 50);
+            }
+            _ => panic!("Expecting rows"),
+        }
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        let manager = manager();
+        for (table, column) in [("xs", "x"), ("ys", "y"), ("zs", "z")] {
+            execute_sql(
+                format!(
+                    "create table {} as select generate_series as {} from generate_series(1, 2000);",
+                    table, column
+                ),
+                vec![],
+                &manager,
+            )
+            .unwrap_or_else(|err| panic!("Creating {} failed: {}", table, err.msg));
+        }
+        let execute = |sql: &str, session: &mut Session| {
+            execute_sql_with_notices(
+                String::from(sql),
+                vec![],
+                &manager,
+                session,
+                &mut vec![],
+                &mut CollectedRows::default(),
+            )
+        };
+        // Billions of rows would take long enough to never finish in a test
+        let mut session = Session::with_statement_timeout(Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let error = execute("select count(*) from xs, ys, zs;", &mut session)
+            .err()
+            .unwrap();
+        assert_eq!(error.code, sqlstate::QUERY_CANCELED);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(execute("select x from xs where x = 7;", &mut session).is_ok());
+
+        let mut session = Session::new();
+        assert!(execute("set statement_timeout = 20;", &mut session).is_ok());
+        let error = execute(
+            "create table pairs as select x from xs, ys, zs;",
+            &mut session,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.code, sqlstate::QUERY_CANCELED);
+        assert!(read_lock(&manager).get_table_meta("pairs").is_err());
+    }
+
+    #[test]
+    fn test_indexes() {
+        let manager = manager();
+        for id in 5..100 {
+            execute_sql(
+                format!("insert into foo values ({}, 'x');", id % 10),
+                vec![],
+                &manager,
+            )
+            .ok()
+            .unwrap();
+        }
+        let expected = |sql: &str| ids(sql, &manager);
+        let unindexed = [
+            expected("select id from foo where id = 3;"),
+            expected("select id from foo where 5 < id and id <= 7 and name = 'x';"),
+            expected("select id from foo where id >= 8 and id > 8;"),
+            expected("select id from foo where id < 2 and id > 2;"),
+            expected("select id from foo where id = null;"),
+        ];
+        assert!(matches!(
+            execute_sql(
+                String::from("create index foo_id on foo (id);"),
+                vec![],
+                &manager
+            ),
+            Ok(QueryResult::Command("CREATE INDEX"))
+        ));
+        execute_sql(
+            String::from("insert into foo values (3, 'y');"),
+            vec![],
+            &manager,
+        )
+        .ok()
+        .unwrap();
+        // Rows come in the order of the table, same as without the index
+        assert_eq!(
+            ids("select id from foo where id = 3;", &manager),
+            [unindexed[0].clone(), vec![MData::Integer(3)]].concat()
+        );
+        assert_eq!(
+            ids(
+                "select id from foo where 5 < id and id <= 7 and name = 'x';",
+                &manager
+            ),
+            unindexed[1]
+        );
+        assert_eq!(
+            ids("select id from foo where id >= 8 and id > 8;", &manager),
+            unindexed[2]
+        );
+        assert_eq!(
+            ids("select id from foo where id < 2 and id > 2;", &manager),
+            unindexed[3]
+        );
+        assert_eq!(
+            ids("select id from foo where id = null;", &manager),
+            unindexed[4]
+        );
+        assert_eq!(
+            rows("explain select id from foo where id = 3;", &manager)[2][2..],
+            [
+                varchar("Index Scan"),
+                varchar("foo using foo_id"),
+                MData::Integer(11)
+            ]
+        );
+        // Comparing values of other types fails like it does without the index
+        assert!(execute_sql(
+            String::from("select id from foo where id = 'x';"),
+            vec![],
+            &manager
+        )
+        .is_err());
+
+        let error = |sql: &str| {
+            execute_sql(String::from(sql), vec![], &manager)
+                .err()
+                .unwrap()
+                .code
+        };
+        assert_eq!(
+            error("create index foo_id on foo (name);"),
+            sqlstate::DUPLICATE_TABLE
+        );
+        assert_eq!(
+            error("create index foo_x on foo (x);"),
+            sqlstate::UNDEFINED_COLUMN
+        );
+        assert_eq!(
+            error("create index bar_id on bar (id);"),
+            sqlstate::UNDEFINED_TABLE
+        );
+    }
+
+    #[test]
+    fn test_summarized_aggregates() {
+        let manager = manager();
+        // Same results as when the rows are scanned because of the WHERE
+        assert_eq!(
+            rows(
+                "select count(*), min(id), max(name) as biggest from foo;",
+                &manager
+            ),
+            rows(
+                "select count(*), min(id), max(name) as biggest from foo where true;",
+                &manager
+            )
+        );
+        assert_eq!(
+            rows("select count(*), min(name), max(id) from foo;", &manager),
+            vec![vec![MData::Integer(4), varchar("A"), MData::Integer(4)]]
+        );
+        assert_eq!(
+            rows("select count(*) from foo limit 1 offset 1;", &manager),
+            Vec::<Vec<MData>>::new()
+        );
+        execute_sql(
+            String::from("create table empty (id integer);"),
+            vec![],
+            &manager,
+        )
+        .unwrap_or_else(|err| panic!("Can't create: {}", err.msg));
+        assert_eq!(
+            rows("select count(*), min(id) from empty;", &manager),
+            vec![vec![MData::Integer(0), MData::Null]]
+        );
+        assert_eq!(
+            named_rows("explain select count(*), max(id) from foo;", &manager)
+                .iter()
+                .map(|row| row.get::<String>("node").unwrap())
+                .collect::<Vec<String>>(),
+            vec!["Project", "Summary Scan"]
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let manager = manager();
+        for sql in [
+            "create table events (id integer, day date);",
+            "insert into events values (1, date '2024-03-01'), (2, date '2023-12-31'), (3, null);",
+        ] {
+            execute_sql(String::from(sql), vec![], &manager)
+                .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+        }
+        assert_eq!(column_type("events", "day", &manager), "DATE");
+        assert_eq!(
+            rows(
+                "select id, day from events where day >= date '2024-01-01' or day is null order by day;",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(1), MData::Date(19783)],
+                vec![MData::Integer(3), MData::Null],
+            ]
+        );
+        assert_eq!(
+            rows("select min(day), max(day) from events;", &manager),
+            vec![vec![MData::Date(19722), MData::Date(19783)]]
+        );
+        match execute_sql(
+            String::from("select id from events where day = 1;"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "Can't compare Date(19783) and Integer(1)"),
+            Ok(_) => panic!("Date should not compare with integer"),
+        }
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let manager = manager();
+        for sql in [
+            "create table logins (id integer, at timestamp);",
+            "insert into logins values (1, timestamp '2024-03-01 08:00:00'), (2, now()), (3, null);",
+        ] {
+            execute_sql(String::from(sql), vec![], &manager)
+                .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+        }
+        assert_eq!(column_type("logins", "at", &manager), "TIMESTAMP");
+        assert_eq!(
+            ids(
+                "select id from logins where at <= current_timestamp order by at desc;",
+                &manager
+            ),
+            vec![MData::Integer(2), MData::Integer(1)]
+        );
+        assert_eq!(
+            rows("select min(at) from logins;", &manager),
+            vec![vec![MData::Timestamp(1_709_280_000_000_000)]]
+        );
+        // Every row of a statement sees the same time
+        assert_eq!(
+            rows("select count(distinct now()) from foo;", &manager),
+            vec![vec![MData::Integer(1)]]
+        );
+        match execute_sql(
+            String::from("select id from logins where at = date '2024-03-01';"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(
+                err.msg,
+                "Can't compare Timestamp(1709280000000000) and Date(19783)"
+            ),
+            Ok(_) => panic!("Timestamp should not compare with date"),
+        }
+    }
+
+    #[test]
+    fn test_decimals() {
+        let manager = manager();
+        for sql in [
+            "create table accounts (id integer, balance decimal(6, 2));",
+            "insert into accounts values (1, decimal '10.10'), (2, 20.2), (3, 5), (4, decimal '0.125');",
+        ] {
+            execute_sql(String::from(sql), vec![], &manager)
+                .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+        }
+        assert_eq!(column_type("accounts", "balance", &manager), "DECIMAL(6,2)");
+        // Values are stored in the scale of the column, rounding half away from zero
+        assert_eq!(
+            ids("select balance from accounts;", &manager),
+            vec![
+                MData::Decimal(1010, 2),
+                MData::Decimal(2020, 2),
+                MData::Decimal(500, 2),
+                MData::Decimal(13, 2),
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select sum(balance), max(balance) from accounts where balance > 1;",
+                &manager
+            ),
+            vec![vec![MData::Decimal(3530, 2), MData::Decimal(2020, 2)]]
+        );
+        assert_eq!(
+            ids(
+                "select id from accounts where balance - decimal '0.005' = decimal '10.095' or id = 4 order by balance;",
+                &manager
+            ),
+            vec![MData::Integer(4), MData::Integer(1)]
+        );
+        match execute_sql(
+            String::from("insert into accounts values (5, 10000);"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "Value out of range for DECIMAL(6,2)"),
+            Ok(_) => panic!("Value should not fit"),
+        }
+    }
+
+    #[test]
+    fn test_rounding_notices() {
+        let manager = manager();
+        let notices = |sql: &str| {
+            let mut notices = vec![];
+            execute_sql_with_notices(
+                String::from(sql),
+                vec![],
+                &manager,
+                &mut Session::new(),
+                &mut notices,
+                &mut CollectedRows::default(),
+            )
+            .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+            notices
+                .into_iter()
+                .map(|notice| {
+                    assert_eq!(notice.code, sqlstate::WARNING);
+                    notice.msg
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(notices("create table accounts (id integer, balance decimal(6, 2));").is_empty());
+        assert!(
+            notices("insert into accounts values (1, decimal '10.10'), (2, 20.2), (3, 5);")
+                .is_empty()
+        );
+        assert_eq!(
+            notices("insert into accounts values (4, decimal '0.125'), (5, 1.5), (6, 0.001);"),
+            vec![
+                "Value 0.125 of column balance rounded to 2 decimals",
+                "Value 0.001 of column balance rounded to 2 decimals",
+            ]
+        );
+        assert_eq!(
+            notices("insert into accounts values (7, 0.1 + 0.2);"),
+            vec!["Value 0.30000000000000004 of column balance rounded to 2 decimals"]
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let manager = manager();
+        for sql in [
+            "create table files (id integer, content bytea);",
+            "insert into files values (1, x'DEADBEEF'), (2, x''), (3, null);",
+        ] {
+            execute_sql(String::from(sql), vec![], &manager)
+                .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+        }
+        assert_eq!(
+            ids("select content from files where id = 1;", &manager),
+            vec![MData::Bytes(vec![0xde, 0xad, 0xbe, 0xef])]
+        );
+        assert_eq!(
+            ids(
+                "select id from files where content = x'deadbeef' or content < x'00';",
+                &manager
+            ),
+            vec![MData::Integer(1), MData::Integer(2)]
+        );
+        match execute_sql(
+            String::from("insert into files values (4, 'text');"),
+            vec![],
+            &manager,
+        ) {
+            Err(_) => {}
+            Ok(_) => panic!("Varchar should not be stored as bytes"),
+        }
+    }
+
+    #[test]
+    fn test_floats() {
+        let manager = manager();
+        match execute_sql(
+            String::from("create table prices as select id, id + 0.5 as price from foo;"),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 4),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(column_type("prices", "price", &manager), "FLOAT");
+        // Integers are stored as floats in a float column
+        match execute_sql(
+            String::from("insert into prices values (5, 2);"),
+            vec![],
+            &manager,
+        ) {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 1),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(
+            rows(
+                "select id, -price from prices where price < 3 order by price desc;",
+                &manager
+            ),
+            vec![
+                vec![MData::Integer(2), MData::Float(-2.5)],
+                vec![MData::Integer(5), MData::Float(-2.0)],
+                vec![MData::Integer(1), MData::Float(-1.5)],
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select sum(price), min(price), max(price), count(price) from prices;",
+                &manager
+            ),
+            vec![vec![
+                MData::Float(14.0),
+                MData::Float(1.5),
+                MData::Float(4.5),
+                MData::Integer(5)
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_statement_statistics() {
+        let manager = manager();
+        rows("select id from foo where id = 1;", &manager);
+        rows("SELECT id FROM foo WHERE id = 2;", &manager);
+        assert!(execute_sql(String::from("select nope from foo;"), vec![], &manager).is_err());
+        assert_eq!(
+            rows(
+                "select fingerprint, calls, errors, row_count from mb_stat_statements order by fingerprint;",
+                &manager
+            ),
+            vec![
+                vec![
+                    varchar("SELECT id FROM foo WHERE id = ?"),
+                    MData::Integer(2),
+                    MData::Integer(0),
+                    MData::Integer(2),
+                ],
+                vec![
+                    varchar("SELECT nope FROM foo"),
+                    MData::Integer(1),
+                    MData::Integer(1),
+                    MData::Integer(0),
+                ],
+            ]
+        );
+        match execute_sql(
+            String::from("insert into mb_stat_statements values ('x', 1, 1, 1, 1.0, 1.0, 1.0);"),
+            vec![],
+            &manager,
+        ) {
+            Err(err) => assert_eq!(err.msg, "Can't insert into system view mb_stat_statements"),
+            Ok(_) => panic!("System view should not accept rows"),
+        }
+    }
+
+    #[test]
+    fn test_panics_fail_the_statement() {
+        let manager = manager();
+        // Lexing a float of two decimal points panics, also when fingerprinting it
+        let sql = String::from("select 1.2.3 from foo;");
+        match execute_sql(sql.clone(), vec![], &manager) {
+            Err(err) => assert_eq!(err.code, sqlstate::INTERNAL_ERROR),
+            Ok(_) => panic!("Statement should fail"),
+        }
+        match prepare_sql(sql, &manager) {
+            Err(err) => assert_eq!(err.code, sqlstate::INTERNAL_ERROR),
+            Ok(_) => panic!("Statement should fail"),
+        }
+    }
+
+    #[test]
+    fn test_import_csv() {
+        let manager = manager();
+        let path = std::env::temp_dir().join(format!("microbat-import-{}.csv", std::process::id()));
+        let sql = format!(
+            "select id, name into outfile '{}' from foo order by id;",
+            path.display()
+        );
+        assert!(execute_with_files(sql, &manager).is_ok());
+        let import = |table: &str, header: &str| {
+            execute_with_files(
+                format!("import csv '{}' into {} {};", path.display(), table, header),
+                &manager,
+            )
+        };
+        assert!(execute_sql(
+            String::from("create table imported (id integer, name varchar);"),
+            vec![],
+            &manager
+        )
+        .is_ok());
+        let imported = import("imported", "with header");
+        // Without skipping the header, its names are no integers
+        let failed = import("imported", "");
+        let refused = execute_sql(
+            format!("import csv '{}' into imported;", path.display()),
+            vec![],
+            &manager,
+        );
+        let outside = execute_with_files(
+            format!(
+                "import csv '../microbat-import-{}.csv' into imported;",
+                std::process::id()
+            ),
+            &manager,
+        );
+        std::fs::remove_file(&path).unwrap();
+        match refused {
+            Err(err) => assert_eq!(err.code, sqlstate::INSUFFICIENT_PRIVILEGE),
+            Ok(_) => panic!("File should not be read without a file directory"),
+        }
+        match outside {
+            Err(err) => assert_eq!(err.code, sqlstate::INSUFFICIENT_PRIVILEGE),
+            Ok(_) => panic!("File outside the file directory should not be read"),
+        }
+        match imported {
+            Ok(QueryResult::Inserted(count)) => assert_eq!(count, 4),
+            _ => panic!("Expecting insert result"),
+        }
+        assert_eq!(
+            rows("select id, name from imported;", &manager),
+            rows("select id, name from foo order by id;", &manager)
+        );
+        match failed {
+            Err(err) => {
+                assert_eq!(err.code, sqlstate::INVALID_TEXT_REPRESENTATION);
+                assert_eq!(
+                    err.msg,
+                    format!("Line 1 of {}: Invalid INTEGER: id", path.display())
+                );
+            }
+            Ok(_) => panic!("Header should not be imported"),
+        }
+        assert_eq!(
+            ids("select count(id) from imported;", &manager),
+            vec![MData::Integer(4)]
+        );
+        match import("imported", "with header") {
+            Err(err) => assert_eq!(err.code, sqlstate::IO_ERROR),
+            Ok(_) => panic!("Missing file should not be imported"),
+        }
+
+        // Rows are imported all or none, also when a value doesn't fit its column
+        std::fs::write(&path, "1.5\n123456.7\n").unwrap();
+        assert!(execute_sql(
+            String::from("create table prices (price decimal(5, 2));"),
+            vec![],
+            &manager
+        )
+        .is_ok());
+        let imported = import("prices", "");
+        std::fs::remove_file(&path).unwrap();
+        match imported {
+            Err(err) => {
+                assert_eq!(err.code, sqlstate::NUMERIC_VALUE_OUT_OF_RANGE);
+                assert_eq!(
+                    err.msg,
+                    format!(
+                        "Line 2 of {}: Value out of range for DECIMAL(5,2)",
+                        path.display()
+                    )
+                );
+            }
+            Ok(_) => panic!("Value out of range should not be imported"),
+        }
+        assert_eq!(
+            ids("select count(price) from prices;", &manager),
+            vec![MData::Integer(0)]
+        );
+    }
+
+    #[test]
+    fn test_system_catalog() {
+        let manager = manager();
+        assert!(execute_sql(
+            String::from("create index foo_name on foo (name);"),
+            vec![],
+            &manager
+        )
+        .is_ok());
+        assert_eq!(
+            rows(
+                "select table_name, table_type, column_count, row_count from microbat_tables \
+                 where table_name = 'foo' or table_name = 'microbat_indexes';",
+                &manager
+            ),
+            vec![
+                vec![
+                    varchar("foo"),
+                    varchar("table"),
+                    MData::Integer(2),
+                    MData::Integer(4)
+                ],
+                vec![
+                    varchar("microbat_indexes"),
+                    varchar("system view"),
+                    MData::Integer(3),
+                    MData::Null
+                ],
+            ]
+        );
+        assert_eq!(
+            rows(
+                "select index_name, table_name, column_name from microbat_indexes;",
+                &manager
+            ),
+            vec![vec![varchar("foo_name"), varchar("foo"), varchar("name")]]
+        );
+        read_lock(&manager).report_session(7, Some(SessionActivity::new()));
+        assert_eq!(
+            rows(
+                "select session_id, user_name, state from microbat_sessions;",
+                &manager
+            ),
+            vec![vec![MData::BigInt(7), MData::Null, varchar("idle")]]
+        );
+        read_lock(&manager).report_session(7, None);
+        assert_eq!(
+            rows("select session_id from microbat_sessions;", &manager),
+            Vec::<Vec<MData>>::new()
+        );
+        for sql in [
+            "insert into microbat_tables values ('x', 'table', 1, 1);",
+            "create index tables_name on microbat_tables (table_name);",
+            "analyze microbat_sessions;",
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.code, sqlstate::WRONG_OBJECT_TYPE, "{}", sql),
+                Ok(_) => panic!("System view should not change: {}", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_copy() {
+        let manager = manager();
+        match execute_sql(String::from("copy foo from stdin;"), vec![], &manager) {
+            Ok(QueryResult::CopyIn(table, schema)) => {
+                assert_eq!(table, "foo");
+                assert_eq!(schema.len(), 2);
+            }
+            Ok(_) => panic!("COPY should start copying"),
+            Err(err) => panic!("COPY failed: {}", err.msg),
+        }
+        match execute_sql(String::from("copy bar from stdin;"), vec![], &manager) {
+            Err(err) => assert_eq!(err.code, sqlstate::UNDEFINED_TABLE),
+            Ok(_) => panic!("COPY to unknown table should fail"),
+        }
+
+        let copied = copy_rows(
+            "foo",
+            (5..105)
+                .map(|id| DataRow::new(vec![MData::Integer(id), MData::Null]))
+                .collect(),
+            &manager,
+            &mut vec![],
+        );
+        assert!(matches!(copied, Ok(QueryResult::Inserted(100))));
+        assert_eq!(rows("select id from foo;", &manager).len(), 104);
+
+        let copied = copy_rows(
+            "foo",
+            vec![
+                DataRow::new(vec![MData::Integer(105), MData::Null]),
+                DataRow::new(vec![MData::Integer(1)]),
+            ],
+            &manager,
+            &mut vec![],
+        );
+        assert!(copied.is_err());
+        assert_eq!(rows("select id from foo;", &manager).len(), 104);
+    }
+
+    #[test]
+    fn test_prepared_statements() {
+        let manager = manager();
+        let prepared_rows = |statement: &PreparedStatement| {
+            let mut rows = CollectedRows::default();
+            match execute_prepared(
+                statement,
+                &manager,
+                &mut Session::new(),
+                &mut vec![],
+                &mut rows,
+            )
+            .map(|result| rows.into_result(result))
+            {
+                Ok(QueryResult::Table(_, rows)) => rows
+                    .into_iter()
+                    .map(|row| row.columns[0].clone())
+                    .collect::<Vec<_>>(),
+                Ok(_) => panic!("Statement did not return a table"),
+                Err(err) => panic!("Statement failed: {}", err.msg),
+            }
+        };
+
+        let prepare = |sql: &str| match prepare_sql(String::from(sql), &manager) {
+            Ok(statement) => statement,
+            Err(err) => panic!("{} failed: {}", sql, err.msg),
+        };
+
+        let mut statement = prepare("select id from foo where id > $1;");
+        match execute_prepared(
+            &statement,
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut CollectedRows::default(),
+        ) {
+            Err(err) => assert_eq!(err.code, sqlstate::UNDEFINED_PARAMETER),
+            Ok(_) => panic!("Unbound statement should not execute"),
+        }
+        assert!(statement.bind(&[MData::Integer(2)]).is_ok());
+        assert_eq!(
+            prepared_rows(&statement),
+            vec![MData::Integer(3), MData::Integer(4)]
+        );
+        // Bound values stay for the next executions until bound anew
+        assert_eq!(prepared_rows(&statement).len(), 2);
+        assert!(statement.bind(&[MData::Integer(3)]).is_ok());
+        assert_eq!(prepared_rows(&statement), vec![MData::Integer(4)]);
+        assert!(statement.bind(&[]).is_err());
+        assert!(execute_prepared(
+            &statement,
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut CollectedRows::default()
+        )
+        .is_err());
+
+        // Statements without parameters are bound when prepared
+        let statement = prepare("select id from foo;");
+        assert_eq!(prepared_rows(&statement).len(), 4);
+        let insert = prepare("insert into foo values (5, 'e');");
+        assert!(execute_prepared(
+            &insert,
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut CollectedRows::default()
+        )
+        .is_ok());
+        assert!(execute_prepared(
+            &insert,
+            &manager,
+            &mut Session::new(),
+            &mut vec![],
+            &mut CollectedRows::default()
+        )
+        .is_ok());
+        assert_eq!(prepared_rows(&statement).len(), 6);
+
+        match prepare_sql(String::from("select nope(id) from foo;"), &manager) {
+            Err(err) => assert_eq!(err.code, sqlstate::UNDEFINED_FUNCTION),
+            Ok(_) => panic!("Statement with an unknown function should not prepare"),
+        }
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        match execute_sql(
+            String::from("select id + 2147483647 from foo;"),
+            vec![],
+            &manager(),
+        ) {
+            Err(err) => assert_eq!(err.msg, "integer out of range"),
+            Ok(_) => panic!("Addition should overflow"),
+        }
+        match execute_sql(
+            String::from("select -(0 - 2147483647 - 1) from foo;"),
+            vec![],
+            &manager(),
+        ) {
+            Err(err) => assert_eq!(err.msg, "integer out of range"),
+            Ok(_) => panic!("Negation should overflow"),
+        }
+    }
+
+    #[test]
+    fn test_bigints() {
+        let manager = manager();
+        for sql in [
+            "create table counters (id bigint, hits integer);",
+            "insert into counters values (1, 10), (5000000000, 2147483647), (null, null);",
+        ] {
+            execute_sql(String::from(sql), vec![], &manager)
+                .unwrap_or_else(|err| panic!("{} failed: {}", sql, err.msg));
+        }
+        assert_eq!(column_type("counters", "id", &manager), "BIGINT");
+        // Integers are widened when stored into a bigint column
+        assert_eq!(
+            rows(
+                "select id, id + hits, sum(id) from counters where id < 10 group by id, hits;",
+                &manager
+            ),
+            vec![vec![MData::BigInt(1), MData::BigInt(11), MData::BigInt(1)]]
+        );
+        assert_eq!(
+            rows("select sum(id), max(id) from counters;", &manager),
+            vec![vec![MData::BigInt(5000000001), MData::BigInt(5000000000)]]
+        );
+        assert_eq!(
+            ids(
+                "select hits from counters order by id desc nulls last;",
+                &manager
+            ),
+            vec![MData::Integer(2147483647), MData::Integer(10), MData::Null]
+        );
+        assert_eq!(
+            rows(
+                "select hits - 1, id + hits, null + 1 from counters where id is null;",
+                &manager
+            ),
+            vec![vec![MData::Null, MData::Null, MData::Null]]
+        );
+        for (sql, error) in [
+            (
+                "select id + 9223372036854775807 from counters;",
+                "integer out of range",
+            ),
+            (
+                "insert into counters values (1, 5000000000);",
+                "Can't put this here",
+            ),
+        ] {
+            match execute_sql(String::from(sql), vec![], &manager) {
+                Err(err) => assert_eq!(err.msg, error),
+                Ok(_) => panic!("{} should fail", sql),
+            }
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let manager = manager();
+        let expected = vec![
+            vec![
+                MData::Varchar(String::from("id")),
+                MData::Varchar(String::from("INTEGER")),
+                MData::Bool(true),
+                MData::Null,
+            ],
+            vec![
+                MData::Varchar(String::from("name")),
+                MData::Varchar(String::from("VARCHAR")),
+                MData::Bool(true),
+                MData::Null,
+            ],
+        ];
+        assert_eq!(rows("describe foo;", &manager), expected);
+        assert_eq!(rows("show columns from foo;", &manager), expected);
+        assert_eq!(rows("describe \"foo\";", &manager), expected);
+
+        match execute_sql(String::from("describe bar;"), vec![], &manager) {
+            Err(err) => assert_eq!(err.msg, "No such table: bar"),
+            Ok(_) => panic!("Describing missing table should fail"),
+        }
+    }
+
+    #[test]
+    fn test_set_and_show() {
+        let manager = manager();
+        let mut session = Session::new();
+        let mut execute = |sql: &str| {
+            let mut rows = CollectedRows::default();
+            execute_sql_with_notices(
+                String::from(sql),
+                vec![],
+                &manager,
+                &mut session,
+                &mut vec![],
+                &mut rows,
+            )
+            .map(|result| rows.into_result(result))
+        };
+        assert!(matches!(
+            execute("set null_display = '<null>';"),
+            Ok(QueryResult::Command("SET"))
+        ));
+        match execute("show null_display;") {
+            Ok(QueryResult::Table(schema, rows)) => {
+                assert_eq!(schema.columns[0].name, "null_display");
+                assert_eq!(
+                    rows[0].columns,
+                    vec![MData::Varchar(String::from("<null>"))]
+                );
+            }
+            _ => panic!("Expecting the value of null_display"),
+        }
+        match execute("set foo = 1;") {
+            Err(err) => assert_eq!(err.code, sqlstate::UNDEFINED_OBJECT),
+            Ok(_) => panic!("Setting unknown parameter should fail"),
+        }
+        assert_eq!(
+            session.take_changes(),
+            vec![(String::from("null_display"), String::from("<null>"))]
+        );
+
+        // Every statement of execute_sql has a session of its own
+        assert_eq!(
+            rows("show null_display;", &manager),
+            vec![vec![MData::Varchar(String::new())]]
+        );
+        assert_eq!(rows("show all;", &manager).len(), 6);
     }
 }
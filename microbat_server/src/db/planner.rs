@@ -0,0 +1,630 @@
+use microbat_protocol::data::{
+    data_values::DataError,
+    table_model::{Column, TableSchema},
+};
+
+use crate::db::index::{choose_index, IndexRange, TableIndex};
+use crate::db::manager::DatabaseManager;
+use crate::db::summary::summarized_table;
+use crate::db::table_function;
+use crate::sql::expression::{Comparison, Expression, Logical};
+use crate::sql::parser::{FromItem, SelectClause, TableFunctionCall};
+
+/// Estimated fraction of rows comparing a column for equality with a value keeps, when the
+/// distinct values of the column aren't known
+const EQUALITY_SELECTIVITY: f64 = 0.1;
+/// Estimated fraction of rows other conditions keep
+const DEFAULT_SELECTIVITY: f64 = 1.0 / 3.0;
+/// Estimated count of rows of a relation that can't be known before reading it
+const DEFAULT_ROWS: f64 = 1000.0;
+/// Least rows of a table for each worker reading it in parallel, as starting a worker costs
+/// more than filtering a few rows
+pub const PARALLEL_ROWS_PER_WORKER: usize = 10_000;
+
+/// What a step of a plan does
+pub enum Step<'a> {
+    /// Every row of a table
+    Scan(&'a str),
+    /// Every row of a large table, partitioned across given count of workers which filter
+    /// and aggregate the partitions in parallel
+    ParallelScan(&'a str, usize),
+    /// Rows of a table with values of an indexed column in a range
+    IndexScan(&'a str, &'a TableIndex, IndexRange),
+    /// Rows a table function returns
+    FunctionScan(&'a TableFunctionCall),
+    /// Simple aggregates of a table answered from its summary without scanning the rows
+    SummaryScan(&'a str),
+    /// Rows of the input for which every condition is true
+    Filter(Vec<&'a dyn Expression>),
+    /// Every combination of a row of the first input with a row of the second
+    NestedLoop(JoinColumns),
+    /// Combinations of a row of the first input with a row of the second having equal keys
+    HashJoin(JoinColumns, Vec<JoinKey<'a>>),
+    Aggregate,
+    Project,
+    Window,
+    Sort,
+    Limit,
+}
+
+/// Key of a hash join, evaluated against the first and the second input
+pub type JoinKey<'a> = (&'a dyn Expression, &'a dyn Expression);
+
+/// Order of the columns of joined rows. Relations of FROM are in the order of FROM in
+/// joined rows whatever order they are joined in, so that names refer to the same columns.
+#[derive(Clone)]
+pub struct JoinColumns {
+    /// Whether each relation of the joined rows is of the first input, and its count of
+    /// columns
+    relations: Vec<(bool, usize)>,
+}
+
+impl JoinColumns {
+    /// Columns of joining inputs of given relations of FROM, with given counts of columns
+    fn new(left: &[usize], right: &[usize], widths: &[usize]) -> Self {
+        let mut relations: Vec<(usize, bool)> = left
+            .iter()
+            .map(|relation| (*relation, true))
+            .chain(right.iter().map(|relation| (*relation, false)))
+            .collect();
+        relations.sort_unstable();
+        JoinColumns {
+            relations: relations
+                .into_iter()
+                .map(|(relation, first)| (first, widths[relation]))
+                .collect(),
+        }
+    }
+
+    /// Joins the values of a row of the first input with the values of a row of the second,
+    /// or the columns of the inputs
+    pub fn join<T: Clone>(&self, left: &[T], right: &[T]) -> Vec<T> {
+        let mut joined = Vec::with_capacity(left.len() + right.len());
+        let (mut left, mut right) = (left, right);
+        for (first, width) in self.relations.iter() {
+            let values = match first {
+                true => &mut left,
+                false => &mut right,
+            };
+            let (taken, rest) = values.split_at(*width);
+            joined.extend_from_slice(taken);
+            *values = rest;
+        }
+        joined
+    }
+}
+
+/// Step in executing a select. Inputs are the steps producing the rows the step consumes.
+///
+/// Steps up to the join of the relations in FROM are executed one by one. Steps after that,
+/// from aggregating to limiting, are executed together from the rows of FROM, as sorting and
+/// window functions need the rows from before projecting.
+pub struct Plan<'a> {
+    pub step: Step<'a>,
+    pub detail: Option<String>,
+    /// Estimated count of rows the step produces, if it can be known before executing
+    pub rows: Option<usize>,
+    pub inputs: Vec<Plan<'a>>,
+}
+
+impl<'a> Plan<'a> {
+    fn new(step: Step<'a>, detail: Option<String>, rows: Option<usize>) -> Self {
+        Plan {
+            step,
+            detail,
+            rows,
+            inputs: vec![],
+        }
+    }
+
+    /// Wraps this plan as the only input of a new step
+    fn wrap(self, step: Step<'a>, detail: Option<String>, rows: Option<usize>) -> Self {
+        Plan {
+            step,
+            detail,
+            rows,
+            inputs: vec![self],
+        }
+    }
+
+    /// Name of the step as EXPLAIN shows it
+    pub fn name(&self) -> &'static str {
+        match self.step {
+            Step::Scan(_) => "Scan",
+            Step::ParallelScan(..) => "Parallel Scan",
+            Step::IndexScan(..) => "Index Scan",
+            Step::FunctionScan(_) => "Function Scan",
+            Step::SummaryScan(_) => "Summary Scan",
+            Step::Filter(_) => "Filter",
+            Step::NestedLoop(_) => "Nested Loop",
+            Step::HashJoin(..) => "Hash Join",
+            Step::Aggregate => "Aggregate",
+            Step::Project => "Project",
+            Step::Window => "Window",
+            Step::Sort => "Sort",
+            Step::Limit => "Limit",
+        }
+    }
+
+    /// Step producing the rows of FROM, which the steps above it are executed from. None
+    /// for a select without FROM.
+    pub fn relation(&self) -> Option<&Plan<'a>> {
+        match self.step {
+            Step::Aggregate | Step::Project | Step::Window | Step::Sort | Step::Limit => {
+                self.inputs.first()?.relation()
+            }
+            _ => Some(self),
+        }
+    }
+}
+
+/// Plans executing given select with the tables of given database.
+///
+/// Conditions of WHERE combined with AND are filtered as early as possible. Conditions on
+/// the columns of a single relation in FROM filter its rows before they are joined with the
+/// others, and the relation is read with an index if the conditions allow it. The rest
+/// filter the joined rows.
+pub fn plan_select<'a>(
+    select: &'a SelectClause,
+    database: &'a (impl DatabaseManager + ?Sized),
+) -> Result<Plan<'a>, DataError> {
+    let mut input = match summarized_table(select) {
+        Some(table) if summarized(select, table, database)? => Some(Plan::new(
+            Step::SummaryScan(table),
+            Some(table.to_owned()),
+            Some(1),
+        )),
+        _ => plan_from(select, database)?,
+    };
+
+    let summarized = matches!(
+        input.as_ref().map(|plan| &plan.step),
+        Some(Step::SummaryScan(_))
+    );
+    let aggregated = select.projection.iter().any(|e| e.aggregate().is_some());
+    if (select.group_by.is_some() || aggregated) && !summarized {
+        let (detail, rows) = match &select.group_by {
+            Some(group_by) if group_by.sets.len() > 1 => {
+                (Some(plural(group_by.sets.len(), "grouping set")), None)
+            }
+            Some(group_by) => (Some(plural(group_by.expressions.len(), "key")), None),
+            // Aggregating without GROUP BY returns a single row
+            None => (None, Some(1)),
+        };
+        input = Some(match input {
+            Some(plan) => plan.wrap(Step::Aggregate, detail, rows),
+            None => Plan::new(Step::Aggregate, detail, rows),
+        });
+    }
+
+    // Select without FROM has no rows to project
+    let rows = input.as_ref().map_or(Some(0), |plan| plan.rows);
+    let detail = Some(plural(select.projection.len(), "column"));
+    let mut plan = match input {
+        Some(plan) => plan.wrap(Step::Project, detail, rows),
+        None => Plan::new(Step::Project, detail, rows),
+    };
+
+    let windows = select
+        .projection
+        .iter()
+        .filter(|e| e.window().is_some())
+        .count();
+    if windows > 0 {
+        plan = plan.wrap(Step::Window, Some(plural(windows, "function")), rows);
+    }
+    if !select.order_by.is_empty() {
+        plan = plan.wrap(Step::Sort, Some(plural(select.order_by.len(), "key")), rows);
+    }
+    if select.limit.is_some() || select.offset > 0 {
+        let detail = match select.limit {
+            Some(limit) => format!("LIMIT {} OFFSET {}", limit, select.offset),
+            None => format!("OFFSET {}", select.offset),
+        };
+        let limit = select.limit.unwrap_or(usize::MAX);
+        let limited = rows.map(|rows| rows.saturating_sub(select.offset).min(limit));
+        plan = plan.wrap(Step::Limit, Some(detail), limited);
+    }
+    Ok(plan)
+}
+
+/// Plans reading and joining the relations of FROM and filtering them with WHERE.
+///
+/// Relations are joined in FROM order unless conditions join them. Then the relation
+/// estimated to have the fewest rows is read first, and joined with the relation the
+/// conditions join it with into the fewest rows, until every relation is joined. Joins
+/// comparing columns of the relations for equality are hash joins.
+fn plan_from<'a>(
+    select: &'a SelectClause,
+    database: &'a (impl DatabaseManager + ?Sized),
+) -> Result<Option<Plan<'a>>, DataError> {
+    let mut relations = vec![];
+    for item in select.from.iter() {
+        let (columns, distinct) = match item {
+            FromItem::Table(table) => (
+                database.get_table_meta(table)?.schema.columns.clone(),
+                database
+                    .statistics(table)
+                    .map(|statistics| statistics.distinct.clone()),
+            ),
+            FromItem::Function(call) => (table_function::columns(call)?, None),
+        };
+        relations.push(Relation { columns, distinct });
+    }
+    let mut conditions = vec![];
+    if let Some(filter) = &select.filter {
+        conjuncts(filter.as_ref(), &mut conditions);
+    }
+    // Conditions on the columns of a single relation, by the relation
+    let mut pushed: Vec<Vec<&dyn Expression>> = relations.iter().map(|_| vec![]).collect();
+    // Conditions on the columns of several relations, with the relations
+    let mut joining = vec![];
+    let mut remaining = vec![];
+    for condition in conditions {
+        match relations_of(condition, &relations) {
+            Some(owners) if owners.len() == 1 => pushed[owners[0]].push(condition),
+            Some(owners) if owners.len() > 1 => joining.push((condition, owners)),
+            _ => remaining.push(condition),
+        }
+    }
+
+    let mut inputs = vec![];
+    for (position, (item, conditions)) in select.from.iter().zip(pushed).enumerate() {
+        inputs.push(plan_scan(position, item, &relations, conditions, database)?);
+    }
+    if inputs.is_empty() {
+        return Ok(None);
+    }
+    let ordered = joining.is_empty();
+    let first = match ordered {
+        true => 0,
+        false => fewest(inputs.iter().map(|input| input.estimate)),
+    };
+    let mut joined = inputs.remove(first);
+    while !inputs.is_empty() {
+        let next = match ordered {
+            true => 0,
+            false => next_join(&joined, &inputs, &joining, &relations),
+        };
+        let input = inputs.remove(next);
+        joined = plan_join(joined, input, &mut joining, &relations);
+    }
+    let mut plan = joined.plan;
+    if !remaining.is_empty() {
+        plan = plan.wrap(Step::Filter(remaining), None, None);
+    }
+    Ok(Some(plan))
+}
+
+/// Plans reading a relation of FROM and filtering it with given conditions on its columns
+fn plan_scan<'a>(
+    position: usize,
+    item: &'a FromItem,
+    relations: &[Relation],
+    conditions: Vec<&'a dyn Expression>,
+    database: &'a (impl DatabaseManager + ?Sized),
+) -> Result<Input<'a>, DataError> {
+    let relation = &relations[position];
+    let selectivity: f64 = conditions
+        .iter()
+        .map(|condition| selectivity(*condition, relation))
+        .product();
+    let (scan, estimate) = match item {
+        FromItem::Table(table) => {
+            let schema = TableSchema {
+                columns: relation.columns.clone(),
+            };
+            let rows = row_count(table, database)?;
+            match choose_index(database.indexes(table), &conditions, &schema) {
+                Some((index, range)) => {
+                    let indexed = index.lookup(&range).len();
+                    let estimate = (rows as f64 * selectivity).min(indexed as f64);
+                    let scan = Plan::new(
+                        Step::IndexScan(table, index, range),
+                        Some(format!("{} using {}", table, index.name)),
+                        Some(indexed),
+                    );
+                    (scan, estimate)
+                }
+                None => {
+                    let workers =
+                        (rows / PARALLEL_ROWS_PER_WORKER).min(database.max_parallel_workers());
+                    let scan = match workers > 1 {
+                        true => Plan::new(
+                            Step::ParallelScan(table, workers),
+                            Some(format!("{}, {}", table, plural(workers, "worker"))),
+                            Some(rows),
+                        ),
+                        false => Plan::new(Step::Scan(table), Some(table.clone()), Some(rows)),
+                    };
+                    (scan, rows as f64 * selectivity)
+                }
+            }
+        }
+        FromItem::Function(call) => {
+            let scan = Plan::new(
+                Step::FunctionScan(call),
+                Some(call.function.name().to_owned()),
+                None,
+            );
+            (scan, DEFAULT_ROWS * selectivity)
+        }
+    };
+    let plan = match conditions.is_empty() {
+        true => scan,
+        false => scan.wrap(Step::Filter(conditions), None, None),
+    };
+    Ok(Input {
+        plan,
+        relations: vec![position],
+        estimate,
+    })
+}
+
+/// Plans joining given inputs with the conditions on the columns of both, which are taken
+/// out of given conditions still to be applied
+fn plan_join<'a>(
+    left: Input<'a>,
+    right: Input<'a>,
+    joining: &mut Vec<(&'a dyn Expression, Vec<usize>)>,
+    relations: &[Relation],
+) -> Input<'a> {
+    let estimate = join_estimate(&left, &right, joining, relations);
+    let mut joined: Vec<usize> = [left.relations.as_slice(), right.relations.as_slice()].concat();
+    joined.sort_unstable();
+    let (applied, rest): (Vec<_>, Vec<_>) = joining
+        .drain(..)
+        .partition(|(_, owners)| owners.iter().all(|owner| joined.contains(owner)));
+    *joining = rest;
+
+    let mut keys = vec![];
+    let mut conditions = vec![];
+    for (condition, _) in applied {
+        match join_key(condition, &left, &right, relations) {
+            Some((key, _)) => keys.push(key),
+            None => conditions.push(condition),
+        }
+    }
+    let widths: Vec<usize> = relations
+        .iter()
+        .map(|relation| relation.columns.len())
+        .collect();
+    let columns = JoinColumns::new(&left.relations, &right.relations, &widths);
+    let mut plan = match keys.is_empty() {
+        true => Plan {
+            step: Step::NestedLoop(columns),
+            detail: None,
+            rows: left
+                .plan
+                .rows
+                .zip(right.plan.rows)
+                .map(|(left, right)| left * right),
+            inputs: vec![left.plan, right.plan],
+        },
+        false => Plan {
+            detail: Some(plural(keys.len(), "key")),
+            step: Step::HashJoin(columns, keys),
+            rows: None,
+            inputs: vec![left.plan, right.plan],
+        },
+    };
+    if !conditions.is_empty() {
+        plan = plan.wrap(Step::Filter(conditions), None, None);
+    }
+    Input {
+        plan,
+        relations: joined,
+        estimate,
+    }
+}
+
+/// Position of the input to join next with the inputs joined so far. Of the inputs
+/// conditions join with them, the one estimated to join into the fewest rows, or the
+/// smallest input if conditions join none.
+fn next_join(
+    joined: &Input,
+    inputs: &[Input],
+    joining: &[(&dyn Expression, Vec<usize>)],
+    relations: &[Relation],
+) -> usize {
+    let connected: Vec<usize> = (0..inputs.len())
+        .filter(|candidate| {
+            let input = &inputs[*candidate];
+            joining.iter().any(|(_, owners)| {
+                owners.iter().any(|owner| input.relations.contains(owner))
+                    && owners.iter().all(|owner| {
+                        input.relations.contains(owner) || joined.relations.contains(owner)
+                    })
+            })
+        })
+        .collect();
+    match connected.is_empty() {
+        true => fewest(inputs.iter().map(|input| input.estimate)),
+        false => {
+            let estimates = connected
+                .iter()
+                .map(|candidate| join_estimate(joined, &inputs[*candidate], joining, relations));
+            connected[fewest(estimates)]
+        }
+    }
+}
+
+/// Estimated count of rows joining given inputs with the conditions that apply to them
+/// produces. Joining columns for equality is estimated to find a match for each distinct
+/// value of the column with fewer, other conditions to keep a fixed fraction of the rows.
+fn join_estimate(
+    left: &Input,
+    right: &Input,
+    joining: &[(&dyn Expression, Vec<usize>)],
+    relations: &[Relation],
+) -> f64 {
+    let mut estimate = left.estimate * right.estimate;
+    for (condition, owners) in joining {
+        let applies = owners
+            .iter()
+            .all(|owner| left.relations.contains(owner) || right.relations.contains(owner));
+        if !applies {
+            continue;
+        }
+        estimate *= match join_key(*condition, left, right, relations) {
+            Some((_, (left_distinct, right_distinct))) => {
+                1.0 / left_distinct.max(right_distinct).max(1.0)
+            }
+            None => DEFAULT_SELECTIVITY,
+        };
+    }
+    estimate
+}
+
+/// Keys a condition comparing a column of the left input with a column of the right for
+/// equality joins the inputs by, as expressions evaluated against the left and the right
+/// input, with the estimated counts of distinct values of the columns. Only columns of the
+/// same type are joined by keys, as comparing values of other types fails and hashing them
+/// wouldn't.
+fn join_key<'a>(
+    condition: &'a dyn Expression,
+    left: &Input,
+    right: &Input,
+    relations: &[Relation],
+) -> Option<(JoinKey<'a>, (f64, f64))> {
+    let comparison = condition
+        .comparison()
+        .filter(|comparison| matches!(comparison.comparison, Comparison::Equal))?;
+    let mut first = column_of(comparison.left.reference()?, relations)?;
+    let mut second = column_of(comparison.right.reference()?, relations)?;
+    let mut keys = (comparison.left.as_ref(), comparison.right.as_ref());
+    if right.relations.contains(&first.0) {
+        std::mem::swap(&mut first, &mut second);
+        keys = (keys.1, keys.0);
+    }
+    if !left.relations.contains(&first.0) || !right.relations.contains(&second.0) {
+        return None;
+    }
+    let column = |(relation, column): (usize, usize)| &relations[relation].columns[column];
+    if column(first).data_type != column(second).data_type {
+        return None;
+    }
+    let distinct = |(relation, column): (usize, usize), input: &Input| {
+        let distinct = relations[relation]
+            .distinct
+            .as_ref()
+            .map_or(f64::MAX, |distinct| distinct[column] as f64);
+        // A relation has at most as many values as rows
+        distinct.min(input.estimate)
+    };
+    Some((keys, (distinct(first, left), distinct(second, right))))
+}
+
+/// Estimated fraction of the rows of a relation a condition on its columns keeps
+fn selectivity(condition: &dyn Expression, relation: &Relation) -> f64 {
+    let Some(comparison) = condition
+        .comparison()
+        .filter(|comparison| matches!(comparison.comparison, Comparison::Equal))
+    else {
+        return DEFAULT_SELECTIVITY;
+    };
+    let column = match (comparison.left.reference(), comparison.right.reference()) {
+        (Some(column), None) | (None, Some(column)) => column,
+        _ => return DEFAULT_SELECTIVITY,
+    };
+    let position = relation
+        .columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(column));
+    match (position, &relation.distinct) {
+        (Some(position), Some(distinct)) => 1.0 / (distinct[position].max(1) as f64),
+        _ => EQUALITY_SELECTIVITY,
+    }
+}
+
+/// Position of the smallest of given estimates, the first of equal ones
+fn fewest(estimates: impl Iterator<Item = f64>) -> usize {
+    let mut fewest: Option<(usize, f64)> = None;
+    for (position, estimate) in estimates.enumerate() {
+        if fewest.is_none_or(|(_, fewest)| estimate < fewest) {
+            fewest = Some((position, estimate));
+        }
+    }
+    fewest.map_or(0, |(position, _)| position)
+}
+
+/// True if the summary of the table of a summarized select can answer it
+fn summarized(
+    select: &SelectClause,
+    table: &str,
+    database: &(impl DatabaseManager + ?Sized),
+) -> Result<bool, DataError> {
+    let Some(summary) = database.summary(table) else {
+        return Ok(false);
+    };
+    let schema = &database.get_table_meta(table)?.schema;
+    Ok(summary.aggregate(select, schema).is_some())
+}
+
+fn row_count(table: &str, database: &(impl DatabaseManager + ?Sized)) -> Result<usize, DataError> {
+    match database.summary(table) {
+        Some(summary) => Ok(summary.rows()),
+        // System views have no summary
+        None => Ok(database.fetch(table)?.len()),
+    }
+}
+
+/// Collects the conditions of a filter combined with AND, which must all be true for a row
+/// to be kept
+fn conjuncts<'a>(filter: &'a dyn Expression, conditions: &mut Vec<&'a dyn Expression>) {
+    match filter.logical() {
+        Some(logical) if matches!(logical.logical, Logical::And) => {
+            conjuncts(logical.left.as_ref(), conditions);
+            conjuncts(logical.right.as_ref(), conditions);
+        }
+        _ => conditions.push(filter),
+    }
+}
+
+/// Relations of FROM the columns a condition refers to are in, in the order of FROM, None
+/// if it refers to a column no relation has. Names refer to the first relation having a
+/// column of the name, like they do when evaluated against the joined rows.
+fn relations_of(condition: &dyn Expression, relations: &[Relation]) -> Option<Vec<usize>> {
+    let mut names = vec![];
+    condition.references(&mut names);
+    let mut owners = vec![];
+    for name in names {
+        owners.push(column_of(&name, relations)?.0);
+    }
+    owners.sort_unstable();
+    owners.dedup();
+    Some(owners)
+}
+
+/// Relation of FROM a column name refers to, and the position of the column in it
+fn column_of(name: &str, relations: &[Relation]) -> Option<(usize, usize)> {
+    relations.iter().enumerate().find_map(|(relation, r)| {
+        let column = r
+            .columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))?;
+        Some((relation, column))
+    })
+}
+
+/// Relation of FROM as planning knows it
+struct Relation {
+    columns: Vec<Column>,
+    /// Count of distinct values of each column, if the relation is an analyzed table
+    distinct: Option<Vec<usize>>,
+}
+
+/// Plan reading relations of FROM, with the relations in the order of FROM and the
+/// estimated count of rows it produces
+struct Input<'a> {
+    plan: Plan<'a>,
+    relations: Vec<usize>,
+    estimate: f64,
+}
+
+fn plural(count: usize, word: &str) -> String {
+    match count {
+        1 => format!("1 {}", word),
+        _ => format!("{} {}s", count, word),
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use microbat_protocol::data::{
+    data_values::{MData, MDataType},
+    table_model::{Column, DataRow, TableSchema},
+};
+use microbat_protocol::{parameters, sqlstate};
+
+use super::MicrobatQueryError;
+
+/// Parameters every session starts with. Those the server decides can't be changed, and are
+/// the same for every session as the server has a single database.
+const DEFAULT_PARAMETERS: [(&str, &str, bool); 6] = [
+    (parameters::SERVER_VERSION, env!("CARGO_PKG_VERSION"), false),
+    (parameters::DATABASE, "microbat", false),
+    (parameters::CLIENT_ENCODING, "UTF8", false),
+    (parameters::NULL_DISPLAY, "", true),
+    (parameters::DATE_STYLE, "ISO", true),
+    (parameters::STATEMENT_TIMEOUT, "0", true),
+];
+
+/// State of a session kept between its statements, i.e. the parameters it reads with SHOW
+/// and changes with SET
+pub struct Session {
+    parameters: BTreeMap<&'static str, String>,
+    // Values of the parameters before the session changes them, which SET ... DEFAULT restores
+    defaults: BTreeMap<&'static str, String>,
+    // Names of the parameters changed since the last call to `take_changes`
+    changes: Vec<&'static str>,
+    // Directory of the files statements may read and write on the server, None for no files
+    file_dir: Option<PathBuf>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    /// Session with every parameter at its default
+    pub fn new() -> Self {
+        let parameters: BTreeMap<_, _> = DEFAULT_PARAMETERS
+            .iter()
+            .map(|(name, value, _)| (*name, String::from(*value)))
+            .collect();
+        Session {
+            defaults: parameters.clone(),
+            parameters,
+            changes: vec![],
+            file_dir: None,
+        }
+    }
+
+    /// Session whose statements time out after given time unless it sets statement_timeout
+    /// itself, e.g. of a server with a statement timeout for every session
+    pub fn with_statement_timeout(timeout: Option<Duration>) -> Self {
+        let mut session = Session::new();
+        let millis = timeout.map_or(0, |timeout| {
+            u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX)
+        });
+        for values in [&mut session.parameters, &mut session.defaults] {
+            values.insert(parameters::STATEMENT_TIMEOUT, millis.to_string());
+        }
+        session
+    }
+
+    /// Time a statement of the session may take, None if statement_timeout is 0
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        match self.parameters[parameters::STATEMENT_TIMEOUT].parse() {
+            Ok(0) | Err(_) => None,
+            Ok(millis) => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    /// Lets the statements of the session read and write files in given directory and its
+    /// subdirectories, or no files if None
+    pub fn set_file_dir(&mut self, file_dir: Option<PathBuf>) {
+        self.file_dir = file_dir;
+    }
+
+    /// Path of a file on the server named in a statement, relative to the file directory of
+    /// the session. Fails if the session has no file directory or the file is outside it,
+    /// also through `..` or symbolic links. A file being created needs only its directory to
+    /// exist.
+    pub fn file_path(&self, path: &str, creating: bool) -> Result<PathBuf, MicrobatQueryError> {
+        let Some(file_dir) = &self.file_dir else {
+            return Err(MicrobatQueryError::new(
+                sqlstate::INSUFFICIENT_PRIVILEGE,
+                String::from(
+                    "Files on the server can't be used, as the server has no file directory",
+                ),
+            ));
+        };
+        let io_error = |path: &Path, err: std::io::Error| {
+            MicrobatQueryError::new(
+                sqlstate::IO_ERROR,
+                format!("Could not resolve {}: {}", path.display(), err),
+            )
+        };
+        let file_dir = fs::canonicalize(file_dir).map_err(|err| io_error(file_dir, err))?;
+        let outside = || {
+            MicrobatQueryError::new(
+                sqlstate::INSUFFICIENT_PRIVILEGE,
+                format!("File {} is outside the file directory of the server", path),
+            )
+        };
+        let inside = |resolved: &Path| resolved.starts_with(&file_dir) && resolved != file_dir;
+        let joined = file_dir.join(path);
+        let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+            return Err(outside());
+        };
+        // Checked before resolving the file itself, so that files outside can't be probed
+        let resolved = fs::canonicalize(parent)
+            .map_err(|err| io_error(parent, err))?
+            .join(name);
+        if !inside(&resolved) {
+            return Err(outside());
+        }
+        if creating {
+            return Ok(resolved);
+        }
+        let resolved = fs::canonicalize(&resolved).map_err(|err| io_error(&resolved, err))?;
+        match inside(&resolved) {
+            true => Ok(resolved),
+            false => Err(outside()),
+        }
+    }
+
+    /// Parameters by their names, in order of the names
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parameters
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+    }
+
+    /// Value of a parameter, named in any case
+    pub fn get(&self, name: &str) -> Result<&str, MicrobatQueryError> {
+        let name = known_parameter(name)?;
+        Ok(&self.parameters[name])
+    }
+
+    /// Sets a parameter to given value, or back to its default if None. Values are checked
+    /// and stored normalized, e.g. `iso` as `ISO`.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), MicrobatQueryError> {
+        let name = known_parameter(name)?;
+        let (_, _, settable) = DEFAULT_PARAMETERS
+            .iter()
+            .find(|(known, ..)| *known == name)
+            .expect("Known parameters have defaults");
+        if !settable {
+            return Err(MicrobatQueryError::new(
+                sqlstate::CANT_CHANGE_RUNTIME_PARAM,
+                format!("Parameter {} can't be changed", name),
+            ));
+        }
+        let value = match value {
+            Some(value) => check_value(name, value)?,
+            None => self.defaults[name].clone(),
+        };
+        self.parameters.insert(name, value);
+        if !self.changes.contains(&name) {
+            self.changes.push(name);
+        }
+        Ok(())
+    }
+
+    /// Takes the parameters changed since the last call with their values, for telling the
+    /// client of them
+    pub fn take_changes(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.changes)
+            .into_iter()
+            .map(|name| (String::from(name), self.parameters[name].clone()))
+            .collect()
+    }
+
+    /// Result of SHOW, a single column named like the parameter
+    pub fn show(&self, name: &str) -> Result<(TableSchema, Vec<DataRow>), MicrobatQueryError> {
+        let value = self.get(name)?;
+        Ok((
+            TableSchema::new(vec![Column::new(
+                String::from(known_parameter(name)?),
+                MDataType::Varchar,
+            )])?,
+            vec![DataRow::new(vec![MData::Varchar(String::from(value))])],
+        ))
+    }
+
+    /// Result of SHOW ALL, every parameter with its value
+    pub fn show_all(&self) -> Result<(TableSchema, Vec<DataRow>), MicrobatQueryError> {
+        let rows = self
+            .parameters()
+            .map(|(name, value)| {
+                DataRow::new(vec![
+                    MData::Varchar(String::from(name)),
+                    MData::Varchar(String::from(value)),
+                ])
+            })
+            .collect();
+        Ok((
+            TableSchema::new(vec![
+                Column::new(String::from("name"), MDataType::Varchar),
+                Column::new(String::from("setting"), MDataType::Varchar),
+            ])?,
+            rows,
+        ))
+    }
+}
+
+/// Name of the parameter given in any case, as parameter names are case insensitive
+fn known_parameter(name: &str) -> Result<&'static str, MicrobatQueryError> {
+    DEFAULT_PARAMETERS
+        .iter()
+        .map(|(known, ..)| *known)
+        .find(|known| known.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            MicrobatQueryError::new(
+                sqlstate::UNDEFINED_OBJECT,
+                format!("Unrecognized parameter {}", name),
+            )
+        })
+}
+
+/// Value for a parameter that can be set, normalized
+fn check_value(name: &str, value: &str) -> Result<String, MicrobatQueryError> {
+    let invalid = |reason: &str| {
+        MicrobatQueryError::new(
+            sqlstate::INVALID_PARAMETER_VALUE,
+            format!(
+                "Invalid value '{}' for parameter {}, {}",
+                value, name, reason
+            ),
+        )
+    };
+    match name {
+        parameters::DATE_STYLE => match value.eq_ignore_ascii_case("ISO") {
+            true => Ok(String::from("ISO")),
+            false => Err(invalid("only ISO is supported")),
+        },
+        parameters::STATEMENT_TIMEOUT => match value.trim().parse::<u64>() {
+            Ok(millis) => Ok(millis.to_string()),
+            Err(_) => Err(invalid("expecting milliseconds")),
+        },
+        _ => Ok(String::from(value)),
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let mut session = Session::new();
+        assert_eq!(session.get("statement_timeout").ok(), Some("0"));
+
+        assert!(session.set("Statement_Timeout", Some("1500")).is_ok());
+        assert!(session.set("null_display", Some("<null>")).is_ok());
+        assert!(session.set("datestyle", Some("iso")).is_ok());
+        assert_eq!(session.get("STATEMENT_TIMEOUT").ok(), Some("1500"));
+        assert_eq!(session.get("null_display").ok(), Some("<null>"));
+        assert_eq!(session.get("datestyle").ok(), Some("ISO"));
+
+        assert!(session.set("statement_timeout", None).is_ok());
+        assert_eq!(session.get("statement_timeout").ok(), Some("0"));
+        assert_eq!(session.statement_timeout(), None);
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        let mut session = Session::with_statement_timeout(Some(Duration::from_secs(2)));
+        assert_eq!(session.get("statement_timeout").ok(), Some("2000"));
+        assert_eq!(session.statement_timeout(), Some(Duration::from_secs(2)));
+
+        assert!(session.set("statement_timeout", Some("0")).is_ok());
+        assert_eq!(session.statement_timeout(), None);
+        // Default of the session is the one it started with
+        assert!(session.set("statement_timeout", None).is_ok());
+        assert_eq!(session.get("statement_timeout").ok(), Some("2000"));
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let mut session = Session::new();
+        let error = session.get("foo").err().unwrap();
+        assert_eq!(error.code, sqlstate::UNDEFINED_OBJECT);
+        assert_eq!(error.msg, "Unrecognized parameter foo");
+
+        let error = session.set("server_version", Some("2.0")).err().unwrap();
+        assert_eq!(error.code, sqlstate::CANT_CHANGE_RUNTIME_PARAM);
+
+        let error = session
+            .set("statement_timeout", Some("soon"))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, sqlstate::INVALID_PARAMETER_VALUE);
+        assert_eq!(
+            error.msg,
+            "Invalid value 'soon' for parameter statement_timeout, expecting milliseconds"
+        );
+        assert!(session.set("datestyle", Some("German")).is_err());
+        assert!(session.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_file_path() {
+        let file_dir = std::env::temp_dir().join(format!("microbat-files-{}", std::process::id()));
+        fs::create_dir_all(file_dir.join("sub")).unwrap();
+        fs::write(file_dir.join("sub/data.csv"), "1\n").unwrap();
+        let mut session = Session::new();
+        let disabled = session.file_path("data.csv", true).err().unwrap();
+        session.set_file_dir(Some(file_dir.clone()));
+        let read = session.file_path("sub/data.csv", false);
+        let created = session.file_path("sub/new.csv", true);
+        let absolute =
+            session.file_path(&file_dir.join("sub/../new.csv").display().to_string(), true);
+        let escaping = session.file_path("../outside.csv", true).err().unwrap();
+        let directory = session.file_path(".", false).err().unwrap();
+        let missing = session.file_path("missing.csv", false).err().unwrap();
+        fs::remove_dir_all(&file_dir).unwrap();
+
+        assert_eq!(disabled.code, sqlstate::INSUFFICIENT_PRIVILEGE);
+        let file_dir = fs::canonicalize(std::env::temp_dir())
+            .unwrap()
+            .join(format!("microbat-files-{}", std::process::id()));
+        assert_eq!(read.ok(), Some(file_dir.join("sub/data.csv")));
+        assert_eq!(created.ok(), Some(file_dir.join("sub/new.csv")));
+        assert_eq!(absolute.ok(), Some(file_dir.join("new.csv")));
+        assert_eq!(escaping.code, sqlstate::INSUFFICIENT_PRIVILEGE);
+        assert_eq!(
+            escaping.msg,
+            "File ../outside.csv is outside the file directory of the server"
+        );
+        assert_eq!(directory.code, sqlstate::INSUFFICIENT_PRIVILEGE);
+        assert_eq!(missing.code, sqlstate::IO_ERROR);
+    }
+
+    #[test]
+    fn test_changes_are_taken_once() {
+        let mut session = Session::new();
+        assert!(session.set("null_display", Some("-")).is_ok());
+        assert!(session.set("statement_timeout", Some("10")).is_ok());
+        assert!(session.set("null_display", Some("NULL")).is_ok());
+        assert_eq!(
+            session.take_changes(),
+            vec![
+                (String::from("null_display"), String::from("NULL")),
+                (String::from("statement_timeout"), String::from("10")),
+            ]
+        );
+        assert!(session.take_changes().is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use microbat_protocol::data::data_values::MData;
+use microbat_protocol::data::date::{format_date, format_timestamp};
+use microbat_protocol::data::decimal::format_decimal;
+use microbat_protocol::data::table_model::TableSchema;
+use microbat_protocol::sqlstate;
+
+use super::deadline::Deadline;
+use super::executor::Rows;
+use super::manager::DatabaseManager;
+use super::session::Session;
+use super::{check_unique_columns, read_lock, write_lock, MicrobatQueryError, QueryResult};
+use crate::sql::parser::SelectClause;
+
+/// Destination of the rows of a select other than the client. The client gets the count of
+/// rows written instead.
+#[derive(Debug, PartialEq)]
+pub enum Sink {
+    /// New table created of the rows, from `SELECT ... INTO name` and `CREATE TABLE name AS`
+    Table(String),
+    /// New CSV file on the server with a header line, from `SELECT ... INTO OUTFILE 'path'`
+    File(String),
+}
+
+impl Sink {
+    /// Executes the select and writes its rows to this sink, failing if producing them
+    /// takes past the deadline. Files are written in the file directory of the session.
+    pub fn run(
+        &self,
+        select: &SelectClause,
+        manager: &Arc<RwLock<impl DatabaseManager + ?Sized>>,
+        session: &Session,
+        deadline: Deadline,
+    ) -> Result<QueryResult, MicrobatQueryError> {
+        match self {
+            Sink::Table(table) => {
+                let mut database = write_lock(manager);
+
+                // Collected before creating the table, as producing the rows borrows the database
+                let (schema, rows) = database.query(select, deadline)?;
+                let rows: Vec<Vec<MData>> = rows.collect::<Result<_, _>>()?;
+                let columns = schema.columns;
+                check_unique_columns(&columns)?;
+                let count = rows.len() as u32;
+                database.create_table(table.clone(), columns)?;
+                database.insert_rows(table, rows)?;
+                Ok(QueryResult::Inserted(count))
+            }
+            Sink::File(path) => {
+                let file = session.file_path(path, true)?;
+                let database = read_lock(manager);
+                let (schema, rows) = database.query(select, deadline)?;
+                let count = write_csv(&file, path, &schema, rows)?;
+                Ok(QueryResult::Inserted(count))
+            }
+        }
+    }
+}
+
+/// Writes rows to a new file as they are produced, an existing file is never overwritten.
+/// The file is removed if writing it fails part way. Errors name the file by `path` as the
+/// statement does. Returns the count of rows written.
+fn write_csv(
+    file: &Path,
+    path: &str,
+    schema: &TableSchema,
+    rows: Rows,
+) -> Result<u32, MicrobatQueryError> {
+    let opened = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(file)
+        .map_err(|err| io_error(path, err))?;
+    let written = write_rows(&mut BufWriter::new(opened), path, schema, rows);
+    if written.is_err() {
+        let _ = fs::remove_file(file);
+    }
+    written
+}
+
+fn write_rows(
+    file: &mut impl Write,
+    path: &str,
+    schema: &TableSchema,
+    rows: Rows,
+) -> Result<u32, MicrobatQueryError> {
+    let header: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|column| csv_field(&column.name))
+        .collect();
+    writeln!(file, "{}", header.join(",")).map_err(|err| io_error(path, err))?;
+    let mut count = 0;
+    for row in rows {
+        let fields: Vec<String> = row?.iter().map(csv_value).collect();
+        writeln!(file, "{}", fields.join(",")).map_err(|err| io_error(path, err))?;
+        count += 1;
+    }
+    file.flush().map_err(|err| io_error(path, err))?;
+    Ok(count)
+}
+
+fn io_error(path: &str, err: std::io::Error) -> MicrobatQueryError {
+    MicrobatQueryError::new(
+        sqlstate::IO_ERROR,
+        format!("Could not write {}: {}", path, err),
+    )
+}
+
+/// Value as a CSV field. NULL is an empty field and an empty string is quoted to tell them
+/// apart, as in PostgreSQL.
+fn csv_value(value: &MData) -> String {
+    match value {
+        MData::Null => String::new(),
+        MData::Varchar(value) if value.is_empty() => String::from("\"\""),
+        MData::Varchar(value) => csv_field(value),
+        MData::Integer(value) => value.to_string(),
+        MData::BigInt(value) => value.to_string(),
+        MData::Bool(value) => value.to_string(),
+        MData::Float(value) => value.to_string(),
+        MData::Decimal(value, scale) => format_decimal(*value, *scale),
+        MData::Date(days) => format_date(*days),
+        MData::Timestamp(micros) => format_timestamp(*micros),
+        MData::Bytes(bytes) => {
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("\\x{}", hex)
+        }
+    }
+}
+
+/// Quotes text containing separators, quotes or line breaks
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
+
+#[cfg(test)]
+mod sink_tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_value() {
+        assert_eq!(csv_value(&MData::Null), "");
+        assert_eq!(csv_value(&MData::Varchar(String::new())), "\"\"");
+        assert_eq!(csv_value(&MData::Varchar(String::from("plain"))), "plain");
+        assert_eq!(
+            csv_value(&MData::Varchar(String::from("say \"hi\", bye"))),
+            "\"say \"\"hi\"\", bye\""
+        );
+        assert_eq!(csv_value(&MData::Decimal(-1050, 2)), "-10.50");
+        assert_eq!(csv_value(&MData::Bytes(vec![0xde, 0xad])), "\\xdead");
+    }
+}
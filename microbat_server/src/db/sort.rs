@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+
+use microbat_protocol::data::{data_values::MData, decimal};
+
+/// Collation used for ordering varchar values
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Collation {
+    /// Orders by the bytes of the value, so upper case letters come before lower case
+    #[default]
+    Binary,
+    /// Orders ignoring case
+    NoCase,
+}
+
+impl Collation {
+    /// Collation by name as used in `COLLATE name`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "BINARY" => Some(Collation::Binary),
+            "NOCASE" => Some(Collation::NoCase),
+            _ => None,
+        }
+    }
+
+    pub fn compare(&self, left: &str, right: &str) -> Ordering {
+        match self {
+            Collation::Binary => left.cmp(right),
+            Collation::NoCase => left.to_lowercase().cmp(&right.to_lowercase()),
+        }
+    }
+}
+
+/// How values of one sort key are ordered
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SortOptions {
+    pub descending: bool,
+    pub nulls_first: bool,
+    pub collation: Collation,
+}
+
+impl SortOptions {
+    /// Nulls are ordered as if they were larger than any value unless `nulls_first` is given,
+    /// so by default they come last in ascending and first in descending order.
+    pub fn new(descending: bool, nulls_first: Option<bool>, collation: Collation) -> Self {
+        SortOptions {
+            descending,
+            nulls_first: nulls_first.unwrap_or(descending),
+            collation,
+        }
+    }
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        SortOptions::new(false, None, Collation::default())
+    }
+}
+
+/// Compares two values of a sort key. Nulls are placed by `nulls_first` regardless of direction.
+pub fn compare_values(left: &MData, right: &MData, options: &SortOptions) -> Ordering {
+    let ordering = match (left, right) {
+        (MData::Null, MData::Null) => return Ordering::Equal,
+        (MData::Null, _) if options.nulls_first => return Ordering::Less,
+        (MData::Null, _) => return Ordering::Greater,
+        (_, MData::Null) if options.nulls_first => return Ordering::Greater,
+        (_, MData::Null) => return Ordering::Less,
+        (MData::Integer(left), MData::Integer(right)) => left.cmp(right),
+        (MData::Varchar(left), MData::Varchar(right)) => options.collation.compare(left, right),
+        (MData::Bool(left), MData::Bool(right)) => left.cmp(right),
+        (MData::Date(left), MData::Date(right)) => left.cmp(right),
+        (MData::Timestamp(left), MData::Timestamp(right)) => left.cmp(right),
+        (MData::Bytes(left), MData::Bytes(right)) => left.cmp(right),
+        (MData::Integer(_) | MData::BigInt(_), MData::Integer(_) | MData::BigInt(_)) => {
+            left.as_bigint().unwrap().cmp(&right.as_bigint().unwrap())
+        }
+        (
+            MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..),
+            MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..),
+        ) => decimal::compare(left.as_decimal().unwrap(), right.as_decimal().unwrap()),
+        (
+            MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+            MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+        ) => {
+            let (left, right) = (left.as_float().unwrap(), right.as_float().unwrap());
+            left.total_cmp(&right)
+        }
+        // Values of a column have the same type, but keep the order total anyway
+        (left, right) => type_rank(left).cmp(&type_rank(right)),
+    };
+    if options.descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// Compares rows of sort keys, key by key
+pub fn compare_rows(left: &[MData], right: &[MData], options: &[SortOptions]) -> Ordering {
+    left.iter()
+        .zip(right.iter())
+        .zip(options.iter())
+        .map(|((left, right), options)| compare_values(left, right, options))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn type_rank(value: &MData) -> u8 {
+    match value {
+        MData::Null => 0,
+        MData::Bool(_) => 1,
+        MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_) => 2,
+        MData::Varchar(_) => 3,
+        MData::Date(_) => 4,
+        MData::Timestamp(_) => 5,
+        MData::Bytes(_) => 6,
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use super::*;
+
+    fn sorted(mut values: Vec<MData>, options: SortOptions) -> Vec<MData> {
+        values.sort_by(|left, right| compare_values(left, right, &options));
+        values
+    }
+
+    fn varchar(value: &str) -> MData {
+        MData::Varchar(String::from(value))
+    }
+
+    #[test]
+    fn test_null_ordering() {
+        let values = vec![MData::Integer(2), MData::Null, MData::Integer(1)];
+        assert_eq!(
+            sorted(values.clone(), SortOptions::default()),
+            vec![MData::Integer(1), MData::Integer(2), MData::Null]
+        );
+        assert_eq!(
+            sorted(
+                values.clone(),
+                SortOptions::new(true, None, Collation::Binary)
+            ),
+            vec![MData::Null, MData::Integer(2), MData::Integer(1)]
+        );
+        assert_eq!(
+            sorted(
+                values.clone(),
+                SortOptions::new(false, Some(true), Collation::Binary)
+            ),
+            vec![MData::Null, MData::Integer(1), MData::Integer(2)]
+        );
+        assert_eq!(
+            sorted(
+                values,
+                SortOptions::new(true, Some(false), Collation::Binary)
+            ),
+            vec![MData::Integer(2), MData::Integer(1), MData::Null]
+        );
+    }
+
+    #[test]
+    fn test_collation() {
+        let values = vec![varchar("b"), varchar("B"), varchar("a"), varchar("C")];
+        assert_eq!(
+            sorted(values.clone(), SortOptions::default()),
+            vec![varchar("B"), varchar("C"), varchar("a"), varchar("b")]
+        );
+        assert_eq!(
+            sorted(values, SortOptions::new(false, None, Collation::NoCase)),
+            vec![varchar("a"), varchar("b"), varchar("B"), varchar("C")]
+        );
+        assert_eq!(Collation::from_name("nocase"), Some(Collation::NoCase));
+        assert_eq!(Collation::from_name("klingon"), None);
+    }
+
+    #[test]
+    fn test_compare_rows() {
+        let options = [
+            SortOptions::default(),
+            SortOptions::new(true, None, Collation::Binary),
+        ];
+        assert_eq!(
+            compare_rows(
+                &[MData::Integer(1), MData::Integer(1)],
+                &[MData::Integer(1), MData::Integer(2)],
+                &options
+            ),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_rows(
+                &[MData::Integer(1), MData::Integer(9)],
+                &[MData::Integer(2), MData::Integer(1)],
+                &options
+            ),
+            Ordering::Less
+        );
+    }
+}
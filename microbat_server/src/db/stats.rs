@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use microbat_protocol::data::{
+    data_values::{MData, MDataType},
+    table_model::{Column, TableSchema},
+};
+
+use crate::sql::lexer::CaseFolding;
+
+/// Name of the system view exposing statement statistics
+pub const STAT_STATEMENTS_VIEW: &str = "mb_stat_statements";
+
+/// Execution statistics of statements, grouped by their fingerprints
+#[derive(Default)]
+pub struct StatementStatistics {
+    statements: HashMap<String, StatementStats>,
+}
+
+#[derive(Default)]
+struct StatementStats {
+    calls: u64,
+    errors: u64,
+    rows: u64,
+    total_time: Duration,
+    max_time: Duration,
+}
+
+impl StatementStatistics {
+    /// Records one execution of a statement. Failed executions have no row count.
+    pub fn record(&mut self, fingerprint: String, elapsed: Duration, rows: Option<u64>) {
+        let stats = self.statements.entry(fingerprint).or_default();
+        stats.calls += 1;
+        match rows {
+            Some(rows) => stats.rows += rows,
+            None => stats.errors += 1,
+        }
+        stats.total_time += elapsed;
+        stats.max_time = stats.max_time.max(elapsed);
+    }
+
+    /// Schema of the statistics system view, column names folded like identifiers
+    pub fn schema(case_folding: CaseFolding) -> TableSchema {
+        let column = |name: &str, data_type| Column::new(case_folding.fold(name), data_type);
+        TableSchema::new(vec![
+            column("fingerprint", MDataType::Varchar),
+            column("calls", MDataType::Integer),
+            column("errors", MDataType::Integer),
+            column("row_count", MDataType::Integer),
+            column("total_ms", MDataType::Float),
+            column("mean_ms", MDataType::Float),
+            column("max_ms", MDataType::Float),
+        ])
+        .expect("Statistics columns are unique")
+    }
+
+    /// Rows of the statistics system view, one per fingerprint
+    pub fn rows(&self) -> Vec<Vec<MData>> {
+        let mut rows = vec![];
+        for (fingerprint, stats) in self.statements.iter() {
+            let total_ms = stats.total_time.as_secs_f64() * 1000.0;
+            rows.push(vec![
+                MData::Varchar(fingerprint.clone()),
+                count(stats.calls),
+                count(stats.errors),
+                count(stats.rows),
+                MData::Float(total_ms),
+                MData::Float(total_ms / stats.calls as f64),
+                MData::Float(stats.max_time.as_secs_f64() * 1000.0),
+            ]);
+        }
+        rows
+    }
+}
+
+// Counts larger than an integer column can hold are capped
+fn count(value: u64) -> MData {
+    MData::Integer(i32::try_from(value).unwrap_or(i32::MAX))
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut statistics = StatementStatistics::default();
+        statistics.record(String::from("A"), Duration::from_millis(2), Some(3));
+        statistics.record(String::from("A"), Duration::from_millis(4), None);
+        statistics.record(String::from("B"), Duration::from_millis(1), Some(0));
+        let mut rows = statistics.rows();
+        rows.sort_by_key(|row| row[0].bytes());
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    MData::Varchar(String::from("A")),
+                    MData::Integer(2),
+                    MData::Integer(1),
+                    MData::Integer(3),
+                    MData::Float(6.0),
+                    MData::Float(3.0),
+                    MData::Float(4.0),
+                ],
+                vec![
+                    MData::Varchar(String::from("B")),
+                    MData::Integer(1),
+                    MData::Integer(0),
+                    MData::Integer(0),
+                    MData::Float(1.0),
+                    MData::Float(1.0),
+                    MData::Float(1.0),
+                ],
+            ]
+        );
+        assert_eq!(
+            rows[0].len(),
+            StatementStatistics::schema(CaseFolding::default())
+                .columns
+                .len()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+
+use microbat_protocol::data::{data_values::MData, table_model::TableSchema};
+
+use crate::db::aggregate::keep;
+use crate::sql::expression::AggregateFunction;
+use crate::sql::parser::{FromItem, SelectClause};
+
+/// Row count and the smallest and largest value of each column of a table, kept up to date
+/// on insert. Rows are never removed, so the values only ever need to grow.
+pub struct TableSummary {
+    rows: usize,
+    min: Vec<Option<MData>>,
+    max: Vec<Option<MData>>,
+}
+
+impl TableSummary {
+    pub fn new(columns: usize) -> Self {
+        TableSummary {
+            rows: 0,
+            min: vec![None; columns],
+            max: vec![None; columns],
+        }
+    }
+
+    /// Count of the rows of the table
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn push(&mut self, row: &[MData]) {
+        self.rows += 1;
+        for (index, value) in row.iter().enumerate() {
+            // Aggregates skip nulls, so they are never the smallest or largest value
+            if *value != MData::Null {
+                keep(&mut self.min[index], value.clone(), Ordering::Less);
+                keep(&mut self.max[index], value.clone(), Ordering::Greater);
+            }
+        }
+    }
+
+    /// Answers each column of the select from this summary. Returns None if some column
+    /// can't be answered, so the rows must be scanned.
+    pub fn aggregate(&self, select: &SelectClause, schema: &TableSchema) -> Option<Vec<MData>> {
+        let mut row = vec![];
+        for expression in select.projection.iter() {
+            let aggregate = expression.aggregate()?;
+            let column = || {
+                let name = aggregate.argument.as_ref()?.reference()?;
+                schema.columns.iter().position(|column| column.name == name)
+            };
+            row.push(match aggregate.function {
+                AggregateFunction::Count => MData::Integer(i32::try_from(self.rows).ok()?),
+                AggregateFunction::Min => self.min[column()?].clone().unwrap_or(MData::Null),
+                AggregateFunction::Max => self.max[column()?].clone().unwrap_or(MData::Null),
+                _ => return None,
+            });
+        }
+        Some(row)
+    }
+}
+
+/// Table of a select that can be answered from the summary of the table instead of scanning
+/// its rows. That is a select of only `COUNT(*)`, `MIN(column)` and `MAX(column)` from a
+/// single table without WHERE, GROUP BY or ORDER BY.
+pub fn summarized_table(select: &SelectClause) -> Option<&str> {
+    let table = match select.from.as_slice() {
+        [FromItem::Table(table)] => table,
+        _ => return None,
+    };
+    if select.filter.is_some() || select.group_by.is_some() || !select.order_by.is_empty() {
+        return None;
+    }
+    let summarized = select.projection.iter().all(|expression| {
+        match (expression.aggregate(), expression.window()) {
+            (Some(aggregate), None) => match (&aggregate.function, &aggregate.argument) {
+                (AggregateFunction::Count, None) => !aggregate.distinct,
+                (AggregateFunction::Min | AggregateFunction::Max, Some(argument)) => {
+                    argument.reference().is_some()
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    });
+    match summarized {
+        true => Some(table),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod summary_tests {
+    use super::*;
+    use crate::sql::parser::{parse_sql, SqlClause};
+
+    fn select(sql: &str) -> SelectClause {
+        match parse_sql(sql.to_owned()) {
+            Ok(SqlClause::Select(select)) => select,
+            _ => panic!("Expecting select: {}", sql),
+        }
+    }
+
+    #[test]
+    fn test_summarized_table() {
+        assert_eq!(
+            summarized_table(&select("select count(*), min(id), max(name) from foo;")),
+            Some("foo")
+        );
+        for sql in [
+            "select count(*) from foo where id > 1;",
+            "select count(*) from foo group by id;",
+            "select count(*) from foo order by 1;",
+            "select count(id) from foo;",
+            "select min(id + 1) from foo;",
+            "select sum(id) from foo;",
+            "select count(*), 1 from foo;",
+            "select count(*) from foo, bar;",
+            "select count(*) from generate_series(1, 3);",
+        ] {
+            assert_eq!(summarized_table(&select(sql)), None, "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use microbat_protocol::data::{
+    data_values::{MData, MDataType},
+    table_model::{Column, TableSchema},
+};
+use microbat_protocol::sqlstate;
+
+use crate::sql::expression::{EvaluationError, TableFunction};
+use crate::sql::parser::TableFunctionCall;
+
+/// Evaluates a table function in FROM into its columns and rows
+pub fn scan(call: &TableFunctionCall) -> Result<(Vec<Column>, Vec<Vec<MData>>), EvaluationError> {
+    // Arguments can't refer to columns of other relations in FROM
+    let no_columns = TableSchema { columns: vec![] };
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+        arguments.push(argument.eval(&no_columns, &[])?);
+    }
+    let rows = match call.function {
+        TableFunction::GenerateSeries => generate_series(&arguments)?,
+    };
+    Ok((columns(call)?, rows))
+}
+
+/// Columns of the rows a table function in FROM returns, named by its aliases
+pub fn columns(call: &TableFunctionCall) -> Result<Vec<Column>, EvaluationError> {
+    let types = match call.function {
+        TableFunction::GenerateSeries => vec![MDataType::Integer],
+    };
+    let names = match (&call.alias, call.column_aliases.is_empty()) {
+        (_, false) => call.column_aliases.clone(),
+        (Some(alias), true) if types.len() == 1 => vec![alias.clone()],
+        _ => vec![String::from(call.function.name())],
+    };
+    if names.len() != types.len() {
+        return Err(EvaluationError {
+            code: sqlstate::SYNTAX_ERROR,
+            msg: format!(
+                "{} returns {} columns but {} names were given",
+                call.function.name(),
+                types.len(),
+                names.len()
+            ),
+        });
+    }
+    Ok(names
+        .into_iter()
+        .zip(types)
+        .map(|(name, data_type)| Column::new(name, data_type))
+        .collect())
+}
+
+/// `generate_series(start, stop[, step])` gives integers from start to stop, both inclusive
+fn generate_series(arguments: &[MData]) -> Result<Vec<Vec<MData>>, EvaluationError> {
+    let (start, stop, step) = match arguments {
+        [MData::Integer(start), MData::Integer(stop)] => (*start, *stop, 1),
+        [MData::Integer(start), MData::Integer(stop), MData::Integer(step)] => {
+            (*start, *stop, *step)
+        }
+        _ => {
+            return Err(EvaluationError {
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: String::from("generate_series takes integers start, stop and optional step"),
+            })
+        }
+    };
+    if step == 0 {
+        return Err(EvaluationError {
+            code: sqlstate::INVALID_PARAMETER_VALUE,
+            msg: String::from("generate_series step can't be zero"),
+        });
+    }
+    let mut rows = vec![];
+    let mut value = start;
+    while (step > 0 && value <= stop) || (step < 0 && value >= stop) {
+        rows.push(vec![MData::Integer(value)]);
+        match value.checked_add(step) {
+            Some(next) => value = next,
+            None => break,
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod table_function_tests {
+    use super::*;
+
+    fn series(arguments: &[i32]) -> Vec<i32> {
+        let arguments: Vec<MData> = arguments.iter().map(|a| MData::Integer(*a)).collect();
+        generate_series(&arguments)
+            .unwrap()
+            .into_iter()
+            .map(|row| match row[0] {
+                MData::Integer(value) => value,
+                _ => panic!("Expecting integers"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_series() {
+        assert_eq!(series(&[1, 3]), vec![1, 2, 3]);
+        assert_eq!(series(&[3, 1]), Vec::<i32>::new());
+        assert_eq!(series(&[0, 10, 4]), vec![0, 4, 8]);
+        assert_eq!(series(&[3, 1, -1]), vec![3, 2, 1]);
+        assert_eq!(
+            series(&[i32::MAX - 1, i32::MAX]),
+            vec![i32::MAX - 1, i32::MAX]
+        );
+        assert!(
+            generate_series(&[MData::Integer(1), MData::Integer(2), MData::Integer(0)]).is_err()
+        );
+        assert!(generate_series(&[MData::Integer(1)]).is_err());
+    }
+}
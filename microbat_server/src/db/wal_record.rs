@@ -0,0 +1,202 @@
+use microbat_protocol::data::table_model::{Column, DataRow, TableSchema};
+use microbat_protocol::messages::frame::{FrameReader, FrameWriter};
+use microbat_protocol::messages::MicrobatMessage;
+use microbat_protocol::MicrobatProtocolError;
+
+/// Starts every snapshot and log, followed by the version of the format as u16
+pub const FILE_MAGIC: &[u8; 4] = b"MBAT";
+
+/// Version of the format of the records, bumped when records change incompatibly
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Length of the magic and the version starting every file
+pub const HEADER_LENGTH: usize = FILE_MAGIC.len() + 2;
+
+const RECORD_TYPE_TABLE: u8 = b'T';
+const RECORD_TYPE_CREATE_TABLE: u8 = b'C';
+const RECORD_TYPE_ROW: u8 = b'R';
+const RECORD_TYPE_ROWS: u8 = b'B';
+const RECORD_TYPE_INDEX: u8 = b'I';
+
+/// Record of a snapshot or a log of `FileManager`.
+///
+/// Records are framed like messages, with their own types, so the files don't change when
+/// messages of the protocol do. Records after `Table` or `CreateTable` are about that table.
+#[derive(Debug, PartialEq)]
+pub enum WalRecord {
+    /// Names the table the records after it are about
+    Table(String),
+    /// Creates a table with given schema
+    CreateTable(String, TableSchema),
+    /// Row inserted into the table
+    Row(DataRow),
+    /// Rows inserted into the table together
+    Rows(Vec<DataRow>),
+    /// Index of the table by its name and the name of its column
+    Index(String, String),
+}
+
+impl MicrobatMessage for WalRecord {
+    fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            WalRecord::Table(name) => {
+                let mut frame = FrameWriter::new(RECORD_TYPE_TABLE);
+                frame.put_str(name);
+                frame.finish()
+            }
+            WalRecord::CreateTable(name, schema) => {
+                let mut frame = FrameWriter::new(RECORD_TYPE_CREATE_TABLE);
+                frame.put_str(name);
+                for column in &schema.columns {
+                    frame.put_str(&column.name).put_type(&column.data_type);
+                }
+                frame.finish()
+            }
+            WalRecord::Row(row) => {
+                let mut frame = FrameWriter::new(RECORD_TYPE_ROW);
+                for value in &row.columns {
+                    frame.put_data(value);
+                }
+                frame.finish()
+            }
+            WalRecord::Rows(rows) => {
+                let mut frame = FrameWriter::new(RECORD_TYPE_ROWS);
+                frame.put_rows(rows);
+                frame.finish()
+            }
+            WalRecord::Index(name, column) => {
+                let mut frame = FrameWriter::new(RECORD_TYPE_INDEX);
+                frame.put_str(name).put_str(column);
+                frame.finish()
+            }
+        }
+    }
+}
+
+/// Header starting every snapshot and log
+pub fn file_header() -> Vec<u8> {
+    let mut header = FILE_MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header
+}
+
+/// Checks the header of a file, failing if it isn't a file of records or its records are
+/// of another version of the format
+pub fn check_header(header: &[u8]) -> Result<(), String> {
+    if header.len() < HEADER_LENGTH || &header[..FILE_MAGIC.len()] != FILE_MAGIC {
+        return Err(String::from("not a snapshot or a log of microbat"));
+    }
+    let version = u16::from_le_bytes([header[FILE_MAGIC.len()], header[FILE_MAGIC.len() + 1]]);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "written in format version {} but this server reads version {}",
+            version, FORMAT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a record, to be given to `read_message_with`
+pub fn deserialize_wal_record(
+    record_type: u8,
+    length: usize,
+    bytes: &[u8],
+) -> Result<WalRecord, MicrobatProtocolError> {
+    if length != bytes.len() {
+        return Err(MicrobatProtocolError::new(format!(
+            "Byte mismatch error. Expecting {} bytes but received {} bytes",
+            length,
+            bytes.len()
+        )));
+    }
+    let mut reader = FrameReader::new(bytes);
+    let record = match record_type {
+        RECORD_TYPE_TABLE => WalRecord::Table(reader.get_str()?),
+        RECORD_TYPE_CREATE_TABLE => {
+            let name = reader.get_str()?;
+            let mut columns = vec![];
+            while !reader.is_empty() {
+                columns.push(Column {
+                    name: reader.get_str()?,
+                    data_type: reader.get_type()?,
+                });
+            }
+            WalRecord::CreateTable(name, TableSchema { columns })
+        }
+        RECORD_TYPE_ROW => {
+            let mut columns = vec![];
+            while !reader.is_empty() {
+                columns.push(reader.get_data()?);
+            }
+            WalRecord::Row(DataRow { columns })
+        }
+        RECORD_TYPE_ROWS => WalRecord::Rows(reader.get_rows()?),
+        RECORD_TYPE_INDEX => WalRecord::Index(reader.get_str()?, reader.get_str()?),
+        unknown => {
+            return Err(MicrobatProtocolError::new(format!(
+                "Unknown record type {}",
+                unknown
+            )))
+        }
+    };
+    reader.finish()?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod wal_record_tests {
+    use super::*;
+    use microbat_protocol::data::data_values::{MData, MDataType};
+
+    fn assert_round_trip(record: WalRecord) {
+        let bytes = record.as_bytes();
+        assert_eq!(
+            deserialize_wal_record(bytes[0], bytes.len() - 5, &bytes[5..]).unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        assert_round_trip(WalRecord::Table(String::from("foo")));
+        assert_round_trip(WalRecord::CreateTable(
+            String::from("foo"),
+            TableSchema::new(vec![
+                Column::new(String::from("id"), MDataType::Integer),
+                Column::new(String::from("name"), MDataType::Varchar),
+            ])
+            .unwrap(),
+        ));
+        assert_round_trip(WalRecord::CreateTable(
+            String::from("empty"),
+            TableSchema { columns: vec![] },
+        ));
+        assert_round_trip(WalRecord::Row(DataRow::new(vec![
+            MData::Integer(1),
+            MData::Null,
+        ])));
+        assert_round_trip(WalRecord::Rows(vec![
+            DataRow::new(vec![MData::Integer(1)]),
+            DataRow::new(vec![MData::Integer(2)]),
+        ]));
+        assert_round_trip(WalRecord::Index(String::from("foo_id"), String::from("id")));
+        assert!(deserialize_wal_record(b'Z', 0, &[]).is_err());
+        // Truncated record
+        assert!(deserialize_wal_record(RECORD_TYPE_INDEX, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn test_check_header() {
+        assert!(check_header(&file_header()).is_ok());
+        assert_eq!(
+            check_header(b"garbage").unwrap_err(),
+            "not a snapshot or a log of microbat"
+        );
+        let mut header = file_header();
+        header[FILE_MAGIC.len()] = 0;
+        assert_eq!(
+            check_header(&header).unwrap_err(),
+            "written in format version 0 but this server reads version 1"
+        );
+    }
+}
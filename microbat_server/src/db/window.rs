@@ -0,0 +1,80 @@
+use microbat_protocol::data::{data_values::MData, table_model::TableSchema};
+
+use crate::db::sort::{compare_rows, SortOptions};
+use crate::sql::expression::{EvaluationError, WindowExpression};
+
+/// Evaluates a window function over all rows of the query.
+///
+/// Rows are ordered by the OVER (ORDER BY ...) keys, ties keeping their original order,
+/// and the returned values are in the original order of `rows`.
+pub fn evaluate_window(
+    window: &WindowExpression,
+    schema: &TableSchema,
+    rows: &[Vec<MData>],
+) -> Result<Vec<MData>, EvaluationError> {
+    let mut keys = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+        let mut key = vec![];
+        for order_by in window.order_by.iter() {
+            key.push(order_by.expression.eval(schema, row)?);
+        }
+        keys.push(key);
+    }
+
+    let sort_options: Vec<SortOptions> =
+        window.order_by.iter().map(|order| order.options).collect();
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by(|left, right| compare_rows(&keys[*left], &keys[*right], &sort_options));
+
+    let mut values = vec![MData::Null; rows.len()];
+    for (position, index) in order.into_iter().enumerate() {
+        values[index] = window.function.value(position);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod window_tests {
+    use microbat_protocol::data::{data_values::MDataType, table_model::Column};
+
+    use super::*;
+    use crate::sql::expression::{ReferenceExpression, WindowFunction};
+    use crate::sql::parser::OrderBy;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![Column::new(String::from("ID"), MDataType::Integer)]).unwrap()
+    }
+
+    fn rows(ids: &[Option<i32>]) -> Vec<Vec<MData>> {
+        ids.iter()
+            .map(|id| vec![id.map_or(MData::Null, MData::Integer)])
+            .collect()
+    }
+
+    #[test]
+    fn test_row_number_without_ordering() {
+        let window = WindowExpression {
+            function: WindowFunction::RowNumber,
+            order_by: vec![],
+        };
+        assert_eq!(
+            evaluate_window(&window, &schema(), &rows(&[Some(3), Some(1), Some(2)])).unwrap(),
+            vec![MData::Integer(1), MData::Integer(2), MData::Integer(3)]
+        );
+    }
+
+    #[test]
+    fn test_row_number_with_ordering() {
+        let window = WindowExpression {
+            function: WindowFunction::RowNumber,
+            order_by: vec![OrderBy {
+                expression: Box::new(ReferenceExpression::new(String::from("ID"))),
+                options: SortOptions::default(),
+            }],
+        };
+        assert_eq!(
+            evaluate_window(&window, &schema(), &rows(&[Some(3), None, Some(1)])).unwrap(),
+            vec![MData::Integer(2), MData::Integer(3), MData::Integer(1)]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! Microbat server as a library, so benchmarks can drive the executor without a connection
+pub mod connect;
+pub mod db;
+pub mod log;
+pub mod sql;
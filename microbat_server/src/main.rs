@@ -1,11 +1,23 @@
-use connect::MicrobatServerOpts;
-
-mod connect;
-mod db;
-mod sql;
+use microbat_server::connect::{self, config};
+use std::env;
+use std::path::PathBuf;
+use std::process;
 
 fn main() {
-    connect::run_microbat(MicrobatServerOpts {
-        bind: String::from("127.0.0.1:7878"),
-    })
+    let mut config = match config::parse_args(env::args().skip(1)) {
+        Ok(config::Command::Run(config)) => *config,
+        Ok(config::Command::Help) => {
+            println!("{}", config::HELP);
+            return;
+        }
+        Err(problem) => {
+            eprintln!("{}\n\n{}", problem, config::HELP);
+            process::exit(2)
+        }
+    };
+    // Tables are kept only in memory unless a data directory is given
+    if config.data_dir.is_none() {
+        config.data_dir = env::var_os("MICROBAT_DATA_DIR").map(PathBuf::from);
+    }
+    connect::run_microbat(config.into_opts())
 }
@@ -1,22 +1,87 @@
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use microbat_protocol::data::{
     data_values::{DataError, MData, MDataType},
+    decimal::{self, MAX_PRECISION},
     table_model::{Column, TableSchema},
 };
+use microbat_protocol::sqlstate;
+
+use super::parser::OrderBy;
 
 #[derive(Debug)]
 pub struct EvaluationError {
+    pub code: &'static str,
     pub msg: String,
 }
 
 impl From<DataError> for EvaluationError {
     fn from(value: DataError) -> Self {
-        EvaluationError { msg: value.msg }
+        EvaluationError {
+            code: value.code,
+            msg: value.msg,
+        }
     }
 }
 
-pub trait Expression {
+/// Expression evaluated against rows. Shared between the workers of queries reading tables in
+/// parallel, so it must be Sync.
+pub trait Expression: Sync {
     fn schema_column(&self, schema: &TableSchema, index: usize) -> Result<Column, EvaluationError>;
-    fn eval(&self, schema: &TableSchema, row: &Vec<MData>) -> Result<MData, EvaluationError>;
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError>;
+
+    /// Binds query parameters to parameter placeholders in this expression.
+    ///
+    /// Expressions with subexpressions must pass this on to them.
+    fn bind(&mut self, _parameters: &[MData]) -> Result<(), EvaluationError> {
+        Ok(())
+    }
+
+    /// Adds the names of the columns this expression refers to, e.g. for telling which
+    /// relation in FROM a condition is about.
+    ///
+    /// Expressions with subexpressions must pass this on to them.
+    fn references(&self, _names: &mut Vec<String>) {}
+
+    /// Returns the window function if this expression is one.
+    ///
+    /// Window functions are computed over the whole result set instead of a single row,
+    /// so they can't be evaluated with `eval`.
+    fn window(&self) -> Option<&WindowExpression> {
+        None
+    }
+
+    /// Returns the aggregate function if this expression is one.
+    ///
+    /// Aggregates are computed over the rows of a group, so they can't be evaluated with `eval`.
+    fn aggregate(&self) -> Option<&AggregateExpression> {
+        None
+    }
+
+    /// Returns the name of the column if this expression is a plain column reference
+    fn reference(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the row value constructor if this expression is one.
+    ///
+    /// Row values like `(a, b)` are compared element by element, so they can't be
+    /// evaluated with `eval` into a single value.
+    fn row_value(&self) -> Option<&RowExpression> {
+        None
+    }
+
+    /// Returns the comparison if this expression is one, e.g. for finding conditions an
+    /// index can answer
+    fn comparison(&self) -> Option<&ComparisonExpression> {
+        None
+    }
+
+    /// Returns the AND or OR if this expression is one
+    fn logical(&self) -> Option<&LogicalExpression> {
+        None
+    }
 }
 
 pub struct AsExpression {
@@ -36,9 +101,25 @@ impl Expression for AsExpression {
         Ok(Column::new(self.name.clone(), sub.data_type.clone()))
     }
 
-    fn eval(&self, schema: &TableSchema, row: &Vec<MData>) -> Result<MData, EvaluationError> {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
         self.expression.eval(schema, row)
     }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.expression.bind(parameters)
+    }
+
+    fn window(&self) -> Option<&WindowExpression> {
+        self.expression.window()
+    }
+
+    fn aggregate(&self) -> Option<&AggregateExpression> {
+        self.expression.aggregate()
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.expression.references(names)
+    }
 }
 
 #[derive(Debug)]
@@ -53,14 +134,11 @@ impl ReferenceExpression {
 }
 
 impl Expression for ReferenceExpression {
-    fn eval(&self, schema: &TableSchema, row: &Vec<MData>) -> Result<MData, EvaluationError> {
-        match schema
-            .columns
-            .iter()
-            .position(|r| r.name.to_uppercase() == self.name)
-        {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        match schema.columns.iter().position(|r| r.name == self.name) {
             Some(index) => Ok(row.get(index).unwrap().clone()),
             None => Err(EvaluationError {
+                code: sqlstate::UNDEFINED_COLUMN,
                 msg: format!("No such column {}", self.name),
             }),
         }
@@ -71,17 +149,22 @@ impl Expression for ReferenceExpression {
         schema: &TableSchema,
         _index: usize,
     ) -> Result<Column, EvaluationError> {
-        match schema
-            .columns
-            .iter()
-            .find(|c| c.name.to_uppercase() == self.name)
-        {
+        match schema.columns.iter().find(|c| c.name == self.name) {
             Some(column) => Ok(Column::new(self.name.clone(), column.data_type.clone())),
             None => Err(EvaluationError {
+                code: sqlstate::UNDEFINED_COLUMN,
                 msg: format!("No such column {}", self.name),
             }),
         }
     }
+
+    fn reference(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        names.push(self.name.clone())
+    }
 }
 
 #[derive(Debug)]
@@ -96,7 +179,7 @@ impl<T> LeafExpression<T> {
 }
 
 impl Expression for LeafExpression<i32> {
-    fn eval(&self, _schema: &TableSchema, _row: &Vec<MData>) -> Result<MData, EvaluationError> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
         Ok(MData::Integer(self.data))
     }
 
@@ -109,23 +192,228 @@ impl Expression for LeafExpression<i32> {
     }
 }
 
+impl Expression for LeafExpression<i64> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::BigInt(self.data))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::BigInt))
+    }
+}
+
+impl Expression for LeafExpression<f64> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::Float(self.data))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Float))
+    }
+}
+
+/// Literals of types without a rust counterpart, e.g. `DATE '2024-01-01'`
+impl Expression for LeafExpression<MData> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(self.data.clone())
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(
+            format!("column_{}", index),
+            self.data.matcher(),
+        ))
+    }
+}
+
+/// `NOW()` or `CURRENT_TIMESTAMP`. The time is read when the expression is parsed, so every
+/// row of a statement gets the same timestamp.
+pub struct NowExpression {
+    name: String,
+    timestamp: i64,
+}
+
+impl NowExpression {
+    pub fn new(name: String) -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock is before 1970");
+        NowExpression {
+            name,
+            timestamp: since_epoch.as_micros() as i64,
+        }
+    }
+}
+
+impl Expression for NowExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::Timestamp(self.timestamp))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        _index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(self.name.clone(), MDataType::Timestamp))
+    }
+}
+
+impl Expression for LeafExpression<bool> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::Bool(self.data))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+}
+
+impl Expression for LeafExpression<String> {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::Varchar(self.data.clone()))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Varchar))
+    }
+}
+
+#[derive(Debug)]
+pub struct NullExpression {}
+
+impl Expression for NullExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Ok(MData::Null)
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Null))
+    }
+}
+
+/// Placeholder for a query parameter, which gets its value when parameters are bound
+#[derive(Debug)]
+pub struct ParameterExpression {
+    // Index of the parameter, starting from 1 as in $1
+    index: usize,
+    value: Option<MData>,
+}
+
+impl ParameterExpression {
+    pub fn new(index: usize) -> Self {
+        Self { index, value: None }
+    }
+}
+
+impl Expression for ParameterExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        match &self.value {
+            Some(value) => Ok(value.clone()),
+            None => Err(EvaluationError {
+                code: sqlstate::UNDEFINED_PARAMETER,
+                msg: format!("Parameter ${} is not bound", self.index),
+            }),
+        }
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        match &self.value {
+            Some(value) => Ok(Column::new(format!("column_{}", index), value.matcher())),
+            None => Err(EvaluationError {
+                code: sqlstate::UNDEFINED_PARAMETER,
+                msg: format!("Parameter ${} is not bound", self.index),
+            }),
+        }
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        match self
+            .index
+            .checked_sub(1)
+            .and_then(|index| parameters.get(index))
+        {
+            Some(value) => {
+                self.value = Some(value.clone());
+                Ok(())
+            }
+            None => Err(EvaluationError {
+                code: sqlstate::UNDEFINED_PARAMETER,
+                msg: format!(
+                    "No value for parameter ${}, {} parameters given",
+                    self.index,
+                    parameters.len()
+                ),
+            }),
+        }
+    }
+}
+
 pub struct NegateExpression {
     pub expression: Box<dyn Expression>,
 }
 
 impl Expression for NegateExpression {
-    fn eval(&self, schema: &TableSchema, row: &Vec<MData>) -> Result<MData, EvaluationError> {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
         let val = self.expression.eval(schema, row)?;
         match val {
-            MData::Null => todo!(),
-            MData::Integer(v) => Ok(MData::Integer(-v)),
-            MData::Varchar(_) => todo!(),
+            MData::Null => Ok(MData::Null),
+            MData::Integer(v) => v.checked_neg().map(MData::Integer).ok_or(EvaluationError {
+                code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+                msg: String::from("integer out of range"),
+            }),
+            MData::BigInt(v) => v.checked_neg().map(MData::BigInt).ok_or(EvaluationError {
+                code: sqlstate::NUMERIC_VALUE_OUT_OF_RANGE,
+                msg: String::from("integer out of range"),
+            }),
+            MData::Float(v) => Ok(MData::Float(-v)),
+            MData::Decimal(v, scale) => Ok(MData::Decimal(-v, scale)),
+            value => Err(EvaluationError {
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: format!("Can't negate {:?}", value),
+            }),
         }
     }
 
     fn schema_column(&self, schema: &TableSchema, index: usize) -> Result<Column, EvaluationError> {
         self.expression.schema_column(schema, index)
     }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.expression.bind(parameters)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.expression.references(names)
+    }
 }
 
 #[derive(Debug)]
@@ -141,7 +429,7 @@ pub struct OperationExpression {
 }
 
 impl Expression for OperationExpression {
-    fn eval(&self, schema: &TableSchema, row: &Vec<MData>) -> Result<MData, EvaluationError> {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
         let l = self.left.eval(schema, row)?;
         let r = self.right.eval(schema, row)?;
         match self.operation {
@@ -150,12 +438,664 @@ impl Expression for OperationExpression {
         }
     }
 
+    fn schema_column(&self, schema: &TableSchema, index: usize) -> Result<Column, EvaluationError> {
+        // Arithmetic with a float is a float, with a decimal a decimal in the larger scale and
+        // with a big integer a big integer, otherwise the operands are integers
+        let left = self.left.schema_column(schema, index)?.data_type;
+        let right = self.right.schema_column(schema, index)?.data_type;
+        let data_type = match (left, right) {
+            (MDataType::Float, _) | (_, MDataType::Float) => MDataType::Float,
+            (MDataType::Decimal { scale: left, .. }, MDataType::Decimal { scale: right, .. }) => {
+                MDataType::Decimal {
+                    precision: MAX_PRECISION,
+                    scale: left.max(right),
+                }
+            }
+            (MDataType::Decimal { scale, .. }, _) | (_, MDataType::Decimal { scale, .. }) => {
+                MDataType::Decimal {
+                    precision: MAX_PRECISION,
+                    scale,
+                }
+            }
+            (MDataType::BigInt, _) | (_, MDataType::BigInt) => MDataType::BigInt,
+            _ => MDataType::Integer,
+        };
+        Ok(Column::new(format!("column_{}", index), data_type))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.left.bind(parameters)?;
+        self.right.bind(parameters)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.left.references(names);
+        self.right.references(names)
+    }
+}
+
+/// Truth value of a condition in SQL's three-valued logic, where NULL is unknown
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl Truth {
+    /// Truth of an evaluated condition. Only booleans and NULL are conditions.
+    pub fn from_mdata(value: &MData) -> Result<Self, EvaluationError> {
+        match value {
+            MData::Bool(true) => Ok(Truth::True),
+            MData::Bool(false) => Ok(Truth::False),
+            MData::Null => Ok(Truth::Unknown),
+            value => Err(EvaluationError {
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: format!("{:?} is not a boolean", value),
+            }),
+        }
+    }
+
+    /// Unknown is represented as NULL in results
+    pub fn to_mdata(self) -> MData {
+        match self {
+            Truth::True => MData::Bool(true),
+            Truth::False => MData::Bool(false),
+            Truth::Unknown => MData::Null,
+        }
+    }
+
+    pub fn and(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::False, _) | (_, Truth::False) => Truth::False,
+            (Truth::True, Truth::True) => Truth::True,
+            _ => Truth::Unknown,
+        }
+    }
+
+    pub fn or(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::True, _) | (_, Truth::True) => Truth::True,
+            (Truth::False, Truth::False) => Truth::False,
+            _ => Truth::Unknown,
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Truth {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(value: bool) -> Self {
+        match value {
+            true => Truth::True,
+            false => Truth::False,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl Comparison {
+    /// Comparison with its operands swapped, e.g. `<` for `>` as `1 > a` is `a < 1`
+    pub fn flipped(&self) -> Comparison {
+        match self {
+            Comparison::Equal => Comparison::Equal,
+            Comparison::NotEqual => Comparison::NotEqual,
+            Comparison::LessThan => Comparison::GreaterThan,
+            Comparison::LessThanOrEqual => Comparison::GreaterThanOrEqual,
+            Comparison::GreaterThan => Comparison::LessThan,
+            Comparison::GreaterThanOrEqual => Comparison::LessThanOrEqual,
+        }
+    }
+
+    /// Compares two values. Comparison with NULL is unknown.
+    pub fn compare(&self, left: &MData, right: &MData) -> Result<Truth, EvaluationError> {
+        let ordering = match (left, right) {
+            (MData::Null, _) | (_, MData::Null) => return Ok(Truth::Unknown),
+            (MData::Integer(l), MData::Integer(r)) => l.cmp(r),
+            (MData::Varchar(l), MData::Varchar(r)) => l.cmp(r),
+            (MData::Bool(l), MData::Bool(r)) => l.cmp(r),
+            (MData::Date(l), MData::Date(r)) => l.cmp(r),
+            (MData::Timestamp(l), MData::Timestamp(r)) => l.cmp(r),
+            (MData::Bytes(l), MData::Bytes(r)) => l.cmp(r),
+            (MData::Integer(_) | MData::BigInt(_), MData::Integer(_) | MData::BigInt(_)) => {
+                left.as_bigint().unwrap().cmp(&right.as_bigint().unwrap())
+            }
+            (
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..),
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..),
+            ) => decimal::compare(left.as_decimal().unwrap(), right.as_decimal().unwrap()),
+            (
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+                MData::Integer(_) | MData::BigInt(_) | MData::Decimal(..) | MData::Float(_),
+            ) => {
+                let (l, r) = (left.as_float().unwrap(), right.as_float().unwrap());
+                l.total_cmp(&r)
+            }
+            (left, right) => {
+                return Err(EvaluationError {
+                    code: sqlstate::DATATYPE_MISMATCH,
+                    msg: format!("Can't compare {:?} and {:?}", left, right),
+                })
+            }
+        };
+        Ok(Truth::from(match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::LessThan => ordering == Ordering::Less,
+            Comparison::LessThanOrEqual => ordering != Ordering::Greater,
+            Comparison::GreaterThan => ordering == Ordering::Greater,
+            Comparison::GreaterThanOrEqual => ordering != Ordering::Less,
+        }))
+    }
+
+    /// Compares row values element by element. Rows are equal when all elements are,
+    /// and ordered by the first elements that differ, so `(a, b) < (c, d)` is
+    /// `a < c OR (a = c AND b < d)`.
+    pub fn compare_rows(&self, left: &[MData], right: &[MData]) -> Result<Truth, EvaluationError> {
+        if left.len() != right.len() {
+            return Err(EvaluationError {
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: format!(
+                    "Can't compare rows of {} and {} values",
+                    left.len(),
+                    right.len()
+                ),
+            });
+        }
+        let (strict, or_equal) = match self {
+            Comparison::Equal => {
+                let mut equal = Truth::True;
+                for (l, r) in left.iter().zip(right.iter()) {
+                    equal = equal.and(Comparison::Equal.compare(l, r)?);
+                }
+                return Ok(equal);
+            }
+            Comparison::NotEqual => return Ok(Comparison::Equal.compare_rows(left, right)?.not()),
+            Comparison::LessThan => (Comparison::LessThan, false),
+            Comparison::LessThanOrEqual => (Comparison::LessThan, true),
+            Comparison::GreaterThan => (Comparison::GreaterThan, false),
+            Comparison::GreaterThanOrEqual => (Comparison::GreaterThan, true),
+        };
+        let mut result = Truth::False;
+        // Whether all elements so far are equal
+        let mut equal = Truth::True;
+        for (l, r) in left.iter().zip(right.iter()) {
+            result = result.or(equal.and(strict.compare(l, r)?));
+            equal = equal.and(Comparison::Equal.compare(l, r)?);
+        }
+        Ok(match or_equal {
+            true => result.or(equal),
+            false => result,
+        })
+    }
+
+    /// Compares two expressions, which are either both single values or both row values
+    fn evaluate(
+        &self,
+        left: &dyn Expression,
+        right: &dyn Expression,
+        schema: &TableSchema,
+        row: &[MData],
+    ) -> Result<Truth, EvaluationError> {
+        match (left.row_value(), right.row_value()) {
+            (None, None) => self.compare(&left.eval(schema, row)?, &right.eval(schema, row)?),
+            (Some(l), Some(r)) => self.compare_rows(
+                &l.eval_elements(schema, row)?,
+                &r.eval_elements(schema, row)?,
+            ),
+            _ => Err(EvaluationError {
+                code: sqlstate::DATATYPE_MISMATCH,
+                msg: String::from("Can't compare a row value with a single value"),
+            }),
+        }
+    }
+}
+
+/// Row value constructor, e.g. `(a, b)` in `(a, b) = (1, 'x')`
+pub struct RowExpression {
+    pub elements: Vec<Box<dyn Expression>>,
+}
+
+impl RowExpression {
+    pub fn eval_elements(
+        &self,
+        schema: &TableSchema,
+        row: &[MData],
+    ) -> Result<Vec<MData>, EvaluationError> {
+        self.elements
+            .iter()
+            .map(|element| element.eval(schema, row))
+            .collect()
+    }
+}
+
+impl Expression for RowExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Err(EvaluationError {
+            code: sqlstate::SYNTAX_ERROR,
+            msg: String::from("Row value can only be used in a comparison"),
+        })
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        _index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Err(EvaluationError {
+            code: sqlstate::SYNTAX_ERROR,
+            msg: String::from("Row value can only be used in a comparison"),
+        })
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        for element in self.elements.iter_mut() {
+            element.bind(parameters)?;
+        }
+        Ok(())
+    }
+
+    fn row_value(&self) -> Option<&RowExpression> {
+        Some(self)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        for element in self.elements.iter() {
+            element.references(names);
+        }
+    }
+}
+
+pub struct ComparisonExpression {
+    pub comparison: Comparison,
+    pub left: Box<dyn Expression>,
+    pub right: Box<dyn Expression>,
+}
+
+impl Expression for ComparisonExpression {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        let truth =
+            self.comparison
+                .evaluate(self.left.as_ref(), self.right.as_ref(), schema, row)?;
+        Ok(truth.to_mdata())
+    }
+
     fn schema_column(
         &self,
         _schema: &TableSchema,
         index: usize,
     ) -> Result<Column, EvaluationError> {
-        // TODO: this is absolutely not correct
-        Ok(Column::new(format!("column_{}", index), MDataType::Integer))
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.left.bind(parameters)?;
+        self.right.bind(parameters)
+    }
+
+    fn comparison(&self) -> Option<&ComparisonExpression> {
+        Some(self)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.left.references(names);
+        self.right.references(names)
+    }
+}
+
+#[derive(Debug)]
+pub enum Logical {
+    And,
+    Or,
+}
+
+/// AND and OR, evaluated in three-valued logic
+pub struct LogicalExpression {
+    pub logical: Logical,
+    pub left: Box<dyn Expression>,
+    pub right: Box<dyn Expression>,
+}
+
+impl Expression for LogicalExpression {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        let l = Truth::from_mdata(&self.left.eval(schema, row)?)?;
+        let r = Truth::from_mdata(&self.right.eval(schema, row)?)?;
+        Ok(match self.logical {
+            Logical::And => l.and(r),
+            Logical::Or => l.or(r),
+        }
+        .to_mdata())
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.left.bind(parameters)?;
+        self.right.bind(parameters)
+    }
+
+    fn logical(&self) -> Option<&LogicalExpression> {
+        Some(self)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.left.references(names);
+        self.right.references(names)
+    }
+}
+
+pub struct NotExpression {
+    pub expression: Box<dyn Expression>,
+}
+
+impl Expression for NotExpression {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        let value = self.expression.eval(schema, row)?;
+        Ok(Truth::from_mdata(&value)?.not().to_mdata())
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.expression.bind(parameters)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.expression.references(names)
+    }
+}
+
+/// `expression [NOT] IN (value, ...)`, which is true when the expression equals any of the
+/// values. Like comparisons it is unknown when no value matches but some are NULL.
+pub struct InExpression {
+    pub expression: Box<dyn Expression>,
+    pub list: Vec<Box<dyn Expression>>,
+    pub negated: bool,
+}
+
+impl Expression for InExpression {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        let mut found = Truth::False;
+        for value in self.list.iter() {
+            found = found.or(Comparison::Equal.evaluate(
+                self.expression.as_ref(),
+                value.as_ref(),
+                schema,
+                row,
+            )?);
+        }
+        Ok(match self.negated {
+            true => found.not(),
+            false => found,
+        }
+        .to_mdata())
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.expression.bind(parameters)?;
+        for value in self.list.iter_mut() {
+            value.bind(parameters)?;
+        }
+        Ok(())
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.expression.references(names);
+        for value in self.list.iter() {
+            value.references(names);
+        }
+    }
+}
+
+/// `expression IS [NOT] NULL`, which unlike comparisons is never unknown
+pub struct IsNullExpression {
+    pub expression: Box<dyn Expression>,
+    pub negated: bool,
+}
+
+impl Expression for IsNullExpression {
+    fn eval(&self, schema: &TableSchema, row: &[MData]) -> Result<MData, EvaluationError> {
+        let is_null = self.expression.eval(schema, row)? == MData::Null;
+        Ok(MData::Bool(is_null != self.negated))
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(format!("column_{}", index), MDataType::Bool))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        self.expression.bind(parameters)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        self.expression.references(names)
+    }
+}
+
+/// Function returning rows, used in FROM like a table
+#[derive(Debug, PartialEq)]
+pub enum TableFunction {
+    GenerateSeries,
+}
+
+impl TableFunction {
+    /// Table function by its name in sql
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "GENERATE_SERIES" => Some(TableFunction::GenerateSeries),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            TableFunction::GenerateSeries => "generate_series",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WindowFunction {
+    RowNumber,
+}
+
+impl WindowFunction {
+    /// Window function by its name in sql
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "ROW_NUMBER" => Some(WindowFunction::RowNumber),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            WindowFunction::RowNumber => "row_number",
+        }
+    }
+
+    /// Value of this function for the row at given position of the window ordering
+    pub fn value(&self, position: usize) -> MData {
+        match self {
+            WindowFunction::RowNumber => MData::Integer(position as i32 + 1),
+        }
+    }
+}
+
+/// Window function call `function() OVER (ORDER BY ...)`
+pub struct WindowExpression {
+    pub function: WindowFunction,
+    pub order_by: Vec<OrderBy>,
+}
+
+impl Expression for WindowExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Err(EvaluationError {
+            code: sqlstate::SYNTAX_ERROR,
+            msg: format!(
+                "Window function {} can only be used as a select column",
+                self.function.name()
+            ),
+        })
+    }
+
+    fn schema_column(
+        &self,
+        _schema: &TableSchema,
+        _index: usize,
+    ) -> Result<Column, EvaluationError> {
+        Ok(Column::new(
+            self.function.name().to_owned(),
+            MDataType::Integer,
+        ))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        for order_by in self.order_by.iter_mut() {
+            order_by.expression.bind(parameters)?;
+        }
+        Ok(())
+    }
+
+    fn window(&self) -> Option<&WindowExpression> {
+        Some(self)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        for order_by in self.order_by.iter() {
+            order_by.expression.references(names);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    /// Concatenates varchar values with the separator in between
+    StringAgg(String),
+}
+
+impl AggregateFunction {
+    /// Aggregate function by its name in sql. Separator of string aggregation is
+    /// a separate argument and this gives the default, which is a comma.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            "STRING_AGG" | "GROUP_CONCAT" => Some(AggregateFunction::StringAgg(String::from(","))),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+            AggregateFunction::StringAgg(_) => "string_agg",
+        }
+    }
+}
+
+/// Aggregate function call, e.g `COUNT(*)`, `COUNT(DISTINCT id)` or `STRING_AGG(name, ', ')`
+pub struct AggregateExpression {
+    pub function: AggregateFunction,
+    // None for COUNT(*)
+    pub argument: Option<Box<dyn Expression>>,
+    /// Aggregates each distinct value once, as in `COUNT(DISTINCT name)`
+    pub distinct: bool,
+}
+
+impl Expression for AggregateExpression {
+    fn eval(&self, _schema: &TableSchema, _row: &[MData]) -> Result<MData, EvaluationError> {
+        Err(EvaluationError {
+            code: sqlstate::SYNTAX_ERROR,
+            msg: format!(
+                "Aggregate function {} can only be used as a select column",
+                self.function.name()
+            ),
+        })
+    }
+
+    fn schema_column(&self, schema: &TableSchema, index: usize) -> Result<Column, EvaluationError> {
+        let data_type = match (&self.function, &self.argument) {
+            (
+                AggregateFunction::Min | AggregateFunction::Max | AggregateFunction::Sum,
+                Some(argument),
+            ) => match (
+                &self.function,
+                argument.schema_column(schema, index)?.data_type,
+            ) {
+                // Integers are summed as big integers, so that sums of large tables fit
+                (AggregateFunction::Sum, MDataType::Integer) => MDataType::BigInt,
+                (_, data_type) => data_type,
+            },
+            (AggregateFunction::StringAgg(_), _) => MDataType::Varchar,
+            _ => MDataType::Integer,
+        };
+        Ok(Column::new(self.function.name().to_owned(), data_type))
+    }
+
+    fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        match &mut self.argument {
+            Some(argument) => argument.bind(parameters),
+            None => Ok(()),
+        }
+    }
+
+    fn aggregate(&self) -> Option<&AggregateExpression> {
+        Some(self)
+    }
+
+    fn references(&self, names: &mut Vec<String>) {
+        if let Some(argument) = &self.argument {
+            argument.references(names);
+        }
     }
 }
@@ -1,21 +1,59 @@
 use std::fmt::Display;
 
 /// Tokens available for parser
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     SHOW,
+    SET,
     TABLES,
+    COLUMNS,
+    DESCRIBE,
+    EXPLAIN,
+    CHECKPOINT,
+    ANALYZE,
 
     CREATE,
     TABLE,
+    INDEX,
+    ON,
     VALUES,
+    COPY,
+    STDIN,
+    IMPORT,
 
     SELECT,
     INSERT,
+    INTO,
+    OUTFILE,
+    RETURNING,
     UPDATE,
     DELETE,
     FROM,
+    WHERE,
+    DISTINCT,
     AS,
+    ORDER,
+    BY,
+    ASC,
+    DESC,
+    NULLS,
+    FIRST,
+    LAST,
+    COLLATE,
+    LIMIT,
+    OFFSET,
+    FETCH,
+    NEXT,
+    ROW,
+    ROWS,
+    ONLY,
+    OVER,
+    GROUP,
+    GROUPING,
+    SETS,
+    ROLLUP,
+    CUBE,
 
     COMMA,
     LPARENS,
@@ -25,16 +63,71 @@ pub enum Token {
     MULTIPLICATION,
     DIVISION,
 
+    TRUE,
+    FALSE,
+    NULL,
+
+    AND,
+    OR,
+    NOT,
+    IS,
+    IN,
+    EQUALS,
+    NOTEQUALS,
+    LESSTHAN,
+    LESSOREQUAL,
+    GREATERTHAN,
+    GREATEROREQUAL,
+
     STRING(String),
+    // Hex string literal, x'DEADBEEF'
+    BYTES(Vec<u8>),
     // Dunno, if this should be signed or unsigned
-    INTEGER(i32),
-    FLOAT(f32),
+    INTEGER(i64),
+    FLOAT(f64),
 
     IDENTIFIER(String),
+    // Placeholder for a query parameter, $1 or ?, numbered from 1
+    PARAMETER(usize),
 
     TERMINATE,
 }
 
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::COMMA => write!(f, ","),
+            Token::LPARENS => write!(f, "("),
+            Token::RPARENS => write!(f, ")"),
+            Token::PLUS => write!(f, "+"),
+            Token::MINUS => write!(f, "-"),
+            Token::MULTIPLICATION => write!(f, "*"),
+            Token::DIVISION => write!(f, "/"),
+            Token::EQUALS => write!(f, "="),
+            Token::NOTEQUALS => write!(f, "<>"),
+            Token::LESSTHAN => write!(f, "<"),
+            Token::LESSOREQUAL => write!(f, "<="),
+            Token::GREATERTHAN => write!(f, ">"),
+            Token::GREATEROREQUAL => write!(f, ">="),
+            Token::TERMINATE => write!(f, ";"),
+            Token::STRING(value) => write!(f, "'{}'", value),
+            Token::BYTES(value) => {
+                write!(f, "x'")?;
+                for byte in value {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+            Token::INTEGER(value) => write!(f, "{}", value),
+            Token::FLOAT(value) => write!(f, "{}", value),
+            Token::IDENTIFIER(value) => write!(f, "{}", value),
+            Token::PARAMETER(index) => write!(f, "${}", index),
+            // Keywords are named as they are written in sql
+            keyword => write!(f, "{:?}", keyword),
+        }
+    }
+}
+
 /// Stateful lexer instance for lexing a piece od SQL.
 ///
 /// Note that the lexer will panic of next() is called on fully
@@ -46,6 +139,74 @@ pub enum Token {
 pub struct Lexer {
     current_position: usize,
     tokens: Vec<Token>,
+    // Where each token starts in the input
+    positions: Vec<SourceRef>,
+    case_folding: CaseFolding,
+}
+
+/// Position in the SQL input, both line and column starting from 1
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SourceRef {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceRef {
+    fn start() -> Self {
+        SourceRef { line: 1, column: 1 }
+    }
+
+    /// Position of the character after given one
+    fn advance(&self, char: char) -> Self {
+        match char {
+            '\n' => SourceRef {
+                line: self.line + 1,
+                column: 1,
+            },
+            _ => SourceRef {
+                line: self.line,
+                column: self.column + 1,
+            },
+        }
+    }
+}
+
+impl Display for SourceRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} column {}", self.line, self.column)
+    }
+}
+
+/// Options altering how the input is lexed
+#[derive(Debug, Default, Clone)]
+pub struct LexerOptions {
+    /// Allows backslash escapes in string literals, e.g. 'O\'Brien' or 'line\n'.
+    /// Doubled single quotes ('O''Brien') are always accepted.
+    pub backslash_escapes: bool,
+    pub case_folding: CaseFolding,
+}
+
+/// How the case of unquoted identifiers is folded. Quoted identifiers keep their case, so
+/// `"Foo"` names a different table than `Foo`, which is the same as `foo` and `FOO`.
+///
+/// The catalog compares names exactly, so names the server creates itself are folded with
+/// the same policy to be found by unquoted identifiers.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum CaseFolding {
+    /// Folds to lower case, as PostgreSQL does
+    #[default]
+    Lower,
+    /// Folds to upper case, as the SQL standard says
+    Upper,
+}
+
+impl CaseFolding {
+    pub fn fold(&self, identifier: &str) -> String {
+        match self {
+            CaseFolding::Lower => identifier.to_lowercase(),
+            CaseFolding::Upper => identifier.to_uppercase(),
+        }
+    }
 }
 
 impl Lexer {
@@ -53,27 +214,52 @@ impl Lexer {
     ///
     /// Lexing happens eagerly and thus this returns a Result.
     pub fn with_input(sql: String) -> Result<Self, LexingError> {
+        Self::with_options(sql, LexerOptions::default())
+    }
+
+    /// Creates a new lexer instance with given input and options.
+    pub fn with_options(sql: String, options: LexerOptions) -> Result<Self, LexingError> {
+        let case_folding = options.case_folding;
         let mut tokens = vec![];
-        let mut buffer = buffer::LexerBuffer::new();
+        let mut positions = vec![];
+        let mut buffer = buffer::LexerBuffer::new(options);
         let mut chars = sql.chars().peekable();
+        let mut position = SourceRef::start();
         while let Some(char) = chars.next() {
-            if let Some(token) = buffer.push_char(char, chars.peek()) {
-                tokens.push(token?)
+            match buffer.push_char(char, chars.peek(), position) {
+                Some(Ok((token, start))) => {
+                    tokens.push(token);
+                    positions.push(start);
+                }
+                Some(Err(mut err)) => {
+                    err.position = Some(buffer.token_start());
+                    return Err(err);
+                }
+                None => {}
             }
+            position = position.advance(char);
         }
-        if tokens.len() == 0 {
+        if tokens.is_empty() {
             return Err(LexingError::new(LexingErrorKind::NoTokens));
         }
         Ok(Lexer {
             tokens,
+            positions,
             current_position: 0,
+            case_folding,
         })
     }
 
+    /// Folds a name the server gives, like a default column name, as identifiers are folded
+    pub fn fold(&self, name: &str) -> String {
+        self.case_folding.fold(name)
+    }
+
     /// Returns a reference to the next token.
     ///
     /// Panics if lexer is consumed, thus use has_next to check if there
     /// actually is a next token.
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> &Token {
         let position = self.current_position;
         self.current_position += 1;
@@ -83,8 +269,6 @@ impl Lexer {
     }
 
     /// Checks if lexer has more tokens
-    /// This has tests but it's not used right now
-    #[allow(dead_code)]
     pub fn has_next(&self) -> bool {
         self.current_position < self.tokens.len()
     }
@@ -94,6 +278,25 @@ impl Lexer {
         self.tokens.get(self.current_position)
     }
 
+    /// Token last returned by next()
+    pub fn last(&self) -> Option<&Token> {
+        self.current_position
+            .checked_sub(1)
+            .and_then(|index| self.tokens.get(index))
+    }
+
+    /// Position of the token last returned by next(), or of the first token if
+    /// nothing has been consumed yet
+    pub fn position(&self) -> SourceRef {
+        let index = self.current_position.saturating_sub(1);
+        self.positions[index.min(self.positions.len() - 1)]
+    }
+
+    /// Position of the token peek() returns, or of the last token if lexer is consumed
+    pub fn peek_position(&self) -> SourceRef {
+        self.positions[self.current_position.min(self.positions.len() - 1)]
+    }
+
     pub fn peek_is(&self, expected: &Token) -> bool {
         match self.peek() {
             Some(token) => token == expected,
@@ -106,6 +309,7 @@ impl Lexer {
             Token::IDENTIFIER(value) => Ok(value.to_owned()),
             _ => Err(LexingError {
                 kind: LexingErrorKind::ExpectingIdentifier,
+                position: Some(self.position()),
             }),
         }
     }
@@ -115,11 +319,16 @@ impl Lexer {
 #[derive(Debug)]
 pub struct LexingError {
     pub kind: LexingErrorKind,
+    // Where the failing token starts, if there is one
+    pub position: Option<SourceRef>,
 }
 
 impl LexingError {
     fn new(kind: LexingErrorKind) -> Self {
-        Self { kind }
+        Self {
+            kind,
+            position: None,
+        }
     }
 }
 
@@ -128,7 +337,11 @@ pub enum LexingErrorKind {
     NoTokens,
     NotInteger,
     StringNotTerminated,
+    IdentifierNotTerminated,
     ExpectingIdentifier,
+    InvalidParameter,
+    ParameterOutOfRange,
+    InvalidHex,
 }
 
 impl Display for LexingErrorKind {
@@ -137,7 +350,19 @@ impl Display for LexingErrorKind {
             LexingErrorKind::NoTokens => write!(f, "Lexer is empty"),
             LexingErrorKind::NotInteger => write!(f, "Doesn't look like an integer"),
             LexingErrorKind::StringNotTerminated => write!(f, "String is not terminated"),
+            LexingErrorKind::IdentifierNotTerminated => {
+                write!(f, "Quoted identifier is not terminated")
+            }
             LexingErrorKind::ExpectingIdentifier => write!(f, "Expecting identifier"),
+            LexingErrorKind::InvalidParameter => {
+                write!(f, "Parameter placeholder must be $ followed by a number")
+            }
+            LexingErrorKind::ParameterOutOfRange => {
+                write!(f, "Parameter placeholder number is out of range")
+            }
+            LexingErrorKind::InvalidHex => {
+                write!(f, "Hex string must have an even count of hex digits")
+            }
         }
     }
 }
@@ -151,32 +376,57 @@ mod buffer {
     enum LexingMode {
         Normal,
         String,
+        Hex,
+        QuotedIdentifier,
         Integer,
         Float,
+        Parameter,
     }
 
     pub struct LexerBuffer {
         mode: LexingMode,
         buffer: String,
+        options: LexerOptions,
+        // Set when the previous character in a string started an escape sequence
+        escaping: bool,
+        // Count of ? placeholders so far, used for numbering them
+        positional_parameters: usize,
+        // Position of the first character of the token in the buffer
+        start: SourceRef,
     }
 
     impl LexerBuffer {
         /// Creates a new LexerBuffer instance
-        pub fn new() -> Self {
+        pub fn new(options: LexerOptions) -> Self {
             Self {
                 buffer: String::new(),
                 mode: LexingMode::Normal,
+                options,
+                escaping: false,
+                positional_parameters: 0,
+                start: SourceRef::start(),
             }
         }
 
-        /// Pushes a new character to the buffer. Returns None if there is no ready token.
+        /// Position where the token being lexed starts
+        pub fn token_start(&self) -> SourceRef {
+            self.start
+        }
+
+        /// Pushes a new character at given position to the buffer. Returns None if there
+        /// is no ready token, otherwise the token and the position where it starts.
         ///
         /// Note that Some value is a Result as there might be an error during lexing.
         pub fn push_char(
             &mut self,
             char: char,
             peek: Option<&char>,
-        ) -> Option<Result<Token, LexingError>> {
+            position: SourceRef,
+        ) -> Option<Result<(Token, SourceRef), LexingError>> {
+            // Nothing is buffered, so this character starts a new token
+            if self.mode == LexingMode::Normal && self.buffer.is_empty() {
+                self.start = position;
+            }
             // Toggle integer mode if char is digit, current lexing mode is normal and buffer is empty
             // This allows digits inside identifiers, but identifier can't start with a digit
             if char.is_numeric() && self.mode == LexingMode::Normal && self.buffer.is_empty() {
@@ -185,10 +435,34 @@ mod buffer {
             if char == '.' && self.mode == LexingMode::Integer {
                 self.mode = LexingMode::Float;
             }
-            if char == '\'' && self.mode != LexingMode::String {
+            // x'...' is a hex string, not an identifier followed by a string
+            if char == '\''
+                && self.mode == LexingMode::Normal
+                && self.buffer.eq_ignore_ascii_case("x")
+            {
+                self.buffer.clear();
+                self.mode = LexingMode::Hex;
+                return None;
+            }
+            if char == '\''
+                && self.mode != LexingMode::String
+                && self.mode != LexingMode::Hex
+                && self.mode != LexingMode::QuotedIdentifier
+            {
                 self.mode = LexingMode::String;
                 return None;
             }
+            if char == '"' && self.mode == LexingMode::Normal && self.buffer.is_empty() {
+                self.mode = LexingMode::QuotedIdentifier;
+                return None;
+            }
+            if char == '$' && self.mode == LexingMode::Normal && self.buffer.is_empty() {
+                self.mode = LexingMode::Parameter;
+                if !peek.is_some_and(|c| c.is_ascii_digit()) {
+                    return Some(Err(LexingError::new(LexingErrorKind::InvalidParameter)));
+                }
+                return None;
+            }
             match self.mode {
                 LexingMode::Normal => {
                     if char.is_whitespace() {
@@ -196,10 +470,19 @@ mod buffer {
                     }
                     self.buffer.push(char);
                     if self.is_delimiting(Some(&char)) {
-                        return Some(Ok(self.pop_token()));
+                        // Wait for the second character of <=, >=, <> and !=
+                        if self.buffer.len() == 1
+                            && matches!(
+                                (char, peek),
+                                ('<', Some('=' | '>')) | ('>' | '!', Some('='))
+                            )
+                        {
+                            return None;
+                        }
+                        return Some(Ok((self.pop_token(), self.start)));
                     }
                     match self.is_delimiting(peek) {
-                        true => Some(Ok(self.pop_token())),
+                        true => Some(Ok((self.pop_token(), self.start))),
                         false => None,
                     }
                 }
@@ -209,7 +492,7 @@ mod buffer {
                     }
                     self.buffer.push(char);
                     match self.is_delimiting(peek) {
-                        true => Some(Ok(self.pop_token())),
+                        true => Some(Ok((self.pop_token(), self.start))),
                         false => None,
                     }
                 }
@@ -219,14 +502,73 @@ mod buffer {
                     }
                     self.buffer.push(char);
                     match self.is_delimiting(peek) {
-                        true => Some(Ok(self.pop_token())),
+                        true => Some(Ok((self.pop_token(), self.start))),
                         false => None,
                     }
                 }
+                LexingMode::Parameter => {
+                    if !char.is_ascii_digit() {
+                        return Some(Err(LexingError::new(LexingErrorKind::InvalidParameter)));
+                    }
+                    self.buffer.push(char);
+                    if !self.is_delimiting(peek) {
+                        return None;
+                    }
+                    if self.buffer.parse::<usize>().is_err() {
+                        return Some(Err(LexingError::new(LexingErrorKind::ParameterOutOfRange)));
+                    }
+                    Some(Ok((self.pop_token(), self.start)))
+                }
+                LexingMode::QuotedIdentifier => {
+                    // Doubled quote is an escaped quote, not the end of the identifier
+                    if self.escaping {
+                        self.escaping = false;
+                        self.buffer.push(char);
+                        return None;
+                    }
+                    if char == '"' && peek == Some(&'"') {
+                        self.escaping = true;
+                        return None;
+                    }
+                    if char == '"' {
+                        return Some(Ok((self.pop_token(), self.start)));
+                    }
+                    if peek.is_none() {
+                        return Some(Err(LexingError::new(
+                            LexingErrorKind::IdentifierNotTerminated,
+                        )));
+                    }
+                    self.buffer.push(char);
+                    None
+                }
                 LexingMode::String => {
+                    // Previous character was an escape, so this one is taken as is
+                    if self.escaping {
+                        self.escaping = false;
+                        if peek.is_none() {
+                            return Some(Err(LexingError::new(
+                                LexingErrorKind::StringNotTerminated,
+                            )));
+                        }
+                        self.buffer.push(match char {
+                            'n' if self.options.backslash_escapes => '\n',
+                            't' if self.options.backslash_escapes => '\t',
+                            other => other,
+                        });
+                        return None;
+                    }
+                    // Doubled quote is an escaped quote, not the end of the string
+                    if char == '\'' && peek == Some(&'\'') {
+                        self.escaping = true;
+                        return None;
+                    }
+                    if char == '\\' && self.options.backslash_escapes {
+                        self.escaping = true;
+                        return None;
+                    }
                     // The string ends here
                     if char == '\'' {
-                        return Some(Ok(self.pop_token()));
+                        return Some(Ok((self.pop_token(), self.start)));
                     }
                     // Reached the end of input and string is not terminated
                     if peek.is_none() {
@@ -235,6 +577,22 @@ mod buffer {
                     self.buffer.push(char);
                     None
                 }
+                LexingMode::Hex => {
+                    if char == '\'' {
+                        if !self.buffer.len().is_multiple_of(2) {
+                            return Some(Err(LexingError::new(LexingErrorKind::InvalidHex)));
+                        }
+                        return Some(Ok((self.pop_token(), self.start)));
+                    }
+                    if !char.is_ascii_hexdigit() {
+                        return Some(Err(LexingError::new(LexingErrorKind::InvalidHex)));
+                    }
+                    if peek.is_none() {
+                        return Some(Err(LexingError::new(LexingErrorKind::StringNotTerminated)));
+                    }
+                    self.buffer.push(char);
+                    None
+                }
             }
         }
 
@@ -244,17 +602,10 @@ mod buffer {
                 if c.is_whitespace() {
                     return true;
                 }
-                return match c {
-                    ',' => true,
-                    '(' => true,
-                    ')' => true,
-                    '+' => true,
-                    '-' => true,
-                    '*' => true,
-                    '/' => true,
-                    ';' => true,
-                    _ => false,
-                };
+                return matches!(
+                    c,
+                    ',' | '(' | ')' | '+' | '-' | '*' | '/' | ';' | '?' | '=' | '<' | '>' | '!'
+                );
             }
             true
         }
@@ -264,16 +615,61 @@ mod buffer {
             let token = match self.mode {
                 LexingMode::Normal => match self.buffer.to_uppercase().as_str() {
                     "SHOW" => Token::SHOW,
+                    "SET" => Token::SET,
                     "TABLES" => Token::TABLES,
+                    "COLUMNS" => Token::COLUMNS,
+                    "DESCRIBE" => Token::DESCRIBE,
+                    "EXPLAIN" => Token::EXPLAIN,
+                    "CHECKPOINT" => Token::CHECKPOINT,
+                    "ANALYZE" => Token::ANALYZE,
                     "CREATE" => Token::CREATE,
                     "TABLE" => Token::TABLE,
+                    "INDEX" => Token::INDEX,
+                    "ON" => Token::ON,
                     "VALUES" => Token::VALUES,
+                    "COPY" => Token::COPY,
+                    "STDIN" => Token::STDIN,
+                    "IMPORT" => Token::IMPORT,
                     "SELECT" => Token::SELECT,
                     "INSERT" => Token::INSERT,
+                    "INTO" => Token::INTO,
+                    "OUTFILE" => Token::OUTFILE,
+                    "RETURNING" => Token::RETURNING,
                     "UPDATE" => Token::UPDATE,
                     "DELETE" => Token::DELETE,
                     "FROM" => Token::FROM,
+                    "WHERE" => Token::WHERE,
+                    "DISTINCT" => Token::DISTINCT,
                     "AS" => Token::AS,
+                    "ORDER" => Token::ORDER,
+                    "BY" => Token::BY,
+                    "ASC" => Token::ASC,
+                    "DESC" => Token::DESC,
+                    "NULLS" => Token::NULLS,
+                    "FIRST" => Token::FIRST,
+                    "LAST" => Token::LAST,
+                    "COLLATE" => Token::COLLATE,
+                    "LIMIT" => Token::LIMIT,
+                    "OFFSET" => Token::OFFSET,
+                    "FETCH" => Token::FETCH,
+                    "NEXT" => Token::NEXT,
+                    "ROW" => Token::ROW,
+                    "ROWS" => Token::ROWS,
+                    "ONLY" => Token::ONLY,
+                    "OVER" => Token::OVER,
+                    "GROUP" => Token::GROUP,
+                    "GROUPING" => Token::GROUPING,
+                    "SETS" => Token::SETS,
+                    "ROLLUP" => Token::ROLLUP,
+                    "CUBE" => Token::CUBE,
+                    "TRUE" => Token::TRUE,
+                    "FALSE" => Token::FALSE,
+                    "NULL" => Token::NULL,
+                    "AND" => Token::AND,
+                    "OR" => Token::OR,
+                    "NOT" => Token::NOT,
+                    "IS" => Token::IS,
+                    "IN" => Token::IN,
                     "," => Token::COMMA,
                     "(" => Token::LPARENS,
                     ")" => Token::RPARENS,
@@ -281,14 +677,39 @@ mod buffer {
                     "-" => Token::MINUS,
                     "*" => Token::MULTIPLICATION,
                     "/" => Token::DIVISION,
+                    "=" => Token::EQUALS,
+                    "<>" | "!=" => Token::NOTEQUALS,
+                    "<" => Token::LESSTHAN,
+                    "<=" => Token::LESSOREQUAL,
+                    ">" => Token::GREATERTHAN,
+                    ">=" => Token::GREATEROREQUAL,
                     ";" => Token::TERMINATE,
-                    value => Token::IDENTIFIER(value.to_string()),
+                    "?" => {
+                        self.positional_parameters += 1;
+                        Token::PARAMETER(self.positional_parameters)
+                    }
+                    _ => Token::IDENTIFIER(self.options.case_folding.fold(&self.buffer)),
                 },
                 LexingMode::String => Token::STRING(self.buffer.to_owned()),
-                LexingMode::Integer => {
-                    Token::INTEGER(self.buffer.parse().expect("This won't happen"))
-                }
+                LexingMode::Hex => Token::BYTES(
+                    (0..self.buffer.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&self.buffer[i..i + 2], 16))
+                        .collect::<Result<_, _>>()
+                        .expect("Hex digits are checked while lexing"),
+                ),
+                LexingMode::QuotedIdentifier => Token::IDENTIFIER(self.buffer.to_owned()),
+                // Integers too large even for a big integer are read as floats
+                LexingMode::Integer => match self.buffer.parse() {
+                    Ok(value) => Token::INTEGER(value),
+                    Err(_) => Token::FLOAT(self.buffer.parse().expect("This won't happen")),
+                },
                 LexingMode::Float => Token::FLOAT(self.buffer.parse().expect("This won't happen")),
+                LexingMode::Parameter => Token::PARAMETER(
+                    self.buffer
+                        .parse()
+                        .expect("Parameter numbers are checked while lexing"),
+                ),
             };
             self.buffer = String::new();
             self.mode = LexingMode::Normal;
@@ -310,15 +731,40 @@ mod tests {
     macro_rules! assert_lexing {
         ( $s:literal, $( $x:expr ),* ) => {
             {
-                let mut expected_tokens = Vec::new();
-                $(
-                    expected_tokens.push($x);
-                )*
+                let expected_tokens = vec![$($x),*];
                 assert_lexer_test(String::from($s), expected_tokens);
             }
         };
     }
 
+    #[test]
+    fn test_token_positions() {
+        let mut lexer =
+            Lexer::with_input(String::from("select 'x y', id,\n  name from foo")).unwrap();
+        let mut positions = vec![];
+        while lexer.has_next() {
+            lexer.next();
+            let position = lexer.position();
+            positions.push((position.line, position.column));
+        }
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1),
+                (1, 8),
+                (1, 13),
+                (1, 15),
+                (1, 17),
+                (2, 3),
+                (2, 8),
+                (2, 13)
+            ]
+        );
+
+        let error = Lexer::with_input(String::from("select\n 12foo")).unwrap_err();
+        assert_eq!(error.position, Some(SourceRef { line: 2, column: 2 }));
+    }
+
     #[test]
     fn test_lexing_errors() {
         assert_lexer_errors_on!("", LexingErrorKind::NoTokens);
@@ -331,6 +777,19 @@ mod tests {
 
         assert_lexer_errors_on!("'foo", LexingErrorKind::StringNotTerminated);
         assert_lexer_errors_on!("'foo bar", LexingErrorKind::StringNotTerminated);
+        assert_lexer_errors_on!("'foo''", LexingErrorKind::StringNotTerminated);
+
+        assert_lexer_errors_on!("$", LexingErrorKind::InvalidParameter);
+        assert_lexer_errors_on!("$a", LexingErrorKind::InvalidParameter);
+        assert_lexer_errors_on!("$1a", LexingErrorKind::InvalidParameter);
+        assert_lexer_errors_on!(
+            "$999999999999999999999999999",
+            LexingErrorKind::ParameterOutOfRange
+        );
+
+        assert_lexer_errors_on!("x'ABC'", LexingErrorKind::InvalidHex);
+        assert_lexer_errors_on!("x'XY'", LexingErrorKind::InvalidHex);
+        assert_lexer_errors_on!("x'AB", LexingErrorKind::StringNotTerminated);
 
         // TODO: Corner cases
         // assert_lexer_errors_on!("foo'", LexingErrorKind::StringNotTerminated);
@@ -341,6 +800,8 @@ mod tests {
         // Reserved words
         assert_lexing!("show", Token::SHOW);
         assert_lexing!("tables", Token::TABLES);
+        assert_lexing!("columns", Token::COLUMNS);
+        assert_lexing!("describe", Token::DESCRIBE);
         assert_lexing!("select", Token::SELECT);
         assert_lexing!("SELECT", Token::SELECT);
         assert_lexing!("SeLeCt", Token::SELECT);
@@ -356,6 +817,11 @@ mod tests {
         assert_lexing!("from", Token::FROM);
         assert_lexing!("as", Token::AS);
 
+        // Literals
+        assert_lexing!("true", Token::TRUE);
+        assert_lexing!("FALSE", Token::FALSE);
+        assert_lexing!("Null", Token::NULL);
+
         // Dividers
         assert_lexing!(",", Token::COMMA);
 
@@ -371,6 +837,8 @@ mod tests {
         assert_lexing!("1", Token::INTEGER(1));
         assert_lexing!("1234", Token::INTEGER(1234));
         assert_lexing!("666", Token::INTEGER(666));
+        assert_lexing!("9223372036854775807", Token::INTEGER(i64::MAX));
+        assert_lexing!("9223372036854775808", Token::FLOAT(9223372036854775808.0));
 
         // Floats
         assert_lexing!("1.1", Token::FLOAT(1.1));
@@ -382,12 +850,48 @@ mod tests {
         assert_lexing!("''", Token::STRING(String::from("")));
         assert_lexing!("'Foo'", Token::STRING(String::from("Foo")));
         assert_lexing!("'Foo bar'", Token::STRING(String::from("Foo bar")));
+        assert_lexing!("'O''Brien'", Token::STRING(String::from("O'Brien")));
+        assert_lexing!("''''", Token::STRING(String::from("'")));
+        assert_lexing!("'foo'''", Token::STRING(String::from("foo'")));
+        assert_lexing!("'a''''b'", Token::STRING(String::from("a''b")));
+        assert_lexing!("'back\\slash'", Token::STRING(String::from("back\\slash")));
+
+        assert_lexing!("x''", Token::BYTES(vec![]));
+        assert_lexing!("x'DEADbeef'", Token::BYTES(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_lexing!("X'00ff'", Token::BYTES(vec![0x00, 0xff]));
 
         // Identifiers
-        assert_lexing!("foo", Token::IDENTIFIER(String::from("FOO")));
-        assert_lexing!("foo1", Token::IDENTIFIER(String::from("FOO1")));
+        assert_lexing!("foo", Token::IDENTIFIER(String::from("foo")));
+        assert_lexing!("foo1", Token::IDENTIFIER(String::from("foo1")));
 
         assert_lexing!(";", Token::TERMINATE);
+
+        // Parameters
+        assert_lexing!("$1", Token::PARAMETER(1));
+        assert_lexing!("$12", Token::PARAMETER(12));
+        assert_lexing!("?", Token::PARAMETER(1));
+    }
+
+    #[test]
+    fn test_parameters() {
+        assert_lexing!(
+            "select $1, $2+1",
+            Token::SELECT,
+            Token::PARAMETER(1),
+            Token::COMMA,
+            Token::PARAMETER(2),
+            Token::PLUS,
+            Token::INTEGER(1)
+        );
+        assert_lexing!(
+            "select ?,?, '?'",
+            Token::SELECT,
+            Token::PARAMETER(1),
+            Token::COMMA,
+            Token::PARAMETER(2),
+            Token::COMMA,
+            Token::STRING(String::from("?"))
+        );
     }
 
     #[test]
@@ -396,9 +900,9 @@ mod tests {
         assert_lexing!("-1;", Token::MINUS, Token::INTEGER(1), Token::TERMINATE);
         assert_lexing!(
             "foo,bar",
-            Token::IDENTIFIER(String::from("FOO")),
+            Token::IDENTIFIER(String::from("foo")),
             Token::COMMA,
-            Token::IDENTIFIER(String::from("BAR"))
+            Token::IDENTIFIER(String::from("bar"))
         );
 
         assert_lexing!(
@@ -438,21 +942,21 @@ mod tests {
         assert_lexing!(
             "select foo, bar from baz",
             Token::SELECT,
-            Token::IDENTIFIER(String::from("FOO")),
+            Token::IDENTIFIER(String::from("foo")),
             Token::COMMA,
-            Token::IDENTIFIER(String::from("BAR")),
+            Token::IDENTIFIER(String::from("bar")),
             Token::FROM,
-            Token::IDENTIFIER(String::from("BAZ"))
+            Token::IDENTIFIER(String::from("baz"))
         );
         assert_lexing!(
             "SELECT foo",
             Token::SELECT,
-            Token::IDENTIFIER(String::from("FOO"))
+            Token::IDENTIFIER(String::from("foo"))
         );
         assert_lexing!(
             "select fOo",
             Token::SELECT,
-            Token::IDENTIFIER(String::from("FOO"))
+            Token::IDENTIFIER(String::from("foo"))
         );
         assert_lexing!(
             "select 123, 42 as foo",
@@ -461,14 +965,131 @@ mod tests {
             Token::COMMA,
             Token::INTEGER(42),
             Token::AS,
-            Token::IDENTIFIER(String::from("FOO"))
+            Token::IDENTIFIER(String::from("foo"))
         );
     }
 
+    #[test]
+    fn test_escaped_quotes_in_strings() {
+        assert_lexing!(
+            "select 'O''Brien', 'x'",
+            Token::SELECT,
+            Token::STRING(String::from("O'Brien")),
+            Token::COMMA,
+            Token::STRING(String::from("x"))
+        );
+        assert_lexing!(
+            "'it''s';",
+            Token::STRING(String::from("it's")),
+            Token::TERMINATE
+        );
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        assert_lexing!(
+            "select \"Order\", \"my \"\"Col\"\"\" from \"it's\"",
+            Token::SELECT,
+            Token::IDENTIFIER(String::from("Order")),
+            Token::COMMA,
+            Token::IDENTIFIER(String::from("my \"Col\"")),
+            Token::FROM,
+            Token::IDENTIFIER(String::from("it's"))
+        );
+        assert_lexer_errors_on!("\"foo", LexingErrorKind::IdentifierNotTerminated);
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        assert_lexing!(
+            "a=1 and b<>2 or b != 3 and c<=d and not c>=-1 or a<b or a > b is not null",
+            Token::IDENTIFIER(String::from("a")),
+            Token::EQUALS,
+            Token::INTEGER(1),
+            Token::AND,
+            Token::IDENTIFIER(String::from("b")),
+            Token::NOTEQUALS,
+            Token::INTEGER(2),
+            Token::OR,
+            Token::IDENTIFIER(String::from("b")),
+            Token::NOTEQUALS,
+            Token::INTEGER(3),
+            Token::AND,
+            Token::IDENTIFIER(String::from("c")),
+            Token::LESSOREQUAL,
+            Token::IDENTIFIER(String::from("d")),
+            Token::AND,
+            Token::NOT,
+            Token::IDENTIFIER(String::from("c")),
+            Token::GREATEROREQUAL,
+            Token::MINUS,
+            Token::INTEGER(1),
+            Token::OR,
+            Token::IDENTIFIER(String::from("a")),
+            Token::LESSTHAN,
+            Token::IDENTIFIER(String::from("b")),
+            Token::OR,
+            Token::IDENTIFIER(String::from("a")),
+            Token::GREATERTHAN,
+            Token::IDENTIFIER(String::from("b")),
+            Token::IS,
+            Token::NOT,
+            Token::NULL
+        );
+    }
+
+    #[test]
+    fn test_backslash_escapes() {
+        let options = LexerOptions {
+            backslash_escapes: true,
+            ..LexerOptions::default()
+        };
+        let mut lexer =
+            Lexer::with_options(String::from(r"'O\'Brien' 'a\nb' 'c\\d' 'e''f'"), options).unwrap();
+        assert_eq!(lexer.next(), &Token::STRING(String::from("O'Brien")));
+        assert_eq!(lexer.next(), &Token::STRING(String::from("a\nb")));
+        assert_eq!(lexer.next(), &Token::STRING(String::from("c\\d")));
+        assert_eq!(lexer.next(), &Token::STRING(String::from("e'f")));
+
+        let error = Lexer::with_options(
+            String::from(r"'foo\'"),
+            LexerOptions {
+                backslash_escapes: true,
+                ..LexerOptions::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, LexingErrorKind::StringNotTerminated);
+    }
+
     #[test]
     fn test_next_identifier() {
         let mut lexer = Lexer::with_input(String::from("foobar")).expect("No");
-        assert_eq!(lexer.next_identifier().unwrap(), "FOOBAR")
+        assert_eq!(lexer.next_identifier().unwrap(), "foobar")
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let identifiers = |sql: &str, case_folding| {
+            let options = LexerOptions {
+                case_folding,
+                ..LexerOptions::default()
+            };
+            let mut lexer = Lexer::with_options(String::from(sql), options).unwrap();
+            let mut identifiers = vec![];
+            while lexer.has_next() {
+                identifiers.push(lexer.next_identifier().unwrap());
+            }
+            identifiers
+        };
+        assert_eq!(
+            identifiers("Foo FOO \"Foo\"", CaseFolding::Lower),
+            vec!["foo", "foo", "Foo"]
+        );
+        assert_eq!(
+            identifiers("Foo foo \"Foo\"", CaseFolding::Upper),
+            vec!["FOO", "FOO", "Foo"]
+        );
     }
 
     #[test]
@@ -515,13 +1136,12 @@ mod tests {
     }
 
     fn assert_lexer_test(input: String, expected_tokens: Vec<Token>) {
-        let mut lexer = Lexer::with_input(input.clone()).expect(
-            format!(
+        let mut lexer = Lexer::with_input(input.clone()).unwrap_or_else(|_| {
+            panic!(
                 "Could not construct lexer from given input: '{}'. Error: ",
                 input.clone()
             )
-            .as_str(),
-        );
+        });
         let expected_token_count = expected_tokens.len().to_owned();
         for (position, expected_token) in expected_tokens.into_iter().enumerate() {
             assert_eq!(
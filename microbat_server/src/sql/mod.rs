@@ -1,3 +1,4 @@
 pub mod expression;
-mod lexer;
+pub mod lexer;
+pub mod normalize;
 pub mod parser;
@@ -0,0 +1,45 @@
+use super::lexer::{Lexer, Token};
+
+/// Fingerprint of a statement for grouping statistics by the shape of the query.
+///
+/// The fingerprint is the statement's tokens with literals and parameters replaced by `?`,
+/// so statements differing only in their values, casing or whitespace share a fingerprint.
+/// Returns None if the statement can't be lexed.
+pub fn fingerprint(sql: &str) -> Option<String> {
+    let mut lexer = Lexer::with_input(sql.to_owned()).ok()?;
+    let mut parts = vec![];
+    while lexer.has_next() {
+        match lexer.next() {
+            Token::TERMINATE => {}
+            Token::INTEGER(_)
+            | Token::FLOAT(_)
+            | Token::STRING(_)
+            | Token::BYTES(_)
+            | Token::PARAMETER(_) => parts.push(String::from("?")),
+            token => parts.push(token.to_string()),
+        }
+    }
+    Some(parts.join(" "))
+}
+
+#[cfg(test)]
+mod normalize_tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint("select id, 'x' from foo where id = 1 and price > 2.5;").unwrap(),
+            "SELECT id , ? FROM foo WHERE id = ? AND price > ?"
+        );
+        assert_eq!(
+            fingerprint("SELECT  id,'y'\nFROM foo WHERE id = $1 AND price > ?;"),
+            fingerprint("select id, 'x' from foo where id = 1 and price > 2.5;")
+        );
+        assert_ne!(
+            fingerprint("select id from foo;"),
+            fingerprint("select name from foo;")
+        );
+        assert_eq!(fingerprint("select 'unterminated"), None);
+    }
+}
@@ -1,38 +1,232 @@
 use std::fmt::Display;
 
+use microbat_protocol::data::data_values::{MData, MDataType};
+use microbat_protocol::data::date::{parse_date, parse_timestamp};
+use microbat_protocol::data::decimal::{parse_decimal, MAX_PRECISION};
+use microbat_protocol::data::table_model::Column;
+
 use super::expression::{
-    AsExpression, Expression, LeafExpression, NegateExpression, Operation, OperationExpression,
-    ReferenceExpression,
+    AggregateExpression, AggregateFunction, AsExpression, Comparison, ComparisonExpression,
+    EvaluationError, Expression, InExpression, IsNullExpression, LeafExpression, Logical,
+    LogicalExpression, NegateExpression, NotExpression, NowExpression, NullExpression, Operation,
+    OperationExpression, ParameterExpression, ReferenceExpression, RowExpression, TableFunction,
+    WindowExpression, WindowFunction,
 };
-use super::lexer::{Lexer, LexingError, LexingErrorKind, Token};
+use super::lexer::{Lexer, LexerOptions, LexingError, LexingErrorKind, SourceRef, Token};
+use crate::db::sink::Sink;
+use crate::db::sort::{Collation, SortOptions};
 
 pub enum SqlClause {
     ShowTables,
-    Select(Vec<Box<dyn Expression>>, Vec<String>),
+    Describe(String),
+    Select(SelectClause),
+    Insert(InsertClause),
+    /// CREATE TABLE name (column type, ...)
+    CreateTable(String, Vec<Column>),
+    /// CREATE TABLE name AS SELECT ...
+    CreateTableAs(String, SelectClause),
+    /// CREATE INDEX name ON table (column)
+    CreateIndex(String, String, String),
+    /// EXPLAIN SELECT ..., describes how the select would be executed
+    Explain(SelectClause),
+    /// COPY name FROM STDIN, inserts the rows the client sends after the statement
+    CopyFrom(String),
+    /// IMPORT CSV 'path' INTO table [WITH HEADER], inserts the rows of a CSV file on the
+    /// server, skipping its first line if it is a header
+    ImportCsv(String, String, bool),
+    /// SET name = value, None for SET name = DEFAULT
+    Set(String, Option<String>),
+    /// SHOW name, the value of a session parameter
+    Show(String),
+    /// SHOW ALL, every session parameter
+    ShowAll,
+    /// CHECKPOINT, writes the tables to storage right away
+    Checkpoint,
+    /// ANALYZE [table], collects statistics of the table or of every table for planning
+    Analyze(Option<String>),
+}
+
+impl SqlClause {
+    /// Binds query parameters to the placeholders in this clause
+    pub fn bind(&mut self, parameters: &[MData]) -> Result<(), EvaluationError> {
+        match self {
+            SqlClause::ShowTables
+            | SqlClause::Describe(_)
+            | SqlClause::CreateTable(..)
+            | SqlClause::CreateIndex(..)
+            | SqlClause::CopyFrom(_)
+            | SqlClause::ImportCsv(..)
+            | SqlClause::Set(..)
+            | SqlClause::Show(_)
+            | SqlClause::ShowAll
+            | SqlClause::Checkpoint
+            | SqlClause::Analyze(_) => Ok(()),
+            SqlClause::Insert(insert) => {
+                for expression in insert.rows.iter_mut().flatten() {
+                    expression.bind(parameters)?;
+                }
+                for expression in insert.returning.iter_mut() {
+                    expression.bind(parameters)?;
+                }
+                Ok(())
+            }
+            SqlClause::Select(select)
+            | SqlClause::CreateTableAs(_, select)
+            | SqlClause::Explain(select) => {
+                for expression in select.projection.iter_mut() {
+                    expression.bind(parameters)?;
+                }
+                for item in select.from.iter_mut() {
+                    if let FromItem::Function(call) = item {
+                        for argument in call.arguments.iter_mut() {
+                            argument.bind(parameters)?;
+                        }
+                    }
+                }
+                if let Some(filter) = select.filter.as_mut() {
+                    filter.bind(parameters)?;
+                }
+                if let Some(group_by) = select.group_by.as_mut() {
+                    for expression in group_by.expressions.iter_mut() {
+                        expression.bind(parameters)?;
+                    }
+                }
+                for order_by in select.order_by.iter_mut() {
+                    order_by.expression.bind(parameters)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct SelectClause {
+    pub projection: Vec<Box<dyn Expression>>,
+    /// Where the rows go from SELECT ... INTO instead of the client
+    pub into: Option<Sink>,
+    pub from: Vec<FromItem>,
+    /// WHERE condition, rows for which it is false or unknown are left out
+    pub filter: Option<Box<dyn Expression>>,
+    pub group_by: Option<GroupBy>,
+    pub order_by: Vec<OrderBy>,
+    /// Maximum number of rows returned, from LIMIT or FETCH FIRST
+    pub limit: Option<usize>,
+    /// Number of rows skipped before returning any
+    pub offset: usize,
+}
+
+/// Source of rows in FROM
+pub enum FromItem {
+    Table(String),
+    Function(TableFunctionCall),
+}
+
+/// Table function in FROM, e.g. `generate_series(1, 10) AS g(n)`
+pub struct TableFunctionCall {
+    pub function: TableFunction,
+    pub arguments: Vec<Box<dyn Expression>>,
+    /// Name of the relation, which names its only column if there are no column aliases
+    pub alias: Option<String>,
+    pub column_aliases: Vec<String>,
+}
+
+/// INSERT INTO table VALUES (...), ... [RETURNING ...]
+pub struct InsertClause {
+    pub table: String,
+    pub rows: Vec<Vec<Box<dyn Expression>>>,
+    /// Expressions evaluated against each inserted row and sent back as a result set
+    pub returning: Vec<Box<dyn Expression>>,
+}
+
+/// Keys of GROUP BY and the grouping sets formed of them
+pub struct GroupBy {
+    pub expressions: Vec<Box<dyn Expression>>,
+    /// Indexes of `expressions` in each grouping set. Plain GROUP BY has a single set
+    /// of all expressions, ROLLUP, CUBE and GROUPING SETS have several.
+    pub sets: Vec<Vec<usize>>,
+}
+
+/// Sort key in ORDER BY
+pub struct OrderBy {
+    pub expression: Box<dyn Expression>,
+    pub options: SortOptions,
 }
 
 #[derive(Debug)]
 pub struct ParseError {
     pub kind: ParseErrorKind,
+    // Where in the SQL parsing failed
+    pub position: Option<SourceRef>,
+}
+
+impl ParseError {
+    /// Creates an error without position. Errors returned by parse_sql get the position
+    /// of the last token read if they don't have a more exact one.
+    fn new(kind: ParseErrorKind) -> Self {
+        ParseError {
+            kind,
+            position: None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParseErrorKind {
     LexingError(LexingErrorKind),
-    UnexpectedToken,
+    UnexpectedToken(Token),
     EndOfTokens,
     NoNud(String),
     NoLed(String),
+    UnknownCollation(String),
+    UnknownFunction(String),
+    UnknownType(String),
+    InvalidDate(String),
+    InvalidTimestamp(String),
+    InvalidDecimal(String),
+    InvalidPrecision(i64, i64),
+    /// GROUP BY key that is not a column
+    GroupingExpression,
+    /// Column of a grouping select neither grouped by nor in an aggregate
+    UngroupedColumn(String),
 }
 
-impl Display for ParseError {
+impl Display for ParseErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.kind {
+        match self {
             ParseErrorKind::LexingError(le) => write!(f, "{}", le),
-            ParseErrorKind::UnexpectedToken => write!(f, "Unexpected token... somewhere"),
+            ParseErrorKind::UnexpectedToken(token) => write!(f, "unexpected token {}", token),
             ParseErrorKind::EndOfTokens => write!(f, "Unexpected end of tokens"),
             ParseErrorKind::NoNud(token) => write!(f, "No nud {}", token),
             ParseErrorKind::NoLed(token) => write!(f, "No led {}", token),
+            ParseErrorKind::UnknownCollation(name) => write!(f, "Unknown collation {}", name),
+            ParseErrorKind::UnknownFunction(name) => write!(f, "Unknown function {}", name),
+            ParseErrorKind::UnknownType(name) => write!(f, "Unknown type {}", name),
+            ParseErrorKind::InvalidDate(value) => write!(f, "Invalid date '{}'", value),
+            ParseErrorKind::InvalidTimestamp(value) => write!(f, "Invalid timestamp '{}'", value),
+            ParseErrorKind::InvalidDecimal(value) => write!(f, "Invalid decimal '{}'", value),
+            ParseErrorKind::InvalidPrecision(precision, scale) => write!(
+                f,
+                "Invalid precision {} and scale {} for DECIMAL",
+                precision, scale
+            ),
+            ParseErrorKind::GroupingExpression => {
+                write!(f, "Only columns can be grouped by in GROUP BY")
+            }
+            ParseErrorKind::UngroupedColumn(name) => write!(
+                f,
+                "Column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                name
+            ),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match &self.position {
+            Some(position) => write!(f, " at {}", position),
+            None => Ok(()),
         }
     }
 }
@@ -41,52 +235,665 @@ impl From<LexingError> for ParseError {
     fn from(value: LexingError) -> Self {
         Self {
             kind: ParseErrorKind::LexingError(value.kind),
+            position: value.position,
         }
     }
 }
 
 pub fn parse_sql(sql: String) -> Result<SqlClause, ParseError> {
-    let mut lexer = Lexer::with_input(sql)?;
+    parse_sql_with_options(sql, LexerOptions::default())
+}
+
+/// Parses sql lexed with given options, e.g. with the case folding of the database
+pub fn parse_sql_with_options(sql: String, options: LexerOptions) -> Result<SqlClause, ParseError> {
+    let mut lexer = Lexer::with_options(sql, options)?;
+    parse_clause(&mut lexer).map_err(|mut err| {
+        if err.position.is_none() {
+            err.position = Some(lexer.position());
+        }
+        err
+    })
+}
+
+fn parse_clause(lexer: &mut Lexer) -> Result<SqlClause, ParseError> {
     match lexer.next() {
-        Token::SHOW => {
-            if lexer.next() == &Token::TABLES {
-                return Ok(SqlClause::ShowTables);
+        Token::SHOW => match lexer.next() {
+            Token::TABLES => Ok(SqlClause::ShowTables),
+            Token::COLUMNS => {
+                if lexer.next() != &Token::FROM {
+                    return Err(unexpected(lexer));
+                }
+                Ok(SqlClause::Describe(lexer.next_identifier()?))
             }
-            Err(ParseError {
-                kind: ParseErrorKind::UnexpectedToken,
-            })
+            Token::IDENTIFIER(name) if name.eq_ignore_ascii_case("all") => Ok(SqlClause::ShowAll),
+            Token::IDENTIFIER(name) => Ok(SqlClause::Show(name.to_owned())),
+            _ => Err(unexpected(lexer)),
+        },
+        Token::SET => {
+            let name = lexer.next_identifier()?;
+            expect(lexer, Token::EQUALS)?;
+            let value = match lexer.next() {
+                Token::IDENTIFIER(value) if value.eq_ignore_ascii_case("default") => None,
+                Token::IDENTIFIER(value) | Token::STRING(value) => Some(value.to_owned()),
+                Token::INTEGER(value) => Some(value.to_string()),
+                _ => return Err(unexpected(lexer)),
+            };
+            Ok(SqlClause::Set(name, value))
         }
-        Token::SELECT => {
-            let mut exprs = vec![];
-            let mut from = vec![];
-            exprs.push(parse_expression(&mut lexer, 0)?);
-            while lexer.peek() == Some(&Token::COMMA) {
+        Token::DESCRIBE => Ok(SqlClause::Describe(lexer.next_identifier()?)),
+        Token::CHECKPOINT => Ok(SqlClause::Checkpoint),
+        Token::ANALYZE => match lexer.peek() {
+            Some(Token::IDENTIFIER(_)) => Ok(SqlClause::Analyze(Some(lexer.next_identifier()?))),
+            _ => Ok(SqlClause::Analyze(None)),
+        },
+        Token::INSERT => {
+            expect(lexer, Token::INTO)?;
+            let table = lexer.next_identifier()?;
+            expect(lexer, Token::VALUES)?;
+            let mut rows = vec![parse_values(lexer)?];
+            while lexer.peek_is(&Token::COMMA) {
                 lexer.next();
-                exprs.push(parse_expression(&mut lexer, 0)?);
+                rows.push(parse_values(lexer)?);
             }
-            if lexer.peek_is(&Token::FROM) {
+            let mut returning = vec![];
+            if lexer.peek_is(&Token::RETURNING) {
                 lexer.next();
-                from.push(lexer.next_identifier()?);
-                while lexer.peek() == Some(&Token::COMMA) {
+                returning.push(parse_expression(lexer, 0)?);
+                while lexer.peek_is(&Token::COMMA) {
+                    lexer.next();
+                    returning.push(parse_expression(lexer, 0)?);
+                }
+            }
+            Ok(SqlClause::Insert(InsertClause {
+                table,
+                rows,
+                returning,
+            }))
+        }
+        Token::COPY => {
+            let table = lexer.next_identifier()?;
+            expect(lexer, Token::FROM)?;
+            expect(lexer, Token::STDIN)?;
+            Ok(SqlClause::CopyFrom(table))
+        }
+        Token::IMPORT => {
+            match lexer.next() {
+                Token::IDENTIFIER(format) if format.eq_ignore_ascii_case("csv") => {}
+                _ => return Err(unexpected(lexer)),
+            }
+            let path = match lexer.next() {
+                Token::STRING(path) => path.clone(),
+                _ => return Err(unexpected(lexer)),
+            };
+            expect(lexer, Token::INTO)?;
+            let table = lexer.next_identifier()?;
+            let header = match lexer.peek() {
+                Some(Token::IDENTIFIER(word)) if word.eq_ignore_ascii_case("with") => {
                     lexer.next();
                     match lexer.next() {
-                        Token::IDENTIFIER(name) => {
-                            from.push(name.to_owned());
-                        }
-                        _ => {
-                            return Err(ParseError {
-                                kind: ParseErrorKind::UnexpectedToken,
-                            })
-                        }
+                        Token::IDENTIFIER(word) if word.eq_ignore_ascii_case("header") => true,
+                        _ => return Err(unexpected(lexer)),
                     }
                 }
+                _ => false,
+            };
+            Ok(SqlClause::ImportCsv(path, table, header))
+        }
+        Token::SELECT => Ok(SqlClause::Select(parse_select(lexer)?)),
+        Token::EXPLAIN => {
+            expect(lexer, Token::SELECT)?;
+            Ok(SqlClause::Explain(parse_select(lexer)?))
+        }
+        Token::CREATE => {
+            if lexer.peek_is(&Token::INDEX) {
+                lexer.next();
+                let name = lexer.next_identifier()?;
+                expect(lexer, Token::ON)?;
+                let table = lexer.next_identifier()?;
+                expect(lexer, Token::LPARENS)?;
+                let column = lexer.next_identifier()?;
+                expect(lexer, Token::RPARENS)?;
+                return Ok(SqlClause::CreateIndex(name, table, column));
+            }
+            expect(lexer, Token::TABLE)?;
+            let table = lexer.next_identifier()?;
+            if lexer.peek_is(&Token::LPARENS) {
+                lexer.next();
+                let mut columns = vec![parse_column_definition(lexer)?];
+                while lexer.peek_is(&Token::COMMA) {
+                    lexer.next();
+                    columns.push(parse_column_definition(lexer)?);
+                }
+                expect(lexer, Token::RPARENS)?;
+                return Ok(SqlClause::CreateTable(table, columns));
+            }
+            expect(lexer, Token::AS)?;
+            expect(lexer, Token::SELECT)?;
+            let select = parse_select(lexer)?;
+            if select.into.is_some() {
+                // The created table is already where the rows go
+                return Err(ParseError::new(ParseErrorKind::UnexpectedToken(
+                    Token::INTO,
+                )));
+            }
+            Ok(SqlClause::CreateTableAs(table, select))
+        }
+        _ => Err(unexpected(lexer)),
+    }
+}
+
+/// Parses a select after the SELECT keyword
+fn parse_select(lexer: &mut Lexer) -> Result<SelectClause, ParseError> {
+    let mut exprs = vec![];
+    let mut from = vec![];
+    exprs.push(parse_expression(lexer, 0)?);
+    while lexer.peek() == Some(&Token::COMMA) {
+        lexer.next();
+        exprs.push(parse_expression(lexer, 0)?);
+    }
+    let mut into = None;
+    if lexer.peek_is(&Token::INTO) {
+        lexer.next();
+        into = Some(parse_sink(lexer)?);
+    }
+    if lexer.peek_is(&Token::FROM) {
+        lexer.next();
+        from.push(parse_from_item(lexer)?);
+        while lexer.peek() == Some(&Token::COMMA) {
+            lexer.next();
+            from.push(parse_from_item(lexer)?);
+        }
+    }
+    let mut filter = None;
+    if lexer.peek_is(&Token::WHERE) {
+        lexer.next();
+        filter = Some(parse_expression(lexer, 0)?);
+    }
+    let mut group_by = None;
+    if lexer.peek_is(&Token::GROUP) {
+        lexer.next();
+        expect(lexer, Token::BY)?;
+        group_by = Some(parse_group_by(lexer)?);
+    }
+    let mut order_by = vec![];
+    if lexer.peek_is(&Token::ORDER) {
+        lexer.next();
+        expect(lexer, Token::BY)?;
+        order_by.push(parse_order_by(lexer)?);
+        while lexer.peek_is(&Token::COMMA) {
+            lexer.next();
+            order_by.push(parse_order_by(lexer)?);
+        }
+    }
+    let mut limit = None;
+    let mut offset = 0;
+    if lexer.peek_is(&Token::LIMIT) {
+        // LIMIT n [OFFSET m]
+        lexer.next();
+        limit = Some(parse_row_count(lexer)?);
+        if lexer.peek_is(&Token::OFFSET) {
+            lexer.next();
+            offset = parse_row_count(lexer)?;
+        }
+    } else {
+        // [OFFSET m {ROW | ROWS}] [FETCH {FIRST | NEXT} [n] {ROW | ROWS} ONLY]
+        if lexer.peek_is(&Token::OFFSET) {
+            lexer.next();
+            offset = parse_row_count(lexer)?;
+            if lexer.peek_is(&Token::ROW) || lexer.peek_is(&Token::ROWS) {
+                lexer.next();
+            }
+        }
+        if lexer.peek_is(&Token::FETCH) {
+            lexer.next();
+            match lexer.next() {
+                Token::FIRST | Token::NEXT => {}
+                _ => return Err(unexpected(lexer)),
+            }
+            limit = Some(match lexer.peek() {
+                Some(Token::INTEGER(_)) => parse_row_count(lexer)?,
+                _ => 1,
+            });
+            match lexer.next() {
+                Token::ROW | Token::ROWS => {}
+                _ => return Err(unexpected(lexer)),
+            }
+            expect(lexer, Token::ONLY)?;
+        }
+    }
+    // Anything else, e.g. HAVING, is an error rather than left out
+    match lexer.peek() {
+        None | Some(Token::TERMINATE) => {}
+        Some(_) => {
+            lexer.next();
+            return Err(unexpected(lexer));
+        }
+    }
+    check_grouping(&exprs, group_by.as_ref())?;
+
+    Ok(SelectClause {
+        projection: exprs,
+        into,
+        from,
+        filter,
+        group_by,
+        order_by,
+        limit,
+        offset,
+    })
+}
+
+/// Parses the target of SELECT ... INTO, either `[TABLE] name` or `OUTFILE 'path'`
+fn parse_sink(lexer: &mut Lexer) -> Result<Sink, ParseError> {
+    match lexer.peek() {
+        Some(Token::OUTFILE) => {
+            lexer.next();
+            match lexer.next() {
+                Token::STRING(path) => Ok(Sink::File(path.clone())),
+                _ => Err(unexpected(lexer)),
+            }
+        }
+        Some(Token::TABLE) => {
+            lexer.next();
+            Ok(Sink::Table(lexer.next_identifier()?))
+        }
+        _ => Ok(Sink::Table(lexer.next_identifier()?)),
+    }
+}
+
+/// Parses a non-negative row count of LIMIT, OFFSET or FETCH FIRST
+fn parse_row_count(lexer: &mut Lexer) -> Result<usize, ParseError> {
+    match lexer.next() {
+        Token::INTEGER(count) if *count >= 0 => Ok(*count as usize),
+        _ => Err(unexpected(lexer)),
+    }
+}
+
+/// Parses a table or a table function call with optional aliases
+fn parse_from_item(lexer: &mut Lexer) -> Result<FromItem, ParseError> {
+    let name = match lexer.next() {
+        Token::IDENTIFIER(name) => name.clone(),
+        _ => return Err(unexpected(lexer)),
+    };
+    if !lexer.peek_is(&Token::LPARENS) {
+        return Ok(FromItem::Table(name));
+    }
+    let function = TableFunction::from_name(&name)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownFunction(name)))?;
+    lexer.next();
+    let mut arguments = vec![];
+    if !lexer.peek_is(&Token::RPARENS) {
+        arguments.push(parse_expression(lexer, 0)?);
+        while lexer.peek_is(&Token::COMMA) {
+            lexer.next();
+            arguments.push(parse_expression(lexer, 0)?);
+        }
+    }
+    expect(lexer, Token::RPARENS)?;
+    let mut alias = None;
+    let mut column_aliases = vec![];
+    if lexer.peek_is(&Token::AS) {
+        lexer.next();
+        alias = Some(lexer.next_identifier()?);
+    } else if let Some(Token::IDENTIFIER(name)) = lexer.peek() {
+        alias = Some(name.clone());
+        lexer.next();
+    }
+    if alias.is_some() && lexer.peek_is(&Token::LPARENS) {
+        lexer.next();
+        column_aliases.push(lexer.next_identifier()?);
+        while lexer.peek_is(&Token::COMMA) {
+            lexer.next();
+            column_aliases.push(lexer.next_identifier()?);
+        }
+        expect(lexer, Token::RPARENS)?;
+    }
+    // The only column of a function is named after it, as in PostgreSQL
+    if alias.is_none() {
+        alias = Some(lexer.fold(function.name()));
+    }
+    Ok(FromItem::Function(TableFunctionCall {
+        function,
+        arguments,
+        alias,
+        column_aliases,
+    }))
+}
+
+/// Parses a column of CREATE TABLE, a name followed by the type
+fn parse_column_definition(lexer: &mut Lexer) -> Result<Column, ParseError> {
+    let name = lexer.next_identifier()?;
+    let type_name = lexer.next_identifier()?;
+    let data_type = match type_name.to_uppercase().as_str() {
+        "INTEGER" | "INT" => MDataType::Integer,
+        "BIGINT" => MDataType::BigInt,
+        "VARCHAR" | "TEXT" => MDataType::Varchar,
+        "BOOLEAN" | "BOOL" => MDataType::Bool,
+        "FLOAT" | "DOUBLE" => MDataType::Float,
+        "DATE" => MDataType::Date,
+        "TIMESTAMP" => MDataType::Timestamp,
+        "DECIMAL" | "NUMERIC" => parse_decimal_type(lexer)?,
+        "BYTEA" | "BLOB" => MDataType::Bytes,
+        _ => return Err(ParseError::new(ParseErrorKind::UnknownType(type_name))),
+    };
+    Ok(Column::new(name, data_type))
+}
+
+/// Parses the optional `(precision[, scale])` after DECIMAL. Scale defaults to zero and
+/// precision to the most there can be.
+fn parse_decimal_type(lexer: &mut Lexer) -> Result<MDataType, ParseError> {
+    let (mut precision, mut scale) = (i64::from(MAX_PRECISION), 0);
+    if lexer.peek_is(&Token::LPARENS) {
+        lexer.next();
+        precision = parse_integer(lexer)?;
+        if lexer.peek_is(&Token::COMMA) {
+            lexer.next();
+            scale = parse_integer(lexer)?;
+        }
+        expect(lexer, Token::RPARENS)?;
+    }
+    if !(1..=i64::from(MAX_PRECISION)).contains(&precision) || !(0..=precision).contains(&scale) {
+        return Err(ParseError::new(ParseErrorKind::InvalidPrecision(
+            precision, scale,
+        )));
+    }
+    Ok(MDataType::Decimal {
+        precision: precision as u8,
+        scale: scale as u8,
+    })
+}
+
+fn parse_integer(lexer: &mut Lexer) -> Result<i64, ParseError> {
+    match lexer.next() {
+        Token::INTEGER(value) => Ok(*value),
+        _ => Err(unexpected(lexer)),
+    }
+}
+
+/// Parses one parenthesized row of VALUES
+fn parse_values(lexer: &mut Lexer) -> Result<Vec<Box<dyn Expression>>, ParseError> {
+    expect(lexer, Token::LPARENS)?;
+    let mut values = vec![parse_expression(lexer, 0)?];
+    while lexer.peek_is(&Token::COMMA) {
+        lexer.next();
+        values.push(parse_expression(lexer, 0)?);
+    }
+    expect(lexer, Token::RPARENS)?;
+    Ok(values)
+}
+
+/// Checks that a select grouping its rows selects only the columns it groups by, outside
+/// aggregates, as the other columns have no single value in a group
+fn check_grouping(
+    projection: &[Box<dyn Expression>],
+    group_by: Option<&GroupBy>,
+) -> Result<(), ParseError> {
+    let keys: Vec<&str> = match group_by {
+        Some(group_by) => group_by
+            .expressions
+            .iter()
+            .filter_map(|key| key.reference())
+            .collect(),
+        None if projection.iter().any(|e| e.aggregate().is_some()) => vec![],
+        None => return Ok(()),
+    };
+    for expression in projection.iter().filter(|e| e.aggregate().is_none()) {
+        let mut names = vec![];
+        expression.references(&mut names);
+        if let Some(name) = names
+            .into_iter()
+            .find(|name| !keys.contains(&name.as_str()))
+        {
+            return Err(ParseError::new(ParseErrorKind::UngroupedColumn(name)));
+        }
+    }
+    Ok(())
+}
+
+/// Parses comma separated grouping elements after GROUP BY. Each element is an expression,
+/// `ROLLUP (...)`, `CUBE (...)` or `GROUPING SETS (...)`, and the grouping sets of the
+/// elements are combined so that every set of one is joined with every set of the others.
+fn parse_group_by(lexer: &mut Lexer) -> Result<GroupBy, ParseError> {
+    let mut expressions = vec![];
+    let mut sets = vec![vec![]];
+    loop {
+        let element_sets = parse_grouping_element(lexer, &mut expressions)?;
+        let mut combined = vec![];
+        for set in sets.iter() {
+            for element_set in element_sets.iter() {
+                combined.push([set.clone(), element_set.clone()].concat());
+            }
+        }
+        sets = combined;
+        if !lexer.peek_is(&Token::COMMA) {
+            break;
+        }
+        lexer.next();
+    }
+    Ok(GroupBy { expressions, sets })
+}
+
+/// Parses one grouping element, pushing its expressions and returning its grouping sets
+fn parse_grouping_element(
+    lexer: &mut Lexer,
+    expressions: &mut Vec<Box<dyn Expression>>,
+) -> Result<Vec<Vec<usize>>, ParseError> {
+    match lexer.peek() {
+        Some(Token::ROLLUP) => {
+            lexer.next();
+            let keys = parse_grouping_list(lexer, expressions)?;
+            // ROLLUP (a, b) groups by (a, b), (a) and ()
+            Ok((0..=keys.len()).rev().map(|n| keys[..n].to_vec()).collect())
+        }
+        Some(Token::CUBE) => {
+            lexer.next();
+            let keys = parse_grouping_list(lexer, expressions)?;
+            // CUBE (a, b) groups by (a, b), (a), (b) and ()
+            Ok((0..1usize << keys.len())
+                .rev()
+                .map(|mask| {
+                    keys.iter()
+                        .enumerate()
+                        .filter(|(position, _)| mask & (1 << (keys.len() - 1 - position)) != 0)
+                        .map(|(_, key)| *key)
+                        .collect()
+                })
+                .collect())
+        }
+        Some(Token::GROUPING) => {
+            lexer.next();
+            expect(lexer, Token::SETS)?;
+            expect(lexer, Token::LPARENS)?;
+            let mut sets = vec![];
+            loop {
+                if lexer.peek_is(&Token::LPARENS) {
+                    sets.push(parse_grouping_list(lexer, expressions)?);
+                } else {
+                    expressions.push(parse_grouping_key(lexer)?);
+                    sets.push(vec![expressions.len() - 1]);
+                }
+                if !lexer.peek_is(&Token::COMMA) {
+                    break;
+                }
+                lexer.next();
+            }
+            expect(lexer, Token::RPARENS)?;
+            Ok(sets)
+        }
+        _ => {
+            expressions.push(parse_grouping_key(lexer)?);
+            Ok(vec![vec![expressions.len() - 1]])
+        }
+    }
+}
+
+/// Parses parenthesized, possibly empty, list of grouping expressions and returns their indexes
+fn parse_grouping_list(
+    lexer: &mut Lexer,
+    expressions: &mut Vec<Box<dyn Expression>>,
+) -> Result<Vec<usize>, ParseError> {
+    expect(lexer, Token::LPARENS)?;
+    let mut keys = vec![];
+    if lexer.peek_is(&Token::RPARENS) {
+        lexer.next();
+        return Ok(keys);
+    }
+    loop {
+        expressions.push(parse_grouping_key(lexer)?);
+        keys.push(expressions.len() - 1);
+        if !lexer.peek_is(&Token::COMMA) {
+            break;
+        }
+        lexer.next();
+    }
+    expect(lexer, Token::RPARENS)?;
+    Ok(keys)
+}
+
+/// Parses a key of GROUP BY, which must be a column as the select columns are matched to
+/// the keys by name
+fn parse_grouping_key(lexer: &mut Lexer) -> Result<Box<dyn Expression>, ParseError> {
+    let key = parse_expression(lexer, 0)?;
+    match key.reference() {
+        Some(_) => Ok(key),
+        None => Err(ParseError::new(ParseErrorKind::GroupingExpression)),
+    }
+}
+
+/// Parses sort key `expression [COLLATE name] [ASC | DESC] [NULLS FIRST | NULLS LAST]`
+fn parse_order_by(lexer: &mut Lexer) -> Result<OrderBy, ParseError> {
+    let expression = parse_expression(lexer, 0)?;
+    let mut collation = Collation::default();
+    if lexer.peek_is(&Token::COLLATE) {
+        lexer.next();
+        let name = lexer.next_identifier()?;
+        collation = Collation::from_name(&name)
+            .ok_or(ParseError::new(ParseErrorKind::UnknownCollation(name)))?;
+    }
+    let mut descending = false;
+    if lexer.peek_is(&Token::ASC) {
+        lexer.next();
+    } else if lexer.peek_is(&Token::DESC) {
+        lexer.next();
+        descending = true;
+    }
+    let mut nulls_first = None;
+    if lexer.peek_is(&Token::NULLS) {
+        lexer.next();
+        nulls_first = match lexer.next() {
+            Token::FIRST => Some(true),
+            Token::LAST => Some(false),
+            _ => return Err(unexpected(lexer)),
+        };
+    }
+    Ok(OrderBy {
+        expression,
+        options: SortOptions::new(descending, nulls_first, collation),
+    })
+}
+
+/// Parses a function call after the function name
+fn parse_function(lexer: &mut Lexer, name: String) -> Result<Box<dyn Expression>, ParseError> {
+    if let Some(mut function) = AggregateFunction::from_name(&name) {
+        expect(lexer, Token::LPARENS)?;
+        let distinct = lexer.peek_is(&Token::DISTINCT);
+        if distinct {
+            lexer.next();
+        }
+        let argument = if function == AggregateFunction::Count
+            && !distinct
+            && lexer.peek_is(&Token::MULTIPLICATION)
+        {
+            lexer.next();
+            None
+        } else {
+            Some(parse_expression(lexer, 0)?)
+        };
+        // STRING_AGG(value, separator) requires the separator, GROUP_CONCAT defaults to a comma
+        if let AggregateFunction::StringAgg(separator) = &mut function {
+            if name.eq_ignore_ascii_case("STRING_AGG") || lexer.peek_is(&Token::COMMA) {
+                expect(lexer, Token::COMMA)?;
+                match lexer.next() {
+                    Token::STRING(value) => *separator = value.clone(),
+                    _ => return Err(unexpected(lexer)),
+                }
             }
+        }
+        expect(lexer, Token::RPARENS)?;
+        return Ok(Box::new(AggregateExpression {
+            function,
+            argument,
+            distinct,
+        }));
+    }
+    if name.eq_ignore_ascii_case("NOW") {
+        expect(lexer, Token::LPARENS)?;
+        expect(lexer, Token::RPARENS)?;
+        return Ok(Box::new(NowExpression::new(String::from("now"))));
+    }
+    let function = WindowFunction::from_name(&name)
+        .ok_or(ParseError::new(ParseErrorKind::UnknownFunction(name)))?;
+    expect(lexer, Token::LPARENS)?;
+    expect(lexer, Token::RPARENS)?;
+    expect(lexer, Token::OVER)?;
+    expect(lexer, Token::LPARENS)?;
+    let mut order_by = vec![];
+    if lexer.peek_is(&Token::ORDER) {
+        lexer.next();
+        expect(lexer, Token::BY)?;
+        order_by.push(parse_order_by(lexer)?);
+        while lexer.peek_is(&Token::COMMA) {
+            lexer.next();
+            order_by.push(parse_order_by(lexer)?);
+        }
+    }
+    expect(lexer, Token::RPARENS)?;
+    Ok(Box::new(WindowExpression { function, order_by }))
+}
 
-            Ok(SqlClause::Select(exprs, from))
+/// Parses the parenthesized values after IN
+fn parse_in_list(
+    lexer: &mut Lexer,
+    expression: Box<dyn Expression>,
+    negated: bool,
+) -> Result<Box<dyn Expression>, ParseError> {
+    expect(lexer, Token::LPARENS)?;
+    let mut list = vec![parse_expression(lexer, 0)?];
+    while lexer.peek_is(&Token::COMMA) {
+        lexer.next();
+        list.push(parse_expression(lexer, 0)?);
+    }
+    expect(lexer, Token::RPARENS)?;
+    Ok(Box::new(InExpression {
+        expression,
+        list,
+        negated,
+    }))
+}
+
+/// Error for the token last read from the lexer
+fn unexpected(lexer: &Lexer) -> ParseError {
+    match lexer.last() {
+        Some(token) => ParseError {
+            kind: ParseErrorKind::UnexpectedToken(token.clone()),
+            position: Some(lexer.position()),
+        },
+        None => ParseError::new(ParseErrorKind::EndOfTokens),
+    }
+}
+
+/// Consumes next token, failing if it is not the expected one
+fn expect(lexer: &mut Lexer, expected: Token) -> Result<(), ParseError> {
+    match lexer.peek() {
+        Some(token) if *token == expected => {
+            lexer.next();
+            Ok(())
         }
-        _ => Err(ParseError {
-            kind: ParseErrorKind::UnexpectedToken,
+        Some(token) => Err(ParseError {
+            kind: ParseErrorKind::UnexpectedToken(token.clone()),
+            position: Some(lexer.peek_position()),
         }),
+        None => Err(ParseError::new(ParseErrorKind::EndOfTokens)),
     }
 }
 
@@ -94,15 +901,80 @@ fn nud(lexer: &mut Lexer) -> Result<Box<dyn Expression>, ParseError> {
     let token = lexer.next();
     let rbp = token.rbp();
     match token {
-        Token::IDENTIFIER(v) => Ok(Box::new(ReferenceExpression::new(v.clone()))),
-        Token::INTEGER(v) => Ok(Box::new(LeafExpression::new(*v))),
-        Token::LPARENS => parse_expression(lexer, 0),
+        Token::IDENTIFIER(v) => {
+            let name = v.clone();
+            let keyword = name.to_uppercase();
+            if lexer.peek_is(&Token::LPARENS) {
+                parse_function(lexer, name)
+            } else if let (Some(Token::STRING(value)), "DATE") = (lexer.peek(), keyword.as_str()) {
+                // DATE 'YYYY-MM-DD'
+                let date = parse_date(value)
+                    .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidDate(value.clone())))?;
+                lexer.next();
+                Ok(Box::new(LeafExpression::new(MData::Date(date))))
+            } else if let (Some(Token::STRING(value)), "TIMESTAMP") =
+                (lexer.peek(), keyword.as_str())
+            {
+                // TIMESTAMP 'YYYY-MM-DD HH:MM:SS'
+                let timestamp = parse_timestamp(value).ok_or_else(|| {
+                    ParseError::new(ParseErrorKind::InvalidTimestamp(value.clone()))
+                })?;
+                lexer.next();
+                Ok(Box::new(LeafExpression::new(MData::Timestamp(timestamp))))
+            } else if let (Some(Token::STRING(value)), "DECIMAL" | "NUMERIC") =
+                (lexer.peek(), keyword.as_str())
+            {
+                // DECIMAL '12.34', which is exact unlike the float 12.34
+                let (value, scale) = parse_decimal(value).ok_or_else(|| {
+                    ParseError::new(ParseErrorKind::InvalidDecimal(value.clone()))
+                })?;
+                lexer.next();
+                Ok(Box::new(LeafExpression::new(MData::Decimal(value, scale))))
+            } else if keyword == "CURRENT_TIMESTAMP" {
+                Ok(Box::new(NowExpression::new(String::from(
+                    "current_timestamp",
+                ))))
+            } else {
+                Ok(Box::new(ReferenceExpression::new(name)))
+            }
+        }
+        // Literals too large for an integer are big integers
+        Token::INTEGER(v) => match i32::try_from(*v) {
+            Ok(v) => Ok(Box::new(LeafExpression::new(v))),
+            Err(_) => Ok(Box::new(LeafExpression::new(*v))),
+        },
+        Token::FLOAT(v) => Ok(Box::new(LeafExpression::new(*v))),
+        Token::STRING(v) => Ok(Box::new(LeafExpression::new(v.clone()))),
+        Token::BYTES(v) => Ok(Box::new(LeafExpression::new(MData::Bytes(v.clone())))),
+        Token::TRUE => Ok(Box::new(LeafExpression::new(true))),
+        Token::FALSE => Ok(Box::new(LeafExpression::new(false))),
+        Token::NULL => Ok(Box::new(NullExpression {})),
+        Token::PARAMETER(index) => Ok(Box::new(ParameterExpression::new(*index))),
+        Token::LPARENS => {
+            let expression = parse_expression(lexer, 0)?;
+            if !lexer.peek_is(&Token::COMMA) {
+                expect(lexer, Token::RPARENS)?;
+                return Ok(expression);
+            }
+            // Several comma separated values make a row value
+            let mut elements = vec![expression];
+            while lexer.peek_is(&Token::COMMA) {
+                lexer.next();
+                elements.push(parse_expression(lexer, 0)?);
+            }
+            expect(lexer, Token::RPARENS)?;
+            Ok(Box::new(RowExpression { elements }))
+        }
         Token::MINUS => Ok(Box::new(NegateExpression {
             expression: parse_expression(lexer, rbp)?,
         })),
-        token => Err(ParseError {
-            kind: ParseErrorKind::NoNud(format!("{:?}", token)),
-        }),
+        Token::NOT => Ok(Box::new(NotExpression {
+            expression: parse_expression(lexer, rbp)?,
+        })),
+        token => Err(ParseError::new(ParseErrorKind::NoNud(format!(
+            "{:?}",
+            token
+        )))),
     }
 }
 
@@ -130,10 +1002,59 @@ fn led(lexer: &mut Lexer, left: Box<dyn Expression>) -> Result<Box<dyn Expressio
                 right,
             }))
         }
-        Token::RPARENS => Ok(left),
-        token => Err(ParseError {
-            kind: ParseErrorKind::NoLed(format!("{:?}", token)),
-        }),
+        Token::AND | Token::OR => {
+            let logical = match token {
+                Token::AND => Logical::And,
+                _ => Logical::Or,
+            };
+            let right = parse_expression(lexer, rbp)?;
+            Ok(Box::new(LogicalExpression {
+                logical,
+                left,
+                right,
+            }))
+        }
+        Token::EQUALS
+        | Token::NOTEQUALS
+        | Token::LESSTHAN
+        | Token::LESSOREQUAL
+        | Token::GREATERTHAN
+        | Token::GREATEROREQUAL => {
+            let comparison = match token {
+                Token::EQUALS => Comparison::Equal,
+                Token::NOTEQUALS => Comparison::NotEqual,
+                Token::LESSTHAN => Comparison::LessThan,
+                Token::LESSOREQUAL => Comparison::LessThanOrEqual,
+                Token::GREATERTHAN => Comparison::GreaterThan,
+                _ => Comparison::GreaterThanOrEqual,
+            };
+            let right = parse_expression(lexer, rbp)?;
+            Ok(Box::new(ComparisonExpression {
+                comparison,
+                left,
+                right,
+            }))
+        }
+        Token::IN => parse_in_list(lexer, left, false),
+        Token::NOT => {
+            expect(lexer, Token::IN)?;
+            parse_in_list(lexer, left, true)
+        }
+        Token::IS => {
+            let negated = lexer.peek_is(&Token::NOT);
+            if negated {
+                lexer.next();
+            }
+            expect(lexer, Token::NULL)?;
+            Ok(Box::new(IsNullExpression {
+                expression: left,
+                negated,
+            }))
+        }
+        token => Err(ParseError::new(ParseErrorKind::NoLed(format!(
+            "{:?}",
+            token
+        )))),
     }
 }
 
@@ -141,11 +1062,21 @@ impl Token {
     fn rbp(&self) -> usize {
         match self {
             Token::INTEGER(_) => 1,
-            Token::PLUS => 5,
-            Token::MINUS => 5,
             Token::AS => 2,
+            Token::OR => 3,
+            Token::AND => 4,
+            Token::NOT => 5,
+            Token::EQUALS
+            | Token::NOTEQUALS
+            | Token::LESSTHAN
+            | Token::LESSOREQUAL
+            | Token::GREATERTHAN
+            | Token::GREATEROREQUAL
+            | Token::IS
+            | Token::IN => 6,
+            Token::PLUS => 7,
+            Token::MINUS => 7,
             Token::LPARENS => 50,
-            Token::RPARENS => 1,
             _ => 0,
         }
     }
@@ -156,9 +1087,7 @@ fn parse_expression(lexer: &mut Lexer, rbp: usize) -> Result<Box<dyn Expression>
     let mut left = nud(lexer)?;
     while lexer
         .peek()
-        .ok_or(ParseError {
-            kind: ParseErrorKind::EndOfTokens,
-        })?
+        .ok_or(ParseError::new(ParseErrorKind::EndOfTokens))?
         .rbp()
         > rbp
     {
@@ -197,6 +1126,38 @@ mod tests {
         assert_expression_error!("112 + 11", ParseErrorKind::EndOfTokens);
     }
 
+    #[test]
+    fn test_parsing_error_positions() {
+        let message = |sql: &str| match parse_sql(sql.to_owned()) {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("Expecting {} to fail", sql),
+        };
+        assert_eq!(
+            message("select a from foo order a;"),
+            "unexpected token a at line 1 column 25"
+        );
+        assert_eq!(
+            message("select a,\n  b from foo\n  order by a nulls;"),
+            "unexpected token ; at line 3 column 19"
+        );
+        assert_eq!(
+            message("select a from foo order by a collate klingon;"),
+            "Unknown collation klingon at line 1 column 38"
+        );
+        assert_eq!(
+            message("show columns foo;"),
+            "unexpected token foo at line 1 column 14"
+        );
+        assert_eq!(
+            message("select string_agg(a, 1) from foo;"),
+            "unexpected token 1 at line 1 column 22"
+        );
+        assert_eq!(
+            message("select a from 'foo"),
+            "String is not terminated at line 1 column 15"
+        );
+    }
+
     #[test]
     fn test_parsing() {
         assert_expression_parsing!("1;", MData::Integer(1));
@@ -215,6 +1176,113 @@ mod tests {
         assert_expression_parsing!("10 - (5 - 2);", MData::Integer(7));
     }
 
+    #[test]
+    fn test_literals() {
+        assert_expression_parsing!("true;", MData::Bool(true));
+        assert_expression_parsing!("FALSE;", MData::Bool(false));
+        assert_expression_parsing!("null;", MData::Null);
+        assert_expression_parsing!("-null;", MData::Null);
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_expression_parsing!("1 = 1;", MData::Bool(true));
+        assert_expression_parsing!("1 <> 1;", MData::Bool(false));
+        assert_expression_parsing!("1 + 1 > 1;", MData::Bool(true));
+        assert_expression_parsing!("-2 >= -1;", MData::Bool(false));
+        assert_expression_parsing!("'a' < 'b';", MData::Bool(true));
+        assert_expression_parsing!("true <= false;", MData::Bool(false));
+        assert_expression_parsing!("1 = null;", MData::Null);
+        assert_expression_parsing!("null <> null;", MData::Null);
+        assert_expression_parsing!("null is null;", MData::Bool(true));
+        assert_expression_parsing!("1 = null is not null;", MData::Bool(false));
+    }
+
+    #[test]
+    fn test_row_values() {
+        assert_expression_parsing!("(1, 'x') = (1, 'x');", MData::Bool(true));
+        assert_expression_parsing!("(1, 'x') <> (1, 'y');", MData::Bool(true));
+        assert_expression_parsing!("(1, 2) < (1, 3);", MData::Bool(true));
+        assert_expression_parsing!("(1, 2) < (0, 3);", MData::Bool(false));
+        assert_expression_parsing!("(1, 2, 3) >= (1, 2, 3);", MData::Bool(true));
+        assert_expression_parsing!("(1, null) = (1, 2);", MData::Null);
+        assert_expression_parsing!("(1, null) = (2, 2);", MData::Bool(false));
+        assert_expression_parsing!("(1, null) < (2, 2);", MData::Bool(true));
+        assert_expression_parsing!("(1, null) < (1, 2);", MData::Null);
+    }
+
+    #[test]
+    fn test_in_lists() {
+        assert_expression_parsing!("2 in (1, 2, 3);", MData::Bool(true));
+        assert_expression_parsing!("4 not in (1, 2, 3);", MData::Bool(true));
+        assert_expression_parsing!("4 in (1, null);", MData::Null);
+        assert_expression_parsing!("1 in (1, null);", MData::Bool(true));
+        assert_expression_parsing!("4 not in (1, null);", MData::Null);
+        assert_expression_parsing!("(2, 'y') in ((1, 'x'), (2, 'y'));", MData::Bool(true));
+        assert_expression_parsing!("(2, 'x') in ((1, 'x'), (2, 'y'));", MData::Bool(false));
+    }
+
+    #[test]
+    fn test_three_valued_logic() {
+        assert_expression_parsing!("1 = 1 and 2 = 2;", MData::Bool(true));
+        assert_expression_parsing!("1 = 2 or 2 = 2 and 1 = 2;", MData::Bool(false));
+        assert_expression_parsing!("not 1 = 2;", MData::Bool(true));
+        assert_expression_parsing!("null and true;", MData::Null);
+        assert_expression_parsing!("null and false;", MData::Bool(false));
+        assert_expression_parsing!("null or true;", MData::Bool(true));
+        assert_expression_parsing!("null or false;", MData::Null);
+        assert_expression_parsing!("not null;", MData::Null);
+        assert_expression_parsing!("not (1 = null);", MData::Null);
+    }
+
+    #[test]
+    fn test_float_expressions() {
+        assert_expression_parsing!("1.5 + 1;", MData::Float(2.5));
+        assert_expression_parsing!("2 - 0.5;", MData::Float(1.5));
+        assert_expression_parsing!("-1.5;", MData::Float(-1.5));
+        assert_expression_parsing!("1.5 > 1;", MData::Bool(true));
+        assert_expression_parsing!("2 = 2.0;", MData::Bool(true));
+    }
+
+    #[test]
+    fn test_bigint_expressions() {
+        assert_expression_parsing!("2147483647;", MData::Integer(i32::MAX));
+        assert_expression_parsing!("2147483648;", MData::BigInt(2147483648));
+        assert_expression_parsing!("2147483647 + 2147483648;", MData::BigInt(4294967295));
+        assert_expression_parsing!("-2147483648 - 1;", MData::BigInt(-2147483649));
+        assert_expression_parsing!("5000000000 > 1;", MData::Bool(true));
+        assert_expression_parsing!("5000000000 = 5000000000.0;", MData::Bool(true));
+    }
+
+    #[test]
+    fn test_date_literals() {
+        assert_expression_parsing!("date '1970-01-02';", MData::Date(1));
+        assert_expression_parsing!("DATE '2024-01-01' < date '2024-01-02';", MData::Bool(true));
+        assert_expression_parsing!("date '2024-01-01' = null;", MData::Null);
+        assert_expression_error!(
+            "date '2024-02-30';",
+            ParseErrorKind::InvalidDate(String::from("2024-02-30"))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_literals() {
+        assert_expression_parsing!(
+            "timestamp '1970-01-01 00:00:01';",
+            MData::Timestamp(1_000_000)
+        );
+        assert_expression_parsing!(
+            "timestamp '2024-01-01 12:00:00' < timestamp '2024-01-01 12:00:00.5';",
+            MData::Bool(true)
+        );
+        assert_expression_parsing!("now() <= current_timestamp;", MData::Bool(true));
+        assert_expression_parsing!("now() > timestamp '2024-01-01';", MData::Bool(true));
+        assert_expression_error!(
+            "timestamp '2024-01-01 25:00:00';",
+            ParseErrorKind::InvalidTimestamp(String::from("2024-01-01 25:00:00"))
+        );
+    }
+
     #[test]
     fn test_negatives() {
         assert_expression_parsing!("2-10;", MData::Integer(-8));
@@ -226,7 +1294,7 @@ mod tests {
         let expr = parse_expression(&mut lexer, 1).unwrap();
         match expr.eval(
             &TableSchema::new(vec![Column::new(String::from("foo"), MDataType::Integer)]).unwrap(),
-            &vec![],
+            &[],
         ) {
             Ok(val) => {
                 assert_eq!(val, evals_to, "{} did not eval as expected", input);
@@ -244,11 +1312,43 @@ mod tests {
             input
         );
         match result {
-            Ok(_) => assert!(false, "Expected \"{}\" to error but it succeeded", input),
+            Ok(_) => panic!("Expected \"{}\" to error but it succeeded", input),
             Err(error) => assert_eq!(error.kind, expected_error),
         }
     }
 
+    #[test]
+    fn test_parameter_binding() {
+        let mut clause = parse_sql(String::from("select $2, $1 + 1;")).unwrap();
+        clause
+            .bind(&[MData::Integer(5), MData::Varchar(String::from("foo"))])
+            .unwrap();
+        let schema =
+            TableSchema::new(vec![Column::new(String::from("foo"), MDataType::Integer)]).unwrap();
+        match clause {
+            SqlClause::Select(SelectClause { projection, .. }) => {
+                assert_eq!(
+                    projection[0].eval(&schema, &[]).unwrap(),
+                    MData::Varchar(String::from("foo"))
+                );
+                assert_eq!(projection[1].eval(&schema, &[]).unwrap(), MData::Integer(6));
+            }
+            _ => panic!("Expecting select"),
+        }
+
+        let mut clause = parse_sql(String::from("select ?, ?;")).unwrap();
+        let error = clause.bind(&[MData::Integer(5)]).err().unwrap();
+        assert_eq!(error.msg, "No value for parameter $2, 1 parameters given");
+
+        let clause = parse_sql(String::from("select $1;")).unwrap();
+        match clause {
+            SqlClause::Select(SelectClause { projection, .. }) => {
+                assert!(projection[0].eval(&schema, &[]).is_err());
+            }
+            _ => panic!("Expecting select"),
+        }
+    }
+
     #[test]
     fn test_show_table_parsing() {
         let sql_ast = parse_sql("SHOW TABLES;".to_owned()).expect("Can't parse SHOW TABLES");
@@ -258,6 +1358,401 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_describe_parsing() {
+        for sql in ["DESCRIBE foo;", "show columns from foo;"] {
+            match parse_sql(sql.to_owned()).unwrap_or_else(|_| panic!("Can't parse {}", sql)) {
+                SqlClause::Describe(table) => assert_eq!(table, "foo"),
+                _ => panic!("Didn't parse {} to Describe", sql),
+            }
+        }
+        assert!(parse_sql("describe;".to_owned()).is_err());
+        assert!(parse_sql("show columns foo;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_set_and_show_parsing() {
+        let cases = [
+            ("SET statement_timeout = 100;", Some("100")),
+            ("set datestyle = iso", Some("iso")),
+            ("set null_display = 'NULL';", Some("NULL")),
+            ("SET null_display = DEFAULT;", None),
+        ];
+        for (sql, expected) in cases {
+            match parse_sql(sql.to_owned()).unwrap_or_else(|_| panic!("Can't parse {}", sql)) {
+                SqlClause::Set(_, value) => assert_eq!(value.as_deref(), expected),
+                _ => panic!("Didn't parse {} to Set", sql),
+            }
+        }
+        assert!(parse_sql("set statement_timeout 100;".to_owned()).is_err());
+        assert!(parse_sql("set statement_timeout = ;".to_owned()).is_err());
+
+        match parse_sql("SHOW DateStyle;".to_owned()).unwrap() {
+            SqlClause::Show(name) => assert_eq!(name, "datestyle"),
+            _ => panic!("Didn't parse to Show"),
+        }
+        assert!(matches!(
+            parse_sql("show all;".to_owned()).unwrap(),
+            SqlClause::ShowAll
+        ));
+        assert!(matches!(
+            parse_sql("Checkpoint;".to_owned()).unwrap(),
+            SqlClause::Checkpoint
+        ));
+    }
+
+    #[test]
+    fn test_analyze_parsing() {
+        match parse_sql("analyze Foo;".to_owned()).unwrap() {
+            SqlClause::Analyze(table) => assert_eq!(table.as_deref(), Some("foo")),
+            _ => panic!("Didn't parse to Analyze"),
+        }
+        assert!(matches!(
+            parse_sql("ANALYZE;".to_owned()).unwrap(),
+            SqlClause::Analyze(None)
+        ));
+    }
+
+    #[test]
+    fn test_order_by_parsing() {
+        let sql =
+            "select a from foo order by a, b collate nocase desc, c nulls first, d asc nulls last;";
+        match parse_sql(sql.to_owned()).unwrap_or_else(|err| panic!("Can't parse: {}", err)) {
+            SqlClause::Select(select) => {
+                let options: Vec<SortOptions> =
+                    select.order_by.iter().map(|order| order.options).collect();
+                assert_eq!(
+                    options,
+                    vec![
+                        SortOptions::new(false, None, Collation::Binary),
+                        SortOptions::new(true, None, Collation::NoCase),
+                        SortOptions::new(false, Some(true), Collation::Binary),
+                        SortOptions::new(false, Some(false), Collation::Binary),
+                    ]
+                );
+            }
+            _ => panic!("Expecting select"),
+        }
+        match parse_sql("select a from foo order by a collate klingon;".to_owned()) {
+            Err(err) => assert_eq!(
+                err.kind,
+                ParseErrorKind::UnknownCollation(String::from("klingon"))
+            ),
+            Ok(_) => panic!("Unknown collation should fail"),
+        }
+        assert!(parse_sql("select a from foo order a;".to_owned()).is_err());
+        assert!(parse_sql("select a from foo order by a nulls;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_limit_parsing() {
+        let limits = |sql: &str| match parse_sql(sql.to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse {}: {}", sql, err))
+        {
+            SqlClause::Select(select) => (select.limit, select.offset),
+            _ => panic!("Expecting select"),
+        };
+        assert_eq!(limits("select a from foo;"), (None, 0));
+        assert_eq!(limits("select a from foo limit 5;"), (Some(5), 0));
+        assert_eq!(limits("select a from foo limit 5 offset 2;"), (Some(5), 2));
+        assert_eq!(limits("select a from foo offset 2 rows;"), (None, 2));
+        assert_eq!(
+            limits("select a from foo order by a offset 1 row fetch next 3 rows only;"),
+            (Some(3), 1)
+        );
+        assert_eq!(
+            limits("select a from foo fetch first row only;"),
+            (Some(1), 0)
+        );
+        assert!(parse_sql("select a from foo limit -1;".to_owned()).is_err());
+        assert!(parse_sql("select a from foo fetch first 2 rows;".to_owned()).is_err());
+        assert!(parse_sql("select a from foo fetch 2 rows only;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_insert_parsing() {
+        match parse_sql("insert into foo values (1, 'a'), (2, null) returning a, b;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::Insert(insert) => {
+                assert_eq!(insert.table, "foo");
+                assert_eq!(insert.rows.len(), 2);
+                assert_eq!(insert.rows[1].len(), 2);
+                assert_eq!(insert.returning.len(), 2);
+            }
+            _ => panic!("Expecting insert"),
+        }
+        assert!(parse_sql("insert foo values (1);".to_owned()).is_err());
+        assert!(parse_sql("insert into foo (1);".to_owned()).is_err());
+        assert!(parse_sql("insert into foo values ();".to_owned()).is_err());
+        assert!(parse_sql("insert into foo values (1) returning;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_copy_parsing() {
+        match parse_sql("COPY foo FROM STDIN;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::CopyFrom(table) => assert_eq!(table, "foo"),
+            _ => panic!("Expecting copy"),
+        }
+        assert!(parse_sql("copy foo from 'foo.csv';".to_owned()).is_err());
+        assert!(parse_sql("copy foo stdin;".to_owned()).is_err());
+        assert!(parse_sql("copy from stdin;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_import_parsing() {
+        match parse_sql("IMPORT CSV '/tmp/foo.csv' INTO foo;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::ImportCsv(path, table, header) => {
+                assert_eq!(path, "/tmp/foo.csv");
+                assert_eq!(table, "foo");
+                assert!(!header);
+            }
+            _ => panic!("Expecting import"),
+        }
+        match parse_sql("import csv 'foo.csv' into Foo with header;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::ImportCsv(_, table, header) => {
+                assert_eq!(table, "foo");
+                assert!(header);
+            }
+            _ => panic!("Expecting import"),
+        }
+        assert!(parse_sql("import 'foo.csv' into foo;".to_owned()).is_err());
+        assert!(parse_sql("import csv foo into foo;".to_owned()).is_err());
+        assert!(parse_sql("import csv 'foo.csv' foo;".to_owned()).is_err());
+        assert!(parse_sql("import csv 'foo.csv' into foo with;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_create_table_as_parsing() {
+        match parse_sql("create table bar as select a, b from foo;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::CreateTableAs(table, select) => {
+                assert_eq!(table, "bar");
+                assert_eq!(select.projection.len(), 2);
+                assert!(matches!(&select.from[..], [FromItem::Table(name)] if name == "foo"));
+            }
+            _ => panic!("Expecting create table as"),
+        }
+        assert!(parse_sql("create table bar select a from foo;".to_owned()).is_err());
+        assert!(parse_sql("create bar as select a from foo;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_create_table_parsing() {
+        match parse_sql("create table bar (a integer, b varchar, c boolean, d float);".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::CreateTable(table, columns) => {
+                assert_eq!(table, "bar");
+                assert_eq!(
+                    columns,
+                    vec![
+                        Column::new(String::from("a"), MDataType::Integer),
+                        Column::new(String::from("b"), MDataType::Varchar),
+                        Column::new(String::from("c"), MDataType::Bool),
+                        Column::new(String::from("d"), MDataType::Float),
+                    ]
+                );
+            }
+            _ => panic!("Expecting create table"),
+        }
+        match parse_sql("create table bar (a geometry);".to_owned()) {
+            Err(err) => assert_eq!(
+                err.kind,
+                ParseErrorKind::UnknownType(String::from("geometry"))
+            ),
+            Ok(_) => panic!("Unknown type should fail"),
+        }
+        assert!(parse_sql("create table bar ();".to_owned()).is_err());
+        assert!(parse_sql("create table bar (a);".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_create_index_parsing() {
+        match parse_sql("CREATE INDEX foo_a ON Foo (A);".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::CreateIndex(name, table, column) => {
+                assert_eq!(
+                    (name.as_str(), table.as_str(), column.as_str()),
+                    ("foo_a", "foo", "a")
+                );
+            }
+            _ => panic!("Expecting create index"),
+        }
+        assert!(parse_sql("create index foo_a foo (a);".to_owned()).is_err());
+        assert!(parse_sql("create index foo_a on foo a;".to_owned()).is_err());
+        assert!(parse_sql("create index on foo (a);".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_decimal_parsing() {
+        let column_type = |sql: &str| match parse_sql(sql.to_owned()) {
+            Ok(SqlClause::CreateTable(_, columns)) => Ok(columns[0].data_type.clone()),
+            Ok(_) => panic!("Expecting create table"),
+            Err(err) => Err(err.kind),
+        };
+        assert_eq!(
+            column_type("create table t (a decimal(10, 2));"),
+            Ok(MDataType::Decimal {
+                precision: 10,
+                scale: 2
+            })
+        );
+        assert_eq!(
+            column_type("create table t (a numeric(5));"),
+            Ok(MDataType::Decimal {
+                precision: 5,
+                scale: 0
+            })
+        );
+        assert_eq!(
+            column_type("create table t (a decimal);"),
+            Ok(MDataType::Decimal {
+                precision: 38,
+                scale: 0
+            })
+        );
+        assert_eq!(
+            column_type("create table t (a decimal(2, 3));"),
+            Err(ParseErrorKind::InvalidPrecision(2, 3))
+        );
+        assert_eq!(
+            column_type("create table t (a decimal(39));"),
+            Err(ParseErrorKind::InvalidPrecision(39, 0))
+        );
+        assert_expression_parsing!("decimal '12.34';", MData::Decimal(1234, 2));
+        assert_expression_parsing!(
+            "numeric '0.1' + numeric '0.2' = numeric '0.3';",
+            MData::Bool(true)
+        );
+        assert_expression_parsing!("-decimal '1.5' < 1;", MData::Bool(true));
+        assert_expression_error!(
+            "decimal '1,5';",
+            ParseErrorKind::InvalidDecimal(String::from("1,5"))
+        );
+    }
+
+    #[test]
+    fn test_bytes_parsing() {
+        assert_expression_parsing!("x'CAFE';", MData::Bytes(vec![0xca, 0xfe]));
+        assert_expression_parsing!("x'01' < x'0100';", MData::Bool(true));
+        match parse_sql("create table t (a bytea, b blob);".to_owned()) {
+            Ok(SqlClause::CreateTable(_, columns)) => {
+                assert_eq!(columns[0].data_type, MDataType::Bytes);
+                assert_eq!(columns[1].data_type, MDataType::Bytes);
+            }
+            _ => panic!("Expecting create table"),
+        }
+    }
+
+    #[test]
+    fn test_explain_parsing() {
+        match parse_sql("explain select id from foo limit 2;".to_owned())
+            .unwrap_or_else(|err| panic!("Can't parse: {}", err))
+        {
+            SqlClause::Explain(select) => {
+                assert_eq!(select.projection.len(), 1);
+                assert_eq!(select.limit, Some(2));
+            }
+            _ => panic!("Expecting explain"),
+        }
+        assert!(parse_sql("explain show tables;".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_group_by_parsing() {
+        let sets = |sql: &str| -> Vec<Vec<usize>> {
+            match parse_sql(sql.to_owned()).unwrap_or_else(|err| panic!("Can't parse: {}", err)) {
+                SqlClause::Select(select) => select.group_by.expect("Expecting group by").sets,
+                _ => panic!("Expecting select"),
+            }
+        };
+        assert_eq!(sets("select a from foo group by a, b;"), vec![vec![0, 1]]);
+        assert_eq!(
+            sets("select a from foo group by rollup (a, b);"),
+            vec![vec![0, 1], vec![0], vec![]]
+        );
+        assert_eq!(
+            sets("select a from foo group by cube (a, b);"),
+            vec![vec![0, 1], vec![0], vec![1], vec![]]
+        );
+        assert_eq!(
+            sets("select a from foo group by grouping sets ((a, b), c, ());"),
+            vec![vec![0, 1], vec![2], vec![]]
+        );
+        assert_eq!(
+            sets("select a from foo group by a, rollup (b);"),
+            vec![vec![0, 1], vec![0]]
+        );
+        assert!(parse_sql("select a from foo group a;".to_owned()).is_err());
+        assert_eq!(
+            parse_sql("select a from foo group by a + 1;".to_owned())
+                .err()
+                .map(|err| err.kind),
+            Some(ParseErrorKind::GroupingExpression)
+        );
+        assert_eq!(
+            parse_sql("select a, b + 1 from foo group by rollup (a);".to_owned())
+                .err()
+                .map(|err| err.kind),
+            Some(ParseErrorKind::UngroupedColumn(String::from("b")))
+        );
+        assert!(parse_sql("select a, count(b) from foo group by a;".to_owned()).is_ok());
+        // HAVING isn't supported, and nothing after the select is left out
+        assert_eq!(
+            parse_sql("select count(*) from foo group by a having count(*) > 1;".to_owned())
+                .err()
+                .map(|err| err.kind),
+            Some(ParseErrorKind::UnexpectedToken(Token::IDENTIFIER(
+                String::from("having")
+            )))
+        );
+        assert!(parse_sql("select a from foo limit 1 2;".to_owned()).is_err());
+        assert!(parse_sql("select a from foo group by grouping (a);".to_owned()).is_err());
+        assert!(parse_sql("select sum(*) from foo;".to_owned()).is_err());
+        assert!(parse_sql("select count(distinct *) from foo;".to_owned()).is_err());
+        assert!(
+            parse_sql("select count(distinct a), sum(distinct a) from foo;".to_owned()).is_ok()
+        );
+        assert!(parse_sql("select string_agg(a) from foo;".to_owned()).is_err());
+        assert!(parse_sql("select string_agg(a, 1) from foo;".to_owned()).is_err());
+        assert!(parse_sql("select group_concat(a) from foo;".to_owned()).is_ok());
+    }
+
+    #[test]
+    fn test_window_function_parsing() {
+        let sql = "select a, row_number() over (order by a desc, b) from foo;";
+        match parse_sql(sql.to_owned()).unwrap_or_else(|err| panic!("Can't parse: {}", err)) {
+            SqlClause::Select(select) => {
+                assert!(select.projection[0].window().is_none());
+                let window = select.projection[1].window().expect("Expecting window");
+                assert_eq!(window.function, WindowFunction::RowNumber);
+                assert_eq!(window.order_by.len(), 2);
+                assert!(window.order_by[0].options.descending);
+            }
+            _ => panic!("Expecting select"),
+        }
+        assert!(parse_sql("select row_number() over () from foo;".to_owned()).is_ok());
+        match parse_sql("select rank() over () from foo;".to_owned()) {
+            Err(err) => assert_eq!(
+                err.kind,
+                ParseErrorKind::UnknownFunction(String::from("rank"))
+            ),
+            Ok(_) => panic!("Unknown function should fail"),
+        }
+        assert!(parse_sql("select row_number() from foo;".to_owned()).is_err());
+        assert!(parse_sql("select row_number() over (order a) from foo;".to_owned()).is_err());
+        assert!(parse_sql("select (1 + 2 from foo;".to_owned()).is_err());
+    }
+
     #[test]
     fn test_sql_parsing_only_with_projection() {
         assert_parsing("select 1;", vec![MData::Integer(1)], vec![]);
@@ -289,23 +1784,58 @@ mod tests {
         assert_parsing(
             "select 1 from bar",
             vec![MData::Integer(1)],
-            vec![String::from("BAR")],
+            vec![String::from("bar")],
         );
         assert_parsing(
             "select 1 from foo, bar",
             vec![MData::Integer(1)],
-            vec![String::from("FOO"), String::from("BAR")],
+            vec![String::from("foo"), String::from("bar")],
         );
+        assert_parsing(
+            "select n from foo, generate_series(1, 3) as g(n)",
+            vec![MData::Integer(1)],
+            vec![String::from("foo"), String::from("generate_series")],
+        );
+        match parse_sql("select x from generate_series(1, $1) x;".to_owned()) {
+            Ok(SqlClause::Select(select)) => match &select.from[..] {
+                [FromItem::Function(call)] => {
+                    assert_eq!(call.arguments.len(), 2);
+                    assert_eq!(call.alias, Some(String::from("x")));
+                    assert!(call.column_aliases.is_empty());
+                }
+                _ => panic!("Expecting a table function"),
+            },
+            _ => panic!("Expecting select"),
+        }
+        match parse_sql("select 1 from nope(1);".to_owned()) {
+            Err(err) => assert_eq!(
+                err.kind,
+                ParseErrorKind::UnknownFunction(String::from("nope"))
+            ),
+            Ok(_) => panic!("Unknown table function should fail"),
+        }
     }
 
     fn assert_parsing(input: &str, expected_projections: Vec<MData>, expected_from: Vec<String>) {
-        let sql_ast = parse_sql(input.to_owned()).expect(format!("Can't parse {}", input).as_str());
+        let sql_ast =
+            parse_sql(input.to_owned()).unwrap_or_else(|_| panic!("Can't parse {}", input));
         match sql_ast {
-            SqlClause::Select(projections, from) => {
+            SqlClause::Select(SelectClause {
+                projection: projections,
+                from,
+                ..
+            }) => {
                 assert_eq!(projections.len(), expected_projections.len());
                 // TODO: actually assert parsing somehow
-                if expected_from.len() > 0 {
-                    assert_eq!(from, expected_from);
+                if !expected_from.is_empty() {
+                    let tables: Vec<String> = from
+                        .into_iter()
+                        .map(|item| match item {
+                            FromItem::Table(name) => name,
+                            FromItem::Function(call) => call.function.name().to_owned(),
+                        })
+                        .collect();
+                    assert_eq!(tables, expected_from);
                 }
             }
 